@@ -0,0 +1,24 @@
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("Unable to read cbindgen.toml");
+    let header = cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("Unable to generate the C header");
+    header.write_to_file(out_dir.join("bigtools.h"));
+    // The committed header is only updated on request, so that building doesn't
+    // write to the source tree (`tests/capi.rs` checks that it's up to date)
+    if env::var_os("BIGTOOLS_CAPI_UPDATE_HEADER").is_some() {
+        header.write_to_file(crate_dir.join("include").join("bigtools.h"));
+    }
+
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-env-changed=BIGTOOLS_CAPI_UPDATE_HEADER");
+}
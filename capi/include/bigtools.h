@@ -0,0 +1,286 @@
+#ifndef BIGTOOLS_H
+#define BIGTOOLS_H
+
+/* This file is generated by cbindgen from src/lib.rs when building. Do not edit it by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The result of a call.
+typedef enum BigtoolsStatus {
+  BIGTOOLS_STATUS_OK = 0,
+  // An iterator has no more items.
+  BIGTOOLS_STATUS_END = 1,
+  // A pointer was null, a string wasn't UTF-8, or an interval or buffer
+  // was invalid.
+  BIGTOOLS_STATUS_INVALID_ARGUMENT = 2,
+  BIGTOOLS_STATUS_IO_ERROR = 3,
+  // The file isn't a bigWig or bigBed (as expected), or is corrupt.
+  BIGTOOLS_STATUS_INVALID_FILE = 4,
+  BIGTOOLS_STATUS_CHROM_NOT_FOUND = 5,
+  BIGTOOLS_STATUS_ZOOM_NOT_FOUND = 6,
+  // An unexpected internal error. This is a bug.
+  BIGTOOLS_STATUS_INTERNAL_ERROR = 7,
+} BigtoolsStatus;
+
+// An iterator over the entries of a bigBed.
+typedef struct BigBedIntervals BigBedIntervals;
+
+// An open bigBed.
+typedef struct BigBedReader BigBedReader;
+
+// An iterator over the values of a bigWig.
+typedef struct BigWigIntervals BigWigIntervals;
+
+// An open bigWig.
+typedef struct BigWigReader BigWigReader;
+
+// An iterator over the records of a zoom level.
+typedef struct BigtoolsZoomIntervals BigtoolsZoomIntervals;
+
+// A value of a bigWig.
+typedef struct BigtoolsValue {
+  uint32_t start;
+  uint32_t end;
+  float value;
+} BigtoolsValue;
+
+// An entry of a bigBed. `rest` is the tab-separated remaining fields, and
+// is valid until the next call with the same iterator.
+typedef struct BigtoolsBedEntry {
+  uint32_t start;
+  uint32_t end;
+  const char *rest;
+} BigtoolsBedEntry;
+
+// A summary of the values over an interval of a zoom level.
+typedef struct BigtoolsZoomRecord {
+  uint32_t start;
+  uint32_t end;
+  uint64_t bases_covered;
+  double min_val;
+  double max_val;
+  double sum;
+  double sum_squares;
+} BigtoolsZoomRecord;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Returns the message of the last error on this thread, or null if there
+// hasn't been one. The message is valid until the next failing call on this
+// thread.
+const char *bigtools_last_error(void);
+
+// Opens the bigWig at `path`, storing the reader in `out`.
+//
+// # Safety
+// `path` must be a null-terminated string and `out` a valid pointer.
+enum BigtoolsStatus bigwig_open(const char *path, struct BigWigReader **out);
+
+// Closes a bigWig. Any strings returned from it are no longer valid.
+//
+// # Safety
+// `reader` must be null or returned by `bigwig_open`, and not already closed.
+void bigwig_close(struct BigWigReader *reader);
+
+// Returns the number of chromosomes in a bigWig.
+//
+// # Safety
+// `reader` must be null or an open bigWig.
+size_t bigwig_chrom_count(const struct BigWigReader *reader);
+
+// Gets the name and length of the chromosome at `index`. Either output may
+// be null. The name is valid until the reader is closed.
+//
+// # Safety
+// `reader` must be an open bigWig, and `name` and `length` null or valid.
+enum BigtoolsStatus bigwig_chrom(struct BigWigReader *reader,
+                                 size_t index,
+                                 const char **name,
+                                 uint32_t *length);
+
+// Returns the number of zoom levels in a bigWig.
+//
+// # Safety
+// `reader` must be null or an open bigWig.
+size_t bigwig_zoom_count(const struct BigWigReader *reader);
+
+// Gets the reduction level of the zoom level at `index`.
+//
+// # Safety
+// `reader` must be an open bigWig and `level` a valid pointer.
+enum BigtoolsStatus bigwig_zoom_level(struct BigWigReader *reader, size_t index, uint32_t *level);
+
+// Starts iterating over the values overlapping `start..end` of `chrom`.
+// The iterator doesn't borrow the reader, and must be freed with
+// `bigwig_intervals_free`.
+//
+// # Safety
+// `reader` must be an open bigWig, `chrom` a null-terminated string, and
+// `out` a valid pointer.
+enum BigtoolsStatus bigwig_intervals(struct BigWigReader *reader,
+                                     const char *chrom,
+                                     uint32_t start,
+                                     uint32_t end,
+                                     struct BigWigIntervals **out);
+
+// Reads the next value into `out`. Returns `BIGTOOLS_STATUS_END` when there
+// are no more values.
+//
+// # Safety
+// `intervals` must be returned by `bigwig_intervals` and `out` a valid
+// pointer.
+enum BigtoolsStatus bigwig_intervals_next(struct BigWigIntervals *intervals,
+                                          struct BigtoolsValue *out);
+
+// Frees an iterator returned by `bigwig_intervals`.
+//
+// # Safety
+// `intervals` must be null or returned by `bigwig_intervals`, and not
+// already freed.
+void bigwig_intervals_free(struct BigWigIntervals *intervals);
+
+// Writes the value of each base of `start..end` of `chrom` to `out`, which
+// must have room for at least `end - start` values. Bases without data are
+// `NAN`.
+//
+// # Safety
+// `reader` must be an open bigWig, `chrom` a null-terminated string, and
+// `out` valid for `out_len` floats.
+enum BigtoolsStatus bigwig_values(struct BigWigReader *reader,
+                                  const char *chrom,
+                                  uint32_t start,
+                                  uint32_t end,
+                                  float *out,
+                                  size_t out_len);
+
+// Starts iterating over the records of the zoom level with
+// `reduction_level` overlapping `start..end` of `chrom`. The iterator must
+// be freed with `bigtools_zoom_intervals_free`.
+//
+// # Safety
+// `reader` must be an open bigWig, `chrom` a null-terminated string, and
+// `out` a valid pointer.
+enum BigtoolsStatus bigwig_zoom_intervals(struct BigWigReader *reader,
+                                          const char *chrom,
+                                          uint32_t start,
+                                          uint32_t end,
+                                          uint32_t reduction_level,
+                                          struct BigtoolsZoomIntervals **out);
+
+// Opens the bigBed at `path`, storing the reader in `out`.
+//
+// # Safety
+// `path` must be a null-terminated string and `out` a valid pointer.
+enum BigtoolsStatus bigbed_open(const char *path, struct BigBedReader **out);
+
+// Closes a bigBed. Any strings returned from it are no longer valid.
+//
+// # Safety
+// `reader` must be null or returned by `bigbed_open`, and not already closed.
+void bigbed_close(struct BigBedReader *reader);
+
+// Returns the number of chromosomes in a bigBed.
+//
+// # Safety
+// `reader` must be null or an open bigBed.
+size_t bigbed_chrom_count(const struct BigBedReader *reader);
+
+// Gets the name and length of the chromosome at `index`. Either output may
+// be null. The name is valid until the reader is closed.
+//
+// # Safety
+// `reader` must be an open bigBed, and `name` and `length` null or valid.
+enum BigtoolsStatus bigbed_chrom(struct BigBedReader *reader,
+                                 size_t index,
+                                 const char **name,
+                                 uint32_t *length);
+
+// Returns the number of zoom levels in a bigBed.
+//
+// # Safety
+// `reader` must be null or an open bigBed.
+size_t bigbed_zoom_count(const struct BigBedReader *reader);
+
+// Gets the reduction level of the zoom level at `index`.
+//
+// # Safety
+// `reader` must be an open bigBed and `level` a valid pointer.
+enum BigtoolsStatus bigbed_zoom_level(struct BigBedReader *reader, size_t index, uint32_t *level);
+
+// Gets the autoSql definition of the bigBed's fields. The string is valid
+// until the reader is closed.
+//
+// # Safety
+// `reader` must be an open bigBed and `out` a valid pointer.
+enum BigtoolsStatus bigbed_autosql(struct BigBedReader *reader, const char **out);
+
+// Starts iterating over the entries overlapping `start..end` of `chrom`.
+// The iterator doesn't borrow the reader, and must be freed with
+// `bigbed_intervals_free`.
+//
+// # Safety
+// `reader` must be an open bigBed, `chrom` a null-terminated string, and
+// `out` a valid pointer.
+enum BigtoolsStatus bigbed_intervals(struct BigBedReader *reader,
+                                     const char *chrom,
+                                     uint32_t start,
+                                     uint32_t end,
+                                     struct BigBedIntervals **out);
+
+// Reads the next entry into `out`. Returns `BIGTOOLS_STATUS_END` when there
+// are no more entries.
+//
+// # Safety
+// `intervals` must be returned by `bigbed_intervals` and `out` a valid
+// pointer.
+enum BigtoolsStatus bigbed_intervals_next(struct BigBedIntervals *intervals,
+                                          struct BigtoolsBedEntry *out);
+
+// Frees an iterator returned by `bigbed_intervals`.
+//
+// # Safety
+// `intervals` must be null or returned by `bigbed_intervals`, and not
+// already freed.
+void bigbed_intervals_free(struct BigBedIntervals *intervals);
+
+// Starts iterating over the records of the zoom level with
+// `reduction_level` overlapping `start..end` of `chrom`. The iterator must
+// be freed with `bigtools_zoom_intervals_free`.
+//
+// # Safety
+// `reader` must be an open bigBed, `chrom` a null-terminated string, and
+// `out` a valid pointer.
+enum BigtoolsStatus bigbed_zoom_intervals(struct BigBedReader *reader,
+                                          const char *chrom,
+                                          uint32_t start,
+                                          uint32_t end,
+                                          uint32_t reduction_level,
+                                          struct BigtoolsZoomIntervals **out);
+
+// Reads the next zoom record into `out`. Returns `BIGTOOLS_STATUS_END` when
+// there are no more records.
+//
+// # Safety
+// `intervals` must be returned by `bigwig_zoom_intervals` or
+// `bigbed_zoom_intervals`, and `out` a valid pointer.
+enum BigtoolsStatus bigtools_zoom_intervals_next(struct BigtoolsZoomIntervals *intervals,
+                                                 struct BigtoolsZoomRecord *out);
+
+// Frees an iterator returned by `bigwig_zoom_intervals` or
+// `bigbed_zoom_intervals`.
+//
+// # Safety
+// `intervals` must be null or a zoom iterator that isn't already freed.
+void bigtools_zoom_intervals_free(struct BigtoolsZoomIntervals *intervals);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BIGTOOLS_H */
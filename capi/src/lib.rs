@@ -0,0 +1,745 @@
+//! A C API for reading bigWigs and bigBeds.
+//!
+//! The header for this API, `include/bigtools.h`, is generated by `cbindgen`
+//! when this crate is built.
+//!
+//! Every fallible function returns a [`BigtoolsStatus`]. When it isn't
+//! `BIGTOOLS_STATUS_OK` (or `BIGTOOLS_STATUS_END`), a message describing the
+//! error can be retrieved with [`bigtools_last_error`] on the same thread.
+//! Readers and iterators are opaque handles, which must be freed with their
+//! matching `_close` or `_free` function. Strings returned through the API
+//! are owned by the handle they came from.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::io;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use bigtools::bbiread::{BBIFileInfo, BBIRead, BBIReadError, CirTreeSearchError};
+use bigtools::utils::reopen::{Reopen, ReopenableFile};
+use bigtools::{
+    BedEntry, BigBedRead, BigBedReadAttachError, BigWigRead, BigWigReadAttachError, Value,
+    ZoomRecord,
+};
+
+/// The result of a call.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BigtoolsStatus {
+    Ok = 0,
+    /// An iterator has no more items.
+    End = 1,
+    /// A pointer was null, a string wasn't UTF-8, or an interval or buffer
+    /// was invalid.
+    InvalidArgument = 2,
+    IoError = 3,
+    /// The file isn't a bigWig or bigBed (as expected), or is corrupt.
+    InvalidFile = 4,
+    ChromNotFound = 5,
+    ZoomNotFound = 6,
+    /// An unexpected internal error. This is a bug.
+    InternalError = 7,
+}
+
+struct Error(BigtoolsStatus, String);
+
+impl Error {
+    fn invalid_argument(message: impl Into<String>) -> Self {
+        Error(BigtoolsStatus::InvalidArgument, message.into())
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error(BigtoolsStatus::IoError, e.to_string())
+    }
+}
+
+impl From<BBIReadError> for Error {
+    fn from(e: BBIReadError) -> Self {
+        match e {
+            BBIReadError::InvalidChromosome(chrom)
+            | BBIReadError::CirTreeSearchError(CirTreeSearchError::InvalidChromosome(chrom)) => {
+                Error(
+                    BigtoolsStatus::ChromNotFound,
+                    format!("Chromosome `{}` is not in the file.", chrom),
+                )
+            }
+            BBIReadError::IoError(e) => e.into(),
+            e => Error(BigtoolsStatus::InvalidFile, e.to_string()),
+        }
+    }
+}
+
+impl From<bigtools::bigwigread::ZoomIntervalError> for Error {
+    fn from(e: bigtools::bigwigread::ZoomIntervalError) -> Self {
+        match e {
+            bigtools::bigwigread::ZoomIntervalError::ReductionLevelNotFound => Error(
+                BigtoolsStatus::ZoomNotFound,
+                "The reduction level is not in the file.".to_owned(),
+            ),
+            bigtools::bigwigread::ZoomIntervalError::BBIReadError(e) => e.into(),
+        }
+    }
+}
+
+impl From<bigtools::bigbedread::ZoomIntervalError> for Error {
+    fn from(e: bigtools::bigbedread::ZoomIntervalError) -> Self {
+        match e {
+            bigtools::bigbedread::ZoomIntervalError::ReductionLevelNotFound => Error(
+                BigtoolsStatus::ZoomNotFound,
+                "The reduction level is not in the file.".to_owned(),
+            ),
+            bigtools::bigbedread::ZoomIntervalError::BBIReadError(e) => e.into(),
+        }
+    }
+}
+
+impl From<BigWigReadAttachError> for Error {
+    fn from(e: BigWigReadAttachError) -> Self {
+        match e {
+            BigWigReadAttachError::IoError(e) => e.into(),
+            BigWigReadAttachError::NotABigWig => Error(
+                BigtoolsStatus::InvalidFile,
+                "The file is not a bigWig.".to_owned(),
+            ),
+            e => Error(BigtoolsStatus::InvalidFile, e.to_string()),
+        }
+    }
+}
+
+impl From<BigBedReadAttachError> for Error {
+    fn from(e: BigBedReadAttachError) -> Self {
+        match e {
+            BigBedReadAttachError::IoError(e) => e.into(),
+            e => Error(BigtoolsStatus::InvalidFile, e.to_string()),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, recording any error (or panic) for `bigtools_last_error`.
+fn guard(f: impl FnOnce() -> Result<BigtoolsStatus, Error>) -> BigtoolsStatus {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(status)) => status,
+        Ok(Err(Error(status, message))) => {
+            set_last_error(message);
+            status
+        }
+        Err(_) => {
+            set_last_error("An internal error occurred.".to_owned());
+            BigtoolsStatus::InternalError
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str, Error> {
+    if s.is_null() {
+        return Err(Error::invalid_argument(format!("`{}` is null.", name)));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| Error::invalid_argument(format!("`{}` is not valid UTF-8.", name)))
+}
+
+unsafe fn ref_arg<'a, T>(p: *mut T, name: &str) -> Result<&'a mut T, Error> {
+    p.as_mut()
+        .ok_or_else(|| Error::invalid_argument(format!("`{}` is null.", name)))
+}
+
+fn check_interval(start: u32, end: u32) -> Result<(), Error> {
+    if start > end {
+        return Err(Error::invalid_argument(format!(
+            "Invalid interval: start ({}) is greater than end ({}).",
+            start, end
+        )));
+    }
+    Ok(())
+}
+
+fn c_chroms(chroms: Vec<bigtools::ChromAndSize>) -> Vec<(CString, u32)> {
+    chroms
+        .into_iter()
+        .map(|c| (CString::new(c.name).unwrap_or_default(), c.length))
+        .collect()
+}
+
+unsafe fn get_chrom(
+    chroms: &[(CString, u32)],
+    index: usize,
+    name: *mut *const c_char,
+    length: *mut u32,
+) -> Result<BigtoolsStatus, Error> {
+    let (chrom_name, chrom_length) = chroms.get(index).ok_or_else(|| {
+        Error::invalid_argument(format!("Chromosome index {} is out of range.", index))
+    })?;
+    if !name.is_null() {
+        *name = chrom_name.as_ptr();
+    }
+    if !length.is_null() {
+        *length = *chrom_length;
+    }
+    Ok(BigtoolsStatus::Ok)
+}
+
+unsafe fn get_zoom_level(
+    info: &BBIFileInfo,
+    index: usize,
+    level: *mut u32,
+) -> Result<BigtoolsStatus, Error> {
+    let level = ref_arg(level, "level")?;
+    *level = info
+        .zoom_headers
+        .get(index)
+        .ok_or_else(|| Error::invalid_argument(format!("Zoom index {} is out of range.", index)))?
+        .reduction_level;
+    Ok(BigtoolsStatus::Ok)
+}
+
+/// A value of a bigWig.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct BigtoolsValue {
+    pub start: u32,
+    pub end: u32,
+    pub value: f32,
+}
+
+/// An entry of a bigBed. `rest` is the tab-separated remaining fields, and
+/// is valid until the next call with the same iterator.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct BigtoolsBedEntry {
+    pub start: u32,
+    pub end: u32,
+    pub rest: *const c_char,
+}
+
+/// A summary of the values over an interval of a zoom level.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct BigtoolsZoomRecord {
+    pub start: u32,
+    pub end: u32,
+    pub bases_covered: u64,
+    pub min_val: f64,
+    pub max_val: f64,
+    pub sum: f64,
+    pub sum_squares: f64,
+}
+
+/// An open bigWig.
+pub struct BigWigReader {
+    read: BigWigRead<ReopenableFile>,
+    chroms: Vec<(CString, u32)>,
+}
+
+/// An open bigBed.
+pub struct BigBedReader {
+    read: BigBedRead<ReopenableFile>,
+    chroms: Vec<(CString, u32)>,
+    autosql: Option<CString>,
+}
+
+/// An iterator over the values of a bigWig.
+pub struct BigWigIntervals {
+    iter: Box<dyn Iterator<Item = Result<Value, BBIReadError>>>,
+}
+
+/// An iterator over the entries of a bigBed.
+pub struct BigBedIntervals {
+    iter: Box<dyn Iterator<Item = Result<BedEntry, BBIReadError>>>,
+    rest: CString,
+}
+
+/// An iterator over the records of a zoom level.
+pub struct BigtoolsZoomIntervals {
+    records: std::vec::IntoIter<ZoomRecord>,
+}
+
+/// Returns the message of the last error on this thread, or null if there
+/// hasn't been one. The message is valid until the next failing call on this
+/// thread.
+#[no_mangle]
+pub extern "C" fn bigtools_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| match &*e.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Opens the bigWig at `path`, storing the reader in `out`.
+///
+/// # Safety
+/// `path` must be a null-terminated string and `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bigwig_open(
+    path: *const c_char,
+    out: *mut *mut BigWigReader,
+) -> BigtoolsStatus {
+    guard(|| {
+        let path = str_arg(path, "path")?;
+        let out = ref_arg(out, "out")?;
+        let read = BigWigRead::open_file(path)?;
+        let chroms = c_chroms(read.get_chroms());
+        *out = Box::into_raw(Box::new(BigWigReader { read, chroms }));
+        Ok(BigtoolsStatus::Ok)
+    })
+}
+
+/// Closes a bigWig. Any strings returned from it are no longer valid.
+///
+/// # Safety
+/// `reader` must be null or returned by `bigwig_open`, and not already closed.
+#[no_mangle]
+pub unsafe extern "C" fn bigwig_close(reader: *mut BigWigReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// Returns the number of chromosomes in a bigWig.
+///
+/// # Safety
+/// `reader` must be null or an open bigWig.
+#[no_mangle]
+pub unsafe extern "C" fn bigwig_chrom_count(reader: *const BigWigReader) -> usize {
+    reader.as_ref().map_or(0, |r| r.chroms.len())
+}
+
+/// Gets the name and length of the chromosome at `index`. Either output may
+/// be null. The name is valid until the reader is closed.
+///
+/// # Safety
+/// `reader` must be an open bigWig, and `name` and `length` null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn bigwig_chrom(
+    reader: *mut BigWigReader,
+    index: usize,
+    name: *mut *const c_char,
+    length: *mut u32,
+) -> BigtoolsStatus {
+    guard(|| get_chrom(&ref_arg(reader, "reader")?.chroms, index, name, length))
+}
+
+/// Returns the number of zoom levels in a bigWig.
+///
+/// # Safety
+/// `reader` must be null or an open bigWig.
+#[no_mangle]
+pub unsafe extern "C" fn bigwig_zoom_count(reader: *const BigWigReader) -> usize {
+    reader
+        .as_ref()
+        .map_or(0, |r| r.read.info.zoom_headers.len())
+}
+
+/// Gets the reduction level of the zoom level at `index`.
+///
+/// # Safety
+/// `reader` must be an open bigWig and `level` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bigwig_zoom_level(
+    reader: *mut BigWigReader,
+    index: usize,
+    level: *mut u32,
+) -> BigtoolsStatus {
+    guard(|| get_zoom_level(&ref_arg(reader, "reader")?.read.info, index, level))
+}
+
+/// Starts iterating over the values overlapping `start..end` of `chrom`.
+/// The iterator doesn't borrow the reader, and must be freed with
+/// `bigwig_intervals_free`.
+///
+/// # Safety
+/// `reader` must be an open bigWig, `chrom` a null-terminated string, and
+/// `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bigwig_intervals(
+    reader: *mut BigWigReader,
+    chrom: *const c_char,
+    start: u32,
+    end: u32,
+    out: *mut *mut BigWigIntervals,
+) -> BigtoolsStatus {
+    guard(|| {
+        let reader = ref_arg(reader, "reader")?;
+        let chrom = str_arg(chrom, "chrom")?;
+        let out = ref_arg(out, "out")?;
+        check_interval(start, end)?;
+        let file = reader.read.inner_read().reopen()?;
+        let read = BigWigRead::with_info(reader.read.info.clone(), file);
+        let iter = read.get_interval_move(chrom, start, end)?;
+        *out = Box::into_raw(Box::new(BigWigIntervals {
+            iter: Box::new(iter),
+        }));
+        Ok(BigtoolsStatus::Ok)
+    })
+}
+
+/// Reads the next value into `out`. Returns `BIGTOOLS_STATUS_END` when there
+/// are no more values.
+///
+/// # Safety
+/// `intervals` must be returned by `bigwig_intervals` and `out` a valid
+/// pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bigwig_intervals_next(
+    intervals: *mut BigWigIntervals,
+    out: *mut BigtoolsValue,
+) -> BigtoolsStatus {
+    guard(|| {
+        let intervals = ref_arg(intervals, "intervals")?;
+        let out = ref_arg(out, "out")?;
+        match intervals.iter.next() {
+            Some(value) => {
+                let value = value?;
+                *out = BigtoolsValue {
+                    start: value.start,
+                    end: value.end,
+                    value: value.value,
+                };
+                Ok(BigtoolsStatus::Ok)
+            }
+            None => Ok(BigtoolsStatus::End),
+        }
+    })
+}
+
+/// Frees an iterator returned by `bigwig_intervals`.
+///
+/// # Safety
+/// `intervals` must be null or returned by `bigwig_intervals`, and not
+/// already freed.
+#[no_mangle]
+pub unsafe extern "C" fn bigwig_intervals_free(intervals: *mut BigWigIntervals) {
+    if !intervals.is_null() {
+        drop(Box::from_raw(intervals));
+    }
+}
+
+/// Writes the value of each base of `start..end` of `chrom` to `out`, which
+/// must have room for at least `end - start` values. Bases without data are
+/// `NAN`.
+///
+/// # Safety
+/// `reader` must be an open bigWig, `chrom` a null-terminated string, and
+/// `out` valid for `out_len` floats.
+#[no_mangle]
+pub unsafe extern "C" fn bigwig_values(
+    reader: *mut BigWigReader,
+    chrom: *const c_char,
+    start: u32,
+    end: u32,
+    out: *mut f32,
+    out_len: usize,
+) -> BigtoolsStatus {
+    guard(|| {
+        let reader = ref_arg(reader, "reader")?;
+        let chrom = str_arg(chrom, "chrom")?;
+        check_interval(start, end)?;
+        let len = (end - start) as usize;
+        if out.is_null() || out_len < len {
+            return Err(Error::invalid_argument(format!(
+                "The output buffer must have room for {} values.",
+                len
+            )));
+        }
+        let values = reader.read.values(chrom, start, end)?;
+        std::slice::from_raw_parts_mut(out, len).copy_from_slice(&values);
+        Ok(BigtoolsStatus::Ok)
+    })
+}
+
+/// Starts iterating over the records of the zoom level with
+/// `reduction_level` overlapping `start..end` of `chrom`. The iterator must
+/// be freed with `bigtools_zoom_intervals_free`.
+///
+/// # Safety
+/// `reader` must be an open bigWig, `chrom` a null-terminated string, and
+/// `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bigwig_zoom_intervals(
+    reader: *mut BigWigReader,
+    chrom: *const c_char,
+    start: u32,
+    end: u32,
+    reduction_level: u32,
+    out: *mut *mut BigtoolsZoomIntervals,
+) -> BigtoolsStatus {
+    guard(|| {
+        let reader = ref_arg(reader, "reader")?;
+        let chrom = str_arg(chrom, "chrom")?;
+        let out = ref_arg(out, "out")?;
+        check_interval(start, end)?;
+        let records = reader
+            .read
+            .get_zoom_interval(chrom, start, end, reduction_level)?
+            .collect::<Result<Vec<_>, _>>()?;
+        *out = Box::into_raw(Box::new(BigtoolsZoomIntervals {
+            records: records.into_iter(),
+        }));
+        Ok(BigtoolsStatus::Ok)
+    })
+}
+
+/// Opens the bigBed at `path`, storing the reader in `out`.
+///
+/// # Safety
+/// `path` must be a null-terminated string and `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bigbed_open(
+    path: *const c_char,
+    out: *mut *mut BigBedReader,
+) -> BigtoolsStatus {
+    guard(|| {
+        let path = str_arg(path, "path")?;
+        let out = ref_arg(out, "out")?;
+        let read = BigBedRead::open_file(path.to_owned())?;
+        let chroms = c_chroms(read.get_chroms());
+        *out = Box::into_raw(Box::new(BigBedReader {
+            read,
+            chroms,
+            autosql: None,
+        }));
+        Ok(BigtoolsStatus::Ok)
+    })
+}
+
+/// Closes a bigBed. Any strings returned from it are no longer valid.
+///
+/// # Safety
+/// `reader` must be null or returned by `bigbed_open`, and not already closed.
+#[no_mangle]
+pub unsafe extern "C" fn bigbed_close(reader: *mut BigBedReader) {
+    if !reader.is_null() {
+        drop(Box::from_raw(reader));
+    }
+}
+
+/// Returns the number of chromosomes in a bigBed.
+///
+/// # Safety
+/// `reader` must be null or an open bigBed.
+#[no_mangle]
+pub unsafe extern "C" fn bigbed_chrom_count(reader: *const BigBedReader) -> usize {
+    reader.as_ref().map_or(0, |r| r.chroms.len())
+}
+
+/// Gets the name and length of the chromosome at `index`. Either output may
+/// be null. The name is valid until the reader is closed.
+///
+/// # Safety
+/// `reader` must be an open bigBed, and `name` and `length` null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn bigbed_chrom(
+    reader: *mut BigBedReader,
+    index: usize,
+    name: *mut *const c_char,
+    length: *mut u32,
+) -> BigtoolsStatus {
+    guard(|| get_chrom(&ref_arg(reader, "reader")?.chroms, index, name, length))
+}
+
+/// Returns the number of zoom levels in a bigBed.
+///
+/// # Safety
+/// `reader` must be null or an open bigBed.
+#[no_mangle]
+pub unsafe extern "C" fn bigbed_zoom_count(reader: *const BigBedReader) -> usize {
+    reader
+        .as_ref()
+        .map_or(0, |r| r.read.info.zoom_headers.len())
+}
+
+/// Gets the reduction level of the zoom level at `index`.
+///
+/// # Safety
+/// `reader` must be an open bigBed and `level` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bigbed_zoom_level(
+    reader: *mut BigBedReader,
+    index: usize,
+    level: *mut u32,
+) -> BigtoolsStatus {
+    guard(|| get_zoom_level(&ref_arg(reader, "reader")?.read.info, index, level))
+}
+
+/// Gets the autoSql definition of the bigBed's fields. The string is valid
+/// until the reader is closed.
+///
+/// # Safety
+/// `reader` must be an open bigBed and `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bigbed_autosql(
+    reader: *mut BigBedReader,
+    out: *mut *const c_char,
+) -> BigtoolsStatus {
+    guard(|| {
+        let reader = ref_arg(reader, "reader")?;
+        let out = ref_arg(out, "out")?;
+        if reader.autosql.is_none() {
+            let autosql = reader.read.autosql()?;
+            reader.autosql = Some(CString::new(autosql.replace('\0', "")).unwrap());
+        }
+        *out = reader.autosql.as_ref().unwrap().as_ptr();
+        Ok(BigtoolsStatus::Ok)
+    })
+}
+
+/// Starts iterating over the entries overlapping `start..end` of `chrom`.
+/// The iterator doesn't borrow the reader, and must be freed with
+/// `bigbed_intervals_free`.
+///
+/// # Safety
+/// `reader` must be an open bigBed, `chrom` a null-terminated string, and
+/// `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bigbed_intervals(
+    reader: *mut BigBedReader,
+    chrom: *const c_char,
+    start: u32,
+    end: u32,
+    out: *mut *mut BigBedIntervals,
+) -> BigtoolsStatus {
+    guard(|| {
+        let reader = ref_arg(reader, "reader")?;
+        let chrom = str_arg(chrom, "chrom")?;
+        let out = ref_arg(out, "out")?;
+        check_interval(start, end)?;
+        let file = reader.read.inner_read().reopen()?;
+        let read = BigBedRead::with_info(reader.read.info.clone(), file);
+        let iter = read.get_interval_move(chrom, start, end)?;
+        *out = Box::into_raw(Box::new(BigBedIntervals {
+            iter: Box::new(iter),
+            rest: CString::default(),
+        }));
+        Ok(BigtoolsStatus::Ok)
+    })
+}
+
+/// Reads the next entry into `out`. Returns `BIGTOOLS_STATUS_END` when there
+/// are no more entries.
+///
+/// # Safety
+/// `intervals` must be returned by `bigbed_intervals` and `out` a valid
+/// pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bigbed_intervals_next(
+    intervals: *mut BigBedIntervals,
+    out: *mut BigtoolsBedEntry,
+) -> BigtoolsStatus {
+    guard(|| {
+        let intervals = ref_arg(intervals, "intervals")?;
+        let out = ref_arg(out, "out")?;
+        match intervals.iter.next() {
+            Some(entry) => {
+                let entry = entry?;
+                intervals.rest = CString::new(entry.rest.replace('\0', "")).unwrap();
+                *out = BigtoolsBedEntry {
+                    start: entry.start,
+                    end: entry.end,
+                    rest: intervals.rest.as_ptr(),
+                };
+                Ok(BigtoolsStatus::Ok)
+            }
+            None => Ok(BigtoolsStatus::End),
+        }
+    })
+}
+
+/// Frees an iterator returned by `bigbed_intervals`.
+///
+/// # Safety
+/// `intervals` must be null or returned by `bigbed_intervals`, and not
+/// already freed.
+#[no_mangle]
+pub unsafe extern "C" fn bigbed_intervals_free(intervals: *mut BigBedIntervals) {
+    if !intervals.is_null() {
+        drop(Box::from_raw(intervals));
+    }
+}
+
+/// Starts iterating over the records of the zoom level with
+/// `reduction_level` overlapping `start..end` of `chrom`. The iterator must
+/// be freed with `bigtools_zoom_intervals_free`.
+///
+/// # Safety
+/// `reader` must be an open bigBed, `chrom` a null-terminated string, and
+/// `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bigbed_zoom_intervals(
+    reader: *mut BigBedReader,
+    chrom: *const c_char,
+    start: u32,
+    end: u32,
+    reduction_level: u32,
+    out: *mut *mut BigtoolsZoomIntervals,
+) -> BigtoolsStatus {
+    guard(|| {
+        let reader = ref_arg(reader, "reader")?;
+        let chrom = str_arg(chrom, "chrom")?;
+        let out = ref_arg(out, "out")?;
+        check_interval(start, end)?;
+        let records = reader
+            .read
+            .get_zoom_interval(chrom, start, end, reduction_level)?
+            .collect::<Result<Vec<_>, _>>()?;
+        *out = Box::into_raw(Box::new(BigtoolsZoomIntervals {
+            records: records.into_iter(),
+        }));
+        Ok(BigtoolsStatus::Ok)
+    })
+}
+
+/// Reads the next zoom record into `out`. Returns `BIGTOOLS_STATUS_END` when
+/// there are no more records.
+///
+/// # Safety
+/// `intervals` must be returned by `bigwig_zoom_intervals` or
+/// `bigbed_zoom_intervals`, and `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn bigtools_zoom_intervals_next(
+    intervals: *mut BigtoolsZoomIntervals,
+    out: *mut BigtoolsZoomRecord,
+) -> BigtoolsStatus {
+    guard(|| {
+        let intervals = ref_arg(intervals, "intervals")?;
+        let out = ref_arg(out, "out")?;
+        match intervals.records.next() {
+            Some(record) => {
+                *out = BigtoolsZoomRecord {
+                    start: record.start,
+                    end: record.end,
+                    bases_covered: record.summary.bases_covered,
+                    min_val: record.summary.min_val,
+                    max_val: record.summary.max_val,
+                    sum: record.summary.sum,
+                    sum_squares: record.summary.sum_squares,
+                };
+                Ok(BigtoolsStatus::Ok)
+            }
+            None => Ok(BigtoolsStatus::End),
+        }
+    })
+}
+
+/// Frees an iterator returned by `bigwig_zoom_intervals` or
+/// `bigbed_zoom_intervals`.
+///
+/// # Safety
+/// `intervals` must be null or a zoom iterator that isn't already freed.
+#[no_mangle]
+pub unsafe extern "C" fn bigtools_zoom_intervals_free(intervals: *mut BigtoolsZoomIntervals) {
+    if !intervals.is_null() {
+        drop(Box::from_raw(intervals));
+    }
+}
@@ -0,0 +1,178 @@
+/*
+ * Exercises the C API. Usage: test_capi <bigWig> <bigBed>
+ *
+ * The bigWig is resources/test/valid.bigWig, and the bigBed is
+ * resources/test/small.bed converted to a bigBed.
+ */
+
+#include <math.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "bigtools.h"
+
+static int failures = 0;
+
+#define CHECK(cond)                                                           \
+    do {                                                                      \
+        if (!(cond)) {                                                        \
+            const char *err = bigtools_last_error();                          \
+            fprintf(stderr, "%s:%d: check failed: %s (last error: %s)\n",     \
+                    __FILE__, __LINE__, #cond, err ? err : "none");           \
+            failures++;                                                       \
+        }                                                                     \
+    } while (0)
+
+static void test_bigwig(const char *path) {
+    BigWigReader *reader = NULL;
+    CHECK(bigwig_open(path, &reader) == BIGTOOLS_STATUS_OK);
+    if (reader == NULL) {
+        return;
+    }
+
+    CHECK(bigwig_chrom_count(reader) == 1);
+    const char *name = NULL;
+    uint32_t length = 0;
+    CHECK(bigwig_chrom(reader, 0, &name, &length) == BIGTOOLS_STATUS_OK);
+    CHECK(name != NULL && strcmp(name, "chr17") == 0);
+    CHECK(length == 83257441);
+    CHECK(bigwig_chrom(reader, 1, &name, &length) == BIGTOOLS_STATUS_INVALID_ARGUMENT);
+
+    /* Intervals agree with values */
+    float values[300];
+    CHECK(bigwig_values(reader, "chr17", 59900, 60200, values, 300) == BIGTOOLS_STATUS_OK);
+    BigWigIntervals *intervals = NULL;
+    CHECK(bigwig_intervals(reader, "chr17", 59900, 60200, &intervals) == BIGTOOLS_STATUS_OK);
+    BigtoolsValue value;
+    size_t count = 0;
+    BigtoolsStatus status;
+    while ((status = bigwig_intervals_next(intervals, &value)) == BIGTOOLS_STATUS_OK) {
+        if (count == 1) {
+            CHECK(value.start == 59900 && value.end == 59947);
+            CHECK(fabsf(value.value - 0.16627f) < 1e-4f);
+        }
+        for (uint32_t i = value.start; i < value.end; i++) {
+            if (i >= 59900 && i < 60200) {
+                CHECK(values[i - 59900] == value.value);
+            }
+        }
+        count++;
+    }
+    CHECK(status == BIGTOOLS_STATUS_END);
+    CHECK(count == 7);
+    bigwig_intervals_free(intervals);
+
+    /* Bases before the first value have no data */
+    CHECK(bigwig_values(reader, "chr17", 0, 10, values, 10) == BIGTOOLS_STATUS_OK);
+    CHECK(isnan(values[0]) && isnan(values[9]));
+
+    /* Zooms */
+    CHECK(bigwig_zoom_count(reader) > 0);
+    uint32_t level = 0;
+    CHECK(bigwig_zoom_level(reader, 0, &level) == BIGTOOLS_STATUS_OK);
+    CHECK(level == 10);
+    BigtoolsZoomIntervals *zooms = NULL;
+    CHECK(bigwig_zoom_intervals(reader, "chr17", 59900, 60200, level, &zooms) == BIGTOOLS_STATUS_OK);
+    BigtoolsZoomRecord record;
+    count = 0;
+    while (bigtools_zoom_intervals_next(zooms, &record) == BIGTOOLS_STATUS_OK) {
+        CHECK(record.end - record.start <= level);
+        CHECK(record.bases_covered > 0);
+        double mean = record.sum / record.bases_covered;
+        CHECK(record.min_val - 1e-6 <= mean && mean <= record.max_val + 1e-6);
+        count++;
+    }
+    CHECK(count > 0);
+    bigtools_zoom_intervals_free(zooms);
+
+    /* Errors */
+    intervals = NULL;
+    CHECK(bigwig_intervals(reader, "chr1", 0, 100, &intervals) == BIGTOOLS_STATUS_CHROM_NOT_FOUND);
+    CHECK(intervals == NULL);
+    CHECK(bigtools_last_error() != NULL && strstr(bigtools_last_error(), "chr1") != NULL);
+    CHECK(bigwig_intervals(reader, "chr17", 100, 0, &intervals) == BIGTOOLS_STATUS_INVALID_ARGUMENT);
+    CHECK(bigwig_values(reader, "chr17", 0, 100, values, 10) == BIGTOOLS_STATUS_INVALID_ARGUMENT);
+    CHECK(bigwig_zoom_intervals(reader, "chr17", 0, 100, 3, &zooms) == BIGTOOLS_STATUS_ZOOM_NOT_FOUND);
+    CHECK(bigwig_intervals(NULL, "chr17", 0, 100, &intervals) == BIGTOOLS_STATUS_INVALID_ARGUMENT);
+
+    bigwig_close(reader);
+}
+
+static void test_bigbed(const char *path) {
+    BigBedReader *reader = NULL;
+    CHECK(bigbed_open(path, &reader) == BIGTOOLS_STATUS_OK);
+    if (reader == NULL) {
+        return;
+    }
+
+    CHECK(bigbed_chrom_count(reader) == 3);
+    int found = 0;
+    for (size_t i = 0; i < bigbed_chrom_count(reader); i++) {
+        const char *name = NULL;
+        uint32_t length = 0;
+        CHECK(bigbed_chrom(reader, i, &name, &length) == BIGTOOLS_STATUS_OK);
+        if (strcmp(name, "chr17") == 0) {
+            CHECK(length == 83257441);
+            found = 1;
+        }
+    }
+    CHECK(found);
+
+    const char *autosql = NULL;
+    CHECK(bigbed_autosql(reader, &autosql) == BIGTOOLS_STATUS_OK);
+    CHECK(autosql != NULL && strstr(autosql, "table") != NULL);
+
+    BigBedIntervals *intervals = NULL;
+    CHECK(bigbed_intervals(reader, "chr17", 0, 1000, &intervals) == BIGTOOLS_STATUS_OK);
+    BigtoolsBedEntry entry;
+    size_t count = 0;
+    while (bigbed_intervals_next(intervals, &entry) == BIGTOOLS_STATUS_OK) {
+        if (count == 0) {
+            CHECK(entry.start == 1 && entry.end == 100);
+            CHECK(strcmp(entry.rest, "test1\t0") == 0);
+        }
+        count++;
+    }
+    CHECK(count == 3);
+    bigbed_intervals_free(intervals);
+
+    /* There are too few entries for any zoom levels */
+    CHECK(bigbed_zoom_count(reader) == 0);
+    uint32_t level = 0;
+    CHECK(bigbed_zoom_level(reader, 0, &level) == BIGTOOLS_STATUS_INVALID_ARGUMENT);
+    BigtoolsZoomIntervals *zooms = NULL;
+    CHECK(bigbed_zoom_intervals(reader, "chr17", 0, 1000, 10, &zooms) == BIGTOOLS_STATUS_ZOOM_NOT_FOUND);
+    CHECK(zooms == NULL);
+
+    CHECK(bigbed_intervals(reader, "chr1", 0, 100, &intervals) == BIGTOOLS_STATUS_CHROM_NOT_FOUND);
+
+    bigbed_close(reader);
+}
+
+static void test_open_errors(const char *bigwig, const char *bigbed) {
+    BigWigReader *bw = NULL;
+    BigBedReader *bb = NULL;
+    CHECK(bigwig_open("does_not_exist.bigWig", &bw) == BIGTOOLS_STATUS_IO_ERROR);
+    CHECK(bigwig_open(bigbed, &bw) == BIGTOOLS_STATUS_INVALID_FILE);
+    CHECK(bigbed_open(bigwig, &bb) == BIGTOOLS_STATUS_INVALID_FILE);
+    CHECK(bigwig_open(NULL, &bw) == BIGTOOLS_STATUS_INVALID_ARGUMENT);
+    CHECK(bw == NULL && bb == NULL);
+    /* Closing null is a no-op */
+    bigwig_close(NULL);
+    bigbed_close(NULL);
+}
+
+int main(int argc, char **argv) {
+    if (argc != 3) {
+        fprintf(stderr, "Usage: %s <bigWig> <bigBed>\n", argv[0]);
+        return 2;
+    }
+    test_bigwig(argv[1]);
+    test_bigbed(argv[2]);
+    test_open_errors(argv[1], argv[2]);
+    if (failures > 0) {
+        fprintf(stderr, "%d checks failed\n", failures);
+        return 1;
+    }
+    return 0;
+}
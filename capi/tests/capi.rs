@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use bigtools::bbi::BigBedWrite;
+use bigtools::bed::bedparser::BedParser;
+use bigtools::bedchromdata::BedParserStreamingIterator;
+use bigtools::utils::chromvalues::ChromValues;
+
+fn resources() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../resources/test")
+}
+
+/// Writes `resources/test/small.bed` as a bigBed.
+fn write_bigbed(path: &Path) -> Result<(), Box<dyn Error>> {
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(2)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    let mut vals_iter = BedParser::from_bed_file(File::open(resources().join("small.bed"))?);
+    let mut outb = BigBedWrite::create_file(path.to_string_lossy().to_string());
+    outb.autosql = {
+        let (_, mut group) = vals_iter.next_chrom().unwrap().unwrap();
+        let first = group.peek().unwrap().unwrap();
+        Some(bigtools::bed::autosql::bed_autosql(&first.rest))
+    };
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr17".to_string(), 83257441);
+    chrom_map.insert("chr18".to_string(), 80373285);
+    chrom_map.insert("chr19".to_string(), 58617616);
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    outb.write(chrom_map, chsi, pool)?;
+    Ok(())
+}
+
+#[test]
+fn test_c_program() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let bigbed = dir.path().join("small.bigBed");
+    write_bigbed(&bigbed)?;
+
+    // The library is built next to the test binary, in `target/<profile>/deps`
+    let lib_dir = std::env::current_exe()?.parent().unwrap().to_path_buf();
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let exe = dir.path().join("test_capi");
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+    let status = Command::new(cc)
+        .arg(manifest_dir.join("tests/c/test_capi.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg(format!("-Wl,-rpath,{}", lib_dir.display()))
+        .args(["-lbigtools_capi", "-lm", "-o"])
+        .arg(&exe)
+        .status()?;
+    assert!(status.success(), "Failed to compile the C test program.");
+
+    // Cargo's library path includes `target/<profile>`, which may have an older build
+    let output = Command::new(&exe)
+        .env_remove("LD_LIBRARY_PATH")
+        .arg(resources().join("valid.bigWig"))
+        .arg(&bigbed)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_header_is_up_to_date() -> Result<(), Box<dyn Error>> {
+    let generated = std::fs::read_to_string(Path::new(env!("OUT_DIR")).join("bigtools.h"))?;
+    let committed =
+        std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("include/bigtools.h"))?;
+    assert!(
+        generated == committed,
+        "include/bigtools.h is out of date; rebuild with BIGTOOLS_CAPI_UPDATE_HEADER=1 to update it."
+    );
+    Ok(())
+}
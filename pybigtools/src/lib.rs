@@ -0,0 +1,729 @@
+//! Python bindings for reading and writing bigWigs and bigBeds.
+//!
+//! The module is built with `maturin` (see `pyproject.toml`). The entrypoint
+//! is `pybigtools.open`, which returns a reader or writer for the path.
+
+use std::collections::HashMap;
+use std::fs::File;
+
+use bigtools::bbiread::BBIRead;
+use bigtools::bbiwrite::InputSortType;
+use bigtools::bed::autosql::bed_autosql;
+use bigtools::bed::bedparser::{BedParser, BedValueError};
+use bigtools::bedchromdata::BedParserStreamingIterator;
+use bigtools::utils::intersect::{IntersectSummary, SummaryTotals};
+use bigtools::utils::reopen::{Reopen, ReopenableFile};
+use bigtools::{
+    BBIFile, BedEntry, BigBedReadAttachError, BigWigReadAttachError, ProcessChromError, Value,
+    ZoomRecord,
+};
+use numpy::{PyArray1, PyReadonlyArray1};
+use pyo3::exceptions::{PyException, PyIOError, PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyIterator};
+use pyo3::{create_exception, wrap_pyfunction};
+
+create_exception!(
+    pybigtools,
+    BBIReadError,
+    PyException,
+    "An error occurred while reading a bigWig or bigBed."
+);
+create_exception!(
+    pybigtools,
+    BBIWriteError,
+    PyException,
+    "An error occurred while writing a bigWig or bigBed."
+);
+
+fn read_error(error: bigtools::BBIReadError) -> PyErr {
+    match error {
+        bigtools::BBIReadError::InvalidChromosome(chrom) => {
+            PyKeyError::new_err(format!("Chromosome `{}` is not in the file.", chrom))
+        }
+        bigtools::BBIReadError::IoError(e) => PyIOError::new_err(e.to_string()),
+        e => BBIReadError::new_err(e.to_string()),
+    }
+}
+
+fn zoom_error(error: bigtools::bigwigread::ZoomIntervalError) -> PyErr {
+    match error {
+        bigtools::bigwigread::ZoomIntervalError::ReductionLevelNotFound => {
+            PyKeyError::new_err("The reduction level is not in the file.")
+        }
+        bigtools::bigwigread::ZoomIntervalError::BBIReadError(e) => read_error(e),
+    }
+}
+
+fn bigbed_zoom_error(error: bigtools::bigbedread::ZoomIntervalError) -> PyErr {
+    match error {
+        bigtools::bigbedread::ZoomIntervalError::ReductionLevelNotFound => {
+            PyKeyError::new_err("The reduction level is not in the file.")
+        }
+        bigtools::bigbedread::ZoomIntervalError::BBIReadError(e) => read_error(e),
+    }
+}
+
+fn write_error<E: std::fmt::Display>(error: ProcessChromError<E>) -> PyErr {
+    match error {
+        ProcessChromError::IoError(e) => PyIOError::new_err(e.to_string()),
+        ProcessChromError::SourceError(e) => BBIWriteError::new_err(e.to_string()),
+        e => BBIWriteError::new_err(e.to_string()),
+    }
+}
+
+fn closed() -> PyErr {
+    PyValueError::new_err("I/O operation on closed file.")
+}
+
+/// Resolves an optional `start` and `end` against the chromosome's length.
+fn bounds(
+    chroms: &[bigtools::ChromAndSize],
+    chrom: &str,
+    start: Option<u32>,
+    end: Option<u32>,
+) -> PyResult<(u32, u32)> {
+    let length = chroms
+        .iter()
+        .find(|c| c.name == chrom)
+        .map(|c| c.length)
+        .ok_or_else(|| {
+            PyKeyError::new_err(format!("Chromosome `{}` is not in the file.", chrom))
+        })?;
+    let start = start.unwrap_or(0);
+    let end = end.unwrap_or(length).min(length);
+    if start > end {
+        return Err(PyValueError::new_err(format!(
+            "Invalid interval: start ({}) is greater than end ({}).",
+            start, end
+        )));
+    }
+    Ok((start, end))
+}
+
+fn chroms_dict(py: Python<'_>, chroms: Vec<bigtools::ChromAndSize>) -> PyResult<PyObject> {
+    let dict = PyDict::new_bound(py);
+    for chrom in chroms {
+        dict.set_item(chrom.name, chrom.length)?;
+    }
+    Ok(dict.into_py(py))
+}
+
+/// A zoom record as a `(start, end, bases_covered, min, max, sum, sum_squares)`
+/// tuple.
+type ZoomTuple = (u32, u32, u64, f64, f64, f64, f64);
+
+fn zoom_tuples(
+    records: impl Iterator<Item = Result<ZoomRecord, bigtools::BBIReadError>>,
+) -> PyResult<Vec<ZoomTuple>> {
+    records
+        .map(|r| {
+            r.map(|r| {
+                (
+                    r.start,
+                    r.end,
+                    r.summary.bases_covered,
+                    r.summary.min_val,
+                    r.summary.max_val,
+                    r.summary.sum,
+                    r.summary.sum_squares,
+                )
+            })
+            .map_err(read_error)
+        })
+        .collect()
+}
+
+#[derive(Copy, Clone)]
+enum SummaryStat {
+    Summary(IntersectSummary),
+    Std,
+}
+
+impl SummaryStat {
+    fn parse(summary: &str) -> PyResult<Self> {
+        Ok(match summary {
+            "mean" => SummaryStat::Summary(IntersectSummary::Mean),
+            "min" => SummaryStat::Summary(IntersectSummary::Min),
+            "max" => SummaryStat::Summary(IntersectSummary::Max),
+            "sum" => SummaryStat::Summary(IntersectSummary::Sum),
+            "std" => SummaryStat::Std,
+            "coverage" => SummaryStat::Summary(IntersectSummary::Coverage),
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Invalid summary `{}`. Options are `mean`, `min`, `max`, `sum`, `std`, or `coverage`.",
+                    summary
+                )))
+            }
+        })
+    }
+}
+
+/// Summarizes `values` over `bins` equally sized bins between `start` and
+/// `end`, like `bigtools intersect`. Bins with no data are `NaN` (or `0` for
+/// the sum and coverage).
+fn bin_values(
+    values: impl Iterator<Item = Result<Value, bigtools::BBIReadError>>,
+    start: u32,
+    end: u32,
+    bins: usize,
+    summary: SummaryStat,
+) -> PyResult<Vec<f64>> {
+    let length = u64::from(end - start);
+    let bin_start = |i: usize| start as u64 + length * i as u64 / bins as u64;
+    let mut totals = vec![SummaryTotals::default(); bins];
+    for value in values {
+        let value = value.map_err(read_error)?;
+        let (vstart, vend) = (u64::from(value.start), u64::from(value.end));
+        // The first bin that could overlap this value
+        let mut i = ((vstart.saturating_sub(start as u64)) * bins as u64 / length.max(1)) as usize;
+        while i < bins && bin_start(i) < vend {
+            let overlap_start = vstart.max(bin_start(i));
+            let overlap_end = vend.min(bin_start(i + 1));
+            if overlap_end > overlap_start {
+                let overlap = (overlap_end - overlap_start) as f64;
+                totals[i].add_value(f64::from(value.value), overlap);
+            }
+            i += 1;
+        }
+    }
+    Ok(totals
+        .iter()
+        .enumerate()
+        .map(|(i, totals)| match summary {
+            SummaryStat::Summary(summary) => {
+                let size = (bin_start(i + 1) - bin_start(i)) as u32;
+                totals.summarize(summary, size)
+            }
+            SummaryStat::Std => totals.std(),
+        })
+        .collect())
+}
+
+/// Iterates over `(start, end, value)` tuples of a bigWig.
+#[pyclass(module = "pybigtools")]
+struct BigWigIntervalIterator {
+    iter: Box<dyn Iterator<Item = Result<Value, bigtools::BBIReadError>> + Send>,
+}
+
+#[pymethods]
+impl BigWigIntervalIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<(u32, u32, f32)>> {
+        self.iter
+            .next()
+            .transpose()
+            .map(|v| v.map(|v| (v.start, v.end, v.value)))
+            .map_err(read_error)
+    }
+}
+
+/// Iterates over `(start, end, rest)` tuples of a bigBed, where `rest` is the
+/// tab-separated remaining fields.
+#[pyclass(module = "pybigtools")]
+struct BigBedEntriesIterator {
+    iter: Box<dyn Iterator<Item = Result<BedEntry, bigtools::BBIReadError>> + Send>,
+}
+
+#[pymethods]
+impl BigBedEntriesIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> PyResult<Option<(u32, u32, String)>> {
+        self.iter
+            .next()
+            .transpose()
+            .map(|v| v.map(|v| (v.start, v.end, v.rest)))
+            .map_err(read_error)
+    }
+}
+
+/// A bigWig opened for reading.
+#[pyclass(module = "pybigtools")]
+struct BigWigRead {
+    bigwig: Option<bigtools::BigWigRead<ReopenableFile>>,
+}
+
+impl BigWigRead {
+    fn bigwig(&mut self) -> PyResult<&mut bigtools::BigWigRead<ReopenableFile>> {
+        self.bigwig.as_mut().ok_or_else(closed)
+    }
+}
+
+#[pymethods]
+impl BigWigRead {
+    /// Returns a dict of chromosome names to lengths, or the length of
+    /// `chrom` if given.
+    #[pyo3(signature = (chrom=None))]
+    fn chroms(&mut self, py: Python<'_>, chrom: Option<&str>) -> PyResult<PyObject> {
+        let chroms = self.bigwig()?.get_chroms();
+        match chrom {
+            Some(chrom) => {
+                let (_, length) = bounds(&chroms, chrom, None, None)?;
+                Ok(length.into_py(py))
+            }
+            None => chroms_dict(py, chroms),
+        }
+    }
+
+    /// Returns the reduction levels of the zooms in the file.
+    fn zooms(&mut self) -> PyResult<Vec<u32>> {
+        Ok(self
+            .bigwig()?
+            .info
+            .zoom_headers
+            .iter()
+            .map(|z| z.reduction_level)
+            .collect())
+    }
+
+    /// Returns an iterator of `(start, end, value)` tuples overlapping the
+    /// interval. `start` and `end` default to the whole chromosome.
+    #[pyo3(signature = (chrom, start=None, end=None))]
+    fn get_interval(
+        &mut self,
+        chrom: &str,
+        start: Option<u32>,
+        end: Option<u32>,
+    ) -> PyResult<BigWigIntervalIterator> {
+        let bigwig = self.bigwig()?;
+        let (start, end) = bounds(&bigwig.get_chroms(), chrom, start, end)?;
+        let file = bigwig.inner_read().reopen()?;
+        let bigwig = bigtools::BigWigRead::with_info(bigwig.info.clone(), file);
+        let iter = bigwig
+            .get_interval_move(chrom, start, end)
+            .map_err(read_error)?;
+        Ok(BigWigIntervalIterator {
+            iter: Box::new(iter),
+        })
+    }
+
+    /// Returns the values of the interval as a NumPy array of float64, with
+    /// `NaN` for bases without data. If `bins` is given, each element is
+    /// instead the `summary` (`mean`, `min`, `max`, `sum`, `std`, or
+    /// `coverage`) of one of `bins` equally sized bins.
+    #[pyo3(signature = (chrom, start=None, end=None, bins=None, summary="mean"))]
+    fn values<'py>(
+        &mut self,
+        py: Python<'py>,
+        chrom: &str,
+        start: Option<u32>,
+        end: Option<u32>,
+        bins: Option<usize>,
+        summary: &str,
+    ) -> PyResult<Bound<'py, PyArray1<f64>>> {
+        let summary = SummaryStat::parse(summary)?;
+        let bigwig = self.bigwig()?;
+        let (start, end) = bounds(&bigwig.get_chroms(), chrom, start, end)?;
+        let values = match bins {
+            None => bigwig
+                .values(chrom, start, end)
+                .map_err(read_error)?
+                .into_iter()
+                .map(f64::from)
+                .collect(),
+            Some(0) => return Err(PyValueError::new_err("`bins` must be greater than 0.")),
+            Some(bins) => {
+                let iter = bigwig.get_interval(chrom, start, end).map_err(read_error)?;
+                bin_values(iter, start, end, bins, summary)?
+            }
+        };
+        Ok(PyArray1::from_vec_bound(py, values))
+    }
+
+    /// Returns a list of `(start, end, bases_covered, min, max, sum,
+    /// sum_squares)` tuples of the zoom level with `reduction_level`
+    /// overlapping the interval.
+    #[pyo3(signature = (chrom, start, end, reduction_level))]
+    fn get_zoom_interval(
+        &mut self,
+        chrom: &str,
+        start: u32,
+        end: u32,
+        reduction_level: u32,
+    ) -> PyResult<Vec<ZoomTuple>> {
+        let iter = self
+            .bigwig()?
+            .get_zoom_interval(chrom, start, end, reduction_level)
+            .map_err(zoom_error)?;
+        zoom_tuples(iter)
+    }
+
+    fn close(&mut self) {
+        self.bigwig = None;
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: PyObject,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> bool {
+        self.close();
+        false
+    }
+}
+
+/// A bigBed opened for reading.
+#[pyclass(module = "pybigtools")]
+struct BigBedRead {
+    bigbed: Option<bigtools::BigBedRead<ReopenableFile>>,
+}
+
+impl BigBedRead {
+    fn bigbed(&mut self) -> PyResult<&mut bigtools::BigBedRead<ReopenableFile>> {
+        self.bigbed.as_mut().ok_or_else(closed)
+    }
+}
+
+#[pymethods]
+impl BigBedRead {
+    /// Returns a dict of chromosome names to lengths, or the length of
+    /// `chrom` if given.
+    #[pyo3(signature = (chrom=None))]
+    fn chroms(&mut self, py: Python<'_>, chrom: Option<&str>) -> PyResult<PyObject> {
+        let chroms = self.bigbed()?.get_chroms();
+        match chrom {
+            Some(chrom) => {
+                let (_, length) = bounds(&chroms, chrom, None, None)?;
+                Ok(length.into_py(py))
+            }
+            None => chroms_dict(py, chroms),
+        }
+    }
+
+    /// Returns the reduction levels of the zooms in the file.
+    fn zooms(&mut self) -> PyResult<Vec<u32>> {
+        Ok(self
+            .bigbed()?
+            .info
+            .zoom_headers
+            .iter()
+            .map(|z| z.reduction_level)
+            .collect())
+    }
+
+    /// Returns the autoSql definition of the file's fields.
+    fn autosql(&mut self) -> PyResult<String> {
+        self.bigbed()?.autosql().map_err(read_error)
+    }
+
+    /// Returns an iterator of `(start, end, rest)` tuples overlapping the
+    /// interval. `start` and `end` default to the whole chromosome.
+    #[pyo3(signature = (chrom, start=None, end=None))]
+    fn get_interval(
+        &mut self,
+        chrom: &str,
+        start: Option<u32>,
+        end: Option<u32>,
+    ) -> PyResult<BigBedEntriesIterator> {
+        let bigbed = self.bigbed()?;
+        let (start, end) = bounds(&bigbed.get_chroms(), chrom, start, end)?;
+        let file = bigbed.inner_read().reopen()?;
+        let bigbed = bigtools::BigBedRead::with_info(bigbed.info.clone(), file);
+        let iter = bigbed
+            .get_interval_move(chrom, start, end)
+            .map_err(read_error)?;
+        Ok(BigBedEntriesIterator {
+            iter: Box::new(iter),
+        })
+    }
+
+    /// Returns a list of `(start, end, bases_covered, min, max, sum,
+    /// sum_squares)` tuples of the zoom level with `reduction_level`
+    /// overlapping the interval.
+    #[pyo3(signature = (chrom, start, end, reduction_level))]
+    fn get_zoom_interval(
+        &mut self,
+        chrom: &str,
+        start: u32,
+        end: u32,
+        reduction_level: u32,
+    ) -> PyResult<Vec<ZoomTuple>> {
+        let iter = self
+            .bigbed()?
+            .get_zoom_interval(chrom, start, end, reduction_level)
+            .map_err(bigbed_zoom_error)?;
+        zoom_tuples(iter)
+    }
+
+    fn close(&mut self) {
+        self.bigbed = None;
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: PyObject,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> bool {
+        self.close();
+        false
+    }
+}
+
+/// Wraps a Python iterator of tuples. The GIL is acquired for each item, so
+/// the iterator can be consumed from the threads writing the file.
+struct PyTupleIter<V> {
+    iter: Py<PyIterator>,
+    convert: fn(&Bound<'_, PyAny>) -> PyResult<(String, V)>,
+}
+
+impl<V> Iterator for PyTupleIter<V> {
+    type Item = Result<(String, V), BedValueError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Python::with_gil(|py| {
+            let mut iter = self.iter.bind(py).clone();
+            let item = match iter.next()? {
+                Ok(item) => item,
+                Err(e) => return Some(Err(BedValueError::InvalidInput(e.to_string()))),
+            };
+            Some((self.convert)(&item).map_err(|e| BedValueError::InvalidInput(e.to_string())))
+        })
+    }
+}
+
+fn bedgraph_tuple(item: &Bound<'_, PyAny>) -> PyResult<(String, Value)> {
+    let (chrom, start, end, value): (String, u32, u32, f32) = item.extract()?;
+    Ok((chrom, Value { start, end, value }))
+}
+
+fn bed_tuple(item: &Bound<'_, PyAny>) -> PyResult<(String, BedEntry)> {
+    let (chrom, start, end, rest): (String, u32, u32, String) = item.extract()?;
+    Ok((chrom, BedEntry { start, end, rest }))
+}
+
+/// Converts per-base arrays (where `NaN` is no data) into values, merging
+/// adjacent bases with the same value.
+fn array_values(arrays: &Bound<'_, PyDict>) -> PyResult<Vec<(String, Value)>> {
+    let mut chroms = vec![];
+    for (chrom, array) in arrays.iter() {
+        let chrom: String = chrom.extract()?;
+        let values: Vec<f32> = if let Ok(array) = array.extract::<PyReadonlyArray1<f32>>() {
+            array.as_array().iter().copied().collect()
+        } else {
+            let array: PyReadonlyArray1<f64> = array.extract()?;
+            array.as_array().iter().map(|v| *v as f32).collect()
+        };
+        chroms.push((chrom, values));
+    }
+    chroms.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = vec![];
+    for (chrom, values) in chroms {
+        let mut run: Option<Value> = None;
+        for (i, v) in values.into_iter().enumerate() {
+            let i = i as u32;
+            match &mut run {
+                Some(r) if r.value == v => r.end = i + 1,
+                _ => {
+                    if let Some(r) = run.take() {
+                        out.push((chrom.clone(), r));
+                    }
+                    if !v.is_nan() {
+                        run = Some(Value {
+                            start: i,
+                            end: i + 1,
+                            value: v,
+                        });
+                    }
+                }
+            }
+        }
+        if let Some(r) = run {
+            out.push((chrom.clone(), r));
+        }
+    }
+    Ok(out)
+}
+
+fn thread_pool(nthreads: usize) -> PyResult<futures::executor::ThreadPool> {
+    futures::executor::ThreadPoolBuilder::new()
+        .pool_size(nthreads.max(1))
+        .create()
+        .map_err(|e| PyIOError::new_err(e.to_string()))
+}
+
+/// A bigWig opened for writing.
+#[pyclass(module = "pybigtools")]
+struct BigWigWrite {
+    path: String,
+}
+
+#[pymethods]
+impl BigWigWrite {
+    /// Writes the bigWig. `chroms` is a dict of chromosome names to lengths.
+    /// `vals` is either an iterable of `(chrom, start, end, value)` tuples,
+    /// grouped by chromosome and sorted by start, or a dict of chromosome
+    /// names to NumPy arrays of per-base values, where `NaN` is no data.
+    #[pyo3(signature = (chroms, vals, nthreads=6))]
+    fn write(
+        &self,
+        py: Python<'_>,
+        chroms: HashMap<String, u32>,
+        vals: &Bound<'_, PyAny>,
+        nthreads: usize,
+    ) -> PyResult<()> {
+        let mut outb = bigtools::BigWigWrite::create_file(self.path.clone());
+        outb.options.input_sort_type = InputSortType::START;
+        let pool = thread_pool(nthreads)?;
+        if let Ok(arrays) = vals.downcast::<PyDict>() {
+            let values = array_values(arrays)?;
+            let vals_iter = BedParser::wrap_iter(values.into_iter().map(Ok::<_, BedValueError>));
+            let chsi = BedParserStreamingIterator::new(vals_iter, true);
+            py.allow_threads(|| outb.write(chroms, chsi, pool))
+                .map_err(write_error)?;
+        } else {
+            let iter = PyTupleIter {
+                iter: vals.iter()?.unbind(),
+                convert: bedgraph_tuple,
+            };
+            let chsi = BedParserStreamingIterator::new(BedParser::wrap_iter(iter), true);
+            py.allow_threads(|| outb.write(chroms, chsi, pool))
+                .map_err(write_error)?;
+        }
+        Ok(())
+    }
+}
+
+/// A bigBed opened for writing.
+#[pyclass(module = "pybigtools")]
+struct BigBedWrite {
+    path: String,
+}
+
+#[pymethods]
+impl BigBedWrite {
+    /// Writes the bigBed. `chroms` is a dict of chromosome names to lengths.
+    /// `vals` is an iterable of `(chrom, start, end, rest)` tuples, grouped
+    /// by chromosome and sorted by start, where `rest` is the tab-separated
+    /// remaining fields. If `autosql` is not given, a standard BED definition
+    /// is used for the number of fields.
+    #[pyo3(signature = (chroms, vals, autosql=None, nthreads=6))]
+    fn write(
+        &self,
+        py: Python<'_>,
+        chroms: HashMap<String, u32>,
+        vals: &Bound<'_, PyAny>,
+        autosql: Option<String>,
+        nthreads: usize,
+    ) -> PyResult<()> {
+        use bigtools::utils::chromvalues::ChromValues;
+
+        let mut outb = bigtools::BigBedWrite::create_file(self.path.clone());
+        outb.options.input_sort_type = InputSortType::START;
+        let pool = thread_pool(nthreads)?;
+        let iter = PyTupleIter {
+            iter: vals.iter()?.unbind(),
+            convert: bed_tuple,
+        };
+        let mut vals_iter = BedParser::wrap_iter(iter);
+        outb.autosql = match autosql {
+            Some(autosql) => Some(autosql),
+            None => {
+                let first = match vals_iter.next_chrom() {
+                    Some(Ok((_, mut group))) => match group.peek() {
+                        Some(Ok(first)) => Some(bed_autosql(&first.rest)),
+                        Some(Err(e)) => return Err(BBIWriteError::new_err(e.to_string())),
+                        None => None,
+                    },
+                    Some(Err(e)) => return Err(BBIWriteError::new_err(e.to_string())),
+                    None => None,
+                };
+                Some(first.unwrap_or_else(|| bed_autosql("")))
+            }
+        };
+        let chsi = BedParserStreamingIterator::new(vals_iter, true);
+        py.allow_threads(|| outb.write(chroms, chsi, pool))
+            .map_err(write_error)?;
+        Ok(())
+    }
+}
+
+/// Opens a bigWig or bigBed. With mode `r`, the type is detected from the
+/// file. With mode `w`, it is chosen from the extension (`.bigWig` or `.bw`,
+/// `.bigBed` or `.bb`).
+#[pyfunction]
+#[pyo3(signature = (path, mode="r"))]
+fn open(py: Python<'_>, path: &str, mode: &str) -> PyResult<PyObject> {
+    match mode {
+        "r" => {
+            let file_type = bigtools::read_file_type(&mut File::open(path)?)?;
+            if file_type == Some(BBIFile::BigWig) {
+                let bigwig = bigtools::BigWigRead::open_file(path).map_err(|e| match e {
+                    BigWigReadAttachError::IoError(e) => PyIOError::new_err(e.to_string()),
+                    e => BBIReadError::new_err(e.to_string()),
+                })?;
+                Ok(BigWigRead {
+                    bigwig: Some(bigwig),
+                }
+                .into_py(py))
+            } else if file_type == Some(BBIFile::BigBed) {
+                let bigbed =
+                    bigtools::BigBedRead::open_file(path.to_string()).map_err(|e| match e {
+                        BigBedReadAttachError::IoError(e) => PyIOError::new_err(e.to_string()),
+                        e => BBIReadError::new_err(e.to_string()),
+                    })?;
+                Ok(BigBedRead {
+                    bigbed: Some(bigbed),
+                }
+                .into_py(py))
+            } else {
+                Err(BBIReadError::new_err(format!(
+                    "`{}` is not a bigWig or bigBed.",
+                    path
+                )))
+            }
+        }
+        "w" => {
+            let lower = path.to_lowercase();
+            if lower.ends_with(".bigwig") || lower.ends_with(".bw") {
+                Ok(BigWigWrite {
+                    path: path.to_string(),
+                }
+                .into_py(py))
+            } else if lower.ends_with(".bigbed") || lower.ends_with(".bb") {
+                Ok(BigBedWrite {
+                    path: path.to_string(),
+                }
+                .into_py(py))
+            } else {
+                Err(PyValueError::new_err(
+                    "Unknown file type for writing. Use a `.bigWig`, `.bw`, `.bigBed`, or `.bb` extension.",
+                ))
+            }
+        }
+        _ => Err(PyValueError::new_err(format!(
+            "Invalid mode `{}`. Options are `r` or `w`.",
+            mode
+        ))),
+    }
+}
+
+#[pymodule]
+fn pybigtools(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(open, m)?)?;
+    m.add_class::<BigWigRead>()?;
+    m.add_class::<BigBedRead>()?;
+    m.add_class::<BigWigWrite>()?;
+    m.add_class::<BigBedWrite>()?;
+    m.add_class::<BigWigIntervalIterator>()?;
+    m.add_class::<BigBedEntriesIterator>()?;
+    m.add("BBIReadError", m.py().get_type_bound::<BBIReadError>())?;
+    m.add("BBIWriteError", m.py().get_type_bound::<BBIWriteError>())?;
+    Ok(())
+}
@@ -0,0 +1,133 @@
+from pathlib import Path
+
+import numpy as np
+import pytest
+
+import pybigtools
+
+TEST_BIGWIG = Path(__file__).parents[2] / "resources" / "test" / "valid.bigWig"
+
+
+@pytest.fixture
+def bigwig():
+    with pybigtools.open(str(TEST_BIGWIG)) as b:
+        yield b
+
+
+def test_open(bigwig):
+    assert isinstance(bigwig, pybigtools.BigWigRead)
+    with pytest.raises(pybigtools.BBIReadError):
+        pybigtools.open(__file__)
+    with pytest.raises(IOError):
+        pybigtools.open("does_not_exist.bigWig")
+    with pytest.raises(ValueError):
+        pybigtools.open(str(TEST_BIGWIG), "a")
+
+
+def test_chroms(bigwig):
+    assert bigwig.chroms() == {"chr17": 83257441}
+    assert bigwig.chroms("chr17") == 83257441
+    with pytest.raises(KeyError):
+        bigwig.chroms("chr1")
+
+
+def test_get_interval(bigwig):
+    intervals = list(bigwig.get_interval("chr17", 59900, 60200))
+    assert len(intervals) == 7
+    assert intervals[1] == (59900, 59947, pytest.approx(0.16627))
+    for start, end, _ in intervals:
+        assert 59900 <= start <= end <= 60200
+    with pytest.raises(KeyError):
+        bigwig.get_interval("chr1", 0, 100)
+
+
+def test_values(bigwig):
+    values = bigwig.values("chr17", 59900, 60200)
+    assert isinstance(values, np.ndarray)
+    assert values.shape == (300,)
+    for start, end, value in bigwig.get_interval("chr17", 59900, 60200):
+        assert np.allclose(values[start - 59900 : end - 59900], value)
+    # Bases before the first value have no data
+    assert np.isnan(bigwig.values("chr17", 0, 10)).all()
+
+
+def test_binned_values(bigwig):
+    values = bigwig.values("chr17", 59900, 60200)
+    means = bigwig.values("chr17", 59900, 60200, bins=3)
+    assert means.shape == (3,)
+    for i, mean in enumerate(means):
+        assert mean == pytest.approx(np.nanmean(values[i * 100 : (i + 1) * 100]))
+    maxes = bigwig.values("chr17", 59900, 60200, bins=3, summary="max")
+    assert maxes[0] == pytest.approx(np.nanmax(values[:100]))
+    coverage = bigwig.values("chr17", 0, 300, bins=3, summary="coverage")
+    assert (coverage == 0).all()
+    with pytest.raises(ValueError):
+        bigwig.values("chr17", 0, 300, bins=3, summary="median")
+
+
+def test_zoom_interval(bigwig):
+    zooms = bigwig.zooms()
+    assert zooms[0] == 10
+    records = bigwig.get_zoom_interval("chr17", 59900, 60200, zooms[0])
+    assert len(records) > 0
+    start, end, bases_covered, min_val, max_val, total, _ = records[0]
+    assert end - start <= zooms[0]
+    assert min_val <= total / bases_covered <= max_val
+    with pytest.raises(KeyError):
+        bigwig.get_zoom_interval("chr17", 59900, 60200, 3)
+
+
+def test_closed():
+    b = pybigtools.open(str(TEST_BIGWIG))
+    b.close()
+    with pytest.raises(ValueError):
+        b.chroms()
+
+
+def test_write_iterator(tmp_path):
+    out = str(tmp_path / "out.bigWig")
+    values = [("chr1", 0, 10, 1.0), ("chr1", 20, 30, 2.5), ("chr2", 5, 6, 3.0)]
+    pybigtools.open(out, "w").write({"chr1": 1000, "chr2": 500}, iter(values))
+
+    with pybigtools.open(out) as b:
+        assert b.chroms() == {"chr1": 1000, "chr2": 500}
+        assert list(b.get_interval("chr1")) == [(0, 10, 1.0), (20, 30, 2.5)]
+        assert list(b.get_interval("chr2")) == [(5, 6, 3.0)]
+
+
+def test_write_arrays(tmp_path):
+    out = str(tmp_path / "out.bigWig")
+    chr1 = np.full(100, np.nan)
+    chr1[10:20] = 1.5
+    chr1[20:25] = 2.0
+    pybigtools.open(out, "w").write({"chr1": 100}, {"chr1": chr1})
+
+    with pybigtools.open(out) as b:
+        assert list(b.get_interval("chr1")) == [(10, 20, 1.5), (20, 25, 2.0)]
+        assert np.array_equal(b.values("chr1"), chr1, equal_nan=True)
+
+
+def test_write_errors(tmp_path):
+    def values():
+        yield ("chr1", 0, 10, 1.0)
+        raise RuntimeError("bad input")
+
+    with pytest.raises(pybigtools.BBIWriteError, match="bad input"):
+        pybigtools.open(str(tmp_path / "out.bigWig"), "w").write({"chr1": 1000}, values())
+    with pytest.raises(pybigtools.BBIWriteError):
+        pybigtools.open(str(tmp_path / "out.bigWig"), "w").write(
+            {"chr1": 1000}, [("chr1", 0, "ten", 1.0)]
+        )
+    with pytest.raises(ValueError):
+        pybigtools.open(str(tmp_path / "out.txt"), "w")
+
+
+def test_bigbed_roundtrip(tmp_path):
+    out = str(tmp_path / "out.bigBed")
+    entries = [("chr1", 0, 10, "a\t0\t+"), ("chr1", 5, 20, "b\t0\t-")]
+    pybigtools.open(out, "w").write({"chr1": 1000}, entries)
+
+    with pybigtools.open(out) as b:
+        assert isinstance(b, pybigtools.BigBedRead)
+        assert list(b.get_interval("chr1")) == [(s, e, r) for _, s, e, r in entries]
+        assert "table bed" in b.autosql()
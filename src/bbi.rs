@@ -1,3 +1,8 @@
+#[cfg(feature = "arrow")]
+pub mod arrowdata;
+#[cfg(feature = "bam")]
+pub mod bamchromdata;
+pub mod bbicopy;
 pub mod bbiread;
 pub mod bbiwrite;
 pub mod bedchromdata;
@@ -5,6 +10,9 @@ pub mod bigbedread;
 pub mod bigbedwrite;
 pub mod bigwigread;
 pub mod bigwigwrite;
+pub mod mergechromdata;
+pub mod normalize;
+pub mod pileupchromdata;
 
 use serde::{Deserialize, Serialize};
 
@@ -53,12 +61,13 @@ pub struct BedEntry {
     pub rest: String,
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum BBIFile {
     BigWig,
     BigBed,
 }
 
+pub use bbicopy::*;
 pub use bbiread::*;
 pub use bbiwrite::*;
 pub use bigbedread::*;
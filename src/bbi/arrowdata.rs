@@ -0,0 +1,349 @@
+//! Conversion between the intervals of bigWigs and bigBeds and Apache Arrow
+//! [`RecordBatch`]es, and reading Parquet or Arrow IPC files as a
+//! [`ChromData`][crate::ChromData] source for writing bigWigs.
+//!
+//! bigWig batches have the columns `chrom` (`Utf8`), `start` and `end`
+//! (`UInt32`), and `value` (`Float32`). bigBed batches have a column for each
+//! field of the file's autoSql: integer and floating point fields have the
+//! Arrow type of the same width, and all other fields (strings, chars, lists,
+//! enums and sets) are `Utf8`, as written in the bigBed. Missing or empty
+//! fields are null.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, AsArray, Float32Array, StringArray, UInt32Array};
+use arrow::compute::{cast_with_options, CastOptions};
+use arrow::datatypes::{DataType, Field, Float32Type, Schema, SchemaRef, UInt32Type};
+use arrow::error::ArrowError;
+use arrow::ipc::reader::FileReader;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+use parquet::errors::ParquetError;
+use thiserror::Error;
+
+use crate::bed::autosql::parse_autosql_fields;
+use crate::bed::bedparser::{BedIteratorStream, BedParser, BedValueError};
+use crate::bedchromdata::BedParserStreamingIterator;
+use crate::utils::reopen::SeekableRead;
+use crate::{BBIReadError, BedEntry, BigBedRead, BigWigRead, Value};
+
+/// The default number of rows in each batch.
+pub const DEFAULT_BATCH_SIZE: usize = 65536;
+
+#[derive(Error, Debug)]
+pub enum ArrowDataError {
+    #[error("{}", .0)]
+    InvalidInput(String),
+    #[error("{}", .0)]
+    ArrowError(#[from] ArrowError),
+    #[error("{}", .0)]
+    ParquetError(#[from] ParquetError),
+    #[error("{}", .0)]
+    BBIReadError(#[from] BBIReadError),
+    #[error("{}", .0)]
+    IoError(#[from] io::Error),
+}
+
+/// The schema of bigWig batches.
+pub fn bigwig_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("chrom", DataType::Utf8, false),
+        Field::new("start", DataType::UInt32, false),
+        Field::new("end", DataType::UInt32, false),
+        Field::new("value", DataType::Float32, false),
+    ]))
+}
+
+/// Creates a batch of `values` on `chrom`, with the schema of
+/// [`bigwig_schema`].
+pub fn bigwig_record_batch(chrom: &str, values: &[Value]) -> Result<RecordBatch, ArrowError> {
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(std::iter::repeat_n(
+            chrom,
+            values.len(),
+        ))),
+        Arc::new(UInt32Array::from_iter_values(
+            values.iter().map(|v| v.start),
+        )),
+        Arc::new(UInt32Array::from_iter_values(values.iter().map(|v| v.end))),
+        Arc::new(Float32Array::from_iter_values(
+            values.iter().map(|v| v.value),
+        )),
+    ];
+    RecordBatch::try_new(bigwig_schema(), columns)
+}
+
+fn check_batch_size(batch_size: usize) -> Result<(), ArrowDataError> {
+    if batch_size == 0 {
+        return Err(ArrowDataError::InvalidInput(
+            "The batch size must be greater than 0.".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+/// Returns an `Iterator` of batches of up to `batch_size` of the `Value`s
+/// overlapping `start..end` of `chrom`. Errors if `batch_size` is 0.
+pub fn bigwig_batches<'a, R: SeekableRead>(
+    bigwig: &'a mut BigWigRead<R>,
+    chrom: &'a str,
+    start: u32,
+    end: u32,
+    batch_size: usize,
+) -> Result<impl Iterator<Item = Result<RecordBatch, ArrowDataError>> + 'a, ArrowDataError> {
+    check_batch_size(batch_size)?;
+    let mut values = bigwig.get_interval(chrom, start, end)?;
+    Ok(std::iter::from_fn(move || {
+        let mut batch = Vec::with_capacity(batch_size);
+        for value in values.by_ref().take(batch_size) {
+            match value {
+                Ok(value) => batch.push(value),
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+        if batch.is_empty() {
+            return None;
+        }
+        Some(bigwig_record_batch(chrom, &batch).map_err(Into::into))
+    }))
+}
+
+fn autosql_type(field_type: &str) -> DataType {
+    match field_type {
+        "byte" => DataType::Int8,
+        "ubyte" => DataType::UInt8,
+        "short" => DataType::Int16,
+        "ushort" => DataType::UInt16,
+        "int" => DataType::Int32,
+        "uint" => DataType::UInt32,
+        "bigint" => DataType::Int64,
+        "float" => DataType::Float32,
+        "double" => DataType::Float64,
+        _ => DataType::Utf8,
+    }
+}
+
+/// The schema of bigBed batches with the fields of `autosql`. The first three
+/// fields are always the chromosome, start, and end (`Utf8`, `UInt32` and
+/// `UInt32`). If `autosql` doesn't declare at least three fields, the
+/// remaining fields are in a single `rest` column.
+pub fn bigbed_schema(autosql: &str) -> SchemaRef {
+    let fields = parse_autosql_fields(autosql).filter(|fields| fields.len() >= 3);
+    let (names, rest): (Vec<&str>, Vec<Field>) = match &fields {
+        Some(fields) => (
+            fields[0..3].iter().map(|f| f.name.as_str()).collect(),
+            fields[3..]
+                .iter()
+                .map(|f| Field::new(&f.name, autosql_type(&f.field_type), true))
+                .collect(),
+        ),
+        None => (
+            vec!["chrom", "chromStart", "chromEnd"],
+            vec![Field::new("rest", DataType::Utf8, true)],
+        ),
+    };
+    let mut schema = vec![
+        Field::new(names[0], DataType::Utf8, false),
+        Field::new(names[1], DataType::UInt32, false),
+        Field::new(names[2], DataType::UInt32, false),
+    ];
+    schema.extend(rest);
+    Arc::new(Schema::new(schema))
+}
+
+/// Creates a batch of `entries` on `chrom`, with a schema from
+/// [`bigbed_schema`]. Fields that can't be parsed as their type are an
+/// error.
+pub fn bigbed_record_batch(
+    schema: SchemaRef,
+    chrom: &str,
+    entries: &[BedEntry],
+) -> Result<RecordBatch, ArrowError> {
+    let rest_fields = &schema.fields()[3..];
+    let mut rest: Vec<Vec<Option<&str>>> =
+        vec![Vec::with_capacity(entries.len()); rest_fields.len()];
+    for entry in entries {
+        let mut split = entry.rest.splitn(rest_fields.len(), '\t');
+        for column in rest.iter_mut() {
+            column.push(split.next().filter(|field| !field.is_empty()));
+        }
+    }
+
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(std::iter::repeat_n(
+            chrom,
+            entries.len(),
+        ))),
+        Arc::new(UInt32Array::from_iter_values(
+            entries.iter().map(|e| e.start),
+        )),
+        Arc::new(UInt32Array::from_iter_values(entries.iter().map(|e| e.end))),
+    ];
+    let options = CastOptions {
+        safe: false,
+        ..Default::default()
+    };
+    for (field, column) in rest_fields.iter().zip(rest) {
+        let strings: ArrayRef = Arc::new(StringArray::from(column));
+        columns.push(cast_with_options(&strings, field.data_type(), &options)?);
+    }
+    RecordBatch::try_new(schema, columns)
+}
+
+/// Returns an `Iterator` of batches of up to `batch_size` of the `BedEntry`s
+/// overlapping `start..end` of `chrom`. `schema` is generally from
+/// [`bigbed_schema`] with the bigBed's autoSql. Errors if `batch_size` is 0.
+pub fn bigbed_batches<'a, R: SeekableRead>(
+    bigbed: &'a mut BigBedRead<R>,
+    schema: SchemaRef,
+    chrom: &'a str,
+    start: u32,
+    end: u32,
+    batch_size: usize,
+) -> Result<impl Iterator<Item = Result<RecordBatch, ArrowDataError>> + 'a, ArrowDataError> {
+    check_batch_size(batch_size)?;
+    let mut entries = bigbed.get_interval(chrom, start, end)?;
+    Ok(std::iter::from_fn(move || {
+        let mut batch = Vec::with_capacity(batch_size);
+        for entry in entries.by_ref().take(batch_size) {
+            match entry {
+                Ok(entry) => batch.push(entry),
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+        if batch.is_empty() {
+            return None;
+        }
+        Some(bigbed_record_batch(schema.clone(), chrom, &batch).map_err(Into::into))
+    }))
+}
+
+struct BigWigColumns {
+    chrom: StringArray,
+    start: UInt32Array,
+    end: UInt32Array,
+    value: Float32Array,
+}
+
+impl BigWigColumns {
+    fn new(batch: &RecordBatch) -> Result<Self, ArrowError> {
+        let column = |name: &str, data_type: &DataType| {
+            let column = batch
+                .column_by_name(name)
+                .ok_or_else(|| ArrowError::SchemaError(format!("Missing column `{}`.", name)))?;
+            let options = CastOptions {
+                safe: false,
+                ..Default::default()
+            };
+            cast_with_options(column, data_type, &options)
+        };
+        Ok(BigWigColumns {
+            chrom: column("chrom", &DataType::Utf8)?.as_string::<i32>().clone(),
+            start: column("start", &DataType::UInt32)?
+                .as_primitive::<UInt32Type>()
+                .clone(),
+            end: column("end", &DataType::UInt32)?
+                .as_primitive::<UInt32Type>()
+                .clone(),
+            value: column("value", &DataType::Float32)?
+                .as_primitive::<Float32Type>()
+                .clone(),
+        })
+    }
+}
+
+/// An `Iterator` of the chromosomes and `Value`s of bigWig-like batches,
+/// which must have `chrom`, `start`, `end` and `value` columns (of any types
+/// that can be cast to those of [`bigwig_schema`]). Rows with a null value
+/// are skipped.
+pub struct RecordBatchValues<I> {
+    batches: I,
+    current: Option<BigWigColumns>,
+    row: usize,
+}
+
+impl<I> RecordBatchValues<I> {
+    pub fn new(batches: I) -> Self {
+        RecordBatchValues {
+            batches,
+            current: None,
+            row: 0,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<RecordBatch, ArrowError>>> Iterator for RecordBatchValues<I> {
+    type Item = Result<(String, Value), BedValueError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(columns) = &self.current {
+                while self.row < columns.chrom.len() {
+                    let row = self.row;
+                    self.row += 1;
+                    if columns.value.is_null(row) {
+                        continue;
+                    }
+                    if columns.chrom.is_null(row)
+                        || columns.start.is_null(row)
+                        || columns.end.is_null(row)
+                    {
+                        return Some(Err(BedValueError::InvalidInput(
+                            "Invalid input: null chrom, start, or end.".to_owned(),
+                        )));
+                    }
+                    let value = Value {
+                        start: columns.start.value(row),
+                        end: columns.end.value(row),
+                        value: columns.value.value(row),
+                    };
+                    return Some(Ok((columns.chrom.value(row).to_owned(), value)));
+                }
+            }
+            let columns = self
+                .batches
+                .next()?
+                .and_then(|batch| BigWigColumns::new(&batch));
+            match columns {
+                Ok(columns) => {
+                    self.current = Some(columns);
+                    self.row = 0;
+                }
+                Err(e) => return Some(Err(BedValueError::InvalidInput(e.to_string()))),
+            }
+        }
+    }
+}
+
+/// A [`ChromData`][crate::ChromData] source of bigWig values from
+/// `RecordBatch`es, which must be sorted by chromosome and start.
+pub type ArrowBigWigData<I> =
+    BedParserStreamingIterator<BedIteratorStream<Value, RecordBatchValues<I>>>;
+
+/// Reads bigWig values from `batches` (see [`RecordBatchValues`]).
+pub fn bigwig_data<I: Iterator<Item = Result<RecordBatch, ArrowError>>>(
+    batches: I,
+) -> ArrowBigWigData<I> {
+    BedParserStreamingIterator::new(BedParser::wrap_iter(RecordBatchValues::new(batches)), false)
+}
+
+/// Reads bigWig values from a Parquet file.
+pub fn parquet_bigwig_data(
+    path: impl AsRef<Path>,
+) -> Result<ArrowBigWigData<ParquetRecordBatchReader>, ArrowDataError> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?
+        .with_batch_size(DEFAULT_BATCH_SIZE)
+        .build()?;
+    Ok(bigwig_data(reader))
+}
+
+/// Reads bigWig values from an Arrow IPC (Feather v2) file.
+pub fn arrow_ipc_bigwig_data(
+    path: impl AsRef<Path>,
+) -> Result<ArrowBigWigData<FileReader<File>>, ArrowDataError> {
+    let reader = FileReader::try_new(File::open(path)?, None)?;
+    Ok(bigwig_data(reader))
+}
@@ -0,0 +1,368 @@
+//! Read coverage from an indexed BAM file, as a [`ChromData`] source for
+//! writing bigWigs.
+//!
+//! [`BamCoverage`] reads each chromosome independently (by seeking with the
+//! BAM index), so that up to 5 chromosomes are read and processed
+//! concurrently, like `BedParserParallelStreamingIterator`. Each value is the
+//! number of reads overlapping a base (or a bin of `bin_size` bases),
+//! multiplied by `scale`. Bases with no coverage are not written.
+//!
+//! Without read extension, only the aligned blocks of a read are counted:
+//! skipped regions (CIGAR `N`) are not, but deletions (CIGAR `D`) are. With
+//! read extension, a read covers `extend` bases from its 5' end, in the
+//! direction of its strand.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+
+use noodles::bam;
+use noodles::bgzf::{self, VirtualPosition};
+use noodles::core::region::Interval;
+use noodles::csi::BinningIndex;
+use noodles::sam::alignment::record::cigar::op::Kind;
+use thiserror::Error;
+
+use crate::bbi::pileupchromdata::CoverageSweep;
+use crate::bbiwrite::QueuedReads;
+use crate::utils::chromvalues::ChromValues;
+use crate::utils::parallel::par_chroms;
+use crate::{ChromData, ChromDataState, ChromProcessingFnOutput, Value};
+
+#[derive(Error, Debug)]
+pub enum BamCoverageError {
+    #[error("{}", .0)]
+    InvalidInput(String),
+    #[error("{}", .0)]
+    IoError(#[from] io::Error),
+}
+
+/// Which reads are counted, and how coverage is computed from them.
+#[derive(Clone, Debug)]
+pub struct BamCoverageOptions {
+    /// Reads with a lower mapping quality are skipped. Reads with an
+    /// unavailable mapping quality (255) are always kept.
+    pub min_mapq: u8,
+    /// Whether to count reads marked as PCR or optical duplicates.
+    pub keep_duplicates: bool,
+    /// If set, each read is extended (or truncated) to this many bases.
+    pub extend: Option<u32>,
+    /// The number of bases in each bin. With `1`, coverage is per base.
+    pub bin_size: u32,
+    /// Every value is multiplied by this.
+    pub scale: f32,
+}
+
+impl Default for BamCoverageOptions {
+    fn default() -> Self {
+        BamCoverageOptions {
+            min_mapq: 0,
+            keep_duplicates: false,
+            extend: None,
+            bin_size: 1,
+            scale: 1.0,
+        }
+    }
+}
+
+struct BamChrom {
+    name: String,
+    id: usize,
+    length: u32,
+    start: VirtualPosition,
+}
+
+type BamReader = bam::io::Reader<bgzf::io::Reader<File>>;
+
+/// Coverage of every chromosome with reads in an indexed BAM file. Either a
+/// `.bai` or a `.csi` index is used, at `<path>.bai` or `<path>.csi`.
+pub struct BamCoverage<E> {
+    path: PathBuf,
+    options: BamCoverageOptions,
+    chrom_sizes: HashMap<String, u32>,
+    chroms: Vec<BamChrom>,
+
+    queued_reads: QueuedReads<BamCoverageError, E>,
+}
+
+impl<E> BamCoverage<E> {
+    pub fn new(path: PathBuf, options: BamCoverageOptions) -> Result<Self, BamCoverageError> {
+        if options.bin_size == 0 {
+            return Err(BamCoverageError::InvalidInput(
+                "The bin size must be greater than 0.".to_owned(),
+            ));
+        }
+        let mut reader = bam::io::reader::Builder.build_from_path(&path)?;
+        let header = reader.read_header()?;
+        let index = bam::fs::read_associated_index(&path).map_err(|e| {
+            BamCoverageError::InvalidInput(format!(
+                "Unable to read the index for `{}` (expected a .bai or .csi index): {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        let mut chrom_sizes = HashMap::new();
+        let mut chroms = vec![];
+        for (id, (name, reference_sequence)) in header.reference_sequences().iter().enumerate() {
+            let name = String::from_utf8_lossy(name).into_owned();
+            let length = u32::try_from(usize::from(reference_sequence.length())).map_err(|_| {
+                BamCoverageError::InvalidInput(format!(
+                    "Chromosome `{}` is too long for a bigWig.",
+                    name
+                ))
+            })?;
+            chrom_sizes.insert(name.clone(), length);
+            let chunks = index.query(id, Interval::from(..))?;
+            // Reads for a chromosome are contiguous in a sorted BAM, so only
+            // the first chunk is needed.
+            if let Some(start) = chunks.iter().map(|c| c.start()).min() {
+                chroms.push(BamChrom {
+                    name,
+                    id,
+                    length,
+                    start,
+                });
+            }
+        }
+        // For speed, we `pop` and go in reverse order.
+        chroms.reverse();
+
+        Ok(BamCoverage {
+            path,
+            options,
+            chrom_sizes,
+            chroms,
+            queued_reads: QueuedReads::new(),
+        })
+    }
+
+    /// The chromosome sizes from the BAM header.
+    pub fn chrom_sizes(&self) -> &HashMap<String, u32> {
+        &self.chrom_sizes
+    }
+
+    /// Counts the reads that pass the filters in `options`, for normalization.
+    /// Chromosomes are counted in parallel, on `nthreads` threads.
+    pub fn count_reads(&self, nthreads: usize) -> Result<u64, BamCoverageError> {
+        let (path, options) = (&self.path, &self.options);
+        let states = vec![(); nthreads.max(1)];
+        let (counts, _) = par_chroms(&self.chroms, states, |_, chrom| {
+            let mut reads = ChromReads::open(path, chrom, options.clone())?;
+            let mut count = 0;
+            while reads.next_read()?.is_some() {
+                count += 1;
+            }
+            Ok::<_, BamCoverageError>(count)
+        })?;
+        Ok(counts.into_iter().sum())
+    }
+}
+
+impl<E: From<io::Error>> ChromData<E> for BamCoverage<E> {
+    type Output = BamChromValues;
+
+    fn advance<
+        F: FnMut(
+            String,
+            Self::Output,
+        ) -> Result<ChromProcessingFnOutput<<Self::Output as ChromValues>::Error>, E>,
+    >(
+        &mut self,
+        do_read: &mut F,
+    ) -> Result<ChromDataState<<Self::Output as ChromValues>::Error>, E> {
+        let begin_next = || -> Result<_, E> {
+            let chrom = match self.chroms.pop() {
+                Some(c) => c,
+                None => return Ok(ChromDataState::Finished),
+            };
+            let name = chrom.name.clone();
+            // The file is opened lazily, so that reading happens on the
+            // thread the chromosome is processed on.
+            let values = BamChromValues {
+                path: self.path.clone(),
+                options: self.options.clone(),
+                chrom,
+                reads: None,
+                sweep: CoverageSweep::new(
+                    self.options.bin_size,
+                    self.options.scale,
+                    self.options.extend.unwrap_or(0),
+                ),
+                pending: VecDeque::new(),
+                error: None,
+                done: false,
+            };
+            let read = do_read(name, values)?;
+            Ok(ChromDataState::NewChrom(read))
+        };
+
+        self.queued_reads.advance(begin_next)
+    }
+}
+
+/// The counted reads of one chromosome, in order of start.
+struct ChromReads {
+    reader: BamReader,
+    record: bam::Record,
+    chrom_id: usize,
+    length: u32,
+    options: BamCoverageOptions,
+    blocks: Vec<(u32, u32)>,
+}
+
+impl ChromReads {
+    fn open(
+        path: &PathBuf,
+        chrom: &BamChrom,
+        options: BamCoverageOptions,
+    ) -> Result<Self, BamCoverageError> {
+        let mut reader = bam::io::reader::Builder.build_from_path(path)?;
+        reader.read_header()?;
+        reader.get_mut().seek(chrom.start)?;
+        Ok(ChromReads {
+            reader,
+            record: bam::Record::default(),
+            chrom_id: chrom.id,
+            length: chrom.length,
+            options,
+            blocks: vec![],
+        })
+    }
+
+    /// Reads up to the next counted read, returning its start. The blocks of
+    /// the chromosome it covers are in `blocks`.
+    fn next_read(&mut self) -> Result<Option<u32>, BamCoverageError> {
+        loop {
+            if self.reader.read_record(&mut self.record)? == 0 {
+                return Ok(None);
+            }
+            match self.record.reference_sequence_id().transpose()? {
+                Some(id) if id == self.chrom_id => {}
+                Some(id) if id < self.chrom_id => continue,
+                _ => return Ok(None),
+            }
+            let flags = self.record.flags();
+            if flags.is_unmapped()
+                || flags.is_secondary()
+                || flags.is_supplementary()
+                || flags.is_qc_fail()
+                || (flags.is_duplicate() && !self.options.keep_duplicates)
+            {
+                continue;
+            }
+            if let Some(mapq) = self.record.mapping_quality() {
+                if mapq.get() < self.options.min_mapq {
+                    continue;
+                }
+            }
+            let start = match self.record.alignment_start().transpose()? {
+                Some(start) => (usize::from(start) - 1) as u32,
+                None => continue,
+            };
+
+            self.blocks.clear();
+            let mut pos = start;
+            for op in self.record.cigar().iter() {
+                let op = op?;
+                let len = op.len() as u32;
+                match op.kind() {
+                    Kind::Match | Kind::SequenceMatch | Kind::SequenceMismatch | Kind::Deletion => {
+                        match self.blocks.last_mut() {
+                            Some(last) if last.1 == pos => last.1 += len,
+                            _ => self.blocks.push((pos, pos + len)),
+                        }
+                        pos += len;
+                    }
+                    Kind::Skip => pos += len,
+                    _ => {}
+                }
+            }
+            if let Some(extend) = self.options.extend {
+                let end = self.blocks.last().map(|b| b.1).unwrap_or(start + 1);
+                self.blocks.clear();
+                if flags.is_reverse_complemented() {
+                    self.blocks.push((end.saturating_sub(extend), end));
+                } else {
+                    self.blocks.push((start, start.saturating_add(extend)));
+                }
+            }
+            let length = self.length;
+            self.blocks.retain_mut(|b| {
+                b.1 = b.1.min(length);
+                b.0 < b.1
+            });
+            return Ok(Some(start));
+        }
+    }
+}
+
+/// The coverage values of one chromosome of a [`BamCoverage`].
+pub struct BamChromValues {
+    path: PathBuf,
+    options: BamCoverageOptions,
+    chrom: BamChrom,
+    reads: Option<ChromReads>,
+    sweep: CoverageSweep,
+    pending: VecDeque<Value>,
+    error: Option<BamCoverageError>,
+    done: bool,
+}
+
+impl BamChromValues {
+    fn fill(&mut self) -> Result<(), BamCoverageError> {
+        if self.reads.is_none() && !self.done {
+            self.reads = Some(ChromReads::open(
+                &self.path,
+                &self.chrom,
+                self.options.clone(),
+            )?);
+        }
+        while self.pending.is_empty() && !self.done {
+            let reads = self.reads.as_mut().unwrap();
+            match reads.next_read()? {
+                Some(start) => {
+                    self.sweep
+                        .flush(start, self.chrom.length, &mut self.pending);
+                    self.sweep.add(&reads.blocks);
+                }
+                None => {
+                    self.sweep.finish(self.chrom.length, &mut self.pending);
+                    self.reads = None;
+                    self.done = true;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChromValues for BamChromValues {
+    type Value = Value;
+    type Error = BamCoverageError;
+
+    fn next(&mut self) -> Option<Result<Value, BamCoverageError>> {
+        if let Some(err) = self.error.take() {
+            return Some(Err(err));
+        }
+        if let Err(err) = self.fill() {
+            self.done = true;
+            return Some(Err(err));
+        }
+        self.pending.pop_front().map(Ok)
+    }
+
+    fn peek(&mut self) -> Option<Result<&Value, &BamCoverageError>> {
+        if self.error.is_none() {
+            if let Err(err) = self.fill() {
+                self.done = true;
+                self.error = Some(err);
+            }
+        }
+        if let Some(err) = &self.error {
+            return Some(Err(err));
+        }
+        self.pending.front().map(Ok)
+    }
+}
@@ -0,0 +1,1102 @@
+//! Block-level copying of bigWig and bigBed files.
+//!
+//! Operations like subsetting a file to a set of chromosomes, replacing the
+//! data of one chromosome, or concatenating per-chromosome files don't need to
+//! re-encode any data. [`BBICopy`] copies the (compressed) data blocks of one
+//! or more source files into a new file, only rewriting a block when its
+//! chromosome id changes, and rebuilds the chromosome tree and index around
+//! them. Zoom levels are either copied from the sources or regenerated from the
+//! copied data.
+//!
+//! ```no_run
+//! # use bigtools::bbicopy::BBICopy;
+//! # use bigtools::BigWigRead;
+//! let input = BigWigRead::open_file("in.bigWig")?;
+//! let autosomes: Vec<String> = (1..=22).map(|i| format!("chr{}", i)).collect();
+//! let report = BBICopy::create_file("out.bigWig".to_string())
+//!     .subset(input, |chrom| autosomes.iter().any(|c| c == chrom))?;
+//! println!("Wrote {} sections", report.total_sections);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::ffi::CString;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use byteorder::{NativeEndian, WriteBytesExt};
+use byteordered::Endianness;
+use futures::executor::block_on;
+use thiserror::Error;
+
+use crate::bbi::{BBIFile, Summary, Value, ZoomRecord, BIGBED_MAGIC, BIGWIG_MAGIC};
+use crate::bbiread::{
+    decompress_block, get_zoom_block_values, read_info, search_overlapping_leaves,
+    seek_past_cir_tree_header, BBIRead, BBIReadError, Block, LeafRange,
+};
+use crate::bbiwrite::{
+    encode_zoom_section, get_rtreeindex, write_blank_headers, write_chrom_tree, write_rtreeindex,
+    write_zooms, BBIWriteOptions, CancellationToken, Section, WriteEvent, WriteObserver,
+    WriteReport, ZoomInfo,
+};
+use crate::bigbedread::parse_block_entries;
+use crate::bigwigread::parse_block_values;
+use crate::utils::tell::Tell;
+
+/// How the zoom levels of a copied file are produced
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CopyZooms {
+    /// Copy the zoom levels if all used sources have the same (non-empty) set
+    /// of zoom levels, otherwise regenerate them.
+    Auto,
+    /// Copy the zoom levels that all used sources have in common.
+    Copy,
+    /// Regenerate the zoom levels from the copied data, as `BigWigWrite` and
+    /// `BigBedWrite` would.
+    Regenerate,
+}
+
+/// A chromosome to copy into the output
+#[derive(Clone, Debug)]
+pub struct CopyChrom {
+    /// The index of the source to copy this chromosome from
+    pub source: usize,
+    pub chrom: String,
+}
+
+#[derive(Error, Debug)]
+pub enum BBICopyError {
+    #[error("{}", .0)]
+    InvalidInput(String),
+    #[error("The copy was cancelled.")]
+    Cancelled,
+    #[error("{}", .0)]
+    BBIReadError(#[from] BBIReadError),
+    #[error("{}", .0)]
+    IoError(#[from] io::Error),
+}
+
+/// What `BBICopy::copy` copies, once the sources are validated
+struct CopyPlan {
+    filetype: BBIFile,
+    /// Sorted by chromosome, the order of the output ids
+    chroms: Vec<CopyChrom>,
+    autosql: Option<String>,
+    field_count: u16,
+    defined_field_count: u16,
+    /// The zoom levels to copy, or `None` to regenerate them
+    copy_levels: Option<Vec<u32>>,
+}
+
+/// Copies data blocks from one or more bigWigs or bigBeds into a new file.
+pub struct BBICopy {
+    pub path: String,
+    pub options: BBIWriteOptions,
+    pub zooms: CopyZooms,
+    pub observer: Option<Arc<dyn WriteObserver>>,
+    pub cancellation: CancellationToken,
+}
+
+impl BBICopy {
+    pub fn create_file(path: String) -> Self {
+        BBICopy {
+            path,
+            options: BBIWriteOptions::default(),
+            zooms: CopyZooms::Auto,
+            observer: None,
+            cancellation: CancellationToken::new(),
+        }
+    }
+
+    /// Checks that the output isn't any of `inpaths` (after resolving links
+    /// and relative paths), since creating it would truncate that source while
+    /// it is read. The sources given to `copy` can't be checked, because they
+    /// aren't necessarily files.
+    pub fn check_inputs(&self, inpaths: &[impl AsRef<Path>]) -> Result<(), BBICopyError> {
+        for inpath in inpaths {
+            if same_file(&self.path, inpath)? {
+                return Err(BBICopyError::InvalidInput(format!(
+                    "The output {} is also an input.",
+                    self.path
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the chromosomes of `source` for which `keep` returns `true`.
+    pub fn subset<B: BBIRead>(
+        self,
+        source: B,
+        keep: impl Fn(&str) -> bool,
+    ) -> Result<WriteReport, BBICopyError> {
+        let chroms = source
+            .get_info()
+            .chrom_info
+            .iter()
+            .filter(|c| keep(&c.name))
+            .map(|c| CopyChrom {
+                source: 0,
+                chrom: c.name.clone(),
+            })
+            .collect();
+        self.copy(vec![source], chroms)
+    }
+
+    /// Copies all chromosomes of all `sources` into one file. If a chromosome
+    /// is in more than one source, this is an error unless `replace` is set, in
+    /// which case the chromosome is taken from the last source that has it.
+    pub fn concat<B: BBIRead>(
+        self,
+        sources: Vec<B>,
+        replace: bool,
+    ) -> Result<WriteReport, BBICopyError> {
+        let mut chroms: BTreeMap<String, usize> = BTreeMap::new();
+        for (i, source) in sources.iter().enumerate() {
+            for chrom in source.get_info().chrom_info.iter() {
+                if let Some(prev) = chroms.insert(chrom.name.clone(), i) {
+                    if !replace {
+                        return Err(BBICopyError::InvalidInput(format!(
+                            "Chromosome {} is in both source {} and source {}.",
+                            chrom.name, prev, i
+                        )));
+                    }
+                }
+            }
+        }
+        let chroms = chroms
+            .into_iter()
+            .map(|(chrom, source)| CopyChrom { source, chrom })
+            .collect();
+        self.copy(sources, chroms)
+    }
+
+    /// Copies the given chromosomes from `sources`. All sources must be the
+    /// same type of file (bigWig or bigBed), and none can be the output (see
+    /// `check_inputs`). If the copy fails or is cancelled, the partially
+    /// written file is removed.
+    pub fn copy<B: BBIRead>(
+        self,
+        mut sources: Vec<B>,
+        chroms: Vec<CopyChrom>,
+    ) -> Result<WriteReport, BBICopyError> {
+        let plan = self.plan(&mut sources, chroms)?;
+        // The output is only created once the sources are known to be valid
+        let res = self.copy_internal(sources, plan);
+        if res.is_err() {
+            let _ = std::fs::remove_file(&self.path);
+        }
+        res
+    }
+
+    /// Checks that `chroms` can be copied from `sources`, and works out what
+    /// is copied.
+    fn plan<B: BBIRead>(
+        &self,
+        sources: &mut [B],
+        mut chroms: Vec<CopyChrom>,
+    ) -> Result<CopyPlan, BBICopyError> {
+        let filetype = validate_sources(sources, &chroms)?;
+        // Output chrom ids are assigned in sorted order, and data must be
+        // written in id order for the index to be valid
+        chroms.sort_by(|a, b| a.chrom.cmp(&b.chrom));
+        let used_sources: Vec<usize> = {
+            let mut used: Vec<usize> = chroms.iter().map(|c| c.source).collect();
+            used.sort_unstable();
+            used.dedup();
+            used
+        };
+
+        let autosql = match filetype {
+            BBIFile::BigWig => None,
+            BBIFile::BigBed => {
+                let mut autosql = None;
+                for &i in used_sources.iter() {
+                    let source_autosql = read_autosql(&mut sources[i])?;
+                    match &autosql {
+                        None => autosql = Some(source_autosql),
+                        Some(a) if *a != source_autosql => {
+                            return Err(BBICopyError::InvalidInput(
+                                "All bigBed sources must have the same autosql.".to_owned(),
+                            ));
+                        }
+                        Some(_) => {}
+                    }
+                }
+                autosql
+            }
+        };
+        let (field_count, defined_field_count) = {
+            let header = &sources[used_sources[0]].get_info().header;
+            (header._field_count, header._defined_field_count)
+        };
+
+        let copy_levels = self.zoom_levels_to_copy(sources, &used_sources);
+        Ok(CopyPlan {
+            filetype,
+            chroms,
+            autosql,
+            field_count,
+            defined_field_count,
+            copy_levels,
+        })
+    }
+
+    fn copy_internal<B: BBIRead>(
+        &self,
+        mut sources: Vec<B>,
+        plan: CopyPlan,
+    ) -> Result<WriteReport, BBICopyError> {
+        let CopyPlan {
+            filetype,
+            chroms,
+            autosql,
+            field_count,
+            defined_field_count,
+            copy_levels,
+        } = plan;
+
+        let notify = |event: WriteEvent| {
+            if let Some(observer) = &self.observer {
+                observer.on_event(event);
+            }
+        };
+
+        let fp = File::create(&self.path)?;
+        let mut file = BufWriter::new(fp);
+
+        write_blank_headers(&mut file)?;
+
+        let autosql_offset = match &autosql {
+            None => 0,
+            Some(autosql) => {
+                let autosql_offset = file.tell()?;
+                let autosql = CString::new(autosql.clone().into_bytes()).map_err(|_| {
+                    BBICopyError::InvalidInput("Invalid autosql: null byte in string".to_owned())
+                })?;
+                file.write_all(autosql.as_bytes_with_nul())?;
+                autosql_offset
+            }
+        };
+
+        let total_summary_offset = file.tell()?;
+        file.write_all(&[0; 40])?;
+
+        let full_data_offset = file.tell()?;
+        // Total items/sections, written at the end
+        file.write_u64::<NativeEndian>(0)?;
+
+        let pre_data = file.tell()?;
+
+        let resolutions: Vec<u32> = match &copy_levels {
+            Some(levels) => levels.clone(),
+            None => {
+                std::iter::successors(Some(self.options.initial_zoom_size), |z| z.checked_mul(4))
+                    .take(self.options.max_zooms as usize)
+                    .collect()
+            }
+        };
+        let mut zoom_levels = resolutions
+            .into_iter()
+            .map(|resolution| {
+                Ok(ZoomLevelOut {
+                    resolution,
+                    data: BufWriter::new(tempfile::tempfile()?),
+                    sections: vec![],
+                    records: Vec::with_capacity(self.options.items_per_slot as usize),
+                    live: None,
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut uncompress_buf_size: usize = 0;
+        let mut sections: Vec<Section> = vec![];
+        let mut chrom_ids: HashMap<String, u32> = HashMap::new();
+        let mut chrom_sizes: HashMap<String, u32> = HashMap::new();
+        let mut chrom_summaries: Vec<(String, Summary)> = vec![];
+        let mut summary: Option<Summary> = None;
+
+        for (out_id, copy_chrom) in chroms.iter().enumerate() {
+            let out_id = out_id as u32;
+            let source = &mut sources[copy_chrom.source];
+            let (src_id, length) = {
+                let info = source.get_info();
+                let chrom = info
+                    .chrom_info
+                    .iter()
+                    .find(|c| c.name == copy_chrom.chrom)
+                    .ok_or_else(|| {
+                        BBICopyError::InvalidInput(format!(
+                            "Chromosome {} not found in source {}.",
+                            copy_chrom.chrom, copy_chrom.source
+                        ))
+                    })?;
+                (chrom.id, chrom.length)
+            };
+            chrom_ids.insert(copy_chrom.chrom.clone(), out_id);
+            chrom_sizes.insert(copy_chrom.chrom.clone(), length);
+            notify(WriteEvent::ChromStarted {
+                chrom: copy_chrom.chrom.clone(),
+                length,
+            });
+
+            let src_compressed = source.get_info().header.uncompress_buf_size > 0;
+            let src_uncompress_buf_size = source.get_info().header.uncompress_buf_size as usize;
+
+            let mut chrom_summary: Option<Summary> = None;
+            let mut items: u64 = 0;
+            let mut coverage = Coverage::default();
+            let mut coverage_values = vec![];
+            let sections_before = sections.len();
+
+            let leaves = chrom_leaves(source, source.get_info().header.full_index_offset, src_id)?;
+            for leaf in leaves {
+                if leaf.start_chrom_ix != src_id || leaf.end_chrom_ix != src_id {
+                    return Err(BBICopyError::InvalidInput(format!(
+                        "A data block for {} spans multiple chromosomes, which can't be copied.",
+                        copy_chrom.chrom
+                    )));
+                }
+                let raw = read_raw_block(source, &leaf.block)?;
+                let mut data = decompress_block(raw.clone(), src_uncompress_buf_size)?;
+
+                match filetype {
+                    BBIFile::BigWig => {
+                        let values = parse_block_values(
+                            &data[..],
+                            Endianness::native(),
+                            src_id,
+                            0,
+                            u32::MAX,
+                        )?
+                        .unwrap_or_default();
+                        for value in values {
+                            items += 1;
+                            add_to_summary(&mut chrom_summary, &value, 1);
+                            if copy_levels.is_none() {
+                                for level in zoom_levels.iter_mut() {
+                                    level.add_value(
+                                        out_id,
+                                        value,
+                                        self.options,
+                                        &mut uncompress_buf_size,
+                                    )?;
+                                }
+                            }
+                        }
+                    }
+                    BBIFile::BigBed => {
+                        let entries = parse_block_entries(
+                            &data[..],
+                            Endianness::native(),
+                            src_id,
+                            0,
+                            u32::MAX,
+                        )?;
+                        for entry in entries {
+                            items += 1;
+                            coverage.add(entry.start, entry.end, &mut coverage_values);
+                        }
+                        for value in coverage_values.drain(..) {
+                            add_to_summary(&mut chrom_summary, &value, 0);
+                            if copy_levels.is_none() {
+                                for level in zoom_levels.iter_mut() {
+                                    level.add_value(
+                                        out_id,
+                                        value,
+                                        self.options,
+                                        &mut uncompress_buf_size,
+                                    )?;
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if self.options.compress {
+                    uncompress_buf_size = uncompress_buf_size.max(data.len());
+                }
+                let out_data = if src_id == out_id && src_compressed == self.options.compress {
+                    raw
+                } else {
+                    set_chrom_id(&mut data, filetype, out_id);
+                    if self.options.compress {
+                        compress(&data)?
+                    } else {
+                        data
+                    }
+                };
+                let offset = file.tell()?;
+                file.write_all(&out_data)?;
+                sections.push(Section {
+                    chrom: out_id,
+                    start: leaf.start_base,
+                    end: leaf.end_base,
+                    offset,
+                    size: out_data.len() as u64,
+                });
+
+                if self.cancellation.is_cancelled() {
+                    return Err(BBICopyError::Cancelled);
+                }
+            }
+
+            if let BBIFile::BigBed = filetype {
+                coverage.finish(&mut coverage_values);
+                for value in coverage_values.drain(..) {
+                    add_to_summary(&mut chrom_summary, &value, 0);
+                    if copy_levels.is_none() {
+                        for level in zoom_levels.iter_mut() {
+                            level.add_value(
+                                out_id,
+                                value,
+                                self.options,
+                                &mut uncompress_buf_size,
+                            )?;
+                        }
+                    }
+                }
+            }
+
+            if let Some(levels) = &copy_levels {
+                for (level, resolution) in zoom_levels.iter_mut().zip(levels.iter()) {
+                    let records = chrom_zoom_records(source, *resolution, src_id)?;
+                    for mut record in records {
+                        record.chrom = out_id;
+                        level.push_record(record, self.options, &mut uncompress_buf_size)?;
+                    }
+                }
+            }
+            for level in zoom_levels.iter_mut() {
+                level.finish_chrom(self.options, &mut uncompress_buf_size)?;
+            }
+
+            let mut chrom_summary = chrom_summary.unwrap_or(Summary {
+                total_items: 0,
+                bases_covered: 0,
+                min_val: 0.0,
+                max_val: 0.0,
+                sum: 0.0,
+                sum_squares: 0.0,
+            });
+            chrom_summary.total_items = items;
+            match &mut summary {
+                None => summary = Some(chrom_summary),
+                Some(summary) => {
+                    summary.total_items += chrom_summary.total_items;
+                    summary.bases_covered += chrom_summary.bases_covered;
+                    summary.min_val = summary.min_val.min(chrom_summary.min_val);
+                    summary.max_val = summary.max_val.max(chrom_summary.max_val);
+                    summary.sum += chrom_summary.sum;
+                    summary.sum_squares += chrom_summary.sum_squares;
+                }
+            }
+            chrom_summaries.push((copy_chrom.chrom.clone(), chrom_summary));
+
+            notify(WriteEvent::ChromFinished {
+                chrom: copy_chrom.chrom.clone(),
+                items,
+                sections: sections.len() - sections_before,
+            });
+            notify(WriteEvent::BytesWritten(file.tell()?));
+        }
+
+        let summary = summary.unwrap_or(Summary {
+            total_items: 0,
+            bases_covered: 0,
+            min_val: 0.0,
+            max_val: 0.0,
+            sum: 0.0,
+            sum_squares: 0.0,
+        });
+        let data_size = file.tell()? - pre_data;
+
+        let chrom_index_start = file.tell()?;
+        write_chrom_tree(&mut file, chrom_sizes, &chrom_ids)?;
+
+        notify(WriteEvent::IndexStarted);
+        let index_start = file.tell()?;
+        let (nodes, levels, total_sections) = get_rtreeindex(sections.into_iter(), self.options);
+        write_rtreeindex(&mut file, nodes, levels, total_sections, self.options)?;
+        notify(WriteEvent::IndexFinished {
+            sections: total_sections,
+        });
+
+        if self.cancellation.is_cancelled() {
+            return Err(BBICopyError::Cancelled);
+        }
+
+        notify(WriteEvent::ZoomsStarted);
+        let zoom_infos = zoom_levels
+            .into_iter()
+            .map(|level| {
+                let data = level.data.into_inner().map_err(|e| e.into_error())?;
+                Ok(ZoomInfo {
+                    resolution: level.resolution,
+                    data,
+                    sections: Box::new(level.sections.into_iter()),
+                })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        let (zoom_entries, zoom_reports) =
+            write_zooms(&mut file, zoom_infos, data_size, self.options)?;
+        let num_zooms = zoom_entries.len() as u16;
+        notify(WriteEvent::ZoomsFinished {
+            levels: zoom_entries.len(),
+        });
+
+        let magic = match filetype {
+            BBIFile::BigWig => BIGWIG_MAGIC,
+            BBIFile::BigBed => BIGBED_MAGIC,
+        };
+
+        file.seek(SeekFrom::Start(0))?;
+        file.write_u32::<NativeEndian>(magic)?;
+        file.write_u16::<NativeEndian>(4)?;
+        file.write_u16::<NativeEndian>(num_zooms)?;
+        file.write_u64::<NativeEndian>(chrom_index_start)?;
+        file.write_u64::<NativeEndian>(full_data_offset)?;
+        file.write_u64::<NativeEndian>(index_start)?;
+        file.write_u16::<NativeEndian>(field_count)?;
+        file.write_u16::<NativeEndian>(defined_field_count)?;
+        file.write_u64::<NativeEndian>(autosql_offset)?;
+        file.write_u64::<NativeEndian>(total_summary_offset)?;
+        file.write_u32::<NativeEndian>(uncompress_buf_size as u32)?;
+        file.write_u64::<NativeEndian>(0)?; // reserved
+
+        debug_assert!(file.tell()? == 64);
+
+        for zoom_entry in zoom_entries {
+            file.write_u32::<NativeEndian>(zoom_entry.reduction_level)?;
+            file.write_u32::<NativeEndian>(0)?;
+            file.write_u64::<NativeEndian>(zoom_entry.data_offset)?;
+            file.write_u64::<NativeEndian>(zoom_entry.index_offset)?;
+        }
+
+        file.seek(SeekFrom::Start(total_summary_offset))?;
+        file.write_u64::<NativeEndian>(summary.bases_covered)?;
+        file.write_f64::<NativeEndian>(summary.min_val)?;
+        file.write_f64::<NativeEndian>(summary.max_val)?;
+        file.write_f64::<NativeEndian>(summary.sum)?;
+        file.write_f64::<NativeEndian>(summary.sum_squares)?;
+
+        // bigWigs store the number of sections, bigBeds the number of items
+        file.seek(SeekFrom::Start(full_data_offset))?;
+        match filetype {
+            BBIFile::BigWig => file.write_u64::<NativeEndian>(total_sections)?,
+            BBIFile::BigBed => file.write_u64::<NativeEndian>(summary.total_items)?,
+        }
+        file.seek(SeekFrom::End(0))?;
+        file.write_u32::<NativeEndian>(magic)?;
+        let file_size = file.tell()?;
+
+        Ok(WriteReport {
+            summary,
+            chrom_summaries,
+            chrom_ids,
+            total_sections,
+            index_levels: levels,
+            zooms: zoom_reports,
+            uncompress_buf_size,
+            file_size,
+            scale: None,
+        })
+    }
+
+    /// Returns the zoom levels to copy, or `None` if they should be regenerated.
+    fn zoom_levels_to_copy<B: BBIRead>(
+        &self,
+        sources: &[B],
+        used_sources: &[usize],
+    ) -> Option<Vec<u32>> {
+        let source_levels: Vec<Vec<u32>> = used_sources
+            .iter()
+            .map(|&i| {
+                sources[i]
+                    .get_info()
+                    .zoom_headers
+                    .iter()
+                    .map(|z| z.reduction_level)
+                    .collect()
+            })
+            .collect();
+        match self.zooms {
+            CopyZooms::Regenerate => None,
+            CopyZooms::Auto => {
+                let first = &source_levels[0];
+                let compatible = !first.is_empty() && source_levels.iter().all(|l| l == first);
+                if compatible {
+                    Some(first.clone())
+                } else {
+                    None
+                }
+            }
+            CopyZooms::Copy => {
+                let mut levels = source_levels[0].clone();
+                levels.retain(|level| source_levels.iter().all(|l| l.contains(level)));
+                levels.sort_unstable();
+                levels.dedup();
+                Some(levels)
+            }
+        }
+    }
+}
+
+/// Renames the chromosomes of a bigWig or bigBed by rewriting only its
+/// chromosome tree: the file at `inpath` is copied to `outpath` (or modified in
+/// place if they are the same file), and a new chromosome tree is appended to
+/// it. Chromosome ids are unchanged, so no data blocks or indices are touched.
+/// Chromosomes not in `renames` keep their name.
+pub fn rename_chroms(
+    inpath: &str,
+    outpath: &str,
+    renames: &HashMap<String, String>,
+) -> Result<(), BBICopyError> {
+    // Everything is checked before the output is written, so that an invalid
+    // rename leaves no partial output (or modified input)
+    let info = read_info(&mut File::open(inpath)?)
+        .map_err(|e| BBICopyError::InvalidInput(format!("{}", e)))?;
+    if info.header.endianness != Endianness::native() {
+        return Err(BBICopyError::InvalidInput(
+            "Renaming chromosomes of a file with non-native endianness is not supported."
+                .to_owned(),
+        ));
+    }
+
+    let mut chrom_ids: HashMap<String, u32> = HashMap::new();
+    let mut chrom_sizes: HashMap<String, u32> = HashMap::new();
+    for chrom in info.chrom_info.iter() {
+        let name = renames.get(&chrom.name).unwrap_or(&chrom.name);
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(BBICopyError::InvalidInput(format!(
+                "Invalid chromosome name: `{}`",
+                name
+            )));
+        }
+        if chrom_ids.insert(name.clone(), chrom.id).is_some() {
+            return Err(BBICopyError::InvalidInput(format!(
+                "Renaming would result in duplicate chromosome {}.",
+                name
+            )));
+        }
+        chrom_sizes.insert(name.clone(), chrom.length);
+    }
+
+    // Copying a file onto itself (through another path) would truncate it
+    if !same_file(inpath, outpath)? {
+        std::fs::copy(inpath, outpath)?;
+    }
+    let mut file = OpenOptions::new().read(true).write(true).open(outpath)?;
+
+    let magic = match info.filetype {
+        BBIFile::BigWig => BIGWIG_MAGIC,
+        BBIFile::BigBed => BIGBED_MAGIC,
+    };
+    let chrom_index_start = file.seek(SeekFrom::End(0))?;
+    let mut file = BufWriter::new(file);
+    write_chrom_tree(&mut file, chrom_sizes, &chrom_ids)?;
+    file.write_u32::<NativeEndian>(magic)?;
+    // The chrom tree offset follows the magic, version, and zoom level count
+    file.seek(SeekFrom::Start(8))?;
+    file.write_u64::<NativeEndian>(chrom_index_start)?;
+    file.flush()?;
+
+    Ok(())
+}
+
+/// Whether `a` and `b` are the same file. A path that doesn't exist isn't the
+/// same as any other.
+fn same_file(a: impl AsRef<Path>, b: impl AsRef<Path>) -> io::Result<bool> {
+    match (a.as_ref().canonicalize(), b.as_ref().canonicalize()) {
+        (Ok(a), Ok(b)) => Ok(a == b),
+        (Err(e), _) | (_, Err(e)) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        (Err(e), _) | (_, Err(e)) => Err(e),
+    }
+}
+
+/// Checks that the sources can be copied from, returning their file type.
+fn validate_sources<B: BBIRead>(
+    sources: &[B],
+    chroms: &[CopyChrom],
+) -> Result<BBIFile, BBICopyError> {
+    if chroms.is_empty() {
+        return Err(BBICopyError::InvalidInput(
+            "No chromosomes to copy.".to_owned(),
+        ));
+    }
+    let mut seen = HashSet::new();
+    for chrom in chroms {
+        let source = sources.get(chrom.source).ok_or_else(|| {
+            BBICopyError::InvalidInput(format!(
+                "Invalid source index {} for {}.",
+                chrom.source, chrom.chrom
+            ))
+        })?;
+        if !source
+            .get_info()
+            .chrom_info
+            .iter()
+            .any(|c| c.name == chrom.chrom)
+        {
+            return Err(BBICopyError::InvalidInput(format!(
+                "Chromosome {} not found in source {}.",
+                chrom.chrom, chrom.source
+            )));
+        }
+        if !seen.insert(&chrom.chrom) {
+            return Err(BBICopyError::InvalidInput(format!(
+                "Chromosome {} is copied more than once.",
+                chrom.chrom
+            )));
+        }
+    }
+    let mut filetype = None;
+    for source in sources.iter() {
+        let info = source.get_info();
+        if info.header.endianness != Endianness::native() {
+            return Err(BBICopyError::InvalidInput(
+                "Copying from a file with non-native endianness is not supported.".to_owned(),
+            ));
+        }
+        match (filetype, info.filetype) {
+            (None, t) => filetype = Some(t),
+            (Some(BBIFile::BigWig), BBIFile::BigWig) | (Some(BBIFile::BigBed), BBIFile::BigBed) => {
+            }
+            _ => {
+                return Err(BBICopyError::InvalidInput(
+                    "All sources must be either bigWigs or bigBeds.".to_owned(),
+                ));
+            }
+        }
+    }
+    filetype.ok_or_else(|| BBICopyError::InvalidInput("No sources to copy from.".to_owned()))
+}
+
+fn read_autosql<B: BBIRead>(source: &mut B) -> Result<String, BBICopyError> {
+    let auto_sql_offset = source.get_info().header.auto_sql_offset;
+    if auto_sql_offset == 0 {
+        return Ok(crate::bed::autosql::BED3.to_string());
+    }
+    let mut reader = BufReader::new(source.reader());
+    reader.seek(SeekFrom::Start(auto_sql_offset))?;
+    let mut buffer = Vec::new();
+    reader.read_until(b'\0', &mut buffer)?;
+    buffer.pop();
+    String::from_utf8(buffer)
+        .map_err(|_| BBICopyError::InvalidInput("Invalid autosql: not UTF-8".to_owned()))
+}
+
+/// Gets all the leaves of the cir tree at `index_offset` for a chromosome
+fn chrom_leaves<B: BBIRead>(
+    source: &mut B,
+    index_offset: u64,
+    chrom_id: u32,
+) -> Result<Vec<LeafRange>, BBICopyError> {
+    let endianness = source.get_info().header.endianness;
+    let reader = source.reader();
+    seek_past_cir_tree_header(reader, endianness, index_offset)
+        .map_err(BBIReadError::CirTreeSearchError)?;
+    let mut leaves = vec![];
+    search_overlapping_leaves(reader, endianness, chrom_id, 0, u32::MAX, &mut |leaf| {
+        leaves.push(leaf)
+    })?;
+    Ok(leaves)
+}
+
+fn chrom_zoom_records<B: BBIRead>(
+    source: &mut B,
+    reduction_level: u32,
+    chrom_id: u32,
+) -> Result<Vec<ZoomRecord>, BBICopyError> {
+    let index_offset = source
+        .get_info()
+        .zoom_headers
+        .iter()
+        .find(|z| z.reduction_level == reduction_level)
+        .ok_or_else(|| {
+            BBICopyError::InvalidInput(format!(
+                "The source has no zoom level with a resolution of {}.",
+                reduction_level
+            ))
+        })?
+        .index_offset;
+    let leaves = chrom_leaves(source, index_offset, chrom_id)?;
+    let mut records = vec![];
+    for leaf in leaves {
+        let mut known_offset = 0;
+        records.extend(get_zoom_block_values(
+            source,
+            leaf.block,
+            &mut known_offset,
+            chrom_id,
+            0,
+            u32::MAX,
+        )?);
+    }
+    Ok(records)
+}
+
+fn read_raw_block<B: BBIRead>(source: &mut B, block: &Block) -> io::Result<Vec<u8>> {
+    let reader = source.reader();
+    reader.seek(SeekFrom::Start(block.offset))?;
+    let mut raw = vec![0u8; block.size as usize];
+    reader.read_exact(&mut raw)?;
+    Ok(raw)
+}
+
+/// Rewrites the chromosome id(s) in a decompressed data block
+fn set_chrom_id(data: &mut [u8], filetype: BBIFile, chrom_id: u32) {
+    let id = chrom_id.to_ne_bytes();
+    match filetype {
+        BBIFile::BigWig => data[0..4].copy_from_slice(&id),
+        BBIFile::BigBed => {
+            // Each entry is chrom id, start, end, then a null-terminated string
+            let mut i = 0;
+            while i + 12 <= data.len() {
+                data[i..i + 4].copy_from_slice(&id);
+                i += 12;
+                while i < data.len() && data[i] != b'\0' {
+                    i += 1;
+                }
+                i += 1;
+            }
+        }
+    }
+}
+
+fn compress(data: &[u8]) -> io::Result<Vec<u8>> {
+    use libdeflater::{CompressionLvl, Compressor};
+
+    let mut compressor = Compressor::new(CompressionLvl::default());
+    let max_sz = compressor.zlib_compress_bound(data.len());
+    let mut compressed_data = vec![0; max_sz];
+    let actual_sz = compressor
+        .zlib_compress(data, &mut compressed_data)
+        .map_err(io::Error::other)?;
+    compressed_data.truncate(actual_sz);
+    Ok(compressed_data)
+}
+
+fn add_to_summary(summary: &mut Option<Summary>, value: &Value, items: u64) {
+    let len = u64::from(value.end - value.start);
+    let val = f64::from(value.value);
+    match summary {
+        None => {
+            *summary = Some(Summary {
+                total_items: items,
+                bases_covered: len,
+                min_val: val,
+                max_val: val,
+                sum: len as f64 * val,
+                sum_squares: len as f64 * val * val,
+            })
+        }
+        Some(summary) => {
+            summary.total_items += items;
+            summary.bases_covered += len;
+            summary.min_val = summary.min_val.min(val);
+            summary.max_val = summary.max_val.max(val);
+            summary.sum += len as f64 * val;
+            summary.sum_squares += len as f64 * val * val;
+        }
+    }
+}
+
+/// Converts sorted bed entries into the coverage depth over them, the same
+/// values `BigBedWrite` summarizes.
+#[derive(Default)]
+struct Coverage {
+    ends: BinaryHeap<Reverse<u32>>,
+    pos: u32,
+}
+
+impl Coverage {
+    fn add(&mut self, start: u32, end: u32, out: &mut Vec<Value>) {
+        self.advance(start, out);
+        self.ends.push(Reverse(end));
+    }
+
+    fn finish(&mut self, out: &mut Vec<Value>) {
+        self.advance(u32::MAX, out);
+        self.pos = 0;
+    }
+
+    /// Outputs the coverage up to `to`
+    fn advance(&mut self, to: u32, out: &mut Vec<Value>) {
+        while let Some(&Reverse(end)) = self.ends.peek() {
+            if end > to {
+                break;
+            }
+            if end > self.pos {
+                out.push(Value {
+                    start: self.pos,
+                    end,
+                    value: self.ends.len() as f32,
+                });
+                self.pos = end;
+            }
+            self.ends.pop();
+        }
+        if !self.ends.is_empty() && self.pos < to {
+            out.push(Value {
+                start: self.pos,
+                end: to,
+                value: self.ends.len() as f32,
+            });
+        }
+        self.pos = to;
+    }
+}
+
+/// The zoom records for one zoom level of the output
+struct ZoomLevelOut {
+    resolution: u32,
+    data: BufWriter<File>,
+    sections: Vec<Section>,
+    /// The records for the current section
+    records: Vec<ZoomRecord>,
+    /// The record being built when regenerating zooms
+    live: Option<ZoomRecord>,
+}
+
+impl ZoomLevelOut {
+    fn add_value(
+        &mut self,
+        chrom: u32,
+        value: Value,
+        options: BBIWriteOptions,
+        uncompress_buf_size: &mut usize,
+    ) -> io::Result<()> {
+        let val = f64::from(value.value);
+        let mut start = value.start;
+        while start < value.end {
+            if let Some(live) = &self.live {
+                if start >= live.start.saturating_add(self.resolution) {
+                    let record = self.live.take().unwrap();
+                    self.push_record(record, options, uncompress_buf_size)?;
+                }
+            }
+            let live = self.live.get_or_insert(ZoomRecord {
+                chrom,
+                start,
+                end: start,
+                summary: Summary {
+                    total_items: 0,
+                    bases_covered: 0,
+                    min_val: val,
+                    max_val: val,
+                    sum: 0.0,
+                    sum_squares: 0.0,
+                },
+            });
+            let end = live.start.saturating_add(self.resolution).min(value.end);
+            let len = f64::from(end - start);
+            live.end = end;
+            live.summary.total_items += 1;
+            live.summary.bases_covered += u64::from(end - start);
+            live.summary.min_val = live.summary.min_val.min(val);
+            live.summary.max_val = live.summary.max_val.max(val);
+            live.summary.sum += len * val;
+            live.summary.sum_squares += len * val * val;
+            start = end;
+        }
+        Ok(())
+    }
+
+    fn push_record(
+        &mut self,
+        record: ZoomRecord,
+        options: BBIWriteOptions,
+        uncompress_buf_size: &mut usize,
+    ) -> io::Result<()> {
+        self.records.push(record);
+        if self.records.len() >= options.items_per_slot as usize {
+            self.flush_section(options, uncompress_buf_size)?;
+        }
+        Ok(())
+    }
+
+    /// Writes out any remaining records for the current chromosome, since
+    /// sections can't span chromosomes
+    fn finish_chrom(
+        &mut self,
+        options: BBIWriteOptions,
+        uncompress_buf_size: &mut usize,
+    ) -> io::Result<()> {
+        if let Some(record) = self.live.take() {
+            self.records.push(record);
+        }
+        self.flush_section(options, uncompress_buf_size)
+    }
+
+    fn flush_section(
+        &mut self,
+        options: BBIWriteOptions,
+        uncompress_buf_size: &mut usize,
+    ) -> io::Result<()> {
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        let records = std::mem::replace(
+            &mut self.records,
+            Vec::with_capacity(options.items_per_slot as usize),
+        );
+        let (section, buf_size) = block_on(encode_zoom_section(options.compress, records))?;
+        *uncompress_buf_size = (*uncompress_buf_size).max(buf_size);
+        self.data.write_all(&section.data)?;
+        self.sections.push(Section {
+            chrom: section.chrom,
+            start: section.start,
+            end: section.end,
+            offset: 0,
+            size: section.data.len() as u64,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage() {
+        let mut coverage = Coverage::default();
+        let mut out = vec![];
+        coverage.add(0, 10, &mut out);
+        coverage.add(5, 15, &mut out);
+        coverage.add(5, 8, &mut out);
+        coverage.add(20, 30, &mut out);
+        coverage.finish(&mut out);
+        let out: Vec<(u32, u32, f32)> = out.iter().map(|v| (v.start, v.end, v.value)).collect();
+        assert_eq!(
+            out,
+            vec![
+                (0, 5, 1.0),
+                (5, 8, 3.0),
+                (8, 10, 2.0),
+                (10, 15, 1.0),
+                (20, 30, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_chrom_id() {
+        let mut data = vec![];
+        for (start, rest) in [(1u32, "a"), (5, ""), (9, "xyz")] {
+            data.extend_from_slice(&7u32.to_ne_bytes());
+            data.extend_from_slice(&start.to_ne_bytes());
+            data.extend_from_slice(&(start + 1).to_ne_bytes());
+            data.extend_from_slice(rest.as_bytes());
+            data.push(b'\0');
+        }
+        set_chrom_id(&mut data, BBIFile::BigBed, 2);
+        let entries = parse_block_entries(&data[..], Endianness::native(), 2, 0, u32::MAX).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].rest, "xyz");
+    }
+}
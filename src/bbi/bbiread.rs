@@ -1,4 +1,5 @@
 use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::sync::Arc;
 use std::vec::Vec;
 
 use byteordered::Endianness;
@@ -10,6 +11,7 @@ use crate::bbi::{
     CIR_TREE_MAGIC,
 };
 use crate::bed::bedparser::BedValueError;
+use crate::utils::chromalias::ChromAliases;
 use crate::utils::reopen::SeekableRead;
 
 #[derive(Copy, Clone, Debug)]
@@ -59,6 +61,8 @@ pub struct BBIFileInfo {
     pub header: BBIHeader,
     pub zoom_headers: Vec<ZoomHeader>,
     pub chrom_info: Vec<ChromInfo>,
+    /// If set, chromosomes can be queried by any of their aliases
+    chrom_aliases: Option<Arc<ChromAliases>>,
 }
 
 pub(crate) struct ChromIdNotFound(pub(crate) String);
@@ -71,13 +75,33 @@ impl From<ChromIdNotFound> for BBIReadError {
 
 impl BBIFileInfo {
     pub(crate) fn chrom_id(&self, chrom_name: &str) -> Result<u32, ChromIdNotFound> {
-        let chrom_info = &self.chrom_info;
-        let chrom = chrom_info.iter().find(|&x| x.name == chrom_name);
-        match chrom {
+        match self.find_chrom(chrom_name) {
             Some(c) => Ok(c.id),
             None => Err(ChromIdNotFound(chrom_name.to_owned())),
         }
     }
+
+    /// The aliases chromosomes can also be queried by, if set.
+    pub fn chrom_aliases(&self) -> Option<&Arc<ChromAliases>> {
+        self.chrom_aliases.as_ref()
+    }
+
+    /// Sets (or clears) the aliases chromosomes can also be queried by.
+    pub fn set_chrom_aliases(&mut self, chrom_aliases: Option<Arc<ChromAliases>>) {
+        self.chrom_aliases = chrom_aliases;
+    }
+
+    /// Finds the chromosome with the given name. If chromosome aliases are set,
+    /// `chrom_name` can also be an alias of the name in this file.
+    pub fn find_chrom(&self, chrom_name: &str) -> Option<&ChromInfo> {
+        let find = |name: &str| self.chrom_info.iter().find(|&x| x.name == name);
+        match &self.chrom_aliases {
+            None => find(chrom_name),
+            Some(aliases) => aliases
+                .resolve(chrom_name, |name| find(name).is_some())
+                .and_then(find),
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -136,59 +160,18 @@ pub trait BBIRead {
         end: u32,
     ) -> Result<Vec<Block>, CirTreeSearchError> {
         // TODO: Move anything relying on self out to separate method
-        let chrom_ix = {
-            let chrom_info = &self.get_info().chrom_info;
-            let chrom = chrom_info.iter().find(|&x| x.name == chrom_name);
-            match chrom {
-                Some(c) => c.id,
-                None => {
-                    return Err(CirTreeSearchError::InvalidChromosome(
-                        chrom_name.to_string(),
-                    ));
-                }
+        let chrom_ix = match self.get_info().find_chrom(chrom_name) {
+            Some(c) => c.id,
+            None => {
+                return Err(CirTreeSearchError::InvalidChromosome(
+                    chrom_name.to_string(),
+                ));
             }
         };
 
         let endianness = self.get_info().header.endianness;
         let mut file = self.reader();
-        file.seek(SeekFrom::Start(at))?;
-        let mut header_data = BytesMut::zeroed(48);
-        file.read_exact(&mut header_data)?;
-
-        match endianness {
-            Endianness::Big => {
-                let magic = header_data.get_u32();
-                if magic != CIR_TREE_MAGIC {
-                    return Err(CirTreeSearchError::UnknownMagic);
-                }
-
-                let _blocksize = header_data.get_u32();
-                let _item_count = header_data.get_u64();
-                let _start_chrom_idx = header_data.get_u32();
-                let _start_base = header_data.get_u32();
-                let _end_chrom_idx = header_data.get_u32();
-                let _end_base = header_data.get_u32();
-                let _end_file_offset = header_data.get_u64();
-                let _item_per_slot = header_data.get_u32();
-                let _reserved = header_data.get_u32();
-            }
-            Endianness::Little => {
-                let magic = header_data.get_u32_le();
-                if magic != CIR_TREE_MAGIC {
-                    return Err(CirTreeSearchError::UnknownMagic);
-                }
-
-                let _blocksize = header_data.get_u32_le();
-                let _item_count = header_data.get_u64_le();
-                let _start_chrom_idx = header_data.get_u32_le();
-                let _start_base = header_data.get_u32_le();
-                let _end_chrom_idx = header_data.get_u32_le();
-                let _end_base = header_data.get_u32_le();
-                let _end_file_offset = header_data.get_u64_le();
-                let _item_per_slot = header_data.get_u32_le();
-                let _reserved = header_data.get_u32_le();
-            }
-        };
+        seek_past_cir_tree_header(&mut file, endianness, at)?;
 
         // TODO: could do some optimization here to check if our interval overlaps with any data
 
@@ -209,6 +192,75 @@ pub trait BBIRead {
     }
 }
 
+/// Seeks to `at` and reads (and checks) the header of a cir tree, leaving the
+/// reader at the root node.
+pub(crate) fn seek_past_cir_tree_header<R: SeekableRead>(
+    file: &mut R,
+    endianness: Endianness,
+    at: u64,
+) -> Result<(), CirTreeSearchError> {
+    file.seek(SeekFrom::Start(at))?;
+    let mut header_data = BytesMut::zeroed(48);
+    file.read_exact(&mut header_data)?;
+
+    match endianness {
+        Endianness::Big => {
+            let magic = header_data.get_u32();
+            if magic != CIR_TREE_MAGIC {
+                return Err(CirTreeSearchError::UnknownMagic);
+            }
+
+            let _blocksize = header_data.get_u32();
+            let _item_count = header_data.get_u64();
+            let _start_chrom_idx = header_data.get_u32();
+            let _start_base = header_data.get_u32();
+            let _end_chrom_idx = header_data.get_u32();
+            let _end_base = header_data.get_u32();
+            let _end_file_offset = header_data.get_u64();
+            let _item_per_slot = header_data.get_u32();
+            let _reserved = header_data.get_u32();
+        }
+        Endianness::Little => {
+            let magic = header_data.get_u32_le();
+            if magic != CIR_TREE_MAGIC {
+                return Err(CirTreeSearchError::UnknownMagic);
+            }
+
+            let _blocksize = header_data.get_u32_le();
+            let _item_count = header_data.get_u64_le();
+            let _start_chrom_idx = header_data.get_u32_le();
+            let _start_base = header_data.get_u32_le();
+            let _end_chrom_idx = header_data.get_u32_le();
+            let _end_base = header_data.get_u32_le();
+            let _end_file_offset = header_data.get_u64_le();
+            let _item_per_slot = header_data.get_u32_le();
+            let _reserved = header_data.get_u32_le();
+        }
+    };
+
+    Ok(())
+}
+
+/// Reads the magic at the start of `file`, returning whether it is a bigWig or
+/// a bigBed, or `None` if it is neither (including if it is too short).
+pub fn read_file_type<R: Read>(file: &mut R) -> io::Result<Option<BBIFile>> {
+    let mut magic = Vec::with_capacity(4);
+    file.take(4).read_to_end(&mut magic)?;
+    let magic = match <[u8; 4]>::try_from(magic) {
+        Ok(magic) => u32::from_be_bytes(magic),
+        Err(_) => return Ok(None),
+    };
+    Ok(match magic {
+        _ if magic == BIGWIG_MAGIC.to_le() || magic == BIGWIG_MAGIC.to_be() => {
+            Some(BBIFile::BigWig)
+        }
+        _ if magic == BIGBED_MAGIC.to_le() || magic == BIGBED_MAGIC.to_be() => {
+            Some(BBIFile::BigBed)
+        }
+        _ => None,
+    })
+}
+
 pub(crate) fn read_info<R: SeekableRead>(
     mut file: &mut R,
 ) -> Result<BBIFileInfo, BBIFileReadInfoError> {
@@ -355,6 +407,7 @@ pub(crate) fn read_info<R: SeekableRead>(
         header,
         zoom_headers,
         chrom_info,
+        chrom_aliases: None,
     };
 
     Ok(info)
@@ -516,6 +569,31 @@ pub(crate) fn search_overlapping_blocks<R: SeekableRead>(
     start: u32,
     end: u32,
     blocks: &mut Vec<Block>,
+) -> io::Result<()> {
+    search_overlapping_leaves(file, endianness, chrom_ix, start, end, &mut |leaf| {
+        blocks.push(leaf.block)
+    })
+}
+
+/// A leaf of the cir tree: a data block and the range of data in it
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct LeafRange {
+    pub(crate) start_chrom_ix: u32,
+    pub(crate) start_base: u32,
+    pub(crate) end_chrom_ix: u32,
+    pub(crate) end_base: u32,
+    pub(crate) block: Block,
+}
+
+/// Like `search_overlapping_blocks`, but passes the full leaf info for each
+/// overlapping block to `on_leaf`.
+pub(crate) fn search_overlapping_leaves<R: SeekableRead>(
+    file: &mut R,
+    endianness: Endianness,
+    chrom_ix: u32,
+    start: u32,
+    end: u32,
+    on_leaf: &mut dyn FnMut(LeafRange),
 ) -> io::Result<()> {
     let mut header_data = BytesMut::zeroed(4);
     file.read_exact(&mut header_data)?;
@@ -603,9 +681,15 @@ pub(crate) fn search_overlapping_blocks<R: SeekableRead>(
                 end_base,
             );
             if block_overlaps {
-                blocks.push(Block {
-                    offset: data_offset,
-                    size: data_size,
+                on_leaf(LeafRange {
+                    start_chrom_ix,
+                    start_base,
+                    end_chrom_ix,
+                    end_base,
+                    block: Block {
+                        offset: data_offset,
+                        size: data_size,
+                    },
                 });
             }
         }
@@ -675,7 +759,7 @@ pub(crate) fn search_overlapping_blocks<R: SeekableRead>(
         }
         for childblock in childblocks {
             file.seek(SeekFrom::Start(childblock))?;
-            search_overlapping_blocks(file, endianness, chrom_ix, start, end, blocks)?;
+            search_overlapping_leaves(file, endianness, chrom_ix, start, end, on_leaf)?;
         }
     }
     Ok(())
@@ -687,8 +771,6 @@ pub(crate) fn get_block_data<B: BBIRead>(
     block: &Block,
     known_offset: u64,
 ) -> io::Result<Cursor<Vec<u8>>> {
-    use libdeflater::Decompressor;
-
     let uncompress_buf_size = bbifile.get_info().header.uncompress_buf_size as usize;
     let file = bbifile.reader();
 
@@ -700,21 +782,31 @@ pub(crate) fn get_block_data<B: BBIRead>(
 
     let mut raw_data = vec![0u8; block.size as usize];
     file.read_exact(&mut raw_data)?;
-    let block_data: Vec<u8> = if uncompress_buf_size > 0 {
-        let mut decompressor = Decompressor::new();
-        let mut outbuf = vec![0; uncompress_buf_size];
-        let decompressed = decompressor
-            .zlib_decompress(&raw_data, &mut outbuf)
-            .unwrap();
-        outbuf.truncate(decompressed);
-        outbuf
-    } else {
-        raw_data
-    };
+    let block_data = decompress_block(raw_data, uncompress_buf_size)?;
 
     Ok(Cursor::new(block_data))
 }
 
+/// Decompresses the raw data of a block. If `uncompress_buf_size` is `0`, the
+/// data is not compressed and is returned as-is.
+pub(crate) fn decompress_block(
+    raw_data: Vec<u8>,
+    uncompress_buf_size: usize,
+) -> io::Result<Vec<u8>> {
+    use libdeflater::Decompressor;
+
+    if uncompress_buf_size == 0 {
+        return Ok(raw_data);
+    }
+    let mut decompressor = Decompressor::new();
+    let mut outbuf = vec![0; uncompress_buf_size];
+    let decompressed = decompressor
+        .zlib_decompress(&raw_data, &mut outbuf)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+    outbuf.truncate(decompressed);
+    Ok(outbuf)
+}
+
 pub(crate) fn get_zoom_block_values<B: BBIRead>(
     bbifile: &mut B,
     block: Block,
@@ -731,7 +823,7 @@ pub(crate) fn get_zoom_block_values<B: BBIRead>(
 
     let endianness = bbifile.get_info().header.endianness;
 
-    let mut bytes = BytesMut::zeroed(itemcount * 64);
+    let mut bytes = BytesMut::zeroed(itemcount * 32);
     data_mut.read_exact(&mut bytes)?;
 
     match endianness {
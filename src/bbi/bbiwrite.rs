@@ -1,7 +1,10 @@
-use std::collections::{BTreeMap, HashMap};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs::File;
 use std::io::{self, BufReader, BufWriter, Seek, SeekFrom, Write};
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use byteorder::{NativeEndian, WriteBytesExt};
 use thiserror::Error;
@@ -24,9 +27,9 @@ use crate::utils::tempfilebuffer::{TempFileBuffer, TempFileBufferWriter};
 use crate::bbi::{Summary, ZoomHeader, ZoomRecord, CHROM_TREE_MAGIC, CIR_TREE_MAGIC};
 
 pub(crate) struct ZoomInfo {
-    resolution: u32,
-    data: File,
-    sections: Box<dyn Iterator<Item = Section>>,
+    pub(crate) resolution: u32,
+    pub(crate) data: File,
+    pub(crate) sections: Box<dyn Iterator<Item = Section>>,
 }
 
 #[derive(Debug)]
@@ -94,6 +97,60 @@ impl Default for BBIWriteOptions {
     }
 }
 
+/// Progress events emitted while writing a bigWig or bigBed. See [`WriteObserver`].
+#[derive(Clone, Debug)]
+pub enum WriteEvent {
+    /// Processing of a new chromosome has started.
+    ChromStarted {
+        chrom: String,
+        length: u32,
+    },
+    /// All the data for a chromosome has been processed and written.
+    ChromFinished {
+        chrom: String,
+        items: u64,
+        sections: usize,
+    },
+    /// The total number of bytes in the output file so far.
+    BytesWritten(u64),
+    /// The main data index is being built and written.
+    IndexStarted,
+    IndexFinished {
+        sections: u64,
+    },
+    /// The zoom levels (and their indices) are being written.
+    ZoomsStarted,
+    ZoomsFinished {
+        levels: usize,
+    },
+}
+
+/// Receives [`WriteEvent`]s during a write. This is called from the thread
+/// driving the write, so implementations should return quickly.
+pub trait WriteObserver: Send + Sync {
+    fn on_event(&self, event: WriteEvent);
+}
+
+/// A handle that can be used to stop an in-progress write. Once cancelled,
+/// the write returns [`ProcessChromError::Cancelled`] and any partially
+/// written output file is removed.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum ProcessChromError<SourceError> {
     #[error("{}", .0)]
@@ -104,6 +161,8 @@ pub enum ProcessChromError<SourceError> {
     IoError(#[from] io::Error),
     #[error("SourceError")]
     SourceError(SourceError),
+    #[error("The write was cancelled")]
+    Cancelled,
 }
 
 impl<W, S> From<io::IntoInnerError<W>> for ProcessChromError<S> {
@@ -127,6 +186,7 @@ pub(crate) type ChromProcessingInputSectionChannel = futures::channel::mpsc::Sen
 pub(crate) struct ChromProcessingInput {
     pub(crate) zooms_channels: Vec<ChromProcessingInputSectionChannel>,
     pub(crate) ftx: ChromProcessingInputSectionChannel,
+    pub(crate) cancellation: CancellationToken,
 }
 
 pub struct ChromProcessingOutput<SourceError> {
@@ -449,14 +509,28 @@ pub(crate) fn write_zooms(
     zooms: Vec<ZoomInfo>,
     data_size: u64,
     options: BBIWriteOptions,
-) -> io::Result<Vec<ZoomHeader>> {
+) -> io::Result<(Vec<ZoomHeader>, Vec<ZoomLevelReport>)> {
     let mut zoom_entries: Vec<ZoomHeader> = vec![];
+    let mut zoom_reports: Vec<ZoomLevelReport> = vec![];
     let mut zoom_count = 0;
     let mut last_zoom_section_count = u64::max_value();
     for zoom in zooms {
+        if zoom_count >= options.max_zooms {
+            zoom_reports.push(ZoomLevelReport {
+                resolution: zoom.resolution,
+                sections: 0,
+                status: ZoomLevelStatus::SkippedMaxZooms,
+            });
+            continue;
+        }
         let mut zoom_file = zoom.data;
         let zoom_size = zoom_file.seek(SeekFrom::End(0))?;
         if zoom_size > (data_size / 2) {
+            zoom_reports.push(ZoomLevelReport {
+                resolution: zoom.resolution,
+                sections: 0,
+                status: ZoomLevelStatus::SkippedTooLarge,
+            });
             continue;
         }
         let zoom_data_offset = file.tell()?;
@@ -471,6 +545,11 @@ pub(crate) fn write_zooms(
 
         let (nodes, levels, total_sections) = get_rtreeindex(sections_iter, options);
         if last_zoom_section_count <= total_sections {
+            zoom_reports.push(ZoomLevelReport {
+                resolution: zoom.resolution,
+                sections: total_sections,
+                status: ZoomLevelStatus::SkippedNoReduction,
+            });
             continue;
         }
         last_zoom_section_count = total_sections;
@@ -488,14 +567,75 @@ pub(crate) fn write_zooms(
             data_offset: zoom_data_offset,
             index_offset: zoom_index_offset,
         });
+        zoom_reports.push(ZoomLevelReport {
+            resolution: zoom.resolution,
+            sections: total_sections,
+            status: ZoomLevelStatus::Written,
+        });
 
         zoom_count += 1;
-        if zoom_count >= options.max_zooms {
-            break;
-        }
     }
 
-    Ok(zoom_entries)
+    Ok((zoom_entries, zoom_reports))
+}
+
+/// Whether a candidate zoom level was written, and if not, why
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ZoomLevelStatus {
+    Written,
+    /// The zoom data was more than half the size of the main data
+    SkippedTooLarge,
+    /// The zoom level had at least as many sections as the previous level
+    SkippedNoReduction,
+    /// `BBIWriteOptions::max_zooms` levels were already written
+    SkippedMaxZooms,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ZoomLevelReport {
+    pub resolution: u32,
+    /// The number of index sections (zero if this level was skipped before indexing)
+    pub sections: u64,
+    pub status: ZoomLevelStatus,
+}
+
+/// Information gathered while writing a bigWig or bigBed, returned from
+/// `BigWigWrite::write` and `BigBedWrite::write`.
+#[derive(Clone, Debug)]
+pub struct WriteReport {
+    /// The total summary, as stored in the file header
+    pub summary: Summary,
+    /// The summary of each chromosome, in the order they were written
+    pub chrom_summaries: Vec<(String, Summary)>,
+    /// The id assigned to each chromosome in the chrom tree
+    pub chrom_ids: HashMap<String, u32>,
+    /// The number of data sections in the main index
+    pub total_sections: u64,
+    /// The number of non-leaf levels in the main index
+    pub index_levels: usize,
+    /// Every candidate zoom level, including those that were skipped
+    pub zooms: Vec<ZoomLevelReport>,
+    /// The maximum uncompressed size of any data or zoom section (0 if uncompressed)
+    pub uncompress_buf_size: usize,
+    /// The final size of the file, in bytes
+    pub file_size: u64,
+    /// The scaling applied to the values, if they were normalized (see
+    /// `normalize::normalize`)
+    pub scale: Option<ValueScale>,
+}
+
+/// A linear transformation of the values of a bigWig: each value is written as
+/// `value * factor + offset`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ValueScale {
+    pub factor: f64,
+    pub offset: f64,
+}
+
+impl ValueScale {
+    pub fn apply(&self, value: f32) -> f32 {
+        (f64::from(value) * self.factor + self.offset) as f32
+    }
 }
 
 /// Potential states encountered when reading `ChromData`
@@ -520,11 +660,43 @@ pub trait ChromData<E: From<io::Error>>: Sized {
     ) -> Result<ChromDataState<<Self::Output as ChromValues>::Error>, E>;
 }
 
+/// The chromosomes a `ChromData` has started reading, ahead of the one that is
+/// currently being written, so that several are processed in parallel.
+pub(crate) struct QueuedReads<Error, E> {
+    queue: VecDeque<Result<ChromDataState<Error>, E>>,
+}
+
+impl<Error, E> QueuedReads<Error, E> {
+    pub(crate) fn new() -> Self {
+        QueuedReads {
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Starts chromosomes with `begin_next` until 5 are queued (or there are
+    /// no more), then returns the first.
+    pub(crate) fn advance(
+        &mut self,
+        mut begin_next: impl FnMut() -> Result<ChromDataState<Error>, E>,
+    ) -> Result<ChromDataState<Error>, E> {
+        while self.queue.len() < (4 + 1)
+            && matches!(
+                self.queue.back(),
+                None | Some(Ok(ChromDataState::NewChrom(..)))
+            )
+        {
+            self.queue.push_back(begin_next());
+        }
+        self.queue.pop_front().unwrap()
+    }
+}
+
 pub struct ChromProcessingFnOutput<Error>(
     pub(crate) WriteSummaryFuture<Error>,
     pub(crate) ChromProcessingOutput<Error>,
 );
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn write_vals<
     Values: ChromValues,
     V: ChromData<ProcessChromError<Values::Error>, Output = Values>,
@@ -537,6 +709,8 @@ pub(crate) async fn write_vals<
     process_chrom: G,
     mut pool: ThreadPool,
     chrom_sizes: HashMap<String, u32>,
+    observer: Option<&dyn WriteObserver>,
+    cancellation: CancellationToken,
 ) -> Result<
     (
         IdMap,
@@ -545,6 +719,7 @@ pub(crate) async fn write_vals<
         Box<dyn Iterator<Item = Section> + 'static>,
         Vec<ZoomInfo>,
         usize,
+        Vec<(String, Summary)>,
     ),
     ProcessChromError<Values::Error>,
 > {
@@ -571,16 +746,29 @@ pub(crate) async fn write_vals<
     let mut raw_file = file.into_inner()?;
 
     let mut summary: Option<Summary> = None;
+    let mut chrom_summaries: Vec<(String, Summary)> = vec![];
     let mut max_uncompressed_buf_size = 0;
 
     let mut chrom_ids = IdMap::default();
 
+    // Chromosomes are returned from `ChromData::advance` in the order they were started,
+    // so we keep track of the names here to match them up with their results
+    let started_chroms: RefCell<VecDeque<String>> = RefCell::new(VecDeque::new());
+    let notify = |event: WriteEvent| {
+        if let Some(observer) = observer {
+            observer.on_event(event);
+        }
+    };
+
     let mut do_read = |chrom: String,
                        data: _|
      -> Result<
         ChromProcessingFnOutput<<Values as ChromValues>::Error>,
         ProcessChromError<_>,
     > {
+        if cancellation.is_cancelled() {
+            return Err(ProcessChromError::Cancelled);
+        }
         let length = match chrom_sizes.get(&chrom) {
             Some(length) => *length,
             None => {
@@ -592,6 +780,11 @@ pub(crate) async fn write_vals<
         };
         // Make a new id for the chromosome
         let chrom_id = chrom_ids.get_id(&chrom);
+        notify(WriteEvent::ChromStarted {
+            chrom: chrom.clone(),
+            length,
+        });
+        started_chroms.borrow_mut().push_back(chrom.clone());
 
         // This converts a ChromValues (streaming iterator) to a (WriteSummaryFuture, ChromProcessingOutput).
         // This is a separate function so this can techincally be run for mulitple chromosomes simulatenously.
@@ -606,7 +799,8 @@ pub(crate) async fn write_vals<
         //   All of this is done for zoom sections too.
         //
         // The futures that are returned are only handles to remote futures that are spawned immediately on `pool`.
-        let (procesing_input, processing_output) = setup_channels(&mut pool, options)?;
+        let (procesing_input, processing_output) =
+            setup_channels(&mut pool, options, cancellation.clone())?;
 
         let (f_remote, f_handle) = process_chrom(
             procesing_input,
@@ -653,11 +847,25 @@ pub(crate) async fn write_vals<
                     Ok(f) => f,
                     Err(e) => return Err(e),
                 };
-                let (chrom_summary, (_num_sections, uncompressed_buf_size)) = joined_future;
+                let (chrom_summary, (num_sections, uncompressed_buf_size)) = joined_future;
                 max_uncompressed_buf_size = max_uncompressed_buf_size.max(uncompressed_buf_size);
                 section_iter.push(sections.into_iter());
                 raw_file = data.await_real_file();
 
+                let chrom = started_chroms
+                    .borrow_mut()
+                    .pop_front()
+                    .expect("Internal error. (Chrom not started).");
+                notify(WriteEvent::ChromFinished {
+                    chrom: chrom.clone(),
+                    items: chrom_summary.total_items,
+                    sections: num_sections,
+                });
+                chrom_summaries.push((chrom, chrom_summary));
+                if observer.is_some() {
+                    notify(WriteEvent::BytesWritten(raw_file.tell()?));
+                }
+
                 for TempZoomInfo {
                     resolution,
                     data_write_future,
@@ -692,6 +900,9 @@ pub(crate) async fn write_vals<
             ChromDataState::Finished => break chrom_ids,
             ChromDataState::Error(err) => return Err(ProcessChromError::SourceError(err)),
         }
+        if cancellation.is_cancelled() {
+            return Err(ProcessChromError::Cancelled);
+        }
     };
 
     let summary_complete = summary.unwrap_or(Summary {
@@ -726,6 +937,7 @@ pub(crate) async fn write_vals<
         section_iter,
         zoom_infos,
         max_uncompressed_buf_size,
+        chrom_summaries,
     ))
 }
 
@@ -761,6 +973,7 @@ async fn write_data<W: Write, SourceError: Send>(
 pub(crate) fn setup_channels<SourceError: Send + 'static>(
     pool: &mut ThreadPool,
     options: BBIWriteOptions,
+    cancellation: CancellationToken,
 ) -> io::Result<(ChromProcessingInput, ChromProcessingOutput<SourceError>)> {
     let (ftx, frx) = channel(options.channel_size);
 
@@ -802,6 +1015,7 @@ pub(crate) fn setup_channels<SourceError: Send + 'static>(
         ChromProcessingInput {
             zooms_channels,
             ftx,
+            cancellation,
         },
         ChromProcessingOutput {
             sections: section_receiver,
@@ -7,11 +7,11 @@
 //! `BedParserParallelStreamingIterator` is a more complicated wrapper that will queue up
 //! to 4 extra chromosomes to be processed concurrently.
 
-use std::collections::VecDeque;
 use std::fs::File;
 use std::io::{self, BufReader, Seek, SeekFrom};
 use std::path::PathBuf;
 
+use crate::bbiwrite::QueuedReads;
 use crate::bed::bedparser::{
     BedChromData, BedFileStream, BedParser, BedValueError, Parser, StateValue, StreamingBedValues,
 };
@@ -76,7 +76,7 @@ pub struct BedParserParallelStreamingIterator<V, O: ChromValues, E> {
     parse_fn: Parser<V>,
     path: PathBuf,
 
-    queued_reads: VecDeque<Result<ChromDataState<<O as ChromValues>::Error>, E>>,
+    queued_reads: QueuedReads<<O as ChromValues>::Error, E>,
 }
 
 impl<V, O: ChromValues, E> BedParserParallelStreamingIterator<V, O, E> {
@@ -98,7 +98,7 @@ impl<V, O: ChromValues, E> BedParserParallelStreamingIterator<V, O, E> {
             parse_fn,
             path,
 
-            queued_reads: VecDeque::new(),
+            queued_reads: QueuedReads::new(),
         }
     }
 }
@@ -117,30 +117,30 @@ impl<V, E: From<io::Error>> ChromData<E>
         &mut self,
         do_read: &mut F,
     ) -> Result<ChromDataState<<Self::Output as ChromValues>::Error>, E> {
-        let mut begin_next = |_self: &mut Self| -> Result<_, E> {
-            let curr = match _self.chrom_indices.pop() {
+        let begin_next = || -> Result<_, E> {
+            let curr = match self.chrom_indices.pop() {
                 Some(c) => c,
                 None => {
                     return Ok(ChromDataState::<<Self::Output as ChromValues>::Error>::Finished);
                 }
             };
 
-            let mut file = match File::open(&_self.path) {
+            let mut file = match File::open(&self.path) {
                 Ok(f) => f,
                 Err(err) => return Ok(ChromDataState::Error(err.into())),
             };
             file.seek(SeekFrom::Start(curr.0))?;
             let mut parser = BedParser::new(BedFileStream {
                 bed: StreamingLineReader::new(BufReader::new(file)),
-                parse: _self.parse_fn,
+                parse: self.parse_fn,
             });
 
             Ok(match parser.next_chrom() {
                 Some(Ok((chrom, group))) => {
-                    let last = _self.last_chrom.replace(chrom.clone());
+                    let last = self.last_chrom.replace(chrom.clone());
                     if let Some(c) = last {
                         // TODO: test this correctly fails
-                        if !_self.allow_out_of_order_chroms && c >= chrom {
+                        if !self.allow_out_of_order_chroms && c >= chrom {
                             return Ok(ChromDataState::Error(BedValueError::InvalidInput("Input bedGraph not sorted by chromosome. Sort with `sort -k1,1 -k2,2n`.".to_string())));
                         }
                     }
@@ -156,16 +156,7 @@ impl<V, E: From<io::Error>> ChromData<E>
             })
         };
 
-        while self.queued_reads.len() < (4 + 1)
-            && matches!(
-                self.queued_reads.back(),
-                None | Some(Ok(ChromDataState::NewChrom(..)))
-            )
-        {
-            let next = begin_next(self);
-            self.queued_reads.push_back(next);
-        }
-        self.queued_reads.pop_front().unwrap()
+        self.queued_reads.advance(begin_next)
     }
 }
 
@@ -204,7 +195,7 @@ mod tests {
 
     use super::*;
     use crate::bed::bedparser::parse_bedgraph;
-    use crate::{BBIWriteOptions, ProcessChromError};
+    use crate::{BBIWriteOptions, CancellationToken, ProcessChromError};
     use std::fs::File;
     use std::io;
     use std::path::PathBuf;
@@ -260,7 +251,7 @@ mod tests {
             let chrom_id = chrom_ids.get_id(&chrom);
 
             let (procesing_input, processing_output) =
-                crate::bbiwrite::setup_channels(&mut pool, options)?;
+                crate::bbiwrite::setup_channels(&mut pool, options, CancellationToken::new())?;
 
             let (f_remote, f_handle) = crate::BigWigWrite::process_chrom(
                 procesing_input,
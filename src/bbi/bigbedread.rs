@@ -3,7 +3,7 @@ use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
 use std::vec::Vec;
 
-use byteordered::ByteOrdered;
+use byteordered::{ByteOrdered, Endianness};
 use thiserror::Error;
 
 use crate::bbi::{BBIFile, BedEntry, ZoomRecord};
@@ -186,6 +186,16 @@ where
         Ok(BigBedRead { info, read })
     }
 
+    /// Does *not* check if the passed `R` matches the provided info (including if the `R` is a bigBed at all!)
+    pub fn with_info(info: BBIFileInfo, read: R) -> Self {
+        BigBedRead { info, read }
+    }
+
+    /// Gets a reference to the inner `R` type, in order to access any info
+    pub fn inner_read(&self) -> &R {
+        &self.read
+    }
+
     /// Reads the autosql from this bigBed
     pub fn autosql(&mut self) -> Result<String, BBIReadError> {
         let auto_sql_offset = self.info.header.auto_sql_offset;
@@ -211,13 +221,7 @@ where
     ) -> Result<impl Iterator<Item = Result<BedEntry, BBIReadError>> + 'a, BBIReadError> {
         let blocks = self.get_overlapping_blocks(chrom_name, start, end)?;
         // TODO: this is only for asserting that the chrom is what we expect
-        let chrom_ix = self
-            .get_info()
-            .chrom_info
-            .iter()
-            .find(|&x| x.name == chrom_name)
-            .unwrap()
-            .id;
+        let chrom_ix = self.info.chrom_id(chrom_name)?;
         Ok(IntervalIter {
             r: std::marker::PhantomData,
             bigbed: self,
@@ -241,13 +245,7 @@ where
     ) -> Result<impl Iterator<Item = Result<BedEntry, BBIReadError>>, BBIReadError> {
         let blocks = self.get_overlapping_blocks(chrom_name, start, end)?;
         // TODO: this is only for asserting that the chrom is what we expect
-        let chrom_ix = self
-            .get_info()
-            .chrom_info
-            .iter()
-            .find(|&x| x.name == chrom_name)
-            .unwrap()
-            .id;
+        let chrom_ix = self.info.chrom_id(chrom_name)?;
         Ok(IntervalIter {
             r: std::marker::PhantomData,
             bigbed: self,
@@ -305,7 +303,26 @@ fn get_block_entries<R: SeekableRead>(
     end: u32,
 ) -> Result<std::vec::IntoIter<BedEntry>, BBIReadError> {
     let block_data_mut = get_block_data(bigbed, &block, *known_offset)?;
-    let mut block_data_mut = ByteOrdered::runtime(block_data_mut, bigbed.info.header.endianness);
+    let entries = parse_block_entries(
+        &block_data_mut.into_inner()[..],
+        bigbed.info.header.endianness,
+        expected_chrom,
+        start,
+        end,
+    )?;
+    *known_offset = block.offset + block.size;
+    Ok(entries.into_iter())
+}
+
+/// Parses the entries of a decompressed bigBed section that overlap `start..end`.
+pub(crate) fn parse_block_entries(
+    block_data_mut: &[u8],
+    endianness: Endianness,
+    expected_chrom: u32,
+    start: u32,
+    end: u32,
+) -> Result<Vec<BedEntry>, BBIReadError> {
+    let mut block_data_mut = ByteOrdered::runtime(block_data_mut, endianness);
     let mut entries: Vec<BedEntry> = Vec::new();
 
     let mut read_entry = || -> Result<BedEntry, BBIReadError> {
@@ -345,6 +362,5 @@ fn get_block_entries<R: SeekableRead>(
         }
     }
 
-    *known_offset = block.offset + block.size;
-    Ok(entries.into_iter())
+    Ok(entries)
 }
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::ffi::CString;
 use std::fs::File;
 use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::sync::Arc;
 
 use futures::executor::{block_on, ThreadPool};
 use futures::future::FutureExt;
@@ -18,14 +19,18 @@ use crate::ChromData;
 use crate::bbi::{BedEntry, Summary, Value, ZoomRecord, BIGBED_MAGIC};
 use crate::bbiwrite::{
     self, encode_zoom_section, get_rtreeindex, write_blank_headers, write_chrom_tree,
-    write_rtreeindex, write_zooms, BBIWriteOptions, ChromProcessingInput, ProcessChromError,
-    SectionData,
+    write_rtreeindex, write_zooms, BBIWriteOptions, CancellationToken, ChromProcessingInput,
+    ProcessChromError, SectionData, WriteEvent, WriteObserver, WriteReport,
 };
 
 pub struct BigBedWrite {
     pub path: String,
     pub options: BBIWriteOptions,
     pub autosql: Option<String>,
+    /// Receives progress events during `write`
+    pub observer: Option<Arc<dyn WriteObserver>>,
+    /// Can be cloned before calling `write` to cancel it from another thread
+    pub cancellation: CancellationToken,
 }
 
 impl BigBedWrite {
@@ -34,6 +39,8 @@ impl BigBedWrite {
             path,
             options: BBIWriteOptions::default(),
             autosql: None,
+            observer: None,
+            cancellation: CancellationToken::new(),
         }
     }
 
@@ -45,7 +52,24 @@ impl BigBedWrite {
         chrom_sizes: HashMap<String, u32>,
         vals: V,
         pool: ThreadPool,
-    ) -> Result<(), ProcessChromError<Values::Error>> {
+    ) -> Result<WriteReport, ProcessChromError<Values::Error>> {
+        let res = self.write_internal(chrom_sizes, vals, pool);
+        if let Err(ProcessChromError::Cancelled) = res {
+            // Don't leave a partially written file behind
+            let _ = std::fs::remove_file(&self.path);
+        }
+        res
+    }
+
+    fn write_internal<
+        Values: ChromValues<Value = BedEntry> + Send + 'static,
+        V: ChromData<ProcessChromError<Values::Error>, Output = Values>,
+    >(
+        &self,
+        chrom_sizes: HashMap<String, u32>,
+        vals: V,
+        pool: ThreadPool,
+    ) -> Result<WriteReport, ProcessChromError<Values::Error>> {
         let fp = File::create(self.path.clone())?;
         let mut file = BufWriter::new(fp);
 
@@ -77,15 +101,24 @@ impl BigBedWrite {
 
         let pre_data = file.tell()?;
         // Write data to file and return
-        let (chrom_ids, summary, mut file, raw_sections_iter, zoom_infos, uncompress_buf_size) =
-            block_on(bbiwrite::write_vals(
-                vals,
-                file,
-                self.options,
-                BigBedWrite::process_chrom,
-                pool,
-                chrom_sizes.clone(),
-            ))?;
+        let (
+            chrom_ids,
+            summary,
+            mut file,
+            raw_sections_iter,
+            zoom_infos,
+            uncompress_buf_size,
+            chrom_summaries,
+        ) = block_on(bbiwrite::write_vals(
+            vals,
+            file,
+            self.options,
+            BigBedWrite::process_chrom,
+            pool,
+            chrom_sizes.clone(),
+            self.observer.as_deref(),
+            self.cancellation.clone(),
+        ))?;
         let data_size = file.tell()? - pre_data;
         let mut current_offset = pre_data;
         let sections_iter = raw_sections_iter.map(|mut section| {
@@ -109,14 +142,34 @@ impl BigBedWrite {
         // Therefore, there is a higher likelihood that the udc file will only need one read for
         // chrom tree + full data index.
         let chrom_index_start = file.tell()?;
-        write_chrom_tree(&mut file, chrom_sizes, &chrom_ids.get_map())?;
+        let chrom_ids = chrom_ids.get_map();
+        write_chrom_tree(&mut file, chrom_sizes, &chrom_ids)?;
 
+        let notify = |event: WriteEvent| {
+            if let Some(observer) = &self.observer {
+                observer.on_event(event);
+            }
+        };
+
+        notify(WriteEvent::IndexStarted);
         let index_start = file.tell()?;
         let (nodes, levels, total_sections) = get_rtreeindex(sections_iter, self.options);
         write_rtreeindex(&mut file, nodes, levels, total_sections, self.options)?;
+        notify(WriteEvent::IndexFinished {
+            sections: total_sections,
+        });
+
+        if self.cancellation.is_cancelled() {
+            return Err(ProcessChromError::Cancelled);
+        }
 
-        let zoom_entries = write_zooms(&mut file, zoom_infos, data_size, self.options)?;
+        notify(WriteEvent::ZoomsStarted);
+        let (zoom_entries, zoom_reports) =
+            write_zooms(&mut file, zoom_infos, data_size, self.options)?;
         let num_zooms = zoom_entries.len() as u16;
+        notify(WriteEvent::ZoomsFinished {
+            levels: zoom_entries.len(),
+        });
 
         file.seek(SeekFrom::Start(0))?;
         file.write_u32::<NativeEndian>(BIGBED_MAGIC)?;
@@ -153,8 +206,19 @@ impl BigBedWrite {
         file.write_u64::<NativeEndian>(summary.total_items)?;
         file.seek(SeekFrom::End(0))?;
         file.write_u32::<NativeEndian>(BIGBED_MAGIC)?;
-
-        Ok(())
+        let file_size = file.tell()?;
+
+        Ok(WriteReport {
+            summary,
+            chrom_summaries,
+            chrom_ids,
+            total_sections,
+            index_levels: levels,
+            zooms: zoom_reports,
+            uncompress_buf_size,
+            file_size,
+            scale: None,
+        })
     }
 
     async fn process_chrom<I>(
@@ -172,6 +236,7 @@ impl BigBedWrite {
         let ChromProcessingInput {
             mut zooms_channels,
             mut ftx,
+            cancellation,
         } = processing_input;
 
         // While we do technically lose precision here by using the f32 in Value, we can reuse the same merge_into method
@@ -497,6 +562,10 @@ impl BigBedWrite {
                     .spawn_with_handle(encode_section(options.compress, items, chrom_id))
                     .expect("Couldn't spawn.");
                 ftx.send(handle.boxed()).await.expect("Couldn't send");
+
+                if cancellation.is_cancelled() {
+                    return Err(ProcessChromError::Cancelled);
+                }
             }
         }
 
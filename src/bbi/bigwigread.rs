@@ -393,53 +393,71 @@ fn get_block_values<R: SeekableRead>(
     start: u32,
     end: u32,
 ) -> Result<Option<std::vec::IntoIter<Value>>, BBIReadError> {
-    let mut block_data_mut = get_block_data(bigwig, &block, *known_offset)?;
+    let block_data_mut = get_block_data(bigwig, &block, *known_offset)?;
+    let values = parse_block_values(
+        block_data_mut,
+        bigwig.info.header.endianness,
+        chrom,
+        start,
+        end,
+    )?;
+    *known_offset = block.offset + block.size;
+    Ok(values.map(|v| v.into_iter()))
+}
 
+/// Parses the values of a decompressed bigWig section, clamped to `start..end`.
+/// Returns `None` if the section is not for `chrom`.
+pub(crate) fn parse_block_values(
+    mut block_data_mut: impl Read,
+    endianness: Endianness,
+    chrom: u32,
+    start: u32,
+    end: u32,
+) -> Result<Option<Vec<Value>>, BBIReadError> {
     use bytes::Buf;
     use bytes::BytesMut;
 
     let mut bytes_header = BytesMut::zeroed(24);
     block_data_mut.read_exact(&mut bytes_header)?;
 
-    let (chrom_id, chrom_start, item_step, item_span, section_type, item_count) =
-        match bigwig.info.header.endianness {
-            Endianness::Big => {
-                let chrom_id = bytes_header.get_u32();
-                let chrom_start = bytes_header.get_u32();
-                let _chrom_end = bytes_header.get_u32();
-                let item_step = bytes_header.get_u32();
-                let item_span = bytes_header.get_u32();
-                let section_type = bytes_header.get_u8();
-                let _reserved = bytes_header.get_u8();
-                let item_count = bytes_header.get_u16();
-                (
-                    chrom_id,
-                    chrom_start,
-                    item_step,
-                    item_span,
-                    section_type,
-                    item_count,
-                )
-            }
-            Endianness::Little => {
-                let chrom_id = bytes_header.get_u32_le();
-                let chrom_start = bytes_header.get_u32_le();
-                let _chrom_end = bytes_header.get_u32_le();
-                let item_step = bytes_header.get_u32_le();
-                let item_span = bytes_header.get_u32_le();
-                let section_type = bytes_header.get_u8();
-                let _reserved = bytes_header.get_u8();
-                let item_count = bytes_header.get_u16_le();
-                (
-                    chrom_id,
-                    chrom_start,
-                    item_step,
-                    item_span,
-                    section_type,
-                    item_count,
-                )
-            }
-        };
+    let (chrom_id, chrom_start, item_step, item_span, section_type, item_count) = match endianness {
+        Endianness::Big => {
+            let chrom_id = bytes_header.get_u32();
+            let chrom_start = bytes_header.get_u32();
+            let _chrom_end = bytes_header.get_u32();
+            let item_step = bytes_header.get_u32();
+            let item_span = bytes_header.get_u32();
+            let section_type = bytes_header.get_u8();
+            let _reserved = bytes_header.get_u8();
+            let item_count = bytes_header.get_u16();
+            (
+                chrom_id,
+                chrom_start,
+                item_step,
+                item_span,
+                section_type,
+                item_count,
+            )
+        }
+        Endianness::Little => {
+            let chrom_id = bytes_header.get_u32_le();
+            let chrom_start = bytes_header.get_u32_le();
+            let _chrom_end = bytes_header.get_u32_le();
+            let item_step = bytes_header.get_u32_le();
+            let item_span = bytes_header.get_u32_le();
+            let section_type = bytes_header.get_u8();
+            let _reserved = bytes_header.get_u8();
+            let item_count = bytes_header.get_u16_le();
+            (
+                chrom_id,
+                chrom_start,
+                item_step,
+                item_span,
+                section_type,
+                item_count,
+            )
+        }
+    };
 
     let mut values: Vec<Value> = Vec::with_capacity(item_count as usize);
 
@@ -456,7 +474,7 @@ fn get_block_values<R: SeekableRead>(
                 let istart = i * 12;
                 let block_item_data: &[u8; 12] = bytes[istart..istart + 12].try_into().unwrap();
                 // bedgraph
-                let (chrom_start, chrom_end, value) = match bigwig.info.header.endianness {
+                let (chrom_start, chrom_end, value) = match endianness {
                     Endianness::Big => {
                         let chrom_start = u32::from_be_bytes([
                             block_item_data[0],
@@ -517,7 +535,7 @@ fn get_block_values<R: SeekableRead>(
             block_data_mut.read_exact(&mut bytes)?;
             for _ in 0..item_count {
                 // variable step
-                let (chrom_start, value) = match bigwig.info.header.endianness {
+                let (chrom_start, value) = match endianness {
                     Endianness::Big => {
                         let chrom_start = bytes.get_u32();
                         let value = bytes.get_f32();
@@ -548,7 +566,7 @@ fn get_block_values<R: SeekableRead>(
             block_data_mut.read_exact(&mut bytes)?;
             for _ in 0..item_count {
                 // fixed step
-                let value = match bigwig.info.header.endianness {
+                let value = match endianness {
                     Endianness::Big => {
                         let value = bytes.get_f32();
                         value
@@ -581,6 +599,5 @@ fn get_block_values<R: SeekableRead>(
         }
     }
 
-    *known_offset = block.offset + block.size;
-    Ok(Some(values.into_iter()))
+    Ok(Some(values))
 }
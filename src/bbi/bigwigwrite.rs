@@ -43,6 +43,7 @@ out.write(chrom_map, vals, pool)?;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::sync::Arc;
 
 use futures::executor::{block_on, ThreadPool};
 use futures::future::FutureExt;
@@ -58,13 +59,17 @@ use crate::ChromData;
 use crate::bbi::{Summary, Value, ZoomRecord, BIGWIG_MAGIC};
 use crate::bbiwrite::{
     self, encode_zoom_section, get_rtreeindex, write_blank_headers, write_chrom_tree,
-    write_rtreeindex, write_zooms, BBIWriteOptions, ChromProcessingInput, ProcessChromError,
-    SectionData,
+    write_rtreeindex, write_zooms, BBIWriteOptions, CancellationToken, ChromProcessingInput,
+    ProcessChromError, SectionData, WriteEvent, WriteObserver, WriteReport,
 };
 
 pub struct BigWigWrite {
     pub path: String,
     pub options: BBIWriteOptions,
+    /// Receives progress events during `write`
+    pub observer: Option<Arc<dyn WriteObserver>>,
+    /// Can be cloned before calling `write` to cancel it from another thread
+    pub cancellation: CancellationToken,
 }
 
 impl BigWigWrite {
@@ -72,6 +77,8 @@ impl BigWigWrite {
         BigWigWrite {
             path,
             options: BBIWriteOptions::default(),
+            observer: None,
+            cancellation: CancellationToken::new(),
         }
     }
 
@@ -83,7 +90,24 @@ impl BigWigWrite {
         chrom_sizes: HashMap<String, u32>,
         vals: V,
         pool: ThreadPool,
-    ) -> Result<(), ProcessChromError<Values::Error>> {
+    ) -> Result<WriteReport, ProcessChromError<Values::Error>> {
+        let res = self.write_internal(chrom_sizes, vals, pool);
+        if let Err(ProcessChromError::Cancelled) = res {
+            // Don't leave a partially written file behind
+            let _ = std::fs::remove_file(&self.path);
+        }
+        res
+    }
+
+    fn write_internal<
+        Values: ChromValues<Value = Value> + Send + 'static,
+        V: ChromData<ProcessChromError<Values::Error>, Output = Values>,
+    >(
+        &self,
+        chrom_sizes: HashMap<String, u32>,
+        vals: V,
+        pool: ThreadPool,
+    ) -> Result<WriteReport, ProcessChromError<Values::Error>> {
         let fp = File::create(self.path.clone())?;
         let mut file = BufWriter::new(fp);
 
@@ -103,15 +127,24 @@ impl BigWigWrite {
 
         let pre_data = file.tell()?;
         // Write data to file and return
-        let (chrom_ids, summary, mut file, raw_sections_iter, zoom_infos, uncompress_buf_size) =
-            block_on(bbiwrite::write_vals(
-                vals,
-                file,
-                self.options,
-                BigWigWrite::process_chrom,
-                pool,
-                chrom_sizes.clone(),
-            ))?;
+        let (
+            chrom_ids,
+            summary,
+            mut file,
+            raw_sections_iter,
+            zoom_infos,
+            uncompress_buf_size,
+            chrom_summaries,
+        ) = block_on(bbiwrite::write_vals(
+            vals,
+            file,
+            self.options,
+            BigWigWrite::process_chrom,
+            pool,
+            chrom_sizes.clone(),
+            self.observer.as_deref(),
+            self.cancellation.clone(),
+        ))?;
         let data_size = file.tell()? - pre_data;
         let mut current_offset = pre_data;
         let sections_iter = raw_sections_iter.map(|mut section| {
@@ -127,14 +160,34 @@ impl BigWigWrite {
         // Putting the chrom tree before the data also has a higher likelihood of being included with the beginning headers,
         // but requires us to know all the data ahead of time (when writing)
         let chrom_index_start = file.tell()?;
-        write_chrom_tree(&mut file, chrom_sizes, &chrom_ids.get_map())?;
+        let chrom_ids = chrom_ids.get_map();
+        write_chrom_tree(&mut file, chrom_sizes, &chrom_ids)?;
 
+        let notify = |event: WriteEvent| {
+            if let Some(observer) = &self.observer {
+                observer.on_event(event);
+            }
+        };
+
+        notify(WriteEvent::IndexStarted);
         let index_start = file.tell()?;
         let (nodes, levels, total_sections) = get_rtreeindex(sections_iter, self.options);
         write_rtreeindex(&mut file, nodes, levels, total_sections, self.options)?;
+        notify(WriteEvent::IndexFinished {
+            sections: total_sections,
+        });
+
+        if self.cancellation.is_cancelled() {
+            return Err(ProcessChromError::Cancelled);
+        }
 
-        let zoom_entries = write_zooms(&mut file, zoom_infos, data_size, self.options)?;
+        notify(WriteEvent::ZoomsStarted);
+        let (zoom_entries, zoom_reports) =
+            write_zooms(&mut file, zoom_infos, data_size, self.options)?;
         let num_zooms = zoom_entries.len() as u16;
+        notify(WriteEvent::ZoomsFinished {
+            levels: zoom_entries.len(),
+        });
 
         file.seek(SeekFrom::Start(0))?;
         file.write_u32::<NativeEndian>(BIGWIG_MAGIC)?;
@@ -170,8 +223,19 @@ impl BigWigWrite {
         file.write_u64::<NativeEndian>(total_sections)?;
         file.seek(SeekFrom::End(0))?;
         file.write_u32::<NativeEndian>(BIGWIG_MAGIC)?;
-
-        Ok(())
+        let file_size = file.tell()?;
+
+        Ok(WriteReport {
+            summary,
+            chrom_summaries,
+            chrom_ids,
+            total_sections,
+            index_levels: levels,
+            zooms: zoom_reports,
+            uncompress_buf_size,
+            file_size,
+            scale: None,
+        })
     }
 
     pub(crate) async fn process_chrom<I: ChromValues<Value = Value>>(
@@ -186,6 +250,7 @@ impl BigWigWrite {
         let ChromProcessingInput {
             mut zooms_channels,
             mut ftx,
+            cancellation,
         } = processing_input;
 
         struct ZoomItem {
@@ -356,6 +421,10 @@ impl BigWigWrite {
                     .spawn_with_handle(encode_section(options.compress, items, chrom_id))
                     .expect("Couldn't spawn.");
                 ftx.send(handle.boxed()).await.expect("Couldn't send");
+
+                if cancellation.is_cancelled() {
+                    return Err(ProcessChromError::Cancelled);
+                }
             }
         }
 
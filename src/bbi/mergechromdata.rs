@@ -0,0 +1,480 @@
+//! The values of multiple bigWigs, merged with a [`Combiner`], as a
+//! [`ChromData`] source for writing bigWigs, and the entries of multiple
+//! bigBeds, merged in order, for writing bigBeds.
+//!
+//! Every chromosome of any input is merged, one at a time. The combiner is
+//! given each segment where the set of inputs with coverage doesn't change
+//! (see [`merge_sections_many_with`]), and inputs without the chromosome have
+//! no coverage. Inputs are reopened for each chromosome. A chromosome must have
+//! the same length in every input that has it.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io;
+use std::iter::Peekable;
+
+use crate::bbiread::{BBIReadError, ChromInfo};
+use crate::bed::autosql::{parse_autosql_fields, AutosqlField};
+use crate::utils::chromvalues::ChromValues;
+use crate::utils::merge::{merge_sections_many_with, Combiner};
+use crate::utils::reopen::{Reopen, SeekableRead};
+use crate::{
+    BedEntry, BigBedRead, BigWigRead, ChromData, ChromDataState, ChromProcessingFnOutput, Value,
+};
+
+type MergedIter = Box<dyn Iterator<Item = Result<Value, BBIReadError>> + Send>;
+
+/// The sizes of the chromosomes of all of the inputs. Errors if a chromosome
+/// has different lengths in different inputs.
+fn merged_chrom_sizes<'a>(
+    inputs: impl Iterator<Item = &'a Vec<ChromInfo>>,
+) -> Result<HashMap<String, u32>, BBIReadError> {
+    let mut chrom_sizes = HashMap::new();
+    for chrom in inputs.flatten() {
+        match chrom_sizes.get(&chrom.name) {
+            Some(length) if *length != chrom.length => {
+                return Err(BBIReadError::InvalidFile(format!(
+                    "Chromosome `{}` has different lengths in the inputs ({} and {}). (Are you using the same assembly?)",
+                    chrom.name, length, chrom.length
+                )));
+            }
+            Some(_) => {}
+            None => {
+                chrom_sizes.insert(chrom.name.clone(), chrom.length);
+            }
+        }
+    }
+    Ok(chrom_sizes)
+}
+
+/// The chromosomes, sorted in reverse (so they can be `pop`ped in order).
+fn sorted_chroms(chrom_sizes: &HashMap<String, u32>) -> Vec<(String, u32)> {
+    let mut chroms: Vec<(String, u32)> = chrom_sizes
+        .iter()
+        .map(|(name, length)| (name.clone(), *length))
+        .collect();
+    chroms.sort();
+    chroms.reverse();
+    chroms
+}
+
+pub struct MergedBigWigs<R, C> {
+    inputs: Vec<BigWigRead<R>>,
+    chrom_sizes: HashMap<String, u32>,
+    // For speed, we `pop` and go in reverse order.
+    chroms: Vec<(String, u32)>,
+    combiner: C,
+    // The next chromosome with values, if it was merged by `is_empty`
+    next: Option<(String, MergedValues)>,
+}
+
+impl<R, C> MergedBigWigs<R, C> {
+    /// Errors if a chromosome has different lengths in different inputs.
+    pub fn new(inputs: Vec<BigWigRead<R>>, combiner: C) -> Result<Self, BBIReadError> {
+        let chrom_sizes = merged_chrom_sizes(inputs.iter().map(|i| &i.info.chrom_info))?;
+        let chroms = sorted_chroms(&chrom_sizes);
+        Ok(MergedBigWigs {
+            inputs,
+            chrom_sizes,
+            chroms,
+            combiner,
+            next: None,
+        })
+    }
+
+    /// The chromosome sizes of all the inputs.
+    pub fn chrom_sizes(&self) -> &HashMap<String, u32> {
+        &self.chrom_sizes
+    }
+}
+
+impl<R, C> MergedBigWigs<R, C>
+where
+    R: Reopen + SeekableRead + Send + 'static,
+    C: Combiner + Clone + Send + 'static,
+{
+    fn merge_chrom(&self, chrom: &str, length: u32) -> Result<MergedValues, BBIReadError> {
+        let iters = self
+            .inputs
+            .iter()
+            .map(|input| -> Result<MergedIter, BBIReadError> {
+                if input.info.find_chrom(chrom).is_none() {
+                    return Ok(Box::new(std::iter::empty()));
+                }
+                Ok(Box::new(
+                    input.reopen()?.get_interval_move(chrom, 0, length)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let iter: MergedIter = Box::new(merge_sections_many_with(iters, self.combiner.clone()));
+        Ok(MergedValues {
+            iter: iter.peekable(),
+        })
+    }
+
+    /// Merges the next chromosome (in order of name).
+    pub fn next_chrom(&mut self) -> Option<Result<(String, MergedValues), BBIReadError>> {
+        if let Some(next) = self.next.take() {
+            return Some(Ok(next));
+        }
+        let (chrom, length) = self.chroms.pop()?;
+        Some(
+            self.merge_chrom(&chrom, length)
+                .map(|values| (chrom, values)),
+        )
+    }
+
+    /// Whether no chromosome has merged values (for example, if the combiner
+    /// leaves every segment without a value). Since writing no values at all
+    /// fails, this can be checked first. Chromosomes are merged until one has
+    /// values, and the ones without are skipped.
+    pub fn is_empty(&mut self) -> Result<bool, BBIReadError> {
+        while self.next.is_none() {
+            let Some(next) = self.next_chrom() else {
+                return Ok(true);
+            };
+            let (chrom, mut values) = next?;
+            if values.peek().is_some() {
+                self.next = Some((chrom, values));
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl<R, C, E> ChromData<E> for MergedBigWigs<R, C>
+where
+    R: Reopen + SeekableRead + Send + 'static,
+    C: Combiner + Clone + Send + 'static,
+    E: From<io::Error>,
+{
+    type Output = MergedValues;
+
+    fn advance<
+        F: FnMut(
+            String,
+            Self::Output,
+        ) -> Result<ChromProcessingFnOutput<<Self::Output as ChromValues>::Error>, E>,
+    >(
+        &mut self,
+        do_read: &mut F,
+    ) -> Result<ChromDataState<<Self::Output as ChromValues>::Error>, E> {
+        Ok(match self.next_chrom() {
+            Some(Ok((chrom, values))) => ChromDataState::NewChrom(do_read(chrom, values)?),
+            Some(Err(e)) => ChromDataState::Error(e),
+            None => ChromDataState::Finished,
+        })
+    }
+}
+
+/// The merged values of one chromosome.
+pub struct MergedValues {
+    iter: Peekable<MergedIter>,
+}
+
+impl ChromValues for MergedValues {
+    type Value = Value;
+    type Error = BBIReadError;
+
+    fn next(&mut self) -> Option<Result<Value, BBIReadError>> {
+        self.iter.next()
+    }
+
+    fn peek(&mut self) -> Option<Result<&Value, &BBIReadError>> {
+        self.iter.peek().map(Result::as_ref)
+    }
+}
+
+/// How the inputs of [`MergedBigBeds`] with different autosql are handled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AutosqlReconcile {
+    /// It is an error.
+    Error,
+    /// Only the fields at the start that are the same (by name and type) in
+    /// every input are kept (at least the chromosome, start, and end), and any
+    /// others are dropped from each entry.
+    Common,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BigBedMergeOptions {
+    pub autosql: AutosqlReconcile,
+    /// The name of each input (like its file), added to each of its entries as
+    /// a last `source` field.
+    pub sources: Option<Vec<String>>,
+}
+
+impl Default for BigBedMergeOptions {
+    fn default() -> Self {
+        BigBedMergeOptions {
+            autosql: AutosqlReconcile::Error,
+            sources: None,
+        }
+    }
+}
+
+/// Adds a `source` field to the end of the fields of `autosql` (or of a bed3,
+/// if it has no fields).
+fn with_source_field(autosql: &str) -> String {
+    let autosql = if autosql.contains('(') {
+        autosql
+    } else {
+        crate::bed::autosql::BED3.trim_start()
+    };
+    let end = autosql.rfind(')').unwrap_or(autosql.len());
+    let mut autosql = autosql[..end].trim_end().to_owned();
+    autosql.push_str("\n    string source;       \"The file the entry was merged from\"\n)\n");
+    autosql
+}
+
+type MergedBedIter = Box<dyn Iterator<Item = Result<BedEntry, BBIReadError>> + Send>;
+
+/// The entries of multiple bigBeds, merged in chromosome and start order, as
+/// a [`ChromData`] source for writing a bigBed. Entries with the same start
+/// are ordered by input.
+pub struct MergedBigBeds<R> {
+    inputs: Vec<BigBedRead<R>>,
+    chrom_sizes: HashMap<String, u32>,
+    // For speed, we `pop` and go in reverse order.
+    chroms: Vec<(String, u32)>,
+    autosql: String,
+    /// The number of fields after the end kept from each entry, if not all
+    fields: Option<usize>,
+    sources: Option<Vec<String>>,
+}
+
+impl<R: SeekableRead> MergedBigBeds<R> {
+    /// Errors if a chromosome has different lengths in different inputs, or
+    /// (depending on `options`) if the inputs have different autosql.
+    pub fn new(
+        mut inputs: Vec<BigBedRead<R>>,
+        options: BigBedMergeOptions,
+    ) -> Result<Self, BBIReadError> {
+        if inputs.is_empty() {
+            return Err(BBIReadError::InvalidFile("There are no inputs.".to_owned()));
+        }
+        if options
+            .sources
+            .as_ref()
+            .is_some_and(|s| s.len() != inputs.len())
+        {
+            return Err(BBIReadError::InvalidFile(
+                "There must be a source name for each input.".to_owned(),
+            ));
+        }
+        let chrom_sizes = merged_chrom_sizes(inputs.iter().map(|i| &i.info.chrom_info))?;
+        let chroms = sorted_chroms(&chrom_sizes);
+
+        let autosqls = inputs
+            .iter_mut()
+            .map(|i| i.autosql())
+            .collect::<Result<Vec<_>, _>>()?;
+        let fields: Vec<Option<Vec<AutosqlField>>> =
+            autosqls.iter().map(|a| parse_autosql_fields(a)).collect();
+        let same = if fields.iter().all(Option::is_some) {
+            fields.iter().all(|f| *f == fields[0])
+        } else {
+            autosqls.iter().all(|a| a.trim() == autosqls[0].trim())
+        };
+        let (autosql, fields) = match options.autosql {
+            _ if same => (autosqls[0].clone(), None),
+            AutosqlReconcile::Error => {
+                return Err(BBIReadError::InvalidFile(
+                    "The inputs have different autosql.".to_owned(),
+                ));
+            }
+            AutosqlReconcile::Common => {
+                let mut common: Vec<AutosqlField> = fields[0].clone().unwrap_or_default();
+                for input in fields.iter() {
+                    let input = input.as_deref().unwrap_or_default();
+                    let same = common
+                        .iter()
+                        .zip(input.iter())
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    common.truncate(same);
+                }
+                if common.len() < 3 {
+                    (crate::bed::autosql::BED3.trim_start().to_owned(), Some(0))
+                } else {
+                    let mut autosql =
+                        "table merged\n\"The common fields of merged bigBeds\"\n(\n".to_owned();
+                    for field in common.iter() {
+                        autosql.push_str(&format!(
+                            "    {} {};    \"{}\"\n",
+                            field.field_type, field.name, field.name
+                        ));
+                    }
+                    autosql.push_str(")\n");
+                    (autosql, Some(common.len() - 3))
+                }
+            }
+        };
+        let autosql = if options.sources.is_some() {
+            with_source_field(&autosql)
+        } else {
+            autosql
+        };
+        Ok(MergedBigBeds {
+            inputs,
+            chrom_sizes,
+            chroms,
+            autosql,
+            fields,
+            sources: options.sources,
+        })
+    }
+}
+
+impl<R> MergedBigBeds<R> {
+    /// The chromosome sizes of all the inputs.
+    pub fn chrom_sizes(&self) -> &HashMap<String, u32> {
+        &self.chrom_sizes
+    }
+
+    /// The autosql of the merged entries.
+    pub fn autosql(&self) -> &str {
+        &self.autosql
+    }
+}
+
+impl<R> MergedBigBeds<R>
+where
+    R: Reopen + SeekableRead + Send + 'static,
+{
+    fn merge_chrom(&self, chrom: &str, length: u32) -> Result<MergedBedEntries, BBIReadError> {
+        let iters = self
+            .inputs
+            .iter()
+            .map(|input| -> Result<MergedBedIter, BBIReadError> {
+                if input.info.find_chrom(chrom).is_none() {
+                    return Ok(Box::new(std::iter::empty()));
+                }
+                Ok(Box::new(
+                    input.reopen()?.get_interval_move(chrom, 0, length)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let (fields, sources) = (self.fields, self.sources.clone());
+        let iter = KWayMerge::new(iters).map(move |entry| {
+            let (input, mut entry) = entry?;
+            if let Some(fields) = fields {
+                entry.rest = entry
+                    .rest
+                    .split('\t')
+                    .take(fields)
+                    .collect::<Vec<_>>()
+                    .join("\t");
+            }
+            if let Some(sources) = &sources {
+                if !entry.rest.is_empty() {
+                    entry.rest.push('\t');
+                }
+                entry.rest.push_str(&sources[input]);
+            }
+            Ok(entry)
+        });
+        let iter: MergedBedIter = Box::new(iter);
+        Ok(MergedBedEntries {
+            iter: iter.peekable(),
+        })
+    }
+}
+
+impl<R, E> ChromData<E> for MergedBigBeds<R>
+where
+    R: Reopen + SeekableRead + Send + 'static,
+    E: From<io::Error>,
+{
+    type Output = MergedBedEntries;
+
+    fn advance<
+        F: FnMut(
+            String,
+            Self::Output,
+        ) -> Result<ChromProcessingFnOutput<<Self::Output as ChromValues>::Error>, E>,
+    >(
+        &mut self,
+        do_read: &mut F,
+    ) -> Result<ChromDataState<<Self::Output as ChromValues>::Error>, E> {
+        let (chrom, length) = match self.chroms.pop() {
+            Some(c) => c,
+            None => return Ok(ChromDataState::Finished),
+        };
+        Ok(match self.merge_chrom(&chrom, length) {
+            Ok(entries) => ChromDataState::NewChrom(do_read(chrom, entries)?),
+            Err(e) => ChromDataState::Error(e),
+        })
+    }
+}
+
+/// A k-way merge of sorted entries, by start, with the index of the input of
+/// each.
+struct KWayMerge {
+    iters: Vec<MergedBedIter>,
+    /// The next entry of each input
+    heads: Vec<Option<BedEntry>>,
+    /// The start and input of each head
+    heap: BinaryHeap<Reverse<(u32, usize)>>,
+    error: Option<BBIReadError>,
+}
+
+impl KWayMerge {
+    fn new(iters: Vec<MergedBedIter>) -> Self {
+        let mut merge = KWayMerge {
+            heads: iters.iter().map(|_| None).collect(),
+            iters,
+            heap: BinaryHeap::new(),
+            error: None,
+        };
+        for input in 0..merge.iters.len() {
+            merge.fill(input);
+        }
+        merge
+    }
+
+    fn fill(&mut self, input: usize) {
+        match self.iters[input].next() {
+            Some(Ok(entry)) => {
+                self.heap.push(Reverse((entry.start, input)));
+                self.heads[input] = Some(entry);
+            }
+            Some(Err(e)) => {
+                self.error.get_or_insert(e);
+            }
+            None => {}
+        }
+    }
+}
+
+impl Iterator for KWayMerge {
+    type Item = Result<(usize, BedEntry), BBIReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        let Reverse((_, input)) = self.heap.pop()?;
+        let entry = self.heads[input].take().unwrap();
+        self.fill(input);
+        Some(Ok((input, entry)))
+    }
+}
+
+/// The merged entries of one chromosome.
+pub struct MergedBedEntries {
+    iter: Peekable<MergedBedIter>,
+}
+
+impl ChromValues for MergedBedEntries {
+    type Value = BedEntry;
+    type Error = BBIReadError;
+
+    fn next(&mut self) -> Option<Result<BedEntry, BBIReadError>> {
+        self.iter.next()
+    }
+
+    fn peek(&mut self) -> Option<Result<&BedEntry, &BBIReadError>> {
+        self.iter.peek().map(Result::as_ref)
+    }
+}
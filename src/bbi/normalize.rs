@@ -0,0 +1,287 @@
+//! Rewriting a bigWig with normalized values.
+//!
+//! Every value is scaled linearly (see [`ValueScale`]), with a factor that is
+//! either given or computed from the total summary of the file (from
+//! [`BigWigRead::get_summary`]). Chromosomes are read and written like
+//! `BedParserParallelStreamingIterator`, with up to 5 at a time, and the zoom
+//! levels are computed from the scaled values.
+//!
+//! ```no_run
+//! # use bigtools::normalize::{normalize, Normalization};
+//! # use bigtools::{BigWigRead, BigWigWrite};
+//! let input = BigWigRead::open_file("in.bigWig")?;
+//! let output = BigWigWrite::create_file("out.bigWig".to_string());
+//! let pool = futures::executor::ThreadPoolBuilder::new().create()?;
+//! let report = normalize(input, output, Normalization::ZScore, pool)?;
+//! println!("Scaled by {}", report.scale.unwrap().factor);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use std::io;
+
+use futures::executor::ThreadPool;
+use thiserror::Error;
+
+use crate::bbiread::BBIReadError;
+use crate::bbiwrite::{ProcessChromError, QueuedReads, ValueScale, WriteReport};
+use crate::utils::chromvalues::ChromValues;
+use crate::utils::reopen::{Reopen, SeekableRead};
+use crate::{
+    BigWigRead, BigWigWrite, ChromData, ChromDataState, ChromProcessingFnOutput, Summary, Value,
+};
+
+#[derive(Error, Debug)]
+pub enum NormalizeError {
+    #[error("{}", .0)]
+    InvalidInput(String),
+    #[error("The write was cancelled.")]
+    Cancelled,
+    #[error("{}", .0)]
+    BBIReadError(#[from] BBIReadError),
+    #[error("{}", .0)]
+    IoError(#[from] io::Error),
+}
+
+impl From<ProcessChromError<BBIReadError>> for NormalizeError {
+    fn from(error: ProcessChromError<BBIReadError>) -> Self {
+        match error {
+            ProcessChromError::InvalidInput(e) | ProcessChromError::InvalidChromosome(e) => {
+                NormalizeError::InvalidInput(e)
+            }
+            ProcessChromError::IoError(e) => NormalizeError::IoError(e),
+            ProcessChromError::SourceError(e) => NormalizeError::BBIReadError(e),
+            ProcessChromError::Cancelled => NormalizeError::Cancelled,
+        }
+    }
+}
+
+/// How the values of a bigWig are normalized. The total signal of a bigWig is
+/// the sum of each value times the number of bases it covers.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Normalization {
+    /// Multiply by a constant factor.
+    Scale(f64),
+    /// Per million: multiply by 1e6 / `total`, or by 1e6 / the total signal
+    /// if `None`.
+    Cpm(Option<f64>),
+    /// Per kilobase of bin per million: like `Cpm`, but also divided by the
+    /// size of the bins (in kilobases) that the values were computed over.
+    Rpkm { total: Option<f64>, bin_size: u32 },
+    /// Subtract the mean and divide by the standard deviation, over the
+    /// covered bases.
+    ZScore,
+    /// Divide by the mean, over the covered bases.
+    Mean,
+}
+
+impl Normalization {
+    /// The scale for a bigWig with the total summary `summary`.
+    pub fn scale(self, summary: &Summary) -> Result<ValueScale, NormalizeError> {
+        let no_summary = || {
+            NormalizeError::InvalidInput(
+                "The bigWig has no total summary (it is empty, or version 1).".to_owned(),
+            )
+        };
+        let total = |total: Option<f64>| match total {
+            Some(total) if total > 0.0 => Ok(total),
+            Some(_) => Err(NormalizeError::InvalidInput(
+                "The total must be positive.".to_owned(),
+            )),
+            None if summary.bases_covered > 0 && summary.sum > 0.0 => Ok(summary.sum),
+            None => Err(no_summary()),
+        };
+        let mean = || {
+            if summary.bases_covered == 0 {
+                return Err(no_summary());
+            }
+            Ok(summary.sum / summary.bases_covered as f64)
+        };
+        let factor = |factor: f64| ValueScale {
+            factor,
+            offset: 0.0,
+        };
+        Ok(match self {
+            Normalization::Scale(f) => factor(f),
+            Normalization::Cpm(t) => factor(1e6 / total(t)?),
+            Normalization::Rpkm { total: t, bin_size } => {
+                if bin_size == 0 {
+                    return Err(NormalizeError::InvalidInput(
+                        "The bin size must be greater than 0.".to_owned(),
+                    ));
+                }
+                factor(1e9 / (total(t)? * f64::from(bin_size)))
+            }
+            Normalization::ZScore => {
+                let mean = mean()?;
+                let variance = summary.sum_squares / summary.bases_covered as f64 - mean * mean;
+                if variance <= 0.0 {
+                    return Err(NormalizeError::InvalidInput(
+                        "The bigWig has no variance.".to_owned(),
+                    ));
+                }
+                let sd = variance.sqrt();
+                ValueScale {
+                    factor: 1.0 / sd,
+                    offset: -mean / sd,
+                }
+            }
+            Normalization::Mean => {
+                let mean = mean()?;
+                if mean == 0.0 {
+                    return Err(NormalizeError::InvalidInput(
+                        "The mean of the bigWig is 0.".to_owned(),
+                    ));
+                }
+                factor(1.0 / mean)
+            }
+        })
+    }
+
+    /// The scale for coverage computed from `count` reads (or entries), for
+    /// normalizing for sequencing depth. An unset total of `Cpm` or `Rpkm` is
+    /// `count`, rather than the total signal. `ZScore` and `Mean` need the
+    /// summary of a bigWig, so aren't supported.
+    pub fn scale_for_count(self, count: u64) -> Result<ValueScale, NormalizeError> {
+        if count == 0 {
+            return Err(NormalizeError::InvalidInput(
+                "Nothing was counted to normalize by.".to_owned(),
+            ));
+        }
+        let count = count as f64;
+        let normalization = match self {
+            Normalization::Cpm(total) => Normalization::Cpm(Some(total.unwrap_or(count))),
+            Normalization::Rpkm { total, bin_size } => Normalization::Rpkm {
+                total: Some(total.unwrap_or(count)),
+                bin_size,
+            },
+            Normalization::Scale(_) => self,
+            Normalization::ZScore | Normalization::Mean => {
+                return Err(NormalizeError::InvalidInput(
+                    "Only scale, cpm and rpkm normalize counted coverage.".to_owned(),
+                ))
+            }
+        };
+        // Every total is set, so the summary isn't used
+        normalization.scale(&Summary {
+            total_items: 0,
+            bases_covered: 0,
+            min_val: 0.0,
+            max_val: 0.0,
+            sum: 0.0,
+            sum_squares: 0.0,
+        })
+    }
+}
+
+/// Writes the values of `input`, normalized with `normalization`, with
+/// `output`. The scale that was applied is in the returned report.
+pub fn normalize<R>(
+    mut input: BigWigRead<R>,
+    output: BigWigWrite,
+    normalization: Normalization,
+    pool: ThreadPool,
+) -> Result<WriteReport, NormalizeError>
+where
+    R: Reopen + SeekableRead + Send + 'static,
+{
+    let scale = normalization.scale(&input.get_summary()?)?;
+    let chrom_sizes = input
+        .info
+        .chrom_info
+        .iter()
+        .map(|c| (c.name.clone(), c.length))
+        .collect();
+    let mut chroms: Vec<(String, u32)> = input
+        .info
+        .chrom_info
+        .iter()
+        .map(|c| (c.name.clone(), c.length))
+        .collect();
+    // For speed, we `pop` and go in reverse order.
+    chroms.reverse();
+    let data = ScaledBigWig {
+        input,
+        chroms,
+        scale,
+        queued_reads: QueuedReads::new(),
+    };
+    let mut report = output.write(chrom_sizes, data, pool)?;
+    report.scale = Some(scale);
+    Ok(report)
+}
+
+struct ScaledBigWig<R, E> {
+    input: BigWigRead<R>,
+    chroms: Vec<(String, u32)>,
+    scale: ValueScale,
+
+    queued_reads: QueuedReads<BBIReadError, E>,
+}
+
+impl<R, E> ChromData<E> for ScaledBigWig<R, E>
+where
+    R: Reopen + SeekableRead + Send + 'static,
+    E: From<io::Error>,
+{
+    type Output = ScaledValues;
+
+    fn advance<
+        F: FnMut(
+            String,
+            Self::Output,
+        ) -> Result<ChromProcessingFnOutput<<Self::Output as ChromValues>::Error>, E>,
+    >(
+        &mut self,
+        do_read: &mut F,
+    ) -> Result<ChromDataState<<Self::Output as ChromValues>::Error>, E> {
+        let begin_next = || -> Result<_, E> {
+            let (chrom, length) = match self.chroms.pop() {
+                Some(c) => c,
+                None => return Ok(ChromDataState::Finished),
+            };
+            let values = match self.input.reopen() {
+                Ok(input) => input.get_interval_move(&chrom, 0, length),
+                Err(e) => Err(e.into()),
+            };
+            let values = match values {
+                Ok(values) => values,
+                Err(e) => return Ok(ChromDataState::Error(e)),
+            };
+            let scale = self.scale;
+            let iter: ScaledIter = Box::new(values.filter_map(move |v| match v {
+                Ok(v) => {
+                    let value = scale.apply(v.value);
+                    value.is_finite().then_some(Ok(Value { value, ..v }))
+                }
+                Err(e) => Some(Err(e)),
+            }));
+            let values = ScaledValues {
+                iter: iter.peekable(),
+            };
+            let read = do_read(chrom, values)?;
+            Ok(ChromDataState::NewChrom(read))
+        };
+
+        self.queued_reads.advance(begin_next)
+    }
+}
+
+type ScaledIter = Box<dyn Iterator<Item = Result<Value, BBIReadError>> + Send>;
+
+/// The scaled values of one chromosome.
+struct ScaledValues {
+    iter: std::iter::Peekable<ScaledIter>,
+}
+
+impl ChromValues for ScaledValues {
+    type Value = Value;
+    type Error = BBIReadError;
+
+    fn next(&mut self) -> Option<Result<Value, BBIReadError>> {
+        self.iter.next()
+    }
+
+    fn peek(&mut self) -> Option<Result<&Value, &BBIReadError>> {
+        self.iter.peek().map(Result::as_ref)
+    }
+}
@@ -0,0 +1,426 @@
+//! The coverage (or pileup) of bed entries, like reads or fragments, as a
+//! [`ChromData`] source for writing bigWigs.
+//!
+//! [`Pileup`] wraps any source of sorted [`BedEntry`]s (like a
+//! [`BedParserStreamingIterator`][crate::bedchromdata::BedParserStreamingIterator]
+//! of a bed file, or a [`MergedBigBeds`][crate::mergechromdata::MergedBigBeds]
+//! of one bigBed). Each value is the number of entries overlapping a base (or
+//! a bin of `bin_size` bases), multiplied by `scale`. Bases with no coverage
+//! are not written.
+//!
+//! With read extension, an entry covers `extend` bases from its 5' end, in the
+//! direction of its strand (the sixth column, where entries without one are
+//! treated as on the `+` strand).
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io;
+
+use crate::utils::chromvalues::ChromValues;
+use crate::{BedEntry, ChromData, ChromDataState, ChromProcessingFnOutput, Value};
+
+/// Which entries are counted, by strand.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PileupStrand {
+    Both,
+    /// Only entries on the `+` strand (or without a strand).
+    Plus,
+    /// Only entries on the `-` strand.
+    Minus,
+}
+
+/// Which entries are counted, and how coverage is computed from them.
+#[derive(Clone, Debug)]
+pub struct PileupOptions {
+    /// If set, each entry is extended (or truncated) to this many bases.
+    pub extend: Option<u32>,
+    pub strand: PileupStrand,
+    /// The number of bases in each bin. With `1`, coverage is per base.
+    pub bin_size: u32,
+    /// Every value is multiplied by this.
+    pub scale: f32,
+}
+
+impl Default for PileupOptions {
+    fn default() -> Self {
+        PileupOptions {
+            extend: None,
+            strand: PileupStrand::Both,
+            bin_size: 1,
+            scale: 1.0,
+        }
+    }
+}
+
+impl PileupOptions {
+    /// Whether an entry with the fields `rest` (after the end) is counted.
+    pub fn is_counted(&self, rest: &str) -> bool {
+        match self.strand {
+            PileupStrand::Both => true,
+            PileupStrand::Plus => !is_minus(rest),
+            PileupStrand::Minus => is_minus(rest),
+        }
+    }
+
+    /// The bases covered by an entry that is counted, truncated to `length`.
+    fn covered(&self, entry: &BedEntry, length: u32) -> Option<(u32, u32)> {
+        let (start, end) = match self.extend {
+            Some(extend) if is_minus(&entry.rest) => (entry.end.saturating_sub(extend), entry.end),
+            Some(extend) => (entry.start, entry.start.saturating_add(extend)),
+            None => (entry.start, entry.end),
+        };
+        let end = end.min(length);
+        (start < end).then_some((start, end))
+    }
+}
+
+fn is_minus(rest: &str) -> bool {
+    rest.split('\t').nth(2) == Some("-")
+}
+
+/// Turns reads into sorted, non-overlapping coverage values, keeping only the
+/// changes in coverage that may still be affected by later reads.
+pub(crate) struct CoverageSweep {
+    bin_size: u32,
+    scale: f32,
+    /// How far before its start a read can begin covering bases.
+    max_shift: u32,
+    /// The change in coverage at the start of each bin
+    deltas: BTreeMap<u32, i32>,
+    depth: i32,
+    run_start: u32,
+}
+
+impl CoverageSweep {
+    /// `max_shift` is how far before its start a read can begin covering
+    /// bases.
+    pub(crate) fn new(bin_size: u32, scale: f32, max_shift: u32) -> Self {
+        CoverageSweep {
+            bin_size,
+            scale,
+            max_shift,
+            deltas: BTreeMap::new(),
+            depth: 0,
+            run_start: 0,
+        }
+    }
+
+    /// Adds a read covering `blocks`, which must be sorted. A read is counted
+    /// at most once per bin.
+    pub(crate) fn add(&mut self, blocks: &[(u32, u32)]) {
+        let mut bins: Option<(u32, u32)> = None;
+        for &(start, end) in blocks {
+            let (first, last) = (start / self.bin_size, (end - 1) / self.bin_size + 1);
+            bins = match bins {
+                Some((s, e)) if first <= e => Some((s, e.max(last))),
+                Some((s, e)) => {
+                    self.add_bins(s, e);
+                    Some((first, last))
+                }
+                None => Some((first, last)),
+            };
+        }
+        if let Some((s, e)) = bins {
+            self.add_bins(s, e);
+        }
+    }
+
+    fn add_bins(&mut self, first: u32, last: u32) {
+        *self.deltas.entry(first).or_insert(0) += 1;
+        *self.deltas.entry(last).or_insert(0) -= 1;
+    }
+
+    /// Outputs all values before `read_start`, which no read starting at or
+    /// after `read_start` can change.
+    pub(crate) fn flush(&mut self, read_start: u32, length: u32, out: &mut VecDeque<Value>) {
+        let safe_bin = read_start.saturating_sub(self.max_shift) / self.bin_size;
+        self.flush_bins(Some(safe_bin), length, out);
+    }
+
+    pub(crate) fn finish(&mut self, length: u32, out: &mut VecDeque<Value>) {
+        self.flush_bins(None, length, out);
+    }
+
+    fn flush_bins(&mut self, until: Option<u32>, length: u32, out: &mut VecDeque<Value>) {
+        while let Some((&bin, &delta)) = self.deltas.iter().next() {
+            if until.is_some_and(|until| bin >= until) {
+                break;
+            }
+            self.deltas.remove(&bin);
+            if delta == 0 {
+                continue;
+            }
+            if self.depth != 0 && bin > self.run_start {
+                out.push_back(Value {
+                    start: self.run_start * self.bin_size,
+                    end: bin.saturating_mul(self.bin_size).min(length),
+                    value: self.depth as f32 * self.scale,
+                });
+            }
+            self.depth += delta;
+            self.run_start = bin;
+        }
+    }
+}
+
+/// The coverage of the entries of `data`, as a source for writing a bigWig.
+pub struct Pileup<D> {
+    data: D,
+    chrom_sizes: HashMap<String, u32>,
+    options: PileupOptions,
+}
+
+impl<D> Pileup<D> {
+    /// Coverage is truncated to the sizes in `chrom_sizes`.
+    pub fn new(
+        data: D,
+        chrom_sizes: HashMap<String, u32>,
+        options: PileupOptions,
+    ) -> Result<Self, io::Error> {
+        if options.bin_size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The bin size must be greater than 0.",
+            ));
+        }
+        Ok(Pileup {
+            data,
+            chrom_sizes,
+            options,
+        })
+    }
+}
+
+impl<D, V, E> ChromData<E> for Pileup<D>
+where
+    D: ChromData<E, Output = V>,
+    V: ChromValues<Value = BedEntry>,
+    E: From<io::Error>,
+{
+    type Output = PileupValues<V>;
+
+    fn advance<
+        F: FnMut(
+            String,
+            Self::Output,
+        ) -> Result<ChromProcessingFnOutput<<Self::Output as ChromValues>::Error>, E>,
+    >(
+        &mut self,
+        do_read: &mut F,
+    ) -> Result<ChromDataState<<Self::Output as ChromValues>::Error>, E> {
+        let (chrom_sizes, options) = (&self.chrom_sizes, &self.options);
+        self.data.advance(&mut |chrom: String, entries: V| {
+            // Unknown chromosomes are an error when writing
+            let length = chrom_sizes.get(&chrom).copied().unwrap_or(u32::MAX);
+            let values = PileupValues {
+                entries,
+                options: options.clone(),
+                length,
+                sweep: CoverageSweep::new(
+                    options.bin_size,
+                    options.scale,
+                    options.extend.unwrap_or(0),
+                ),
+                last_start: 0,
+                pending: VecDeque::new(),
+                error: None,
+                done: false,
+            };
+            do_read(chrom, values)
+        })
+    }
+}
+
+/// The coverage values of one chromosome of a [`Pileup`].
+pub struct PileupValues<V: ChromValues> {
+    entries: V,
+    options: PileupOptions,
+    length: u32,
+    sweep: CoverageSweep,
+    last_start: u32,
+    pending: VecDeque<Value>,
+    error: Option<V::Error>,
+    done: bool,
+}
+
+impl<V: ChromValues<Value = BedEntry>> PileupValues<V> {
+    fn fill(&mut self) -> Result<(), V::Error> {
+        while self.pending.is_empty() && !self.done {
+            match self.entries.next() {
+                Some(Ok(entry)) => {
+                    if entry.start < self.last_start {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "Input not sorted at {} (after {}). Sort with `sort -k1,1 -k2,2n`.",
+                                entry.start, self.last_start
+                            ),
+                        )
+                        .into());
+                    }
+                    self.last_start = entry.start;
+                    if !self.options.is_counted(&entry.rest) {
+                        continue;
+                    }
+                    self.sweep
+                        .flush(entry.start, self.length, &mut self.pending);
+                    if let Some(covered) = self.options.covered(&entry, self.length) {
+                        self.sweep.add(&[covered]);
+                    }
+                }
+                Some(Err(e)) => return Err(e),
+                None => {
+                    self.sweep.finish(self.length, &mut self.pending);
+                    self.done = true;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<V: ChromValues<Value = BedEntry>> ChromValues for PileupValues<V> {
+    type Value = Value;
+    type Error = V::Error;
+
+    fn next(&mut self) -> Option<Result<Value, V::Error>> {
+        if let Some(err) = self.error.take() {
+            return Some(Err(err));
+        }
+        if let Err(err) = self.fill() {
+            self.done = true;
+            return Some(Err(err));
+        }
+        self.pending.pop_front().map(Ok)
+    }
+
+    fn peek(&mut self) -> Option<Result<&Value, &V::Error>> {
+        if self.error.is_none() {
+            if let Err(err) = self.fill() {
+                self.done = true;
+                self.error = Some(err);
+            }
+        }
+        if let Some(err) = &self.error {
+            return Some(Err(err));
+        }
+        self.pending.front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sweep(options: &PileupOptions, reads: &[(u32, Vec<(u32, u32)>)]) -> Vec<Value> {
+        let mut sweep =
+            CoverageSweep::new(options.bin_size, options.scale, options.extend.unwrap_or(0));
+        let mut out = VecDeque::new();
+        for (start, blocks) in reads {
+            sweep.flush(*start, 100, &mut out);
+            sweep.add(blocks);
+        }
+        sweep.finish(100, &mut out);
+        out.into_iter().collect()
+    }
+
+    fn v(start: u32, end: u32, value: f32) -> Value {
+        Value { start, end, value }
+    }
+
+    #[test]
+    fn test_coverage_sweep() {
+        let options = PileupOptions::default();
+        let reads = [
+            (0, vec![(0, 10)]),
+            (5, vec![(5, 15)]),
+            (10, vec![(10, 12), (20, 25)]),
+            (30, vec![(30, 35)]),
+            (35, vec![(35, 40)]),
+        ];
+        assert_eq!(
+            sweep(&options, &reads),
+            vec![
+                v(0, 5, 1.0),
+                v(5, 12, 2.0),
+                v(12, 15, 1.0),
+                v(20, 25, 1.0),
+                v(30, 40, 1.0),
+            ]
+        );
+
+        // A read is counted once per bin, even if split, and the last bin is
+        // truncated to the chromosome length
+        let options = PileupOptions {
+            bin_size: 10,
+            scale: 0.5,
+            ..Default::default()
+        };
+        let reads = [
+            (0, vec![(0, 2), (4, 6)]),
+            (5, vec![(5, 15)]),
+            (40, vec![(40, 41), (95, 100)]),
+        ];
+        assert_eq!(
+            sweep(&options, &reads),
+            vec![
+                v(0, 10, 1.0),
+                v(10, 20, 0.5),
+                v(40, 50, 0.5),
+                v(90, 100, 0.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extended_sweep() {
+        // Reverse reads extended backwards can start before later reads
+        let options = PileupOptions {
+            extend: Some(10),
+            ..Default::default()
+        };
+        let reads = [
+            (10, vec![(10, 20)]),
+            (12, vec![(5, 15)]),
+            (30, vec![(30, 40)]),
+        ];
+        assert_eq!(
+            sweep(&options, &reads),
+            vec![
+                v(5, 10, 1.0),
+                v(10, 15, 2.0),
+                v(15, 20, 1.0),
+                v(30, 40, 1.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_covered() {
+        let entry = |start, end, rest: &str| BedEntry {
+            start,
+            end,
+            rest: rest.to_owned(),
+        };
+        let mut options = PileupOptions {
+            extend: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(options.covered(&entry(10, 15, ""), 100), Some((10, 30)));
+        assert_eq!(
+            options.covered(&entry(10, 15, "r\t0\t-"), 100),
+            Some((0, 15))
+        );
+        assert_eq!(options.covered(&entry(90, 95, ""), 100), Some((90, 100)));
+        options.extend = None;
+        assert_eq!(options.covered(&entry(10, 15, ""), 100), Some((10, 15)));
+
+        assert!(options.is_counted(""));
+        options.strand = PileupStrand::Plus;
+        assert!(options.is_counted(""));
+        assert!(options.is_counted("r\t0\t+"));
+        assert!(!options.is_counted("r\t0\t-"));
+        options.strand = PileupStrand::Minus;
+        assert!(options.is_counted("r\t0\t-"));
+        assert!(!options.is_counted("r\t0\t+"));
+    }
+}
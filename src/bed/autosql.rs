@@ -49,3 +49,42 @@ table bed
     def.push(')');
     def
 }
+
+/// A field of an autoSql table declaration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutosqlField {
+    /// The declared type, like `uint`, `char[1]` or `int[blockCount]`.
+    pub field_type: String,
+    pub name: String,
+}
+
+/// Parses the fields of an autoSql table declaration, ignoring comments.
+/// Returns `None` if there is no field list.
+pub fn parse_autosql_fields(autosql: &str) -> Option<Vec<AutosqlField>> {
+    let mut stripped = String::with_capacity(autosql.len());
+    let mut in_quotes = false;
+    for c in autosql.chars() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if !in_quotes {
+            stripped.push(c);
+        }
+    }
+    let start = stripped.find('(')? + 1;
+    let end = stripped.rfind(')')?;
+    if end < start {
+        return None;
+    }
+    stripped[start..end]
+        .split(';')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            let (field_type, name) = field.rsplit_once(char::is_whitespace)?;
+            Some(AutosqlField {
+                field_type: field_type.trim().to_owned(),
+                name: name.to_owned(),
+            })
+        })
+        .collect()
+}
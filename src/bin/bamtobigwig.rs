@@ -0,0 +1,142 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use clap::{App, Arg, ArgMatches};
+
+use bigtools::bamchromdata::{BamCoverage, BamCoverageOptions};
+use bigtools::bbi::BigWigWrite;
+use bigtools::normalize::Normalization;
+use bigtools::utils::chromsizes::load_chrom_sizes;
+
+fn parse_arg<T: FromStr>(
+    matches: &ArgMatches,
+    name: &str,
+    what: &str,
+) -> Result<T, Box<dyn Error>> {
+    let value = matches.value_of(name).unwrap();
+    value
+        .parse()
+        .map_err(|_| format!("Invalid argument for `{}`: must be {}", name, what).into())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = App::new("BamToBigWig")
+        .about("Computes the read coverage of an indexed BAM and writes it to a bigWig. Chromosomes are read and processed in parallel.")
+        .arg(Arg::new("bam")
+                .help("The coordinate-sorted BAM to compute coverage from. Must have a .bai or .csi index next to it.")
+                .index(1)
+                .required(true)
+            )
+        .arg(Arg::new("output")
+                .help("The output bigwig path")
+                .index(2)
+                .required(true)
+            )
+        .arg(Arg::new("chromsizes")
+                .long("chromsizes")
+                .help("A chromosome sizes file (or FASTA index, .2bit, bigWig or bigBed). If not given, the sizes from the BAM header are used.")
+                .takes_value(true))
+        .arg(Arg::new("binsize")
+                .short('b')
+                .long("binsize")
+                .help("Output the number of reads overlapping each bin of this many bases, rather than the coverage of each base.")
+                .takes_value(true)
+                .default_value("1"))
+        .arg(Arg::new("minmapq")
+                .short('q')
+                .long("min-mapq")
+                .help("Skip reads with a mapping quality less than this.")
+                .takes_value(true)
+                .default_value("0"))
+        .arg(Arg::new("keepduplicates")
+                .long("keep-duplicates")
+                .help("Count reads marked as duplicates. By default, they are skipped. Unmapped, secondary, supplementary, and QC-failed reads are always skipped."))
+        .arg(Arg::new("extend")
+                .short('e')
+                .long("extend")
+                .help("Extend (or truncate) each read to this many bases, in the direction of its strand. Spliced alignments are treated as a single block.")
+                .takes_value(true))
+        .arg(Arg::new("normalize")
+                .short('n')
+                .long("normalize")
+                .help("How to normalize for sequencing depth. `cpm` is counts per million counted reads; `rpkm` is additionally per kilobase of bin.")
+                .takes_value(true)
+                .possible_values(["none", "cpm", "rpkm"])
+                .default_value("none"))
+        .arg(Arg::new("scale")
+                .long("scale")
+                .help("Multiply all values by this, in addition to any normalization.")
+                .takes_value(true)
+                .default_value("1"))
+        .arg(Arg::new("nthreads")
+                .short('t')
+                .help("Set the number of threads to use.")
+                .takes_value(true)
+                .default_value("6"))
+        .arg(Arg::new("nzooms")
+                .short('z')
+                .help("Set the maximum of zooms to create.")
+                .takes_value(true)
+                .default_value("10"))
+        .arg(Arg::new("uncompressed")
+                .short('u')
+                .help("Don't use compression."))
+        .get_matches();
+
+    let bampath = PathBuf::from(matches.value_of("bam").unwrap());
+    let bigwigpath = matches.value_of("output").unwrap().to_owned();
+    let nthreads: usize = parse_arg(&matches, "nthreads", "a positive number")?;
+    let nzooms: u32 = parse_arg(&matches, "nzooms", "a positive number")?;
+    let uncompressed = matches.is_present("uncompressed");
+    let scale: f32 = parse_arg(&matches, "scale", "a number")?;
+
+    let mut options = BamCoverageOptions {
+        min_mapq: parse_arg(&matches, "minmapq", "between 0 and 255")?,
+        keep_duplicates: matches.is_present("keepduplicates"),
+        extend: match matches.value_of("extend") {
+            Some(_) => Some(parse_arg(&matches, "extend", "a positive number")?),
+            None => None,
+        },
+        bin_size: parse_arg(&matches, "binsize", "a positive number")?,
+        scale,
+    };
+    let normalization = match matches.value_of("normalize") {
+        Some("cpm") => Some(Normalization::Cpm(None)),
+        Some("rpkm") => Some(Normalization::Rpkm {
+            total: None,
+            bin_size: options.bin_size,
+        }),
+        _ => None,
+    };
+    if let Some(normalization) = normalization {
+        let total_reads =
+            BamCoverage::<()>::new(bampath.clone(), options.clone())?.count_reads(nthreads)?;
+        let factor = normalization.scale_for_count(total_reads)?.factor as f32;
+        eprintln!(
+            "Counted {} reads; scaling by {}",
+            total_reads,
+            factor * scale
+        );
+        options.scale = factor * scale;
+    }
+
+    let data = BamCoverage::new(bampath, options)?;
+    let chrom_map = match matches.value_of("chromsizes") {
+        Some(chromsizes) => load_chrom_sizes(chromsizes)?,
+        None => data.chrom_sizes().clone(),
+    };
+
+    let mut outb = BigWigWrite::create_file(bigwigpath);
+    outb.options.max_zooms = nzooms;
+    outb.options.compress = !uncompressed;
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(nthreads)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    outb.write(chrom_map, data, pool)?;
+
+    Ok(())
+}
@@ -1,7 +1,5 @@
-use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
 
 use bigtools::bed::indexer::index_chroms;
@@ -11,6 +9,7 @@ use clap::{App, Arg};
 use bigtools::bbi::BigWigWrite;
 use bigtools::bbiwrite::InputSortType;
 use bigtools::bed::bedparser::{parse_bedgraph, BedParser};
+use bigtools::utils::chromsizes::load_chrom_sizes;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let matches = App::new("BedGraphToBigWig")
@@ -21,7 +20,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .required(true)
             )
         .arg(Arg::new("chromsizes")
-                .help("A chromosome sizes file. Each line should be have a chromosome and its size in bases, separated by whitespace.")
+                .help("A chromosome sizes file. Each line should be have a chromosome and its size in bases, separated by whitespace. A FASTA index (.fai), a .2bit file, or an existing bigWig or bigBed can also be used.")
                 .index(2)
                 .required(true)
             )
@@ -100,21 +99,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     outb.options.max_zooms = nzooms;
     outb.options.compress = !uncompressed;
     outb.options.input_sort_type = input_sort_type;
-    let chrom_map: HashMap<String, u32> = BufReader::new(File::open(chrom_map)?)
-        .lines()
-        .filter(|l| match l {
-            Ok(s) => !s.is_empty(),
-            _ => true,
-        })
-        .map(|l| {
-            let words = l.expect("Split error");
-            let mut split = words.split_whitespace();
-            (
-                split.next().expect("Missing chrom").to_owned(),
-                split.next().expect("Missing size").parse::<u32>().unwrap(),
-            )
-        })
-        .collect();
+    let chrom_map = load_chrom_sizes(&chrom_map)?;
 
     let pool = futures::executor::ThreadPoolBuilder::new()
         .pool_size(nthreads)
@@ -132,7 +117,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     } else {
         let infile = File::open(&bedgraphpath)?;
         let large_file = infile.metadata()?.len() >= 200_000_000;
-        let parallel = matches.value_of("auto");
+        let parallel = matches.value_of("parallel");
         let parallel = match (nthreads, parallel) {
             (1, _) | (_, None) | (_, Some("auto")) => large_file,
             (_, Some("yes")) => true,
@@ -142,7 +127,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                     "Unexpected value for `parallel`: \"{}\". Defaulting to `auto`.",
                     v
                 );
-                true
+                large_file
             }
         };
         if parallel {
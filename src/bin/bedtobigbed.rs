@@ -1,7 +1,5 @@
-use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
 
 use bigtools::bedchromdata::BedParserStreamingIterator;
 use clap::{App, Arg};
@@ -9,6 +7,7 @@ use clap::{App, Arg};
 use bigtools::bbi::BigBedWrite;
 use bigtools::bbiwrite::InputSortType;
 use bigtools::bed::bedparser::BedParser;
+use bigtools::utils::chromsizes::load_chrom_sizes;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let matches = App::new("BedToBigBed")
@@ -18,7 +17,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .required(true)
             )
         .arg(Arg::new("chromsizes")
-                .help("A chromosome sizes file. Each line should be have a chromosome and its size in bases, separated by whitespace.")
+                .help("A chromosome sizes file. Each line should be have a chromosome and its size in bases, separated by whitespace. A FASTA index (.fai), a .2bit file, or an existing bigWig or bigBed can also be used.")
                 .index(2)
                 .required(true)
             )
@@ -94,21 +93,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     outb.options.max_zooms = nzooms;
     outb.options.compress = !uncompressed;
     outb.options.input_sort_type = input_sort_type;
-    let chrom_map: HashMap<String, u32> = BufReader::new(File::open(chrom_map)?)
-        .lines()
-        .filter(|l| match l {
-            Ok(s) => !s.is_empty(),
-            _ => true,
-        })
-        .map(|l| {
-            let words = l.expect("Split error");
-            let mut split = words.split_whitespace();
-            (
-                split.next().expect("Missing chrom").to_owned(),
-                split.next().expect("Missing size").parse::<u32>().unwrap(),
-            )
-        })
-        .collect();
+    let chrom_map = load_chrom_sizes(&chrom_map)?;
 
     let pool = futures::executor::ThreadPoolBuilder::new()
         .pool_size(nthreads)
@@ -0,0 +1,206 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::str::FromStr;
+
+use clap::{App, Arg, ArgMatches};
+
+use bigtools::bbi::{BigBedRead, BigWigWrite};
+use bigtools::bed::bedparser::BedParser;
+use bigtools::bedchromdata::BedParserStreamingIterator;
+use bigtools::mergechromdata::{BigBedMergeOptions, MergedBigBeds};
+use bigtools::normalize::Normalization;
+use bigtools::pileupchromdata::{Pileup, PileupOptions, PileupStrand};
+use bigtools::utils::chromsizes::load_chrom_sizes;
+use bigtools::{read_file_type, BBIFile};
+
+fn parse_arg<T: FromStr>(
+    matches: &ArgMatches,
+    name: &str,
+    what: &str,
+) -> Result<T, Box<dyn Error>> {
+    let value = matches.value_of(name).unwrap();
+    value
+        .parse()
+        .map_err(|_| format!("Invalid argument for `{}`: must be {}", name, what).into())
+}
+
+/// Counts the entries of a bed file that pass the filters in `options`.
+fn count_bed(path: &str, options: &PileupOptions) -> Result<u64, Box<dyn Error>> {
+    let mut total = 0;
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let rest = line.splitn(4, '\t').nth(3).unwrap_or("");
+        if options.is_counted(rest) {
+            total += 1;
+        }
+    }
+    Ok(total)
+}
+
+/// Counts the entries of a bigBed that pass the filters in `options`.
+fn count_bigbed(path: &str, options: &PileupOptions) -> Result<u64, Box<dyn Error>> {
+    let mut bigbed = BigBedRead::open_file(path.to_owned())?;
+    let chroms: Vec<(String, u32)> = bigbed
+        .info
+        .chrom_info
+        .iter()
+        .map(|c| (c.name.clone(), c.length))
+        .collect();
+    let mut total = 0;
+    for (chrom, length) in chroms {
+        for entry in bigbed.get_interval(&chrom, 0, length)? {
+            if options.is_counted(&entry?.rest) {
+                total += 1;
+            }
+        }
+    }
+    Ok(total)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let matches = App::new("BedToBigWig")
+        .about("Computes the coverage (pileup) of the entries of a bed or bigBed, like reads or fragments, and writes it to a bigWig.")
+        .arg(Arg::new("input")
+                .help("The bed (sorted with `sort -k1,1 -k2,2n`) or bigBed to compute coverage from. bigBeds are detected from their contents, whatever the extension.")
+                .index(1)
+                .required(true)
+            )
+        .arg(Arg::new("output")
+                .help("The output bigwig path")
+                .index(2)
+                .required(true)
+            )
+        .arg(Arg::new("chromsizes")
+                .long("chromsizes")
+                .help("A chromosome sizes file (or FASTA index, .2bit, bigWig or bigBed). Required for a bed; for a bigBed, its sizes are used if not given.")
+                .takes_value(true))
+        .arg(Arg::new("binsize")
+                .short('b')
+                .long("binsize")
+                .help("Output the number of entries overlapping each bin of this many bases, rather than the coverage of each base.")
+                .takes_value(true)
+                .default_value("1"))
+        .arg(Arg::new("extend")
+                .short('e')
+                .long("extend")
+                .help("Extend (or truncate) each entry to this many bases from its 5' end, in the direction of its strand (the sixth column). Entries without a strand are treated as on the + strand.")
+                .takes_value(true))
+        .arg(Arg::new("strand")
+                .long("strand")
+                .help("Only count the entries on this strand. Entries without a strand are counted as on the + strand.")
+                .takes_value(true)
+                .possible_values(["both", "plus", "minus"])
+                .default_value("both"))
+        .arg(Arg::new("normalize")
+                .short('n')
+                .long("normalize")
+                .help("How to normalize for sequencing depth. `cpm` is counts per million counted entries; `rpkm` is additionally per kilobase of bin.")
+                .takes_value(true)
+                .possible_values(["none", "cpm", "rpkm"])
+                .default_value("none"))
+        .arg(Arg::new("scale")
+                .long("scale")
+                .help("Multiply all values by this, in addition to any normalization.")
+                .takes_value(true)
+                .default_value("1"))
+        .arg(Arg::new("nthreads")
+                .short('t')
+                .help("Set the number of threads to use.")
+                .takes_value(true)
+                .default_value("6"))
+        .arg(Arg::new("nzooms")
+                .short('z')
+                .help("Set the maximum of zooms to create.")
+                .takes_value(true)
+                .default_value("10"))
+        .arg(Arg::new("uncompressed")
+                .short('u')
+                .help("Don't use compression."))
+        .get_matches();
+
+    let inpath = matches.value_of("input").unwrap();
+    let bigwigpath = matches.value_of("output").unwrap().to_owned();
+    let nthreads: usize = parse_arg(&matches, "nthreads", "a positive number")?;
+    let nzooms: u32 = parse_arg(&matches, "nzooms", "a positive number")?;
+    let uncompressed = matches.is_present("uncompressed");
+    let scale: f32 = parse_arg(&matches, "scale", "a number")?;
+    let bigbed = match read_file_type(&mut File::open(inpath)?)? {
+        Some(BBIFile::BigBed) => true,
+        Some(BBIFile::BigWig) => {
+            return Err("The input must be a bed or bigBed, not a bigWig.".into())
+        }
+        None => false,
+    };
+
+    let mut options = PileupOptions {
+        extend: match matches.value_of("extend") {
+            Some(_) => Some(parse_arg(&matches, "extend", "a positive number")?),
+            None => None,
+        },
+        strand: match matches.value_of("strand") {
+            Some("plus") => PileupStrand::Plus,
+            Some("minus") => PileupStrand::Minus,
+            _ => PileupStrand::Both,
+        },
+        bin_size: parse_arg(&matches, "binsize", "a positive number")?,
+        scale,
+    };
+    let normalization = match matches.value_of("normalize") {
+        Some("cpm") => Some(Normalization::Cpm(None)),
+        Some("rpkm") => Some(Normalization::Rpkm {
+            total: None,
+            bin_size: options.bin_size,
+        }),
+        _ => None,
+    };
+    if let Some(normalization) = normalization {
+        let total = if bigbed {
+            count_bigbed(inpath, &options)?
+        } else {
+            count_bed(inpath, &options)?
+        };
+        let factor = normalization.scale_for_count(total)?.factor as f32;
+        eprintln!("Counted {} entries; scaling by {}", total, factor * scale);
+        options.scale = factor * scale;
+    }
+
+    let mut outb = BigWigWrite::create_file(bigwigpath);
+    outb.options.max_zooms = nzooms;
+    outb.options.compress = !uncompressed;
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(nthreads)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    if bigbed {
+        let input = BigBedRead::open_file(inpath.to_owned())?;
+        let data = MergedBigBeds::new(vec![input], BigBedMergeOptions::default())?;
+        let chrom_map = match matches.value_of("chromsizes") {
+            Some(chromsizes) => load_chrom_sizes(chromsizes)?,
+            None => data.chrom_sizes().clone(),
+        };
+        let data = Pileup::new(data, chrom_map.clone(), options)?;
+        outb.write(chrom_map, data, pool)?;
+    } else {
+        let chrom_map = match matches.value_of("chromsizes") {
+            Some(chromsizes) => load_chrom_sizes(chromsizes)?,
+            None => return Err("`--chromsizes` is required for a bed input.".into()),
+        };
+        let vals_iter = BedParser::from_bed_file(File::open(inpath)?);
+        let data = BedParserStreamingIterator::new(vals_iter, false);
+        let data = Pileup::new(data, chrom_map.clone(), options)?;
+        outb.write(chrom_map, data, pool)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::error::Error;
+use std::fs::File;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+
+use clap::{App, Arg, ArgMatches};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use bigtools::arrowdata::{bigbed_batches, bigbed_schema, DEFAULT_BATCH_SIZE};
+use bigtools::bbi::{BBIRead, BigBedRead};
+
+fn parse_arg<T: FromStr>(
+    matches: &ArgMatches,
+    name: &str,
+    what: &str,
+) -> Result<T, Box<dyn Error>> {
+    let value = matches.value_of(name).unwrap();
+    value
+        .parse()
+        .map_err(|_| format!("Invalid argument for `{}`: must be {}", name, what).into())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let default_batch_size = DEFAULT_BATCH_SIZE.to_string();
+    let matches = App::new("BigBedToParquet")
+        .about("Converts an input bigBed to a Parquet file, sorted by chromosome and start. There is a column for each field of the bigBed's autoSql, typed by the autoSql types.")
+        .arg(Arg::new("bigbed")
+            .help("the bigbed to convert to parquet")
+            .index(1)
+            .required(true)
+        )
+        .arg(Arg::new("parquet")
+            .help("the path of the parquet file to output to")
+            .index(2)
+            .required(true)
+        )
+        .arg(Arg::new("batchsize")
+            .long("batch-size")
+            .help("The number of rows to convert and write at a time.")
+            .takes_value(true)
+            .default_value(&default_batch_size))
+        .arg(Arg::new("uncompressed")
+            .short('u')
+            .help("Don't use compression."))
+        .get_matches();
+
+    let bigbedpath = matches.value_of("bigbed").unwrap().to_owned();
+    let parquetpath = matches.value_of("parquet").unwrap();
+    let batch_size = parse_arg::<NonZeroUsize>(&matches, "batchsize", "a positive number")?.get();
+    let compression = if matches.is_present("uncompressed") {
+        Compression::UNCOMPRESSED
+    } else {
+        Compression::SNAPPY
+    };
+
+    let mut bigbed = BigBedRead::open_file(bigbedpath)?;
+    let schema = bigbed_schema(&bigbed.autosql()?);
+    let props = WriterProperties::builder()
+        .set_compression(compression)
+        .build();
+    let mut writer = ArrowWriter::try_new(File::create(parquetpath)?, schema.clone(), Some(props))?;
+
+    let mut chroms = bigbed.get_chroms();
+    chroms.sort_by(|a, b| a.name.cmp(&b.name));
+    for chrom in chroms {
+        let batches = bigbed_batches(
+            &mut bigbed,
+            schema.clone(),
+            &chrom.name,
+            0,
+            chrom.length,
+            batch_size,
+        )?;
+        for batch in batches {
+            writer.write(&batch?)?;
+        }
+    }
+    writer.close()?;
+
+    Ok(())
+}
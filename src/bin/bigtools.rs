@@ -1,114 +1,124 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
 use std::fs::File;
-use std::io::{self, BufReader, BufWriter, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
-use bigtools::{BigWigRead, BigWigReadAttachError};
+use bigtools::bbicopy::{rename_chroms, BBICopy, CopyZooms};
+use bigtools::bed::bedparser::BedParser;
+use bigtools::bedchromdata::BedParserStreamingIterator;
+use bigtools::mergechromdata::{
+    AutosqlReconcile, BigBedMergeOptions, MergedBigBeds, MergedBigWigs,
+};
+use bigtools::normalize::{normalize, Normalization};
+use bigtools::utils::calc::{CalcCombiner, Expr, MissingData};
+use bigtools::utils::chromsizes::load_chrom_sizes;
+use bigtools::utils::correlate::{
+    bin_values, correlate, CorrelateBins, CorrelateOptions, Correlation, PairSummary,
+};
+use bigtools::utils::histogram::{
+    distribution, Distribution, HistogramBins, HistogramOptions, QuantileMethod,
+};
+use bigtools::utils::liftover::{liftover_entries, liftover_values, Chains, Lifted, LiftoverError};
+use bigtools::utils::matrix::{self, MatrixMode, MatrixOptions, MatrixRegion, ReferencePoint};
+use bigtools::utils::threshold::{
+    threshold_chroms, ThresholdOptions, ThresholdRegion, THRESHOLD_AUTOSQL,
+};
+use bigtools::{
+    read_file_type, BBIFile, BBIReadError, BedEntry, BigBedWrite, BigWigRead, BigWigWrite,
+    WriteReport,
+};
 use clap::{App, Arg};
 
-use bigtools::bbi::{BigBedRead, BigBedReadAttachError};
-use bigtools::utils::reopen::SeekableRead;
+use bigtools::bbi::BigBedRead;
+use bigtools::utils::intersect::{
+    self, IntersectError, IntersectMode, IntersectOptions, IntersectSource, IntersectSummary,
+    UnknownChroms,
+};
+use bigtools::utils::reopen::ReopenableFile;
 use bigtools::utils::streaming_linereader::StreamingLineReader;
 
-struct IntersectOptions {}
-
-fn intersect<R: SeekableRead + 'static>(
+fn intersect(
     apath: String,
-    mut b: BigBedRead<R>,
-    _options: IntersectOptions,
-) -> io::Result<()> {
-    let bedin = File::open(apath)?;
-    let mut bedstream = StreamingLineReader::new(BufReader::with_capacity(64 * 1024, bedin));
-
-    let stdout = io::stdout();
-    let handle = stdout.lock();
-    let mut bedoutwriter = BufWriter::with_capacity(64 * 1024, handle);
+    bpath: String,
+    outpath: String,
+    options: IntersectOptions,
+    nthreads: usize,
+) -> Result<(), Box<dyn Error>> {
+    // A bigBed `a` is intersected by walking both files in order
+    if let Some(BBIFile::BigBed) = read_file_type(&mut File::open(&apath)?)? {
+        if !matches!(
+            read_file_type(&mut File::open(&bpath)?)?,
+            Some(BBIFile::BigBed)
+        ) {
+            return Err("When `a` is a bigBed, `b` must also be a bigBed.".into());
+        }
+        let a = BigBedRead::open_file(apath)?;
+        let b = BigBedRead::open_file(bpath)?;
+        if outpath == "-" {
+            let stdout = io::stdout();
+            let out = BufWriter::with_capacity(64 * 1024, stdout.lock());
+            intersect::intersect_bigbeds(&a, &b, &options, nthreads, out)?;
+        } else {
+            let out = BufWriter::with_capacity(64 * 1024, File::create(outpath)?);
+            intersect::intersect_bigbeds(&a, &b, &options, nthreads, out)?;
+        }
+        return Ok(());
+    }
+    let a = BufReader::with_capacity(64 * 1024, File::open(apath)?);
+    fn run<B: IntersectSource>(
+        a: BufReader<File>,
+        mut b: B,
+        outpath: String,
+        options: IntersectOptions,
+    ) -> Result<(), IntersectError> {
+        if outpath == "-" {
+            let stdout = io::stdout();
+            let out = BufWriter::with_capacity(64 * 1024, stdout.lock());
+            intersect::intersect(a, &mut b, &options, out)
+        } else {
+            let out = BufWriter::with_capacity(64 * 1024, File::create(outpath)?);
+            intersect::intersect(a, &mut b, &options, out)
+        }
+    }
 
-    while let Some(line) = bedstream.read() {
-        let line = line?;
-        let mut split = line.trim_end().splitn(4, '\t');
-        let chrom = split.next().ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Missing chrom: {}", line),
-            )
-        })?;
-        let start = split
-            .next()
-            .ok_or_else(|| {
-                io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("Missing start: {}", line),
-                )
-            })?
-            .parse::<u32>()
-            .map_err(|_| {
-                io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("Invalid start: {:}", line),
-                )
-            })?;
-        let end = split
-            .next()
-            .ok_or_else(|| {
-                io::Error::new(io::ErrorKind::InvalidData, format!("Missing end: {}", line))
-            })?
-            .parse::<u32>()
-            .map_err(|_| {
-                io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("Invalid end: {:}", line),
-                )
-            })?;
-        let interval = b.get_interval(chrom, start, end);
-        let interval = match interval {
-            Ok(interval) => interval,
-            Err(e) => {
-                eprintln!(
-                    "An error occured when intersecting {}:{}-{}: {}",
-                    chrom, start, end, e
-                );
-                continue;
+    match read_file_type(&mut File::open(&bpath)?)? {
+        Some(BBIFile::BigWig) => run(a, BigWigRead::open_file(&bpath)?, outpath, options)?,
+        Some(BBIFile::BigBed) => {
+            if let IntersectMode::Summary(_) = options.mode {
+                return Err("Summaries are only supported when `b` is a bigWig.".into());
             }
-        };
-
-        for raw_val in interval {
-            let val = match raw_val {
-                Ok(val) => val,
-                Err(e) => {
-                    eprintln!(
-                        "An error occured when intersecting {}:{}-{}: {}",
-                        chrom, start, end, e
-                    );
-                    continue;
-                }
-            };
-            bedoutwriter.write_fmt(format_args!(
-                "{}\t{}\t{}\t{}\n",
-                chrom, val.start, val.end, val.rest
-            ))?;
+            run(a, BigBedRead::open_file(bpath)?, outpath, options)?
         }
+        None => return Err("Only bigWigs and bigBeds are supported as `b` files.".into()),
     }
-
     Ok(())
 }
 
-fn chromintersect(apath: String, bpath: String, outpath: String) -> io::Result<()> {
-    let chroms = match BigWigRead::open_file(&bpath) {
-        Ok(bigwig) => bigwig.info.chrom_info,
-        Err(BigWigReadAttachError::NotABigWig) => match BigBedRead::open_file(bpath) {
-            Ok(bigbed) => bigbed.info.chrom_info,
-            Err(BigBedReadAttachError::NotABigBed) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    format!("Only bigWigs and bigBeds are supported as `b` files."),
-                ));
-            }
-            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}", e))),
-        },
-        Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}", e))),
+fn chromintersect(
+    apath: String,
+    bpath: String,
+    outpath: String,
+    matches: &clap::ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    let chroms = match read_file_type(&mut File::open(&bpath)?)? {
+        Some(BBIFile::BigWig) => BigWigRead::open_file(&bpath)?.info.chrom_info,
+        Some(BBIFile::BigBed) => BigBedRead::open_file(bpath)?.info.chrom_info,
+        None => return Err("Only bigWigs and bigBeds are supported as `b` files.".into()),
     };
     let chroms = HashSet::from_iter(chroms.into_iter().map(|c| c.name));
 
+    // bigWigs and bigBeds are copied without re-encoding their data
+    let is_bbi = read_file_type(&mut File::open(&apath)?)?.is_some();
+    if is_bbi {
+        if outpath == "-" {
+            return Err("bigWig and bigBed output can't be written to stdout.".into());
+        }
+        let copy = copy_options(matches, outpath.clone());
+        return subset(apath, outpath, chroms, false, copy);
+    }
+
     fn write<T: Write>(
         chroms: HashSet<String>,
         apath: String,
@@ -147,24 +157,513 @@ fn chromintersect(apath: String, bpath: String, outpath: String) -> io::Result<(
     Ok(())
 }
 
-fn main() -> Result<(), BigBedReadAttachError> {
+enum CopyInputs {
+    BigWig(Vec<BigWigRead<ReopenableFile>>),
+    BigBed(Vec<BigBedRead<ReopenableFile>>),
+}
+
+/// Opens all inputs, which must all be bigWigs or all be bigBeds
+fn open_copy_inputs(paths: &[String]) -> Result<CopyInputs, Box<dyn Error>> {
+    let filetype = read_file_type(&mut File::open(&paths[0])?)?;
+    for path in &paths[1..] {
+        if read_file_type(&mut File::open(path)?)? != filetype {
+            return Err(format!(
+                "{} is not the same type of file as {}. The inputs must all be bigWigs or all be bigBeds.",
+                path, paths[0]
+            )
+            .into());
+        }
+    }
+    match filetype {
+        Some(BBIFile::BigWig) => {
+            let inputs = paths
+                .iter()
+                .map(|p| BigWigRead::open_file(p))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(CopyInputs::BigWig(inputs))
+        }
+        Some(BBIFile::BigBed) => {
+            let inputs = paths
+                .iter()
+                .map(|p| BigBedRead::open_file(p.clone()))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(CopyInputs::BigBed(inputs))
+        }
+        None => Err(format!("{} is not a bigWig or bigBed.", paths[0]).into()),
+    }
+}
+
+fn copy_options(matches: &clap::ArgMatches, outpath: String) -> BBICopy {
+    let mut copy = BBICopy::create_file(outpath);
+    copy.zooms = match matches.value_of("zooms").unwrap() {
+        "copy" => CopyZooms::Copy,
+        "regenerate" => CopyZooms::Regenerate,
+        _ => CopyZooms::Auto,
+    };
+    copy.options.compress = !matches.is_present("uncompressed");
+    copy
+}
+
+fn print_copy_report(report: &WriteReport) {
+    eprintln!(
+        "Copied {} chromosomes ({} sections, {} zoom levels written)",
+        report.chrom_ids.len(),
+        report.total_sections,
+        report
+            .zooms
+            .iter()
+            .filter(|z| z.status == bigtools::ZoomLevelStatus::Written)
+            .count()
+    );
+}
+
+fn subset(
+    inpath: String,
+    outpath: String,
+    chroms: HashSet<String>,
+    exclude: bool,
+    copy: BBICopy,
+) -> Result<(), Box<dyn Error>> {
+    copy.check_inputs(&[&inpath])?;
+    let keep = |chrom: &str| chroms.contains(chrom) != exclude;
+    let report = match open_copy_inputs(&[inpath])? {
+        CopyInputs::BigWig(mut inputs) => copy.subset(inputs.remove(0), keep)?,
+        CopyInputs::BigBed(mut inputs) => copy.subset(inputs.remove(0), keep)?,
+    };
+    print_copy_report(&report);
+    eprintln!("Wrote {}", outpath);
+    Ok(())
+}
+
+fn cat(
+    inpaths: Vec<String>,
+    outpath: String,
+    replace: bool,
+    copy: BBICopy,
+) -> Result<(), Box<dyn Error>> {
+    copy.check_inputs(&inpaths)?;
+    let report = match open_copy_inputs(&inpaths)? {
+        CopyInputs::BigWig(inputs) => copy.concat(inputs, replace)?,
+        CopyInputs::BigBed(inputs) => copy.concat(inputs, replace)?,
+    };
+    print_copy_report(&report);
+    eprintln!("Wrote {}", outpath);
+    Ok(())
+}
+
+/// Splits `calc` inputs (`name=path` or `path`) into their names and paths.
+fn calc_inputs(inputs: &[&str]) -> Result<(Vec<String>, Vec<String>), Box<dyn Error>> {
+    let is_name = |name: &str| {
+        name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+    };
+    let mut names = vec![];
+    let mut paths = vec![];
+    for (i, input) in inputs.iter().enumerate() {
+        let (name, path) = match input.split_once('=') {
+            Some((name, path)) if is_name(name) => (name.to_owned(), path),
+            _ if i < 26 => (((b'a' + i as u8) as char).to_string(), *input),
+            _ => {
+                return Err(format!(
+                    "Input `{}` needs a name (as `name=path`): only the first 26 inputs are named by position.",
+                    input
+                )
+                .into())
+            }
+        };
+        if names.contains(&name) {
+            return Err(format!("More than one input is named `{}`.", name).into());
+        }
+        names.push(name);
+        paths.push(path.to_owned());
+    }
+    Ok((names, paths))
+}
+
+/// A bed entry, with its chromosome, start, end and the rest of its columns.
+type BedLine = (String, u32, u32, Vec<String>);
+
+/// Reads the entries of a bed file, skipping header lines.
+fn read_bed(path: &str) -> Result<Vec<BedLine>, Box<dyn Error>> {
+    let mut entries = vec![];
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("track")
+            || line.starts_with("browser")
+        {
+            continue;
+        }
+        let mut split = line.split('\t');
+        let chrom = split.next().unwrap();
+        let mut coord = |what: &str| {
+            split
+                .next()
+                .and_then(|s| s.parse::<u32>().ok())
+                .ok_or_else(|| format!("Invalid {}: {}", what, line))
+        };
+        let start = coord("start")?;
+        let end = coord("end")?;
+        let rest = split.map(|s| s.to_owned()).collect();
+        entries.push((chrom.to_owned(), start, end, rest));
+    }
+    Ok(entries)
+}
+
+/// Reads the regions of a bed file for `matrix`, with their names (the fourth
+/// column, or `chrom:start-end`). The sixth column is the strand.
+fn matrix_regions(path: &str) -> Result<Vec<(MatrixRegion, String)>, Box<dyn Error>> {
+    Ok(read_bed(path)?
+        .into_iter()
+        .map(|(chrom, start, end, rest)| {
+            let name = rest
+                .first()
+                .cloned()
+                .unwrap_or_else(|| format!("{}:{}-{}", chrom, start, end));
+            let minus_strand = rest.get(2).is_some_and(|s| s == "-");
+            let region = MatrixRegion {
+                chrom,
+                start,
+                end,
+                minus_strand,
+            };
+            (region, name)
+        })
+        .collect())
+}
+
+/// Escapes a string for JSON output.
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// A number for JSON output (`null` if it isn't finite).
+fn json_number(v: f64) -> String {
+    if v.is_finite() {
+        v.to_string()
+    } else {
+        "null".to_owned()
+    }
+}
+
+fn write_correlation<W: Write>(
+    mut out: W,
+    names: &[&str],
+    correlation: &Correlation,
+    json: bool,
+) -> io::Result<()> {
+    let summary_fields = |s: &PairSummary| {
+        [
+            s.mean_a,
+            s.mean_b,
+            s.sd_a,
+            s.sd_b,
+            s.slope,
+            s.intercept,
+            s.pearson,
+            s.spearman,
+        ]
+    };
+    const SUMMARY_NAMES: [&str; 8] = [
+        "mean_a",
+        "mean_b",
+        "sd_a",
+        "sd_b",
+        "slope",
+        "intercept",
+        "pearson",
+        "spearman",
+    ];
+    if json {
+        let matrix = |m: &Vec<Vec<f64>>| {
+            let rows: Vec<String> = m
+                .iter()
+                .map(|r| {
+                    let r: Vec<String> = r.iter().map(|v| json_number(*v)).collect();
+                    format!("[{}]", r.join(", "))
+                })
+                .collect();
+            format!("[{}]", rows.join(", "))
+        };
+        let names: Vec<String> = names.iter().map(|n| json_string(n)).collect();
+        let pairs: Vec<String> = correlation
+            .pairs
+            .iter()
+            .map(|(i, j, s)| {
+                let fields: Vec<String> = SUMMARY_NAMES
+                    .iter()
+                    .zip(summary_fields(s))
+                    .map(|(name, v)| format!("\"{}\": {}", name, json_number(v)))
+                    .collect();
+                format!(
+                    "{{\"a\": {}, \"b\": {}, \"bins\": {}, {}}}",
+                    names[*i],
+                    names[*j],
+                    s.bins,
+                    fields.join(", ")
+                )
+            })
+            .collect();
+        writeln!(
+            out,
+            "{{\"names\": [{}], \"pearson\": {}, \"spearman\": {}, \"pairs\": [{}]}}",
+            names.join(", "),
+            matrix(&correlation.pearson),
+            matrix(&correlation.spearman),
+            pairs.join(", ")
+        )?;
+    } else {
+        for (method, matrix) in [
+            ("pearson", &correlation.pearson),
+            ("spearman", &correlation.spearman),
+        ] {
+            writeln!(out, "#{}\t{}", method, names.join("\t"))?;
+            for (name, row) in names.iter().zip(matrix.iter()) {
+                let row: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+                writeln!(out, "{}\t{}", name, row.join("\t"))?;
+            }
+        }
+        writeln!(out, "#a\tb\tbins\t{}", SUMMARY_NAMES.join("\t"))?;
+        for (i, j, s) in correlation.pairs.iter() {
+            let fields: Vec<String> = summary_fields(s).iter().map(|v| v.to_string()).collect();
+            writeln!(
+                out,
+                "{}\t{}\t{}\t{}",
+                names[*i],
+                names[*j],
+                s.bins,
+                fields.join("\t")
+            )?;
+        }
+    }
+    out.flush()
+}
+
+fn write_distribution<W: Write>(mut out: W, distribution: &Distribution) -> io::Result<()> {
+    writeln!(out, "#quantile\tvalue")?;
+    for (q, value) in distribution.quantiles.iter() {
+        writeln!(out, "{}\t{}", q, value)?;
+    }
+    writeln!(out, "#chrom\tbases\tcovered\tcoverage")?;
+    for chrom in distribution.chroms.iter() {
+        writeln!(
+            out,
+            "{}\t{}\t{}\t{}",
+            chrom.chrom,
+            chrom.bases,
+            chrom.covered,
+            chrom.coverage()
+        )?;
+    }
+    let histogram = &distribution.histogram;
+    writeln!(out, "#start\tend\tbases")?;
+    let (first, last) = (
+        histogram.edges[0],
+        histogram.edges[histogram.edges.len() - 1],
+    );
+    writeln!(out, "-inf\t{}\t{}", first, histogram.underflow)?;
+    for (edges, count) in histogram.edges.windows(2).zip(histogram.counts.iter()) {
+        writeln!(out, "{}\t{}\t{}", edges[0], edges[1], count)?;
+    }
+    writeln!(out, "{}\tinf\t{}", last, histogram.overflow)?;
+    out.flush()
+}
+
+struct LiftoverOptions {
+    unmapped: Option<String>,
+    chrom_sizes: Option<String>,
+    min_fraction: f64,
+    nthreads: usize,
+    compress: bool,
+}
+
+/// The unmapped records of a liftover, written (if asked for) as they're found.
+struct UnmappedOut {
+    out: Option<BufWriter<File>>,
+    count: usize,
+}
+
+/// The mapped records of `lifted`, for writing. The unmapped records are
+/// written to `unmapped` with `write_record`.
+fn mapped_records<T: Send + 'static>(
+    lifted: impl Iterator<Item = Result<Lifted<T>, LiftoverError>> + Send + 'static,
+    unmapped: Arc<Mutex<UnmappedOut>>,
+    write_record: impl Fn(&mut BufWriter<File>, &str, &T) -> io::Result<()> + Send + 'static,
+) -> impl Iterator<Item = Result<(String, T), io::Error>> + Send + 'static {
+    lifted.filter_map(move |r| match r {
+        Ok(Lifted::Mapped(chrom, record)) => Some(Ok((chrom, record))),
+        Ok(Lifted::Unmapped(chrom, record)) => {
+            let mut unmapped = unmapped.lock().unwrap();
+            unmapped.count += 1;
+            let written = match unmapped.out.as_mut() {
+                Some(out) => write_record(out, &chrom, &record),
+                None => Ok(()),
+            };
+            written.err().map(Err)
+        }
+        Err(e) => Some(Err(io::Error::other(e))),
+    })
+}
+
+fn liftover(
+    inpath: String,
+    chains: Chains,
+    outpath: String,
+    options: LiftoverOptions,
+) -> Result<(), Box<dyn Error>> {
+    let chrom_sizes = match &options.chrom_sizes {
+        Some(path) => load_chrom_sizes(path)?,
+        None => chains.target_sizes(),
+    };
+    let chains = Arc::new(chains);
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(options.nthreads)
+        .create()
+        .expect("Unable to create thread pool.");
+    let out = match &options.unmapped {
+        Some(path) => Some(BufWriter::new(File::create(path)?)),
+        None => None,
+    };
+    let unmapped = Arc::new(Mutex::new(UnmappedOut { out, count: 0 }));
+    let find_unmapped = options.unmapped.is_some();
+    let report = match read_file_type(&mut File::open(&inpath)?)? {
+        Some(BBIFile::BigWig) => {
+            let input = BigWigRead::open_file(&inpath)?;
+            let lifted = liftover_values(input, chains, find_unmapped);
+            let values = mapped_records(lifted, unmapped.clone(), |out, chrom, v| {
+                writeln!(out, "{}\t{}\t{}\t{}", chrom, v.start, v.end, v.value)
+            });
+            let data = BedParserStreamingIterator::new(BedParser::wrap_iter(values), false);
+            let mut outb = BigWigWrite::create_file(outpath.clone());
+            outb.options.compress = options.compress;
+            outb.write(chrom_sizes, data, pool)?
+        }
+        Some(BBIFile::BigBed) => {
+            let mut input = BigBedRead::open_file(inpath)?;
+            let autosql = input.autosql()?;
+            let lifted = liftover_entries(input, chains, options.min_fraction, find_unmapped);
+            let entries = mapped_records(lifted, unmapped.clone(), |out, chrom, e| {
+                write!(out, "{}\t{}\t{}", chrom, e.start, e.end)?;
+                if !e.rest.is_empty() {
+                    write!(out, "\t{}", e.rest)?;
+                }
+                writeln!(out)
+            });
+            let data = BedParserStreamingIterator::new(BedParser::wrap_iter(entries), false);
+            let mut outb = BigBedWrite::create_file(outpath.clone());
+            outb.autosql = (!autosql.is_empty()).then_some(autosql);
+            outb.options.compress = options.compress;
+            outb.write(chrom_sizes, data, pool)?
+        }
+        None => return Err("Only bigWigs and bigBeds can be lifted over.".into()),
+    };
+    let mut unmapped = unmapped.lock().unwrap();
+    if let Some(out) = unmapped.out.as_mut() {
+        out.flush()?;
+    }
+    eprintln!(
+        "Wrote {} mapped records to {} ({} unmapped)",
+        report.summary.total_items, outpath, unmapped.count
+    );
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
     let matches = App::new("BigTools")
         .subcommand(
             App::new("intersect")
-                .about("Intersect all entries of a bed with a bigBed")
+                .about("Intersect all entries of a bed with a bigBed or bigWig, like `bedtools intersect`. By default, the overlapping part of `a` is written for each overlap. If `a` and `b` are both bigBeds, they are intersected by walking both in order, one chromosome per thread, and the output is sorted by chromosome.")
                 .arg(
                     Arg::new("a")
                         .short('a')
-                        .help("Each entry in this bed is compared against b for overlaps.")
+                        .help("Each entry in this bed or bigBed is compared against b for overlaps.")
                         .takes_value(true)
                         .required(true),
                 )
                 .arg(
                     Arg::new("b")
                         .short('b')
-                        .help("Each entry in a will be compared against this bigBed for overlaps.")
+                        .help("Each entry in a will be compared against this bigBed or bigWig for overlaps.")
                         .takes_value(true)
                         .required(true),
+                )
+                .arg(
+                    Arg::new("out")
+                        .short('o')
+                        .help("The name of the output file (or - for stdout)")
+                        .takes_value(true)
+                        .default_value("-"),
+                )
+                .arg(
+                    Arg::new("wa")
+                        .long("wa")
+                        .help("Write the original entry in a for each overlap."),
+                )
+                .arg(
+                    Arg::new("wb")
+                        .long("wb")
+                        .help("Write the overlapping entry (or value) in b after each overlap."),
+                )
+                .arg(
+                    Arg::new("unique")
+                        .short('u')
+                        .help("Write each entry in a once if it has any overlaps.")
+                        .conflicts_with_all(&["wa", "wb", "noverlap", "count", "summary"]),
+                )
+                .arg(
+                    Arg::new("noverlap")
+                        .short('v')
+                        .help("Only write the entries in a that have no overlaps.")
+                        .conflicts_with_all(&["wa", "wb", "count", "summary"]),
+                )
+                .arg(
+                    Arg::new("count")
+                        .short('c')
+                        .help("Write each entry in a with the number of overlaps.")
+                        .conflicts_with_all(&["wa", "wb", "summary"]),
+                )
+                .arg(
+                    Arg::new("summary")
+                        .long("summary")
+                        .help("Write each entry in a with a summary of the overlapping values of a bigWig. `mean`, `min` and `max` are `NaN` with no overlaps; `coverage` is the fraction of bases covered.")
+                        .takes_value(true)
+                        .possible_values(["mean", "min", "max", "sum", "coverage"])
+                        .conflicts_with_all(&["wa", "wb"]),
+                )
+                .arg(
+                    Arg::new("fraction")
+                        .short('f')
+                        .help("The minimum overlap, as a fraction of the entry in a.")
+                        .takes_value(true)
+                        .default_value("0"),
+                )
+                .arg(
+                    Arg::new("unknownchroms")
+                        .long("unknown-chroms")
+                        .help("How to handle entries in a on chromosomes that aren't in b: treat them as having no overlaps, or error.")
+                        .takes_value(true)
+                        .possible_values(["nooverlaps", "error"])
+                        .default_value("nooverlaps"),
+                )
+                .arg(
+                    Arg::new("nthreads")
+                        .short('t')
+                        .help("Set the number of threads to use, when `a` is a bigBed")
+                        .takes_value(true)
+                        .default_value("6"),
                 ),
         )
         .subcommand(
@@ -173,7 +672,7 @@ fn main() -> Result<(), BigBedReadAttachError> {
                 .arg(
                     Arg::new("a")
                         .short('a')
-                        .help("The file to take data from (currently supports: bed)")
+                        .help("The file to take data from (supports: bed, bigWig or bigBed)")
                         .takes_value(true)
                         .required(true),
                 )
@@ -187,9 +686,611 @@ fn main() -> Result<(), BigBedReadAttachError> {
                 .arg(
                     Arg::new("out")
                         .short('o')
-                        .help("The name of the output file (or - for stdout). Outputted in same format as `a`")
+                        .help("The name of the output file (or - for stdout, for a bed). Outputted in same format as `a`")
                         .takes_value(true)
                         .required(true),
+                )
+                .arg(
+                    Arg::new("zooms")
+                        .long("zooms")
+                        .help("For a bigWig or bigBed, whether to copy zoom levels from `a` or regenerate them. `auto` copies when possible.")
+                        .takes_value(true)
+                        .possible_values(["auto", "copy", "regenerate"])
+                        .default_value("auto"),
+                )
+                .arg(
+                    Arg::new("uncompressed")
+                        .short('u')
+                        .help("For a bigWig or bigBed, don't use compression."),
+                ),
+        )
+        .subcommand(
+            App::new("subset")
+                .about("Create a new bigWig or bigBed containing only some chromosomes of the input, without re-encoding the data")
+                .arg(
+                    Arg::new("input")
+                        .help("The input bigWig or bigBed")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("The output file")
+                        .index(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("chrom")
+                        .long("chrom")
+                        .help("A chromosome to keep. Can be given multiple times.")
+                        .takes_value(true)
+                        .multiple_occurrences(true),
+                )
+                .arg(
+                    Arg::new("chromsfile")
+                        .long("chromsfile")
+                        .help("A file of chromosomes to keep, as the first column of each line (e.g. a chrom.sizes file)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("exclude")
+                        .long("exclude")
+                        .help("Keep all chromosomes except the given ones"),
+                )
+                .arg(
+                    Arg::new("zooms")
+                        .long("zooms")
+                        .help("Whether to copy zoom levels from the input or regenerate them. `auto` copies when possible.")
+                        .takes_value(true)
+                        .possible_values(["auto", "copy", "regenerate"])
+                        .default_value("auto"),
+                )
+                .arg(
+                    Arg::new("uncompressed")
+                        .short('u')
+                        .help("Don't use compression."),
+                ),
+        )
+        .subcommand(
+            App::new("cat")
+                .about("Concatenate the chromosomes of multiple bigWigs or bigBeds into one file, without re-encoding the data")
+                .arg(
+                    Arg::new("inputs")
+                        .help("The input files, all either bigWigs or bigBeds")
+                        .multiple_values(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .help("The output file")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("replace")
+                        .long("replace")
+                        .help("If a chromosome is in more than one input, take it from the last one instead of erroring. Useful to replace the data of a chromosome."),
+                )
+                .arg(
+                    Arg::new("zooms")
+                        .long("zooms")
+                        .help("Whether to copy zoom levels from the inputs or regenerate them. `auto` copies when all inputs have the same zoom levels.")
+                        .takes_value(true)
+                        .possible_values(["auto", "copy", "regenerate"])
+                        .default_value("auto"),
+                )
+                .arg(
+                    Arg::new("uncompressed")
+                        .short('u')
+                        .help("Don't use compression."),
+                ),
+        )
+        .subcommand(
+            App::new("renamechroms")
+                .about("Rename the chromosomes of a bigWig or bigBed, rewriting only the chromosome tree")
+                .arg(
+                    Arg::new("input")
+                        .help("The input bigWig or bigBed")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("The output file. Can be the same as the input to rename in place.")
+                        .index(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("map")
+                        .long("map")
+                        .help("A tab-separated file with the current name in the first column and the new name in the second")
+                        .takes_value(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("calc")
+                .about("Evaluate an arithmetic expression over the values of bigWigs, writing a bigWig")
+                .arg(
+                    Arg::new("expression")
+                        .help("The expression, like `log2((a + 1) / (b + 1))`. Supports numbers, the input names, + - * /, parentheses, log, log2, log10, exp, abs, sqrt, min and max.")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("The output bigWig")
+                        .index(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("inputs")
+                        .help("The input bigWigs, as `name=path` or `path`. Unnamed inputs are named by their position: `a`, `b`, `c`, ...")
+                        .index(3)
+                        .multiple_values(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("missing")
+                        .long("missing")
+                        .help("How inputs without data are handled, where another input has data: their value is `nan` or `zero`, or the region is skipped. Regions where the expression isn't finite are not written.")
+                        .takes_value(true)
+                        .possible_values(["nan", "zero", "skip"])
+                        .default_value("nan"),
+                )
+                .arg(
+                    Arg::new("pseudocount")
+                        .long("pseudocount")
+                        .help("Added to the value of every input before evaluating.")
+                        .takes_value(true)
+                        .default_value("0"),
+                )
+                .arg(
+                    Arg::new("nthreads")
+                        .short('t')
+                        .help("Set the number of threads to use")
+                        .takes_value(true)
+                        .default_value("6"),
+                )
+                .arg(
+                    Arg::new("uncompressed")
+                        .short('u')
+                        .help("Don't use compression."),
+                ),
+        )
+        .subcommand(
+            App::new("normalize")
+                .about("Rewrite a bigWig with normalized values. The zoom levels are recomputed.")
+                .arg(
+                    Arg::new("input")
+                        .help("The input bigWig")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("The output bigWig")
+                        .index(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("mode")
+                        .long("mode")
+                        .help("How to normalize. `scale` multiplies by `--factor`; `cpm` scales to per million of `--total` (by default, the total signal: the sum of each value times its bases); `rpkm` is additionally per kilobase of `--bin-size`; `zscore` and `mean` use the mean and standard deviation over the covered bases.")
+                        .takes_value(true)
+                        .possible_values(["scale", "cpm", "rpkm", "zscore", "mean"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("factor")
+                        .long("factor")
+                        .help("The factor for `scale`")
+                        .takes_value(true)
+                        .required_if_eq("mode", "scale"),
+                )
+                .arg(
+                    Arg::new("total")
+                        .long("total")
+                        .help("The total for `cpm` and `rpkm`, like the number of mapped reads")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("binsize")
+                        .long("bin-size")
+                        .help("The bin size for `rpkm`")
+                        .takes_value(true)
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::new("nthreads")
+                        .short('t')
+                        .help("Set the number of threads to use")
+                        .takes_value(true)
+                        .default_value("6"),
+                )
+                .arg(
+                    Arg::new("uncompressed")
+                        .short('u')
+                        .help("Don't use compression."),
+                ),
+        )
+        .subcommand(
+            App::new("matrix")
+                .about("Compute a matrix of the signal of bigWigs over bins of regions, like deepTools computeMatrix")
+                .arg(
+                    Arg::new("regions")
+                        .help("The regions, as a bed file. The fourth column is the name and the sixth is the strand, if given.")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("The output file")
+                        .index(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("bigwigs")
+                        .help("The input bigWigs")
+                        .index(3)
+                        .multiple_values(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("mode")
+                        .long("mode")
+                        .help("With `reference-point`, bins are placed around the `--reference-point` of each region. With `scale-regions`, each region is scaled to `--body` bases, between its flanks.")
+                        .takes_value(true)
+                        .possible_values(["reference-point", "scale-regions"])
+                        .default_value("reference-point"),
+                )
+                .arg(
+                    Arg::new("referencepoint")
+                        .long("reference-point")
+                        .help("The point of each region for `reference-point`, in the direction of its strand.")
+                        .takes_value(true)
+                        .possible_values(["start", "end", "center"])
+                        .default_value("start"),
+                )
+                .arg(
+                    Arg::new("upstream")
+                        .short('b')
+                        .long("upstream")
+                        .help("The number of bases upstream of the reference point or region")
+                        .takes_value(true)
+                        .default_value("1000"),
+                )
+                .arg(
+                    Arg::new("downstream")
+                        .short('a')
+                        .long("downstream")
+                        .help("The number of bases downstream of the reference point or region")
+                        .takes_value(true)
+                        .default_value("1000"),
+                )
+                .arg(
+                    Arg::new("body")
+                        .long("body")
+                        .help("The number of bases each region is scaled to, for `scale-regions`")
+                        .takes_value(true)
+                        .default_value("1000"),
+                )
+                .arg(
+                    Arg::new("binsize")
+                        .long("bin-size")
+                        .help("The size of each bin. The flank and body sizes must be multiples of it.")
+                        .takes_value(true)
+                        .default_value("10"),
+                )
+                .arg(
+                    Arg::new("summary")
+                        .long("summary")
+                        .help("The summary of the values in each bin. `mean`, `min` and `max` are over the covered bases; `sum` is of each value times its bases; `coverage` is the fraction of bases covered.")
+                        .takes_value(true)
+                        .possible_values(["mean", "min", "max", "sum", "coverage"])
+                        .default_value("mean"),
+                )
+                .arg(
+                    Arg::new("missingaszero")
+                        .long("missing-as-zero")
+                        .help("Bins without values are 0, instead of `nan`."),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("The output format. `tsv` has a line for each region, with its chrom, start, end, name and strand, followed by the bins of each bigWig. `npy` is an array with the shape (bigWigs, regions, bins). Defaults to `npy` if the output ends with `.npy`, or `tsv` otherwise.")
+                        .takes_value(true)
+                        .possible_values(["tsv", "npy"]),
+                )
+                .arg(
+                    Arg::new("nthreads")
+                        .short('t')
+                        .help("Set the number of threads to use")
+                        .takes_value(true)
+                        .default_value("6"),
+                ),
+        )
+        .subcommand(
+            App::new("threshold")
+                .about("Call the regions of a bigWig where the signal is greater than a threshold, writing a bed or bigBed. Each region has its max, mean and summit.")
+                .arg(
+                    Arg::new("input")
+                        .help("The input bigWig")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("The output bed or bigBed. Use `-` for a bed to stdout.")
+                        .index(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("threshold")
+                        .long("threshold")
+                        .help("Bases with a value greater than this pass")
+                        .takes_value(true)
+                        .allow_hyphen_values(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("minlength")
+                        .long("min-length")
+                        .help("The minimum length of a region")
+                        .takes_value(true)
+                        .default_value("1"),
+                )
+                .arg(
+                    Arg::new("maxgap")
+                        .long("max-gap")
+                        .help("Regions separated by fewer bases than this are merged")
+                        .takes_value(true)
+                        .default_value("0"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("The output format. Defaults to `bigbed` if the output ends with `.bb` or `.bigBed`, or `bed` otherwise.")
+                        .takes_value(true)
+                        .possible_values(["bed", "bigbed"]),
+                )
+                .arg(
+                    Arg::new("nthreads")
+                        .short('t')
+                        .help("Set the number of threads to use")
+                        .takes_value(true)
+                        .default_value("6"),
+                )
+                .arg(
+                    Arg::new("uncompressed")
+                        .short('u')
+                        .help("Don't use compression."),
+                ),
+        )
+        .subcommand(
+            App::new("correlate")
+                .about("Compute the pairwise Pearson and Spearman correlations of bigWigs over bins, with a summary of the scatter of each pair")
+                .arg(
+                    Arg::new("output")
+                        .help("The output file. Use `-` for stdout.")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("bigwigs")
+                        .help("The input bigWigs")
+                        .index(2)
+                        .multiple_values(true)
+                        .min_values(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("binsize")
+                        .long("bin-size")
+                        .help("The size of the bins over every chromosome. The value of each bin is the mean over its bases, with bases without values as 0.")
+                        .takes_value(true)
+                        .default_value("10000"),
+                )
+                .arg(
+                    Arg::new("regions")
+                        .long("regions")
+                        .help("A bed file of regions to use as the bins, instead of bins of `--bin-size`")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("blacklist")
+                        .long("blacklist")
+                        .help("A bed file of regions. Bins overlapping them are excluded.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("skipzeros")
+                        .long("skip-zeros")
+                        .help("Exclude bins that are 0 in every bigWig"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .help("The output format. `tsv` has the Pearson and Spearman matrices, followed by a line for each pair. Defaults to `json` if the output ends with `.json`, or `tsv` otherwise.")
+                        .takes_value(true)
+                        .possible_values(["tsv", "json"]),
+                )
+                .arg(
+                    Arg::new("nthreads")
+                        .short('t')
+                        .help("Set the number of threads to use")
+                        .takes_value(true)
+                        .default_value("6"),
+                ),
+        )
+        .subcommand(
+            App::new("histogram")
+                .about("Compute the distribution of the values of a bigWig, weighted by the bases they cover: quantiles, the coverage of each chromosome, and a histogram")
+                .arg(
+                    Arg::new("input")
+                        .help("The input bigWig")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("The output file. Use `-` for stdout.")
+                        .index(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("bins")
+                        .long("bins")
+                        .help("The number of bins of the histogram")
+                        .takes_value(true)
+                        .default_value("100"),
+                )
+                .arg(
+                    Arg::new("min")
+                        .long("min")
+                        .help("The start of the first bin. Defaults to the minimum value of the bigWig.")
+                        .takes_value(true)
+                        .allow_hyphen_values(true),
+                )
+                .arg(
+                    Arg::new("max")
+                        .long("max")
+                        .help("The end of the last bin. Defaults to the maximum value of the bigWig.")
+                        .takes_value(true)
+                        .allow_hyphen_values(true),
+                )
+                .arg(
+                    Arg::new("log")
+                        .long("log")
+                        .help("Use bins of equal width in log space. The minimum must be positive."),
+                )
+                .arg(
+                    Arg::new("quantiles")
+                        .long("quantiles")
+                        .help("The quantiles to compute, separated by commas")
+                        .takes_value(true)
+                        .default_value("0.01,0.05,0.25,0.5,0.75,0.95,0.99"),
+                )
+                .arg(
+                    Arg::new("exact")
+                        .long("exact")
+                        .help("Compute exact quantiles, from the bases of each distinct value, instead of approximating them with a t-digest. This may use a lot of memory."),
+                )
+                .arg(
+                    Arg::new("compression")
+                        .long("compression")
+                        .help("The compression of the t-digest. Higher is more accurate.")
+                        .takes_value(true)
+                        .default_value("100"),
+                )
+                .arg(
+                    Arg::new("regions")
+                        .long("regions")
+                        .help("A bed file of regions to read, instead of every chromosome")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("nthreads")
+                        .short('t')
+                        .help("Set the number of threads to use")
+                        .takes_value(true)
+                        .default_value("6"),
+                ),
+        )
+        .subcommand(
+            App::new("bigbedmerge")
+                .about("Merge the entries of multiple bigBeds into one bigBed, in chromosome and start order")
+                .arg(
+                    Arg::new("output")
+                        .help("The output bigBed")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("inputs")
+                        .help("The input bigBeds")
+                        .index(2)
+                        .multiple_values(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("source")
+                        .long("source")
+                        .help("Add the input file of each entry as a last column"),
+                )
+                .arg(
+                    Arg::new("autosql")
+                        .long("autosql")
+                        .help("How inputs with different autosql are handled: error, or only keep the fields at the start that are common to every input.")
+                        .takes_value(true)
+                        .possible_values(["error", "common"])
+                        .default_value("error"),
+                )
+                .arg(
+                    Arg::new("nthreads")
+                        .short('t')
+                        .help("Set the number of threads to use")
+                        .takes_value(true)
+                        .default_value("6"),
+                )
+                .arg(
+                    Arg::new("uncompressed")
+                        .short('u')
+                        .help("Don't use compression."),
+                ),
+        )
+        .subcommand(
+            App::new("liftover")
+                .about("Map the values of a bigWig or the entries of a bigBed to another assembly with a UCSC chain file")
+                .arg(
+                    Arg::new("input")
+                        .help("The input bigWig or bigBed")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("chain")
+                        .help("The chain file (optionally gzipped)")
+                        .index(2)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .help("The output bigWig or bigBed (the same type as the input)")
+                        .index(3)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("unmapped")
+                        .long("unmapped")
+                        .help("Write the records (or parts of bigWig values) that couldn't be mapped to this file, as bedGraph or bed lines")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("chromsizes")
+                        .long("chromsizes")
+                        .help("The chromosome sizes of the target assembly (or FASTA index, .2bit, bigWig or bigBed). If not given, the sizes in the chain file are used.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("minfraction")
+                        .long("min-fraction")
+                        .help("The minimum fraction of the bases of a bigBed entry that must map for it to be mapped")
+                        .takes_value(true)
+                        .default_value("0.95"),
+                )
+                .arg(
+                    Arg::new("nthreads")
+                        .short('t')
+                        .help("Set the number of threads to use")
+                        .takes_value(true)
+                        .default_value("6"),
+                )
+                .arg(
+                    Arg::new("uncompressed")
+                        .short('u')
+                        .help("Don't use compression."),
                 ),
         )
         .get_matches();
@@ -200,10 +1301,48 @@ fn main() -> Result<(), BigBedReadAttachError> {
 
             let apath = matches.value_of("a").unwrap().to_owned();
             let bpath = matches.value_of("b").unwrap().to_owned();
+            let outpath = matches.value_of("out").unwrap().to_owned();
 
-            let b = BigBedRead::open_file(bpath)?;
+            let mode = if matches.is_present("unique") {
+                IntersectMode::Unique
+            } else if matches.is_present("noverlap") {
+                IntersectMode::NoOverlap
+            } else if matches.is_present("count") {
+                IntersectMode::Count
+            } else if let Some(summary) = matches.value_of("summary") {
+                IntersectMode::Summary(match summary {
+                    "mean" => IntersectSummary::Mean,
+                    "min" => IntersectSummary::Min,
+                    "max" => IntersectSummary::Max,
+                    "sum" => IntersectSummary::Sum,
+                    _ => IntersectSummary::Coverage,
+                })
+            } else {
+                IntersectMode::Overlaps {
+                    write_a: matches.is_present("wa"),
+                    write_b: matches.is_present("wb"),
+                }
+            };
+            let min_fraction = match matches.value_of("fraction").unwrap().parse::<f64>() {
+                Ok(f) if (0.0..=1.0).contains(&f) => f,
+                _ => return Err("Invalid argument for `f`: must be between 0 and 1".into()),
+            };
+            let unknown_chroms = match matches.value_of("unknownchroms") {
+                Some("error") => UnknownChroms::Error,
+                _ => UnknownChroms::NoOverlaps,
+            };
+            let options = IntersectOptions {
+                mode,
+                min_fraction,
+                unknown_chroms,
+            };
+            let nthreads = matches
+                .value_of("nthreads")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| "Invalid argument for `nthreads`: must be a positive number")?;
 
-            intersect(apath, b, IntersectOptions {})?;
+            intersect(apath, bpath, outpath, options, nthreads)?;
         }
         Some(("chromintersect", matches)) => {
             eprintln!("---BigTools chromintersect---");
@@ -212,7 +1351,573 @@ fn main() -> Result<(), BigBedReadAttachError> {
             let bpath = matches.value_of("b").unwrap().to_owned();
             let outpath = matches.value_of("out").unwrap().to_owned();
 
-            chromintersect(apath, bpath, outpath)?;
+            chromintersect(apath, bpath, outpath, matches)?;
+        }
+        Some(("subset", matches)) => {
+            eprintln!("---BigTools subset---");
+
+            let inpath = matches.value_of("input").unwrap().to_owned();
+            let outpath = matches.value_of("output").unwrap().to_owned();
+            let mut chroms: HashSet<String> = matches
+                .values_of("chrom")
+                .map(|c| c.map(|c| c.to_owned()).collect())
+                .unwrap_or_default();
+            if let Some(chromsfile) = matches.value_of("chromsfile") {
+                let reader = BufReader::new(File::open(chromsfile)?);
+                for line in reader.lines() {
+                    let line = line?;
+                    if let Some(chrom) = line.split_whitespace().next() {
+                        chroms.insert(chrom.to_owned());
+                    }
+                }
+            }
+            let exclude = matches.is_present("exclude");
+            let copy = copy_options(matches, outpath.clone());
+
+            subset(inpath, outpath, chroms, exclude, copy)?;
+        }
+        Some(("cat", matches)) => {
+            eprintln!("---BigTools cat---");
+
+            let inpaths: Vec<String> = matches
+                .values_of("inputs")
+                .unwrap()
+                .map(|p| p.to_owned())
+                .collect();
+            let outpath = matches.value_of("output").unwrap().to_owned();
+            let replace = matches.is_present("replace");
+            let copy = copy_options(matches, outpath.clone());
+
+            cat(inpaths, outpath, replace, copy)?;
+        }
+        Some(("renamechroms", matches)) => {
+            eprintln!("---BigTools renamechroms---");
+
+            let inpath = matches.value_of("input").unwrap();
+            let outpath = matches.value_of("output").unwrap();
+            let mappath = matches.value_of("map").unwrap();
+
+            let mut renames = HashMap::new();
+            let reader = BufReader::new(File::open(mappath)?);
+            for (i, line) in reader.lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let mut split = line.split_whitespace();
+                match (split.next(), split.next()) {
+                    (Some(from), Some(to)) => {
+                        renames.insert(from.to_owned(), to.to_owned());
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Invalid line {} in {}: expected two columns",
+                            i + 1,
+                            mappath
+                        )
+                        .into());
+                    }
+                }
+            }
+
+            rename_chroms(inpath, outpath, &renames)?;
+        }
+        Some(("calc", matches)) => {
+            eprintln!("---BigTools calc---");
+
+            let expression = matches.value_of("expression").unwrap();
+            let outpath = matches.value_of("output").unwrap().to_owned();
+            let inputs: Vec<&str> = matches.values_of("inputs").unwrap().collect();
+            let missing = match matches.value_of("missing").unwrap() {
+                "zero" => MissingData::Zero,
+                "skip" => MissingData::Skip,
+                _ => MissingData::NaN,
+            };
+            let pseudocount = matches
+                .value_of("pseudocount")
+                .unwrap()
+                .parse::<f64>()
+                .map_err(|_| "Invalid argument for `pseudocount`: must be a number")?;
+            let nthreads = matches
+                .value_of("nthreads")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| "Invalid argument for `nthreads`: must be a positive number")?;
+
+            let (names, paths) = calc_inputs(&inputs)?;
+            let names: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+            let combiner = CalcCombiner {
+                expr: Expr::parse(expression, &names)?,
+                missing,
+                pseudocount,
+            };
+            let inputs = paths
+                .iter()
+                .map(|p| BigWigRead::open_file(p))
+                .collect::<Result<Vec<_>, _>>()?;
+            let data = MergedBigWigs::new(inputs, combiner)?;
+
+            let mut outb = BigWigWrite::create_file(outpath.clone());
+            outb.options.compress = !matches.is_present("uncompressed");
+            let pool = futures::executor::ThreadPoolBuilder::new()
+                .pool_size(nthreads)
+                .create()
+                .expect("Unable to create thread pool.");
+            outb.write(data.chrom_sizes().clone(), data, pool)?;
+            eprintln!("Wrote {}", outpath);
+        }
+        Some(("normalize", matches)) => {
+            eprintln!("---BigTools normalize---");
+
+            let inpath = matches.value_of("input").unwrap();
+            let outpath = matches.value_of("output").unwrap().to_owned();
+            let number = |name: &str| -> Result<Option<f64>, Box<dyn Error>> {
+                match matches.value_of(name) {
+                    None => Ok(None),
+                    Some(v) => match v.parse::<f64>() {
+                        Ok(v) => Ok(Some(v)),
+                        Err(_) => {
+                            Err(format!("Invalid argument for `{}`: must be a number", name).into())
+                        }
+                    },
+                }
+            };
+            let normalization = match matches.value_of("mode").unwrap() {
+                "scale" => Normalization::Scale(number("factor")?.unwrap()),
+                "cpm" => Normalization::Cpm(number("total")?),
+                "rpkm" => Normalization::Rpkm {
+                    total: number("total")?,
+                    bin_size: matches.value_of("binsize").unwrap().parse().map_err(|_| {
+                        "Invalid argument for `bin-size`: must be a positive number"
+                    })?,
+                },
+                "zscore" => Normalization::ZScore,
+                _ => Normalization::Mean,
+            };
+            let nthreads = matches
+                .value_of("nthreads")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| "Invalid argument for `nthreads`: must be a positive number")?;
+
+            let input = BigWigRead::open_file(inpath)?;
+            let mut outb = BigWigWrite::create_file(outpath.clone());
+            outb.options.compress = !matches.is_present("uncompressed");
+            let pool = futures::executor::ThreadPoolBuilder::new()
+                .pool_size(nthreads)
+                .create()
+                .expect("Unable to create thread pool.");
+            let report = normalize(input, outb, normalization, pool)?;
+            if let Some(scale) = report.scale {
+                eprintln!(
+                    "Scaled values by {} (with an offset of {})",
+                    scale.factor, scale.offset
+                );
+            }
+            eprintln!("Wrote {}", outpath);
+        }
+        Some(("matrix", matches)) => {
+            eprintln!("---BigTools matrix---");
+
+            let regionspath = matches.value_of("regions").unwrap();
+            let outpath = matches.value_of("output").unwrap();
+            let bigwigs: Vec<&str> = matches.values_of("bigwigs").unwrap().collect();
+            let length = |name: &str| -> Result<u32, Box<dyn Error>> {
+                matches.value_of(name).unwrap().parse::<u32>().map_err(|_| {
+                    format!("Invalid argument for `{}`: must be a positive number", name).into()
+                })
+            };
+            let (upstream, downstream) = (length("upstream")?, length("downstream")?);
+            let mode = match matches.value_of("mode").unwrap() {
+                "scale-regions" => MatrixMode::ScaleRegions {
+                    upstream,
+                    body: length("body")?,
+                    downstream,
+                },
+                _ => MatrixMode::ReferencePoint {
+                    point: match matches.value_of("referencepoint").unwrap() {
+                        "end" => ReferencePoint::End,
+                        "center" => ReferencePoint::Center,
+                        _ => ReferencePoint::Start,
+                    },
+                    upstream,
+                    downstream,
+                },
+            };
+            let summary = match matches.value_of("summary").unwrap() {
+                "min" => IntersectSummary::Min,
+                "max" => IntersectSummary::Max,
+                "sum" => IntersectSummary::Sum,
+                "coverage" => IntersectSummary::Coverage,
+                _ => IntersectSummary::Mean,
+            };
+            let options = MatrixOptions {
+                mode,
+                bin_size: length("binsize")?,
+                summary,
+                missing_as_zero: matches.is_present("missingaszero"),
+            };
+            let npy = match matches.value_of("format") {
+                Some(format) => format == "npy",
+                None => outpath.ends_with(".npy"),
+            };
+            let nthreads = matches
+                .value_of("nthreads")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| "Invalid argument for `nthreads`: must be a positive number")?;
+
+            let (regions, names): (Vec<_>, Vec<_>) =
+                matrix_regions(regionspath)?.into_iter().unzip();
+            let matrices = bigwigs
+                .iter()
+                .map(|path| -> Result<_, Box<dyn Error>> {
+                    let bigwig = BigWigRead::open_file(path)?;
+                    Ok(matrix::matrix(&bigwig, &regions, &options, nthreads)?)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let mut out = BufWriter::new(File::create(outpath)?);
+            if npy {
+                matrix::write_npy(&mut out, &matrices)?;
+            } else {
+                write!(out, "#chrom\tstart\tend\tname\tstrand")?;
+                for path in bigwigs.iter() {
+                    for bin in 0..options.bins()? {
+                        write!(out, "\t{}:{}", path, bin)?;
+                    }
+                }
+                writeln!(out)?;
+                for (i, (region, name)) in regions.iter().zip(names.iter()).enumerate() {
+                    let strand = if region.minus_strand { '-' } else { '+' };
+                    write!(
+                        out,
+                        "{}\t{}\t{}\t{}\t{}",
+                        region.chrom, region.start, region.end, name, strand
+                    )?;
+                    for matrix in matrices.iter() {
+                        for value in matrix.row(i) {
+                            write!(out, "\t{}", value)?;
+                        }
+                    }
+                    writeln!(out)?;
+                }
+            }
+            eprintln!(
+                "Wrote {} regions by {} bins for {} bigWigs to {}",
+                regions.len(),
+                options.bins()?,
+                bigwigs.len(),
+                outpath
+            );
+        }
+        Some(("threshold", matches)) => {
+            eprintln!("---BigTools threshold---");
+
+            let inpath = matches.value_of("input").unwrap();
+            let outpath = matches.value_of("output").unwrap().to_owned();
+            let threshold = matches
+                .value_of("threshold")
+                .unwrap()
+                .parse::<f32>()
+                .map_err(|_| "Invalid argument for `threshold`: must be a number")?;
+            let length = |name: &str| -> Result<u32, Box<dyn Error>> {
+                matches.value_of(name).unwrap().parse::<u32>().map_err(|_| {
+                    format!("Invalid argument for `{}`: must be a positive number", name).into()
+                })
+            };
+            let options = ThresholdOptions {
+                threshold,
+                min_length: length("minlength")?,
+                max_gap: length("maxgap")?,
+            };
+            let bigbed = match matches.value_of("format") {
+                Some(format) => format == "bigbed",
+                None => outpath.ends_with(".bb") || outpath.ends_with(".bigBed"),
+            };
+            let nthreads = matches
+                .value_of("nthreads")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| "Invalid argument for `nthreads`: must be a positive number")?;
+
+            let input = BigWigRead::open_file(inpath)?;
+            let chroms = threshold_chroms(&input, options, nthreads)?;
+            let count;
+            if bigbed {
+                if outpath == "-" {
+                    return Err("bigBed output can't be written to stdout.".into());
+                }
+                let chrom_sizes = input
+                    .info
+                    .chrom_info
+                    .iter()
+                    .map(|c| (c.name.clone(), c.length))
+                    .collect();
+                // The entries are read by the writer's threads
+                let written = Arc::new(AtomicUsize::new(0));
+                let counter = written.clone();
+                let entries = chroms.flat_map(move |chrom| {
+                    let (chrom, regions) = match chrom {
+                        Ok(chrom) => chrom,
+                        Err(e) => return vec![Err(io::Error::new(io::ErrorKind::Other, e))],
+                    };
+                    counter.fetch_add(regions.len(), Ordering::Relaxed);
+                    regions
+                        .into_iter()
+                        .map(|r| {
+                            let entry = BedEntry {
+                                start: r.start,
+                                end: r.end,
+                                rest: r.rest(),
+                            };
+                            Ok((chrom.clone(), entry))
+                        })
+                        .collect()
+                });
+                let data = BedParserStreamingIterator::new(BedParser::wrap_iter(entries), false);
+                let mut outb = BigBedWrite::create_file(outpath.clone());
+                outb.autosql = Some(THRESHOLD_AUTOSQL.to_owned());
+                outb.options.compress = !matches.is_present("uncompressed");
+                let pool = futures::executor::ThreadPoolBuilder::new()
+                    .pool_size(nthreads)
+                    .create()
+                    .expect("Unable to create thread pool.");
+                outb.write(chrom_sizes, data, pool)?;
+                count = written.load(Ordering::Relaxed);
+            } else {
+                fn write<W: Write>(
+                    chroms: impl Iterator<Item = Result<(String, Vec<ThresholdRegion>), BBIReadError>>,
+                    mut out: W,
+                ) -> Result<usize, Box<dyn Error>> {
+                    let mut count = 0;
+                    for chrom in chroms {
+                        let (chrom, regions) = chrom?;
+                        count += regions.len();
+                        for r in regions {
+                            writeln!(out, "{}\t{}\t{}\t{}", chrom, r.start, r.end, r.rest())?;
+                        }
+                    }
+                    out.flush()?;
+                    Ok(count)
+                }
+                if outpath == "-" {
+                    let stdout = io::stdout();
+                    count = write(chroms, BufWriter::new(stdout.lock()))?;
+                } else {
+                    count = write(chroms, BufWriter::new(File::create(&outpath)?))?;
+                }
+            }
+            eprintln!("Wrote {} regions to {}", count, outpath);
+        }
+        Some(("correlate", matches)) => {
+            eprintln!("---BigTools correlate---");
+
+            let outpath = matches.value_of("output").unwrap();
+            let paths: Vec<&str> = matches.values_of("bigwigs").unwrap().collect();
+            let regions = |path: Option<&str>| -> Result<_, Box<dyn Error>> {
+                Ok(match path {
+                    Some(path) => read_bed(path)?
+                        .into_iter()
+                        .map(|(chrom, start, end, _)| (chrom, start, end))
+                        .collect(),
+                    None => vec![],
+                })
+            };
+            let bins = match matches.value_of("regions") {
+                Some(path) => CorrelateBins::Regions(regions(Some(path))?),
+                None => {
+                    CorrelateBins::Fixed(matches.value_of("binsize").unwrap().parse().map_err(
+                        |_| "Invalid argument for `bin-size`: must be a positive number",
+                    )?)
+                }
+            };
+            let options = CorrelateOptions {
+                bins,
+                blacklist: regions(matches.value_of("blacklist"))?,
+                skip_zeros: matches.is_present("skipzeros"),
+            };
+            let json = match matches.value_of("format") {
+                Some(format) => format == "json",
+                None => outpath.ends_with(".json"),
+            };
+            let nthreads = matches
+                .value_of("nthreads")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| "Invalid argument for `nthreads`: must be a positive number")?;
+
+            let bigwigs = paths
+                .iter()
+                .map(|p| BigWigRead::open_file(p))
+                .collect::<Result<Vec<_>, _>>()?;
+            let values = bin_values(&bigwigs, &options, nthreads)?;
+            let correlation = correlate(&values);
+            if outpath == "-" {
+                let stdout = io::stdout();
+                write_correlation(BufWriter::new(stdout.lock()), &paths, &correlation, json)?;
+            } else {
+                let out = BufWriter::new(File::create(outpath)?);
+                write_correlation(out, &paths, &correlation, json)?;
+            }
+            eprintln!(
+                "Correlated {} bigWigs over {} bins",
+                paths.len(),
+                values.first().map_or(0, |v| v.len())
+            );
+        }
+        Some(("histogram", matches)) => {
+            eprintln!("---BigTools histogram---");
+
+            let inpath = matches.value_of("input").unwrap();
+            let outpath = matches.value_of("output").unwrap();
+            let bins = matches
+                .value_of("bins")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| "Invalid argument for `bins`: must be a positive number")?;
+            let bound = |name: &str| -> Result<Option<f64>, Box<dyn Error>> {
+                matches
+                    .value_of(name)
+                    .map(|v| v.parse::<f64>())
+                    .transpose()
+                    .map_err(|_| {
+                        format!("Invalid argument for `{}`: must be a number", name).into()
+                    })
+            };
+            let (min, max) = (bound("min")?, bound("max")?);
+            let quantiles = matches
+                .value_of("quantiles")
+                .unwrap()
+                .split(',')
+                .map(|q| match q.trim().parse::<f64>() {
+                    Ok(q) if (0.0..=1.0).contains(&q) => Ok(q),
+                    _ => Err("Invalid argument for `quantiles`: must be between 0 and 1"),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let method = if matches.is_present("exact") {
+                QuantileMethod::Exact
+            } else {
+                QuantileMethod::TDigest(
+                    matches
+                        .value_of("compression")
+                        .unwrap()
+                        .parse::<f64>()
+                        .map_err(|_| "Invalid argument for `compression`: must be a number")?,
+                )
+            };
+            let regions = match matches.value_of("regions") {
+                Some(path) => Some(
+                    read_bed(path)?
+                        .into_iter()
+                        .map(|(chrom, start, end, _)| (chrom, start, end))
+                        .collect(),
+                ),
+                None => None,
+            };
+            let nthreads = matches
+                .value_of("nthreads")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| "Invalid argument for `nthreads`: must be a positive number")?;
+
+            let mut input = BigWigRead::open_file(inpath)?;
+            let (min, max) = match (min, max) {
+                (Some(min), Some(max)) => (min, max),
+                _ => {
+                    let summary = input.get_summary()?;
+                    (
+                        min.unwrap_or(summary.min_val),
+                        max.unwrap_or(summary.max_val),
+                    )
+                }
+            };
+            let bins = if matches.is_present("log") {
+                HistogramBins::Log { min, max, bins }
+            } else {
+                HistogramBins::Linear { min, max, bins }
+            };
+            let options = HistogramOptions {
+                bins,
+                quantiles,
+                method,
+                regions,
+            };
+            let distribution = distribution(&input, &options, nthreads)?;
+            if outpath == "-" {
+                let stdout = io::stdout();
+                write_distribution(BufWriter::new(stdout.lock()), &distribution)?;
+            } else {
+                write_distribution(BufWriter::new(File::create(outpath)?), &distribution)?;
+            }
+            let covered: u64 = distribution.chroms.iter().map(|c| c.covered).sum();
+            eprintln!("Computed the distribution over {} bases", covered);
+        }
+        Some(("bigbedmerge", matches)) => {
+            eprintln!("---BigTools bigbedmerge---");
+
+            let outpath = matches.value_of("output").unwrap().to_owned();
+            let paths: Vec<&str> = matches.values_of("inputs").unwrap().collect();
+            let options = BigBedMergeOptions {
+                autosql: match matches.value_of("autosql") {
+                    Some("common") => AutosqlReconcile::Common,
+                    _ => AutosqlReconcile::Error,
+                },
+                sources: matches
+                    .is_present("source")
+                    .then(|| paths.iter().map(|p| p.to_string()).collect()),
+            };
+            let nthreads = matches
+                .value_of("nthreads")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| "Invalid argument for `nthreads`: must be a positive number")?;
+
+            let inputs = paths
+                .iter()
+                .map(|p| BigBedRead::open_file(p.to_string()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let data = MergedBigBeds::new(inputs, options)?;
+
+            let mut outb = BigBedWrite::create_file(outpath.clone());
+            outb.autosql = Some(data.autosql().to_owned());
+            outb.options.compress = !matches.is_present("uncompressed");
+            let pool = futures::executor::ThreadPoolBuilder::new()
+                .pool_size(nthreads)
+                .create()
+                .expect("Unable to create thread pool.");
+            outb.write(data.chrom_sizes().clone(), data, pool)?;
+            eprintln!("Wrote {}", outpath);
+        }
+        Some(("liftover", matches)) => {
+            eprintln!("---BigTools liftover---");
+
+            let inpath = matches.value_of("input").unwrap().to_owned();
+            let chainpath = matches.value_of("chain").unwrap();
+            let outpath = matches.value_of("output").unwrap().to_owned();
+            let min_fraction = match matches.value_of("minfraction").unwrap().parse::<f64>() {
+                Ok(f) if (0.0..=1.0).contains(&f) => f,
+                _ => {
+                    return Err(
+                        "Invalid argument for `min-fraction`: must be between 0 and 1".into(),
+                    )
+                }
+            };
+            let nthreads = matches
+                .value_of("nthreads")
+                .unwrap()
+                .parse::<usize>()
+                .map_err(|_| "Invalid argument for `nthreads`: must be a positive number")?;
+            let options = LiftoverOptions {
+                unmapped: matches.value_of("unmapped").map(|p| p.to_owned()),
+                chrom_sizes: matches.value_of("chromsizes").map(|p| p.to_owned()),
+                min_fraction,
+                nthreads,
+                compress: !matches.is_present("uncompressed"),
+            };
+
+            let chains = Chains::from_file(chainpath)?;
+            liftover(inpath, chains, outpath, options)?;
         }
         None => {
             eprintln!("No command. Use bigtools -help to see help.");
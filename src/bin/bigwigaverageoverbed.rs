@@ -2,18 +2,49 @@ use std::collections::VecDeque;
 use std::error::Error;
 use std::fs::File;
 use std::io::{self, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::sync::Arc;
 
 use bigtools::bed::bedparser::{parse_bed, BedParser};
 use bigtools::bed::indexer::index_chroms;
+use bigtools::utils::chromalias::ChromAliases;
 use bigtools::utils::chromvalues::ChromValues;
 use bigtools::utils::reopen::{Reopen, SeekableRead};
 use bigtools::utils::streaming_linereader::StreamingLineReader;
 use clap::{App, Arg};
 
 use bigtools::bbi::BigWigRead;
-use bigtools::utils::misc::{stats_for_bed_item, Name};
+use bigtools::utils::misc::{
+    stats_for_bed_item_with_options, BigWigAverageOverBedStats, Name, StatsOptions,
+};
 use crossbeam_channel::TryRecvError;
 
+/// Which optional stats columns are written.
+#[derive(Copy, Clone)]
+struct Columns {
+    min_max: bool,
+    extra: bool,
+}
+
+impl Columns {
+    fn format(self, stats: &BigWigAverageOverBedStats) -> String {
+        let entry = &stats.entry;
+        let mut line = format!(
+            "{}\t{}\t{:.3}\t{:.3}\t{:.3}",
+            entry.size, entry.bases, entry.sum, entry.mean0, entry.mean
+        );
+        if self.min_max {
+            line += &format!("\t{:.3}\t{:.3}", stats.min, stats.max);
+        }
+        if self.extra {
+            line += &format!(
+                "\t{:.3}\t{:.3}\t{:.3}",
+                stats.std, stats.median, stats.coverage
+            );
+        }
+        format!("{}\t{}", entry.name, line)
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let matches = App::new("BigWigAverageOverBed")
         .arg(Arg::new("bigwig")
@@ -33,14 +64,31 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             )
         .arg(Arg::new("namecol")
                 .short('n')
-                .help("Supports three types of options: `interval`, `none`, or a column number (one indexed). If `interval`, the name column in the output will be the interval in the form of `chrom:start-end`. If `none`, then all columns will be included in the output file. Otherwise, the one-indexed column will be used as the name. By default, column 4 is used as a name column.")
+                .help("Supports three types of options: `interval`, `none`, or a column number (one indexed). If `interval`, the name column in the output will be the interval in the form of `chrom:start-end`. If `none`, then all columns will be included in the output file, with the stats appended. Otherwise, the one-indexed column will be used as the name. By default, column 4 is used as a name column.")
                 .default_value("4")
             )
+        .arg(Arg::new("minmax")
+            .long("min-max")
+            .help("Also output the minimum and maximum value of each entry (after the mean).")
+            )
+        .arg(Arg::new("extra")
+            .long("extra-stats")
+            .help("Also output the standard deviation and median of the covered bases, and the fraction of bases covered, of each entry (after the minimum and maximum, if given).")
+            )
+        .arg(Arg::new("blocks")
+            .long("blocks")
+            .help("For bed12 entries, only include the bases in the blocks (e.g. exons). The size is then the total size of the blocks. Entries with fewer columns are treated as one block.")
+            )
         .arg(Arg::new("nthreads")
             .short('t')
             .help("Number of threads to use. Defaults to 1.")
             .default_value("1")
             )
+        .arg(Arg::new("chromalias")
+            .long("chromalias")
+            .takes_value(true)
+            .help("A chromAlias file, so that chromosomes in the bed can use different names than the bigWig (e.g. `1` instead of `chr1`).")
+            )
         .get_matches();
 
     let bigwigpath = matches.value_of("bigwig").unwrap();
@@ -48,6 +96,11 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     let bedoutpath = matches.value_of("output").unwrap();
 
     let mut inbigwig = BigWigRead::open_file(bigwigpath)?;
+    if let Some(path) = matches.value_of("chromalias") {
+        inbigwig
+            .info
+            .set_chrom_aliases(Some(Arc::new(ChromAliases::from_file(path)?)));
+    }
     let outbed = File::create(bedoutpath)?;
     let mut bedoutwriter = BufWriter::new(outbed);
 
@@ -74,6 +127,15 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
         None => Name::Column(3),
     };
 
+    let columns = Columns {
+        min_max: matches.is_present("minmax"),
+        extra: matches.is_present("extra"),
+    };
+    let options = StatsOptions {
+        name,
+        blocks: matches.is_present("blocks"),
+    };
+
     let nthreads: usize = {
         let nthreads = matches.value_of("nthreads").unwrap();
         match nthreads.parse() {
@@ -91,7 +153,8 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
             start: u64,
             chrom: String,
             bedinpath: String,
-            name: Name,
+            options: StatsOptions,
+            columns: Columns,
             inbigwig: &mut BigWigRead<R>,
         ) -> Result<File, Box<dyn Error + Send + Sync>> {
             let mut tmp = tempfile::tempfile()?;
@@ -129,18 +192,9 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                     Some(Ok(entry)) => entry,
                 };
 
-                let entry = match stats_for_bed_item(name, &chrom, entry, inbigwig) {
-                    Ok(stats) => stats,
-                    Err(e) => {
-                        return Err(e.into());
-                    }
-                };
+                let stats = stats_for_bed_item_with_options(options, &chrom, entry, inbigwig)?;
 
-                let stats = format!(
-                    "{}\t{}\t{:.3}\t{:.3}\t{:.3}",
-                    entry.size, entry.bases, entry.sum, entry.mean0, entry.mean
-                );
-                writeln!(&mut tmp, "{}\t{}", entry.name, stats)?
+                writeln!(&mut tmp, "{}", columns.format(&stats))?
             }
 
             Ok(tmp)
@@ -173,7 +227,8 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                         Err(_) => break,
                     };
 
-                    let result = process_chrom(start, chrom, bedinpath, name, &mut inbigwig);
+                    let result =
+                        process_chrom(start, chrom, bedinpath, options, columns, &mut inbigwig);
                     result_sender.send(result).unwrap();
                 }
             };
@@ -203,8 +258,14 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                                 }
                             };
 
-                            let result =
-                                process_chrom(start, chrom, bedinpath, name, &mut inbigwig);
+                            let result = process_chrom(
+                                start,
+                                chrom,
+                                bedinpath,
+                                options,
+                                columns,
+                                &mut inbigwig,
+                            );
                             result_sender.send(result).unwrap();
                         }
                     }
@@ -232,23 +293,17 @@ fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
                 Some(Ok(line)) => line,
             };
 
-            let (chrom, entry) = parse_bed(line).ok_or_else(|| {
-                io::Error::new(
+            let (chrom, entry) =
+                parse_bed(line.trim_end_matches(['\n', '\r'])).ok_or_else(|| {
+                    io::Error::new(
                     io::ErrorKind::InvalidData,
                     "Invalid bed: A minimum of 3 columns must be specified (chrom, start, end).",
                 )
-            })??;
+                })??;
 
-            let entry = match stats_for_bed_item(name, chrom, entry, &mut inbigwig) {
-                Ok(stats) => stats,
-                Err(e) => return Err(e.into()),
-            };
+            let stats = stats_for_bed_item_with_options(options, chrom, entry, &mut inbigwig)?;
 
-            let stats = format!(
-                "{}\t{}\t{:.3}\t{:.3}\t{:.3}",
-                entry.size, entry.bases, entry.sum, entry.mean0, entry.mean
-            );
-            writeln!(&mut bedoutwriter, "{}\t{}", entry.name, stats)?
+            writeln!(&mut bedoutwriter, "{}", columns.format(&stats))?
         }
     }
 
@@ -1,225 +1,249 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::VecDeque;
 use std::error::Error;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str::FromStr;
 
-use clap::{App, Arg};
-use thiserror::Error;
+use clap::{App, Arg, ArgMatches};
+use futures::executor::ThreadPool;
+use futures::future::{FutureExt, RemoteHandle};
+use futures::task::SpawnExt;
 
-use bigtools::bbi::Value;
-use bigtools::bbi::{BBIRead, BigWigRead, BigWigWrite};
+use bigtools::bbi::mergechromdata::MergedBigWigs;
+use bigtools::bbi::{BigWigRead, BigWigReadAttachError, BigWigWrite};
 use bigtools::bbiread::BBIReadError;
 use bigtools::utils::chromvalues::ChromValues;
-use bigtools::utils::filebufferedchannel;
-use bigtools::utils::merge::merge_sections_many;
-use bigtools::utils::reopen::ReopenableFile;
-use bigtools::{ChromData, ChromDataState, ChromProcessingFnOutput};
+use bigtools::utils::merge::{Combiner, MergeCombiner, MergeFunction};
+use bigtools::utils::reopen::Reopen;
+
+/// A [`MergeCombiner`] that, if `drop_zeros`, also leaves out combined values
+/// of zero.
+#[derive(Clone)]
+struct OutputCombiner {
+    combiner: MergeCombiner,
+    drop_zeros: bool,
+}
 
-pub struct MergingValues {
-    // We Box<dyn Iterator> because other this would be a mess to try to type
-    iter: std::iter::Peekable<Box<dyn Iterator<Item = Result<Value, MergingValuesError>> + Send>>,
+impl Combiner for OutputCombiner {
+    fn combine(&self, values: &[(usize, f32)], inputs: usize) -> Option<f32> {
+        self.combiner
+            .combine(values, inputs)
+            .filter(|v| !self.drop_zeros || *v != 0.0)
+    }
 }
 
-impl MergingValues {
-    pub fn new<I: 'static>(iters: Vec<I>) -> Self
-    where
-        I: Iterator<Item = Result<Value, MergingValuesError>> + Send,
-    {
-        let iter: Box<dyn Iterator<Item = Result<Value, MergingValuesError>> + Send> = Box::new(
-            merge_sections_many(iters)
-                .filter(|x| x.as_ref().map(|v| v.value != 0.0).unwrap_or(true)),
-        );
-        MergingValues {
-            iter: iter.peekable(),
+/// Splits `combiner` into the combiners for merging chunks of `chunk_size`
+/// inputs, and for merging the results of those. Only possible if combining
+/// chunks first gives the same result.
+fn chunk_combiners(
+    combiner: &MergeCombiner,
+    inputs: usize,
+    chunk_size: usize,
+) -> Result<(Vec<MergeCombiner>, MergeCombiner), String> {
+    let reduce = match combiner.function {
+        MergeFunction::Sum | MergeFunction::Count => MergeFunction::Sum,
+        MergeFunction::Min => MergeFunction::Min,
+        MergeFunction::Max => MergeFunction::Max,
+        MergeFunction::Mean | MergeFunction::Median => {
+            return Err(format!(
+            "Merging more than {} bigWigs is only supported with `sum`, `min`, `max`, or `count`.",
+            chunk_size
+        ))
         }
+    };
+    if combiner.fill.is_some() {
+        return Err(format!(
+            "Merging more than {} bigWigs is not supported with a fill value.",
+            chunk_size
+        ));
     }
+    let chunks = (0..inputs)
+        .step_by(chunk_size)
+        .map(|start| MergeCombiner {
+            weights: combiner
+                .weights
+                .as_ref()
+                .map(|w| w.iter().skip(start).take(chunk_size).copied().collect()),
+            threshold: None,
+            ..combiner.clone()
+        })
+        .collect();
+    let mut reduce = MergeCombiner::new(reduce);
+    reduce.threshold = combiner.threshold;
+    Ok((chunks, reduce))
 }
 
-#[derive(Error, Debug)]
-pub enum MergingValuesError {
-    #[error("{}", .0)]
-    BBIReadError(#[from] BBIReadError),
-    #[error("{}", .0)]
-    MismatchedChroms(String),
-    #[error("{}", .0)]
-    Other(String),
-    #[error("{}", .0)]
-    IoError(#[from] io::Error),
+/// A file that is only opened when it's reopened, so that inputs waiting to
+/// be merged don't hold open files.
+pub struct UnopenedFile {
+    path: String,
+    file: Option<File>,
 }
 
-impl ChromValues for MergingValues {
-    type Value = Value;
-    type Error = MergingValuesError;
-
-    fn next(&mut self) -> Option<Result<Value, MergingValuesError>> {
-        match self.iter.next() {
-            Some(Ok(v)) => Some(Ok(v)),
-            Some(Err(e)) => Some(Err(e.into())),
-            None => None,
-        }
+impl UnopenedFile {
+    fn file(&mut self) -> io::Result<&mut File> {
+        self.file
+            .as_mut()
+            .ok_or_else(|| io::Error::other("The file is not open."))
     }
+}
 
-    fn peek(&mut self) -> Option<Result<&Value, &MergingValuesError>> {
-        match self.iter.peek() {
-            Some(Ok(v)) => Some(Ok(v)),
-            Some(Err(err)) => Some(Err(err)),
-            None => None,
-        }
+impl Reopen for UnopenedFile {
+    fn reopen(&self) -> io::Result<Self> {
+        Ok(UnopenedFile {
+            path: self.path.clone(),
+            file: Some(File::open(&self.path)?),
+        })
     }
 }
 
-pub fn get_merged_vals(
-    bigwigs: Vec<BigWigRead<ReopenableFile>>,
-    max_zooms: usize,
-) -> Result<
-    (
-        impl Iterator<Item = Result<(String, u32, MergingValues), MergingValuesError>>,
-        HashMap<String, u32>,
-    ),
-    MergingValuesError,
-> {
-    let (chrom_sizes, chrom_map) = {
-        // NOTE: We don't need to worry about max fds here because chroms are cached.
+impl Seek for UnopenedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file()?.seek(pos)
+    }
+}
 
-        // Get sizes for each and check that all files (that have the chrom) agree
-        // Check that all chrom sizes match for all files
-        let mut chrom_sizes = BTreeMap::new();
-        let mut chrom_map = HashMap::new();
-        for chrom in bigwigs.iter().flat_map(BBIRead::get_chroms).map(|c| c.name) {
-            if chrom_sizes.get(&chrom).is_some() {
-                continue;
-            }
-            let mut size = None;
-            let sizes = Vec::with_capacity(bigwigs.len());
-            let mut bws = Vec::with_capacity(bigwigs.len());
-            for w in bigwigs.iter() {
-                let chroms = w.get_chroms();
-                let res = chroms.iter().find(|v| v.name == chrom);
-                let res = match res {
-                    Some(res) => res,
-                    None => continue,
-                };
-                match size {
-                    Some(all_size) => {
-                        if all_size != res.length {
-                            eprintln!("Chrom '{:?}' had different sizes in the bigwig files. (Are you using the same assembly?)", chrom);
-                            return Err(MergingValuesError::MismatchedChroms(
-                                "Invalid input (nonmatching chroms)".to_owned(),
-                            ));
-                        }
-                    }
-                    None => {
-                        size = Some(res.length);
-                    }
-                }
-                // We don't want to a new file descriptor for every chrom
-                bws.push((w.info.clone(), w.inner_read().path.to_string()));
-            }
-            let size = sizes[0];
-            if !sizes.iter().all(|s| *s == size) {
-                eprintln!("Chrom '{:?}' had different sizes in the bigwig files. (Are you using the same assembly?)", chrom);
-                return Err(MergingValuesError::MismatchedChroms(
-                    "Invalid input (nonmatching chroms)".to_owned(),
-                ));
-            }
+impl Read for UnopenedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file()?.read(buf)
+    }
+}
 
-            chrom_sizes.insert(chrom.clone(), (size, bws));
-            chrom_map.insert(chrom.clone(), size);
-        }
+/// A bigWig to merge. Only its header is kept in memory: the file is only
+/// opened while its values are read, so at most the inputs of one merge are
+/// open at once.
+pub type MergeInput = BigWigRead<UnopenedFile>;
+
+pub fn open_input(path: String) -> Result<MergeInput, BigWigReadAttachError> {
+    let bigwig = BigWigRead::open_file(&path)?;
+    Ok(BigWigRead::with_info(
+        bigwig.info,
+        UnopenedFile { path, file: None },
+    ))
+}
 
-        (chrom_sizes, chrom_map)
-    };
+/// A rough estimate of the memory used by each input while it's read: the
+/// block index of a large chromosome, and a decompressed block.
+const INPUT_MEMORY: usize = 4 * 1024 * 1024;
 
-    const MAX_FDS: usize = 1000;
-    const PARALLEL_CHROMS: usize = 1;
+/// The number of inputs that can be merged at once, with `parallel`
+/// chromosomes merged at a time.
+fn merge_fan_in(
+    max_open_files: usize,
+    memory: usize,
+    parallel: usize,
+    max_zooms: usize,
+) -> Result<usize, Box<dyn Error>> {
     // This might be a *bit* conservative, but is really mostly an estimate
-    let max_bw_fds: usize = MAX_FDS
-        - 1 /* output bigWig (data) */
-        - 1 /* index */
-        - (1 /* data sections */ + 1  /* index sections */ + max_zooms /* zoom data sections */ + max_zooms /* zoom index sections */) * PARALLEL_CHROMS;
-
-    let iter = chrom_sizes.into_iter().map(move |(chrom, (size, bws))| {
-        if bws.len() > max_bw_fds {
-            eprintln!("Number of bigWigs to merge would exceed the maximum number of file descriptors. Splitting into chunks.");
-
-            let mut merges: Vec<Box<dyn Iterator<Item = Result<Value, MergingValuesError>> + Send>> = bws
-                .into_iter()
-                .map(|b| {
-                    let f = ReopenableFile { file: File::open(&b.1)?, path: b.1 };
-                    let b = BigWigRead::with_info(b.0, f);
-                    let iter = b.get_interval_move(&chrom, 1, size).map(|i| i.map(|r| r.map_err(|e| MergingValuesError::BBIReadError(e))))?;
-                    Ok(Box::new(iter) as Box<_>)
-                })
-                .collect::<Result<Vec<_>, BBIReadError>>()?;
-
-            while merges.len() > max_bw_fds {
-                merges = {
-                    let len = merges.len();
-                    let mut vals = merges.into_iter().peekable();
-                    let mut merges: Vec<Box<dyn Iterator<Item = Result<Value, MergingValuesError>> + Send>> = Vec::with_capacity(len/max_bw_fds+1);
-
-                    while vals.peek().is_some() {
-                        let chunk = vals.by_ref().take(max_bw_fds).collect::<Vec<_>>();
-                        let mut mergingvalues = MergingValues::new(chunk);
-                        let (mut sender, receiver) = filebufferedchannel::lazy_channel::<Value>(3200)?;
-                        while let Some(val) = mergingvalues.next() {
-                            let val = val?;
-                            sender.send(val).unwrap();
-                        }
+    let reserved = 3 /* stdio */
+        + 1 /* output (data) */
+        + 1 /* index */
+        + (1 /* data sections */ + 1  /* index sections */ + max_zooms /* zoom data sections */ + max_zooms /* zoom index sections */) * parallel;
+    let by_fds = max_open_files.saturating_sub(reserved) / parallel;
+    let by_memory = memory / (INPUT_MEMORY * parallel);
+    let fan_in = by_fds.min(by_memory);
+    if fan_in < 2 {
+        return Err(format!(
+            "The open file limit ({}) and memory budget ({} MiB) are too low to merge with {} threads.",
+            max_open_files,
+            memory / (1024 * 1024),
+            parallel
+        )
+        .into());
+    }
+    Ok(fan_in)
+}
 
-                        merges.push(Box::new(receiver.into_iter().map(Result::Ok)));
-                    }
-                    merges
-                };
+/// Merges each chunk of `fan_in` inputs, with the corresponding combiner, into
+/// a temporary bigWig in `dir`. Chunks that merge to nothing are skipped.
+fn merge_chunks(
+    inputs: Vec<MergeInput>,
+    combiners: Vec<MergeCombiner>,
+    fan_in: usize,
+    dir: &Path,
+    level: usize,
+    pool: &ThreadPool,
+) -> Result<Vec<MergeInput>, Box<dyn Error>> {
+    let mut merged = Vec::with_capacity(combiners.len());
+    let mut inputs = inputs.into_iter();
+    for (i, combiner) in combiners.into_iter().enumerate() {
+        let chunk: Vec<_> = inputs.by_ref().take(fan_in).collect();
+        let temporary: Vec<_> = chunk
+            .iter()
+            .map(|input| input.inner_read().path.clone())
+            .collect();
+        let path = dir
+            .join(format!("merge-{}-{}.bigWig", level, i))
+            .to_string_lossy()
+            .to_string();
+        // Zeros are kept until the final merge, since they can still change
+        // its result (the minimum of a zero and a positive value, say)
+        let combiner = OutputCombiner {
+            combiner,
+            drop_zeros: false,
+        };
+        let mut data = MergedBigWigs::new(chunk, combiner)?;
+        // A chunk without any values isn't written at all
+        if !data.is_empty()? {
+            let mut outb = BigWigWrite::create_file(path.clone());
+            // Zooms are only needed in the final output
+            outb.options.max_zooms = 0;
+            let chrom_map = data.chrom_sizes().clone();
+            outb.write(chrom_map, data, pool.clone())?;
+            merged.push(open_input(path)?);
+        }
+        // The inputs of later levels are our own temporary files
+        if level > 0 {
+            for path in temporary {
+                std::fs::remove_file(path)?;
             }
-
-            let mergingvalues = MergingValues::new(merges);
-            Ok((chrom, size, mergingvalues))
-        } else {
-            let iters: Vec<_> = bws
-                .into_iter()
-                .map(|b| {
-                    let f = ReopenableFile { file: File::open(&b.1)?, path: b.1 };
-                    let b = BigWigRead::with_info(b.0, f);
-                    b.get_interval_move(&chrom, 1, size).map(|i| i.map(|r| r.map_err(|e| MergingValuesError::BBIReadError(e))))
-                })
-                .collect::<Result<Vec<_>, _>>()?;
-            let mergingvalues = MergingValues::new(iters);
-
-            Ok((chrom, size, mergingvalues))
         }
-    });
-
-    Ok((iter, chrom_map))
+    }
+    Ok(merged)
 }
 
-struct ChromGroupReadImpl {
-    iter: Box<dyn Iterator<Item = Result<(String, u32, MergingValues), MergingValuesError>> + Send>,
+/// Merges `inputs` in a tree of temporary bigWigs in `dir`, until at most
+/// `fan_in` are left. Returns these and the combiner to merge them with.
+fn tree_merge(
+    inputs: Vec<MergeInput>,
+    combiner: MergeCombiner,
+    fan_in: usize,
+    dir: &Path,
+    pool: &ThreadPool,
+) -> Result<(Vec<MergeInput>, MergeCombiner), Box<dyn Error>> {
+    if inputs.len() <= fan_in {
+        return Ok((inputs, combiner));
+    }
+    let (chunk_combiners, reduce) = chunk_combiners(&combiner, inputs.len(), fan_in)?;
+    eprintln!(
+        "Merging {} bigWigs in chunks of {} through temporary bigWigs.",
+        inputs.len(),
+        fan_in
+    );
+    let mut inputs = merge_chunks(inputs, chunk_combiners, fan_in, dir, 0, pool)?;
+    let mut level = 1;
+    while inputs.len() > fan_in {
+        let intermediate = MergeCombiner {
+            threshold: None,
+            ..reduce.clone()
+        };
+        let chunk_combiners = vec![intermediate; inputs.len().div_ceil(fan_in)];
+        inputs = merge_chunks(inputs, chunk_combiners, fan_in, dir, level, pool)?;
+        level += 1;
+    }
+    Ok((inputs, reduce))
 }
 
-impl<E: From<io::Error>> ChromData<E> for ChromGroupReadImpl {
-    type Output = MergingValues;
-
-    fn advance<
-        F: FnMut(
-            String,
-            Self::Output,
-        ) -> Result<ChromProcessingFnOutput<<Self::Output as ChromValues>::Error>, E>,
-    >(
-        &mut self,
-        do_read: &mut F,
-    ) -> Result<ChromDataState<<Self::Output as ChromValues>::Error>, E> {
-        let next = self.iter.next();
-        Ok(match next {
-            Some(Err(err)) => ChromDataState::Error(err.into()),
-            Some(Ok((chrom, _, mergingvalues))) => {
-                let read = do_read(chrom, mergingvalues)?;
-
-                ChromDataState::NewChrom(read)
-            }
-            None => ChromDataState::Finished,
-        })
-    }
+fn parse_arg<T: FromStr>(
+    matches: &ArgMatches,
+    name: &str,
+    what: &str,
+) -> Result<T, Box<dyn Error>> {
+    let value = matches.value_of(name).unwrap();
+    value
+        .parse()
+        .map_err(|_| format!("Invalid argument for `{}`: must be {}", name, what).into())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -246,14 +270,54 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .help("Set the number of threads to use")
                 .takes_value(true)
                 .default_value("6"))
+        .arg(Arg::new("combine")
+                .long("combine")
+                .help("How the values of the inputs are combined. `count` is the number of nonzero values.")
+                .takes_value(true)
+                .possible_values(["sum", "mean", "min", "max", "median", "count"])
+                .default_value("sum"))
+        .arg(Arg::new("weights")
+                .long("weights")
+                .help("A comma-separated weight for each input (in the order of `-b`, then `-l`), that its values are multiplied by. With `mean` or `median`, this is a weighted mean or median.")
+                .takes_value(true))
+        .arg(Arg::new("threshold")
+                .long("threshold")
+                .help("Drop combined values below this. With a threshold or fill value, combined values of zero are kept (by default, they are dropped).")
+                .takes_value(true))
+        .arg(Arg::new("fill")
+                .long("fill")
+                .help("The value of inputs without coverage where another input has coverage. By default, these inputs are ignored.")
+                .takes_value(true))
+        .arg(Arg::new("maxopenfiles")
+                .long("max-open-files")
+                .help("The maximum number of files to have open at once. With more inputs than can be open at once, inputs are merged in chunks through temporary bigWigs (only with `sum`, `min`, `max`, or `count`, and no fill value).")
+                .takes_value(true)
+                .default_value("1000"))
+        .arg(Arg::new("memory")
+                .long("memory")
+                .help("A rough memory budget, in MiB, which limits the number of inputs merged at once.")
+                .takes_value(true)
+                .default_value("4096"))
+        .arg(Arg::new("tmpdir")
+                .long("tmp-dir")
+                .help("The directory for temporary bigWigs. Defaults to the system temporary directory.")
+                .takes_value(true))
         .get_matches();
 
     let output = matches.value_of("output").unwrap().to_owned();
-    let mut bigwigs: Vec<BigWigRead<ReopenableFile>> = vec![];
+    let bigwig_output = if output.ends_with(".bw") || output.ends_with(".bigWig") {
+        true
+    } else if output.ends_with(".bedGraph") {
+        false
+    } else {
+        eprintln!("Invalid output file. Must end with .bw or .bigWig for bigwig or .bedGraph for bedGraph");
+        return Ok(());
+    };
+    let mut bigwigs: Vec<MergeInput> = vec![];
 
     if let Some(bws) = matches.values_of("bigwig") {
         for name in bws {
-            match BigWigRead::open_file(name) {
+            match open_input(name.to_owned()) {
                 Ok(bw) => bigwigs.push(bw),
                 Err(e) => {
                     eprintln!("Error when opening bigwig ({}): {:?}", name, e);
@@ -274,7 +338,7 @@ fn main() -> Result<(), Box<dyn Error>> {
             let lines = BufReader::new(list_file).lines();
             for line in lines {
                 let name = line?;
-                match BigWigRead::open_file(&name) {
+                match open_input(name.clone()) {
                     Ok(bw) => bigwigs.push(bw),
                     Err(e) => {
                         eprintln!("Error when opening bigwig ({}): {:?}", name, e);
@@ -295,44 +359,109 @@ fn main() -> Result<(), Box<dyn Error>> {
         parsed.unwrap()
     };
 
-    let (iter, chrom_map) = get_merged_vals(bigwigs, 10)?;
-
-    match output {
-        output if output.ends_with(".bw") || output.ends_with(".bigWig") => {
-            let outb = BigWigWrite::create_file(output);
-            let pool = futures::executor::ThreadPoolBuilder::new()
-                .pool_size(nthreads)
-                .create()
-                .expect("Unable to create thread pool.");
-            let all_values = ChromGroupReadImpl {
-                iter: Box::new(iter),
-            };
-            outb.write(chrom_map, all_values, pool)?;
+    let function = match matches.value_of("combine").unwrap() {
+        "sum" => MergeFunction::Sum,
+        "mean" => MergeFunction::Mean,
+        "min" => MergeFunction::Min,
+        "max" => MergeFunction::Max,
+        "median" => MergeFunction::Median,
+        "count" => MergeFunction::Count,
+        _ => unreachable!(),
+    };
+    let mut combiner = MergeCombiner::new(function);
+    if let Some(weights) = matches.value_of("weights") {
+        let weights = weights
+            .split(',')
+            .map(|w| w.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| "Invalid argument for `weights`: must be comma-separated numbers")?;
+        if weights.len() != bigwigs.len() {
+            return Err(format!(
+                "Invalid argument for `weights`: expected {} weights (one per input), got {}",
+                bigwigs.len(),
+                weights.len()
+            )
+            .into());
         }
-        output if output.ends_with(".bedGraph") => {
-            // TODO: convert to multi-threaded
-            use std::io::Write;
+        combiner.weights = Some(weights);
+    }
+    if matches.is_present("threshold") {
+        combiner.threshold = Some(parse_arg(&matches, "threshold", "a number")?);
+    }
+    if matches.is_present("fill") {
+        combiner.fill = Some(parse_arg(&matches, "fill", "a number")?);
+    }
 
-            let bedgraph = File::create(output)?;
-            let mut writer = io::BufWriter::new(bedgraph);
+    let max_open_files: usize = parse_arg(&matches, "maxopenfiles", "a positive number")?;
+    let memory = parse_arg::<usize>(&matches, "memory", "a positive number")? * 1024 * 1024;
+    let tmpdir = match matches.value_of("tmpdir") {
+        Some(dir) => tempfile::tempdir_in(dir)?,
+        None => tempfile::tempdir()?,
+    };
+
+    // Zeros are only meaningful if asked for with a threshold or fill value
+    let drop_zeros = combiner.threshold.is_none() && combiner.fill.is_none();
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(nthreads)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    // Both outputs merge up to `nthreads` chromosomes at a time
+    let outb = bigwig_output.then(|| BigWigWrite::create_file(output.clone()));
+    let max_zooms = outb
+        .as_ref()
+        .map_or(0, |outb| outb.options.max_zooms as usize);
+    let fan_in = merge_fan_in(max_open_files, memory, nthreads, max_zooms)?;
+    let (bigwigs, combiner) = tree_merge(bigwigs, combiner, fan_in, tmpdir.path(), &pool)?;
+    let combiner = OutputCombiner {
+        combiner,
+        drop_zeros,
+    };
+    let mut data = MergedBigWigs::new(bigwigs, combiner)?;
 
-            for v in iter {
-                let (chrom, _, mut values) = v?;
+    if let Some(outb) = outb {
+        if data.is_empty()? {
+            return Err("The merged bigWig would have no values.".into());
+        }
+        let chrom_map = data.chrom_sizes().clone();
+        outb.write(chrom_map, data, pool)?;
+    } else {
+        let bedgraph = File::create(output)?;
+        let mut writer = io::BufWriter::new(bedgraph);
+
+        // Chromosomes are merged in parallel into temporary files, which are
+        // then copied to the output in order
+        let mut in_flight = VecDeque::with_capacity(nthreads);
+        let mut finish =
+            |handle: RemoteHandle<Result<File, BBIReadError>>| -> Result<(), Box<dyn Error>> {
+                let mut file = futures::executor::block_on(handle)?;
+                file.seek(SeekFrom::Start(0))?;
+                io::copy(&mut file, &mut writer)?;
+                Ok(())
+            };
+        while let Some(next) = data.next_chrom() {
+            let (chrom, mut values) = next?;
+            let (remote, handle) = async move {
+                let mut out = io::BufWriter::new(tempfile::tempfile()?);
                 while let Some(val) = values.next() {
                     let val = val?;
-                    writer.write_fmt(format_args!(
-                        "{}\t{}\t{}\t{}\n",
-                        chrom, val.start, val.end, val.value
-                    ))?;
+                    writeln!(out, "{}\t{}\t{}\t{}", chrom, val.start, val.end, val.value)?;
                 }
+                Ok(out.into_inner().map_err(|e| e.into_error())?)
+            }
+            .remote_handle();
+            pool.spawn(remote).expect("Couldn't spawn future.");
+            in_flight.push_back(handle);
+            if in_flight.len() >= nthreads {
+                finish(in_flight.pop_front().unwrap())?;
             }
         }
-        _ => {
-            eprintln!("Invalid output file. Must end with .bw or .bigWig for bigwig or .bedGraph for bedGraph");
-            return Ok(());
+        while let Some(handle) = in_flight.pop_front() {
+            finish(handle)?;
         }
+        writer.flush()?;
     }
 
-    //TODO: fails with too many open files
     Ok(())
 }
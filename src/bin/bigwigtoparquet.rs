@@ -0,0 +1,75 @@
+use std::error::Error;
+use std::fs::File;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
+
+use clap::{App, Arg, ArgMatches};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use bigtools::arrowdata::{bigwig_batches, bigwig_schema, DEFAULT_BATCH_SIZE};
+use bigtools::bbi::{BBIRead, BigWigRead};
+
+fn parse_arg<T: FromStr>(
+    matches: &ArgMatches,
+    name: &str,
+    what: &str,
+) -> Result<T, Box<dyn Error>> {
+    let value = matches.value_of(name).unwrap();
+    value
+        .parse()
+        .map_err(|_| format!("Invalid argument for `{}`: must be {}", name, what).into())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let default_batch_size = DEFAULT_BATCH_SIZE.to_string();
+    let matches = App::new("BigWigToParquet")
+        .about("Converts an input bigWig to a Parquet file with `chrom`, `start`, `end`, and `value` columns, sorted by chromosome and start.")
+        .arg(Arg::new("bigwig")
+            .help("the bigwig to convert to parquet")
+            .index(1)
+            .required(true)
+        )
+        .arg(Arg::new("parquet")
+            .help("the path of the parquet file to output to")
+            .index(2)
+            .required(true)
+        )
+        .arg(Arg::new("batchsize")
+            .long("batch-size")
+            .help("The number of rows to convert and write at a time.")
+            .takes_value(true)
+            .default_value(&default_batch_size))
+        .arg(Arg::new("uncompressed")
+            .short('u')
+            .help("Don't use compression."))
+        .get_matches();
+
+    let bigwigpath = matches.value_of("bigwig").unwrap();
+    let parquetpath = matches.value_of("parquet").unwrap();
+    let batch_size = parse_arg::<NonZeroUsize>(&matches, "batchsize", "a positive number")?.get();
+    let compression = if matches.is_present("uncompressed") {
+        Compression::UNCOMPRESSED
+    } else {
+        Compression::SNAPPY
+    };
+
+    let mut bigwig = BigWigRead::open_file(bigwigpath)?;
+    let props = WriterProperties::builder()
+        .set_compression(compression)
+        .build();
+    let mut writer =
+        ArrowWriter::try_new(File::create(parquetpath)?, bigwig_schema(), Some(props))?;
+
+    let mut chroms = bigwig.get_chroms();
+    chroms.sort_by(|a, b| a.name.cmp(&b.name));
+    for chrom in chroms {
+        for batch in bigwig_batches(&mut bigwig, &chrom.name, 0, chrom.length, batch_size)? {
+            writer.write(&batch?)?;
+        }
+    }
+    writer.close()?;
+
+    Ok(())
+}
@@ -2,17 +2,25 @@ use std::error::Error;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::path::Path;
+use std::sync::Arc;
 
 use clap::{App, Arg};
 
 use bigtools::bbi::BigWigRead;
-use bigtools::bbiread::BBIReadError;
+use bigtools::utils::chromalias::ChromAliases;
+use bigtools::utils::intersect::{is_header, IntersectSummary, SummaryTotals};
 use bigtools::utils::reopen::SeekableRead;
 use bigtools::utils::streaming_linereader::StreamingLineReader;
 
 struct Options {
     withnames: bool,
     delimiter: String,
+    /// The value of bases (or bins) without data
+    missing: f32,
+    /// Whether values are reversed for `-` strand regions
+    strand: bool,
+    bins: Option<usize>,
+    summary: IntersectSummary,
 }
 
 fn write<R: SeekableRead + 'static>(
@@ -20,7 +28,7 @@ fn write<R: SeekableRead + 'static>(
     mut bigwigin: BigWigRead<R>,
     out: File,
     options: Options,
-) -> Result<(), BBIReadError> {
+) -> Result<(), Box<dyn Error>> {
     let uniquenames = {
         if !options.withnames {
             true
@@ -28,6 +36,7 @@ fn write<R: SeekableRead + 'static>(
             let reader = BufReader::new(File::open(bedinpath)?);
             let mut lines = reader
                 .lines()
+                .filter(|line| !matches!(line, Ok(line) if is_header(line.trim())))
                 .take(10)
                 .map(|line| -> io::Result<Option<String>> {
                     let l = line?;
@@ -51,32 +60,57 @@ fn write<R: SeekableRead + 'static>(
         }
     };
 
-    let bedin = File::open(bedinpath).unwrap();
+    let bedin = File::open(bedinpath)?;
     let mut bedstream = StreamingLineReader::new(BufReader::new(bedin));
     let mut outwriter = BufWriter::new(out);
 
+    let mut line_number = 0;
     while let Some(line) = bedstream.read() {
         let line = line?;
-        let mut split = line.trim().splitn(5, '\t');
-        let chrom = split.next().expect("Missing chrom");
-        let start = split.next().expect("Missing start").parse::<u32>().unwrap();
-        let end = split.next().expect("Missing end").parse::<u32>().unwrap();
+        line_number += 1;
+        let line = line.trim();
+        if is_header(line) {
+            continue;
+        }
+        let invalid = |what: &str| -> Box<dyn Error> {
+            format!("Invalid bed at line {} ({}): {}", line_number, what, line).into()
+        };
+        let mut split = line.split('\t');
+        let chrom = split.next().ok_or_else(|| invalid("missing chrom"))?;
+        let start = split
+            .next()
+            .ok_or_else(|| invalid("missing start"))?
+            .parse::<u32>()
+            .map_err(|_| invalid("invalid start"))?;
+        let end = split
+            .next()
+            .ok_or_else(|| invalid("missing end"))?
+            .parse::<u32>()
+            .map_err(|_| invalid("invalid end"))?;
+        if end < start {
+            return Err(invalid("end before start"));
+        }
         let name = split.next();
-        let interval = bigwigin
-            .get_interval(chrom, start, end)?
-            .collect::<Result<Vec<_>, _>>()?;
-        let size = end - start;
-        let mut vals: Vec<f32> = vec![0f32; size as usize];
-        for val in interval {
-            for i in val.start..val.end {
-                vals[(i - start) as usize] = val.value;
+        let minus_strand = split.nth(1) == Some("-");
+
+        // Bases without data are `NaN`
+        let mut vals = bigwigin.values(chrom, start, end)?;
+        if let Some(bins) = options.bins {
+            vals = bin_values(&vals, bins, options.summary);
+        }
+        if !options.missing.is_nan() {
+            for v in vals.iter_mut().filter(|v| v.is_nan()) {
+                *v = options.missing;
             }
         }
+        if options.strand && minus_strand {
+            vals.reverse();
+        }
         let vals_strings: Vec<String> = vals.into_iter().map(|v| v.to_string()).collect();
         let vals_string = &vals_strings[..].join(&options.delimiter);
         if options.withnames {
             let uniquename = if uniquenames {
-                name.expect("Bad bed format (no name).").to_owned()
+                name.ok_or_else(|| invalid("no name"))?.to_owned()
             } else {
                 format!("{}:{}-{}", chrom, start, end)
             };
@@ -91,6 +125,21 @@ fn write<R: SeekableRead + 'static>(
     Ok(())
 }
 
+/// Summarizes per-base `values` (`NaN` where there is no data) into `bins`
+/// bins of (nearly) equal size, like the bins of a region in `intersect`.
+fn bin_values(values: &[f32], bins: usize, summary: IntersectSummary) -> Vec<f32> {
+    (0..bins)
+        .map(|i| {
+            let bin = &values[values.len() * i / bins..values.len() * (i + 1) / bins];
+            let mut totals = SummaryTotals::default();
+            for value in bin.iter().filter(|v| !v.is_nan()) {
+                totals.add_value(f64::from(*value), 1.0);
+            }
+            totals.summarize(summary, bin.len() as u32) as f32
+        })
+        .collect()
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let matches = App::new("BigWigInfo")
         .arg(Arg::new("bigwig")
@@ -117,6 +166,34 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .takes_value(true)
                 .help("Sets the delimiter to use for the output file. (Defaults to tab).")
             )
+        .arg(Arg::new("missing")
+                .long("missing")
+                .takes_value(true)
+                .default_value("0")
+                .allow_hyphen_values(true)
+                .help("The value written for bases (or bins) without data: a number, or `nan`.")
+            )
+        .arg(Arg::new("strand")
+                .long("strand")
+                .help("Reverse the values of regions on the `-` strand (the sixth column), so that they are written 5' to 3'.")
+            )
+        .arg(Arg::new("bins")
+                .long("bins")
+                .takes_value(true)
+                .help("Summarize each region into this many bins (of nearly equal size), instead of writing each base.")
+            )
+        .arg(Arg::new("summary")
+                .long("summary")
+                .takes_value(true)
+                .possible_values(["mean", "min", "max", "sum", "coverage"])
+                .default_value("mean")
+                .help("The summary of each bin, with `--bins`. `mean`, `min` and `max` are over the bases with data; `coverage` is the fraction of bases with data.")
+            )
+        .arg(Arg::new("chromalias")
+                .long("chromalias")
+                .takes_value(true)
+                .help("A chromAlias file, so that chromosomes in the bed can use different names than the bigWig (e.g. `1` instead of `chr1`).")
+            )
         .get_matches();
 
     let bigwigpath = matches.value_of("bigwig").unwrap();
@@ -135,10 +212,40 @@ fn main() -> Result<(), Box<dyn Error>> {
         return Ok(());
     }
 
+    let chrom_aliases = match matches.value_of("chromalias") {
+        Some(path) => Some(Arc::new(ChromAliases::from_file(path)?)),
+        None => None,
+    };
+
+    let missing = match matches.value_of("missing").unwrap() {
+        "nan" | "NaN" => f32::NAN,
+        missing => missing
+            .parse::<f32>()
+            .map_err(|_| "Invalid argument for `missing`: must be a number or `nan`")?,
+    };
+    let bins = match matches.value_of("bins") {
+        Some(bins) => match bins.parse::<usize>() {
+            Ok(bins) if bins > 0 => Some(bins),
+            _ => return Err("Invalid argument for `bins`: must be a positive number".into()),
+        },
+        None => None,
+    };
+    let summary = match matches.value_of("summary").unwrap() {
+        "min" => IntersectSummary::Min,
+        "max" => IntersectSummary::Max,
+        "sum" => IntersectSummary::Sum,
+        "coverage" => IntersectSummary::Coverage,
+        _ => IntersectSummary::Mean,
+    };
+
     let out = File::create(outputpath)?;
     let options = Options {
         withnames,
         delimiter,
+        missing,
+        strand: matches.is_present("strand"),
+        bins,
+        summary,
     };
 
     #[cfg(feature = "remote")]
@@ -146,16 +253,19 @@ fn main() -> Result<(), Box<dyn Error>> {
         if bigwigpath.starts_with("http") {
             use bigtools::utils::remote_file::RemoteFile;
             let f = RemoteFile::new(bigwigpath);
-            let inbigwig = BigWigRead::open(f)?;
+            let mut inbigwig = BigWigRead::open(f)?;
+            inbigwig.info.set_chrom_aliases(chrom_aliases);
             write(bedin, inbigwig, out, options)?;
         } else {
-            let inbigwig = BigWigRead::open_file(bigwigpath)?;
+            let mut inbigwig = BigWigRead::open_file(bigwigpath)?;
+            inbigwig.info.set_chrom_aliases(chrom_aliases);
             write(bedin, inbigwig, out, options)?;
         }
     }
     #[cfg(not(feature = "remote"))]
     {
-        let inbigwig = BigWigRead::open_file(bigwigpath)?;
+        let mut inbigwig = BigWigRead::open_file(bigwigpath)?;
+        inbigwig.info.set_chrom_aliases(chrom_aliases);
         write(&bedin, inbigwig, out, options)?;
     }
 
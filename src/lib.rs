@@ -16,6 +16,13 @@ in a number of ways. First, the info (in the form of [`BBIFileInfo`]) is availab
 in `info` fields. However, to access the main data, the most common method to call
 is [`BigWigRead::get_interval`] or [`BigBedRead::get_interval`], which returns an
 `Iterator` of [`Value`]s or [`BedEntry`]s overlapping the provided region, respectively.
+With the `arrow` feature, the `arrowdata` module converts these to Apache Arrow
+`RecordBatch`es. For binned summaries of many regions (like for heatmaps), see
+[`utils::matrix`]; for the regions where a bigWig passes a threshold, see
+[`utils::threshold`]; for the correlation between bigWigs, see
+[`utils::correlate`]; for the distribution of the values of a bigWig, see
+[`utils::histogram`]; and for mapping values or entries to another assembly
+with a chain file, see [`utils::liftover`].
 
 ## Writing
 
@@ -39,13 +46,30 @@ simulatenously. The
 and
 [`BedParserParallelStreamingIterator`][crate::bbi::bedchromdata::BedParserParallelStreamingIterator]
 types provide serial processing of a bed-like value stream (either from a
-file or an iterator) or concurrent processing from a file. Generally, these
-underlying details aren't necessary unless implementing a new data source.
+file or an iterator) or concurrent processing from a file. With the `bam`
+feature, `bamchromdata::BamCoverage` provides the read coverage of an indexed
+BAM file, and with the `arrow` feature, `arrowdata::parquet_bigwig_data`
+reads the values of a Parquet file.
+`mergechromdata::MergedBigWigs` merges the values of multiple bigWigs with a
+`Combiner` (like `utils::calc::CalcCombiner`, which evaluates an arithmetic
+expression), and `mergechromdata::MergedBigBeds` merges the entries of multiple
+bigBeds. `pileupchromdata::Pileup` computes the coverage of the entries of a
+bed or bigBed source (like reads or fragments). Generally, these underlying
+details aren't necessary unless implementing a new data source.
 
 Given some implementation of [`ChromData`] (like [`BedParserStreamingIterator`][crate::bbi::bedchromdata::BedParserStreamingIterator]),
 a bigWig can be created using [`BigWigWrite::write`] or a bigBed with
 [`BigBedWrite::write`]. Both take a map of chromosome sizes, the aforementioned
-data, and a `ThreadPool` to spawn processing on.
+data, and a `ThreadPool` to spawn processing on. On success, a [`WriteReport`]
+is returned with the summaries, index and zoom information that were written.
+
+## Copying
+
+To subset, concatenate, or replace the chromosomes of existing files without
+re-encoding their data, use [`BBICopy`][crate::bbi::bbicopy::BBICopy], which
+copies the compressed data blocks directly and only rebuilds the indices.
+To rewrite a bigWig with scaled values (and recomputed zoom levels), use
+[`normalize::normalize`][crate::bbi::normalize::normalize].
 */
 
 pub mod bbi;
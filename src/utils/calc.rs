@@ -0,0 +1,379 @@
+//! Arithmetic expressions over the values of multiple bigWigs.
+//!
+//! An expression is made of numbers, input names, `+`, `-`, `*`, `/`,
+//! parentheses, and the functions `log` (natural), `log2`, `log10`, `exp`,
+//! `abs`, `sqrt`, `min` and `max` (the last two of any number of arguments).
+//! For example, `log2((treat + 1) / (control + 1))`.
+//!
+//! A [`CalcCombiner`] evaluates an expression for each segment of the inputs,
+//! and can be written to a bigWig with
+//! [`MergedBigWigs`][crate::bbi::mergechromdata::MergedBigWigs].
+
+use thiserror::Error;
+
+use crate::utils::merge::Combiner;
+
+#[derive(Error, Debug)]
+pub enum CalcError {
+    #[error("Invalid expression: {}", .0)]
+    InvalidExpression(String),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Function {
+    Log,
+    Log2,
+    Log10,
+    Exp,
+    Abs,
+    Sqrt,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "log" => Function::Log,
+            "log2" => Function::Log2,
+            "log10" => Function::Log10,
+            "exp" => Function::Exp,
+            "abs" => Function::Abs,
+            "sqrt" => Function::Sqrt,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            _ => return None,
+        })
+    }
+
+    fn is_variadic(self) -> bool {
+        matches!(self, Function::Min | Function::Max)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    /// The value of the input at this index.
+    Input(usize),
+    Neg(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+impl Expr {
+    /// Parses `expr`, where inputs are referred to by their name in `inputs`.
+    pub fn parse(expr: &str, inputs: &[&str]) -> Result<Expr, CalcError> {
+        let tokens = tokenize(expr)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+            inputs,
+        };
+        let parsed = parser.expr()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(parsed),
+            Some(token) => Err(CalcError::InvalidExpression(format!(
+                "unexpected `{}`",
+                token
+            ))),
+        }
+    }
+
+    /// Evaluates the expression, with the value of each input in `inputs`.
+    /// `min` and `max` ignore `NaN`s.
+    pub fn eval(&self, inputs: &[f64]) -> f64 {
+        match self {
+            Expr::Number(n) => *n,
+            Expr::Input(i) => inputs[*i],
+            Expr::Neg(e) => -e.eval(inputs),
+            Expr::Add(a, b) => a.eval(inputs) + b.eval(inputs),
+            Expr::Sub(a, b) => a.eval(inputs) - b.eval(inputs),
+            Expr::Mul(a, b) => a.eval(inputs) * b.eval(inputs),
+            Expr::Div(a, b) => a.eval(inputs) / b.eval(inputs),
+            Expr::Call(function, args) => {
+                let mut args = args.iter().map(|a| a.eval(inputs));
+                match function {
+                    Function::Min => args.fold(f64::NAN, f64::min),
+                    Function::Max => args.fold(f64::NAN, f64::max),
+                    _ => {
+                        let x = args.next().unwrap();
+                        match function {
+                            Function::Log => x.ln(),
+                            Function::Log2 => x.log2(),
+                            Function::Log10 => x.log10(),
+                            Function::Exp => x.exp(),
+                            Function::Abs => x.abs(),
+                            Function::Sqrt => x.sqrt(),
+                            Function::Min | Function::Max => unreachable!(),
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Ident(i) => write!(f, "{}", i),
+            Token::Op(c) => write!(f, "{}", c),
+        }
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, CalcError> {
+    let mut tokens = vec![];
+    let mut chars = expr.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            let mut last = ' ';
+            while let Some(&(i, c)) = chars.peek() {
+                let exponent_sign = (c == '-' || c == '+') && (last == 'e' || last == 'E');
+                if !(c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign) {
+                    break;
+                }
+                end = i + c.len_utf8();
+                last = c;
+                chars.next();
+            }
+            let number = &expr[start..end];
+            let number = number.parse().map_err(|_| {
+                CalcError::InvalidExpression(format!("invalid number `{}`", number))
+            })?;
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Ident(expr[start..end].to_owned()));
+        } else if "+-*/(),".contains(c) {
+            tokens.push(Token::Op(c));
+            chars.next();
+        } else {
+            return Err(CalcError::InvalidExpression(format!("unexpected `{}`", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    inputs: &'a [&'a str],
+}
+
+impl Parser<'_> {
+    fn next_op(&mut self, ops: &str) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(c)) if ops.contains(*c) => {
+                self.pos += 1;
+                Some(*c)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: char) -> Result<(), CalcError> {
+        match self.next_op(&op.to_string()) {
+            Some(_) => Ok(()),
+            None => Err(CalcError::InvalidExpression(format!("expected `{}`", op))),
+        }
+    }
+
+    // expr := term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Expr, CalcError> {
+        let mut expr = self.term()?;
+        while let Some(op) = self.next_op("+-") {
+            let rhs = Box::new(self.term()?);
+            expr = match op {
+                '+' => Expr::Add(Box::new(expr), rhs),
+                _ => Expr::Sub(Box::new(expr), rhs),
+            };
+        }
+        Ok(expr)
+    }
+
+    // term := unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Expr, CalcError> {
+        let mut expr = self.unary()?;
+        while let Some(op) = self.next_op("*/") {
+            let rhs = Box::new(self.unary()?);
+            expr = match op {
+                '*' => Expr::Mul(Box::new(expr), rhs),
+                _ => Expr::Div(Box::new(expr), rhs),
+            };
+        }
+        Ok(expr)
+    }
+
+    // unary := ('-' | '+') unary | atom
+    fn unary(&mut self) -> Result<Expr, CalcError> {
+        match self.next_op("-+") {
+            Some('-') => Ok(Expr::Neg(Box::new(self.unary()?))),
+            Some(_) => self.unary(),
+            None => self.atom(),
+        }
+    }
+
+    // atom := number | input | function '(' expr (',' expr)* ')' | '(' expr ')'
+    fn atom(&mut self) -> Result<Expr, CalcError> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::Op('(')) => {
+                let expr = self.expr()?;
+                self.expect(')')?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => {
+                if self.next_op("(").is_none() {
+                    return match self.inputs.iter().position(|i| *i == name) {
+                        Some(i) => Ok(Expr::Input(i)),
+                        None => Err(CalcError::InvalidExpression(format!(
+                            "unknown input `{}`",
+                            name
+                        ))),
+                    };
+                }
+                let function = Function::from_name(&name).ok_or_else(|| {
+                    CalcError::InvalidExpression(format!("unknown function `{}`", name))
+                })?;
+                let mut args = vec![self.expr()?];
+                while self.next_op(",").is_some() {
+                    args.push(self.expr()?);
+                }
+                self.expect(')')?;
+                if !function.is_variadic() && args.len() != 1 {
+                    return Err(CalcError::InvalidExpression(format!(
+                        "`{}` takes one argument",
+                        name
+                    )));
+                }
+                Ok(Expr::Call(function, args))
+            }
+            Some(token) => Err(CalcError::InvalidExpression(format!(
+                "unexpected `{}`",
+                token
+            ))),
+            None => Err(CalcError::InvalidExpression(
+                "unexpected end of expression".to_owned(),
+            )),
+        }
+    }
+}
+
+/// How inputs without coverage are handled, where another input has coverage.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MissingData {
+    /// Their value is `NaN`.
+    NaN,
+    /// Their value is zero.
+    Zero,
+    /// The segment has no value.
+    Skip,
+}
+
+/// A `Combiner` that evaluates an expression. Segments where the result isn't
+/// finite have no value.
+#[derive(Clone, Debug)]
+pub struct CalcCombiner {
+    pub expr: Expr,
+    pub missing: MissingData,
+    /// Added to the value of every input (after `missing` is applied).
+    pub pseudocount: f64,
+}
+
+impl Combiner for CalcCombiner {
+    fn combine(&self, values: &[(usize, f32)], inputs: usize) -> Option<f32> {
+        let missing = match self.missing {
+            MissingData::Skip if values.len() < inputs => return None,
+            MissingData::Skip | MissingData::NaN => f64::NAN,
+            MissingData::Zero => 0.0,
+        };
+        let mut args = vec![missing; inputs];
+        for (i, v) in values {
+            args[*i] = f64::from(*v);
+        }
+        for arg in args.iter_mut() {
+            *arg += self.pseudocount;
+        }
+        let value = self.expr.eval(&args) as f32;
+        value.is_finite().then_some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str, inputs: &[f64]) -> f64 {
+        Expr::parse(expr, &["treat", "control"])
+            .unwrap()
+            .eval(inputs)
+    }
+
+    #[test]
+    fn test_expressions() {
+        assert_eq!(eval("log2((treat + 1) / (control + 1))", &[7.0, 1.0]), 2.0);
+        assert_eq!(eval("treat - control * 2", &[7.0, 1.0]), 5.0);
+        assert_eq!(eval("-(treat - control) / 2", &[7.0, 1.0]), -3.0);
+        assert_eq!(eval("max(treat, control, 10)", &[7.0, 1.0]), 10.0);
+        assert_eq!(eval("min(treat, control)", &[7.0, f64::NAN]), 7.0);
+        assert_eq!(eval("1.5e1 + exp(0) - abs(-1)", &[0.0, 0.0]), 15.0);
+
+        for invalid in [
+            "treat +",
+            "(treat",
+            "treat control",
+            "foo",
+            "log(1, 2)",
+            "bar(1)",
+            "1 $ 2",
+        ] {
+            assert!(
+                Expr::parse(invalid, &["treat", "control"]).is_err(),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_calc_combiner() {
+        let mut combiner = CalcCombiner {
+            expr: Expr::parse("a - b", &["a", "b"]).unwrap(),
+            missing: MissingData::Zero,
+            pseudocount: 1.0,
+        };
+        assert_eq!(combiner.combine(&[(0, 3.0), (1, 1.0)], 2), Some(2.0));
+        assert_eq!(combiner.combine(&[(1, 1.0)], 2), Some(-1.0));
+        combiner.missing = MissingData::NaN;
+        assert_eq!(combiner.combine(&[(1, 1.0)], 2), None);
+        combiner.missing = MissingData::Skip;
+        assert_eq!(combiner.combine(&[(0, 3.0)], 2), None);
+        combiner.expr = Expr::parse("a / (b - 1)", &["a", "b"]).unwrap();
+        assert_eq!(combiner.combine(&[(0, 3.0), (1, 0.0)], 2), None);
+    }
+}
@@ -0,0 +1,173 @@
+//! Chromosome name aliases, such as `chr1`/`1` or `chrM`/`MT`.
+//!
+//! Aliases are usually loaded from a UCSC `chromAlias.txt` file. Two formats
+//! are supported:
+//! - The current format, with a `#` header naming each column's naming
+//!   authority (e.g. `# ucsc assembly genbank ensembl`), where every
+//!   (non-empty) column of a line is a name of the same chromosome.
+//! - The older three-column table dump (`alias chrom source`) without a
+//!   header, where the first two columns are names of the same chromosome and
+//!   the source is ignored.
+//!
+//! Any other tab-separated file without a header is treated like the current
+//! format: all the names on a line are aliases of each other.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ChromAliasError {
+    #[error("Invalid chromAlias file at line {}: {}", .line, .message)]
+    InvalidLine { line: usize, message: String },
+    #[error("{}", .0)]
+    IoError(#[from] io::Error),
+}
+
+/// A set of groups of chromosome names, where all names in a group refer to
+/// the same chromosome.
+#[derive(Clone, Debug, Default)]
+pub struct ChromAliases {
+    groups: Vec<Vec<String>>,
+    by_name: HashMap<String, usize>,
+}
+
+impl ChromAliases {
+    pub fn new() -> Self {
+        ChromAliases::default()
+    }
+
+    /// Loads aliases from a chromAlias file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ChromAliasError> {
+        ChromAliases::from_reader(File::open(path)?)
+    }
+
+    /// Loads aliases in the chromAlias format.
+    pub fn from_reader(reader: impl Read) -> Result<Self, ChromAliasError> {
+        let mut aliases = ChromAliases::new();
+        let mut has_header = false;
+        for (i, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            if line.starts_with('#') {
+                has_header = true;
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').map(|f| f.trim()).collect();
+            let names: Vec<&str> = if !has_header && fields.len() == 3 {
+                fields[..2].to_vec()
+            } else {
+                fields
+            };
+            if let Some(name) = names.iter().find(|n| n.contains(char::is_whitespace)) {
+                return Err(ChromAliasError::InvalidLine {
+                    line: i + 1,
+                    message: format!("Chromosome name `{}` contains whitespace.", name),
+                });
+            }
+            aliases.add_group(names.into_iter().filter(|n| !n.is_empty()));
+        }
+        Ok(aliases)
+    }
+
+    /// Adds a group of names for the same chromosome. If any of the names are
+    /// already known, the groups are merged.
+    pub fn add_group<S: Into<String>>(&mut self, names: impl IntoIterator<Item = S>) {
+        let names: Vec<String> = names.into_iter().map(Into::into).collect();
+        if names.is_empty() {
+            return;
+        }
+        let mut existing: Vec<usize> = names
+            .iter()
+            .filter_map(|n| self.by_name.get(n).copied())
+            .collect();
+        existing.sort_unstable();
+        existing.dedup();
+        let group = match existing.first() {
+            Some(&group) => group,
+            None => {
+                self.groups.push(vec![]);
+                self.groups.len() - 1
+            }
+        };
+        for &other in existing.iter().skip(1) {
+            let moved = std::mem::take(&mut self.groups[other]);
+            for name in moved {
+                self.by_name.insert(name.clone(), group);
+                self.groups[group].push(name);
+            }
+        }
+        for name in names {
+            if !self.by_name.contains_key(&name) {
+                self.by_name.insert(name.clone(), group);
+                self.groups[group].push(name);
+            }
+        }
+    }
+
+    /// Returns all known names for `name` (including itself), or an empty
+    /// slice if `name` is not known.
+    pub fn aliases(&self, name: &str) -> &[String] {
+        match self.by_name.get(name) {
+            Some(&group) => &self.groups[group],
+            None => &[],
+        }
+    }
+
+    /// Finds the name for `name` for which `is_native` returns `true`. If
+    /// `name` itself is native, it is returned.
+    pub fn resolve<'a>(
+        &'a self,
+        name: &'a str,
+        is_native: impl Fn(&str) -> bool,
+    ) -> Option<&'a str> {
+        if is_native(name) {
+            return Some(name);
+        }
+        self.aliases(name)
+            .iter()
+            .map(|n| n.as_str())
+            .find(|n| is_native(n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_formats() {
+        let current = "# ucsc\tassembly\tensembl\nchr1\t1\t1\nchrM\tMT\t\nchrX\tX\tX\n";
+        let aliases = ChromAliases::from_reader(current.as_bytes()).unwrap();
+        assert_eq!(aliases.aliases("MT"), &["chrM", "MT"]);
+        assert_eq!(aliases.resolve("1", |n| n == "chr1"), Some("chr1"));
+        assert_eq!(aliases.resolve("chrX", |n| n == "X"), Some("X"));
+        assert_eq!(aliases.resolve("chr2", |n| n == "2"), None);
+
+        let legacy = "1\tchr1\tensembl\nNC_000001.11\tchr1\trefseq\n";
+        let aliases = ChromAliases::from_reader(legacy.as_bytes()).unwrap();
+        assert_eq!(aliases.aliases("1"), &["1", "chr1", "NC_000001.11"]);
+        assert_eq!(aliases.aliases("ensembl"), &[] as &[String]);
+
+        let invalid = "chr1\t1\nchr2\tchr 2\n";
+        match ChromAliases::from_reader(invalid.as_bytes()) {
+            Err(ChromAliasError::InvalidLine { line, .. }) => assert_eq!(line, 2),
+            _ => panic!("Expected an error"),
+        }
+    }
+
+    #[test]
+    fn test_merge_groups() {
+        let mut aliases = ChromAliases::new();
+        aliases.add_group(["chr1", "1"]);
+        aliases.add_group(["CM000663.2", "NC_000001.11"]);
+        aliases.add_group(["1", "NC_000001.11"]);
+        assert_eq!(aliases.aliases("CM000663.2").len(), 4);
+        assert_eq!(aliases.resolve("CM000663.2", |n| n == "chr1"), Some("chr1"));
+    }
+}
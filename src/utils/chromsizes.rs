@@ -0,0 +1,244 @@
+//! Loading chromosome sizes for writing bigWigs and bigBeds.
+//!
+//! Sizes can be loaded from:
+//! - A `.chrom.sizes` file: whitespace-separated chromosome name and size,
+//!   one per line. Any further columns are ignored.
+//! - A FASTA `.fai` index, whose first two columns are the name and size.
+//! - A `.2bit` file, from the sequence index and record headers.
+//! - An existing bigWig or bigBed, from its chromosome tree.
+//!
+//! The format is detected from the contents of the file, not its extension.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use byteordered::{ByteOrdered, Endianness};
+use thiserror::Error;
+
+use crate::bbi::{BIGBED_MAGIC, BIGWIG_MAGIC};
+use crate::bbiread::read_info;
+use crate::utils::reopen::SeekableRead;
+
+const TWOBIT_MAGIC: u32 = 0x1A41_2743;
+
+#[derive(Error, Debug)]
+pub enum ChromSizesError {
+    #[error("Invalid chrom sizes at line {}: {}", .line, .message)]
+    InvalidLine { line: usize, message: String },
+    #[error("Duplicate chromosome `{}` at line {}", .chrom, .line)]
+    DuplicateChrom { chrom: String, line: usize },
+    #[error("Invalid file: {}", .0)]
+    InvalidFile(String),
+    #[error("{}", .0)]
+    IoError(#[from] io::Error),
+}
+
+/// Loads chromosome sizes from the file at `path`, in any supported format.
+pub fn load_chrom_sizes(path: impl AsRef<Path>) -> Result<HashMap<String, u32>, ChromSizesError> {
+    read_chrom_sizes(File::open(path)?)
+}
+
+/// Reads chromosome sizes, in any supported format.
+pub fn read_chrom_sizes<R: SeekableRead>(
+    mut reader: R,
+) -> Result<HashMap<String, u32>, ChromSizesError> {
+    let mut magic = [0u8; 4];
+    let read = read_up_to(&mut reader, &mut magic)?;
+    reader.seek(SeekFrom::Start(0))?;
+    if read == 4 {
+        let le = u32::from_le_bytes(magic);
+        let be = u32::from_be_bytes(magic);
+        if le == TWOBIT_MAGIC {
+            return read_twobit_sizes(reader, Endianness::Little);
+        }
+        if be == TWOBIT_MAGIC {
+            return read_twobit_sizes(reader, Endianness::Big);
+        }
+        if [le, be]
+            .iter()
+            .any(|m| *m == BIGWIG_MAGIC || *m == BIGBED_MAGIC)
+        {
+            return read_bbi_sizes(reader);
+        }
+    }
+    read_text_sizes(BufReader::new(reader))
+}
+
+/// Reads chromosome sizes from a `.chrom.sizes` or `.fai` file. Empty lines
+/// and lines starting with `#` are ignored.
+pub fn read_text_sizes(reader: impl BufRead) -> Result<HashMap<String, u32>, ChromSizesError> {
+    let mut sizes = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_num = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut split = line.split_whitespace();
+        let chrom = split.next().unwrap();
+        let size = split.next().ok_or_else(|| ChromSizesError::InvalidLine {
+            line: line_num,
+            message: format!("Missing size for chromosome `{}`", chrom),
+        })?;
+        let size = size
+            .parse::<u32>()
+            .map_err(|_| ChromSizesError::InvalidLine {
+                line: line_num,
+                message: format!("Invalid size for chromosome `{}`: `{}`", chrom, size),
+            })?;
+        if sizes.insert(chrom.to_owned(), size).is_some() {
+            return Err(ChromSizesError::DuplicateChrom {
+                chrom: chrom.to_owned(),
+                line: line_num,
+            });
+        }
+    }
+    Ok(sizes)
+}
+
+fn read_bbi_sizes<R: SeekableRead>(mut reader: R) -> Result<HashMap<String, u32>, ChromSizesError> {
+    let info =
+        read_info(&mut reader).map_err(|e| ChromSizesError::InvalidFile(format!("{}", e)))?;
+    Ok(info
+        .chrom_info
+        .into_iter()
+        .map(|c| (c.name, c.length))
+        .collect())
+}
+
+/// Reads the sequence sizes of a `.2bit` file. Each sequence's size is stored
+/// at the start of its record, so this seeks once per sequence.
+fn read_twobit_sizes<R: SeekableRead>(
+    reader: R,
+    endianness: Endianness,
+) -> Result<HashMap<String, u32>, ChromSizesError> {
+    let mut reader = ByteOrdered::runtime(reader, endianness);
+    let _magic = reader.read_u32()?;
+    let version = reader.read_u32()?;
+    if version > 1 {
+        return Err(ChromSizesError::InvalidFile(format!(
+            "Unsupported 2bit version: {}",
+            version
+        )));
+    }
+    let seq_count = reader.read_u32()?;
+    let _reserved = reader.read_u32()?;
+
+    let mut index = Vec::with_capacity(seq_count as usize);
+    for _ in 0..seq_count {
+        let name_size = reader.read_u8()?;
+        let mut name = vec![0u8; name_size as usize];
+        reader.read_exact(&mut name)?;
+        let name = String::from_utf8(name).map_err(|_| {
+            ChromSizesError::InvalidFile("2bit sequence name is not UTF-8".to_owned())
+        })?;
+        let offset = if version == 1 {
+            reader.read_u64()?
+        } else {
+            u64::from(reader.read_u32()?)
+        };
+        index.push((name, offset));
+    }
+
+    let mut sizes = HashMap::with_capacity(index.len());
+    for (i, (name, offset)) in index.into_iter().enumerate() {
+        reader.seek(SeekFrom::Start(offset))?;
+        let size = reader.read_u32()?;
+        if sizes.contains_key(&name) {
+            return Err(ChromSizesError::InvalidFile(format!(
+                "Duplicate 2bit sequence `{}` (sequence {})",
+                name,
+                i + 1
+            )));
+        }
+        sizes.insert(name, size);
+    }
+    Ok(sizes)
+}
+
+/// Like `read_exact`, but returns the number of bytes read if the end of the
+/// reader is hit first.
+fn read_up_to(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_text_sizes() {
+        let sizes = "chr1\t1000\n\n# comment\nchr2 2000 extra\n";
+        let sizes = read_chrom_sizes(Cursor::new(sizes)).unwrap();
+        assert_eq!(sizes.len(), 2);
+        assert_eq!(sizes["chr2"], 2000);
+
+        let fai = "chr1\t248956422\t112\t70\t71\nchrM\t16569\t253105810\t70\t71\n";
+        let sizes = read_chrom_sizes(Cursor::new(fai)).unwrap();
+        assert_eq!(sizes["chr1"], 248956422);
+        assert_eq!(sizes["chrM"], 16569);
+
+        match read_chrom_sizes(Cursor::new("chr1\t10\nchr2\n")) {
+            Err(ChromSizesError::InvalidLine { line, .. }) => assert_eq!(line, 2),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match read_chrom_sizes(Cursor::new("chr1\t10\nchr1\t-5\n")) {
+            Err(ChromSizesError::InvalidLine { line, .. }) => assert_eq!(line, 2),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+        match read_chrom_sizes(Cursor::new("chr1\t10\n\nchr1\t10\n")) {
+            Err(ChromSizesError::DuplicateChrom { chrom, line }) => {
+                assert_eq!(chrom, "chr1");
+                assert_eq!(line, 3);
+            }
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_twobit_sizes() {
+        use byteorder::{LittleEndian, WriteBytesExt};
+
+        // A 2bit header and index with two sequences, followed by the start
+        // of each record (only the size is read)
+        let mut data = vec![];
+        data.write_u32::<LittleEndian>(TWOBIT_MAGIC).unwrap();
+        data.write_u32::<LittleEndian>(0).unwrap();
+        data.write_u32::<LittleEndian>(2).unwrap();
+        data.write_u32::<LittleEndian>(0).unwrap();
+        let index_size = 2 * (1 + 4 + 4);
+        let first_offset = (16 + index_size) as u32;
+        for (name, offset) in [("chrA", first_offset), ("chrB", first_offset + 4)] {
+            data.write_u8(name.len() as u8).unwrap();
+            data.extend_from_slice(name.as_bytes());
+            data.write_u32::<LittleEndian>(offset).unwrap();
+        }
+        data.write_u32::<LittleEndian>(12345).unwrap();
+        data.write_u32::<LittleEndian>(678).unwrap();
+
+        let sizes = read_chrom_sizes(Cursor::new(data)).unwrap();
+        assert_eq!(sizes.len(), 2);
+        assert_eq!(sizes["chrA"], 12345);
+        assert_eq!(sizes["chrB"], 678);
+    }
+
+    #[test]
+    fn test_bbi_sizes() {
+        let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("resources/test/valid.bigWig");
+        let sizes = load_chrom_sizes(path).unwrap();
+        assert_eq!(sizes.len(), 1);
+        assert_eq!(sizes["chr17"], 83257441);
+    }
+}
@@ -0,0 +1,373 @@
+//! Correlation between bigWigs over bins, for replicate QC (like deepTools
+//! `multiBigwigSummary` and `plotCorrelation`).
+//!
+//! The value of each bin is the mean over its bases, with bases without values
+//! as 0. Like [`matrix`](crate::utils::matrix), it is computed from a zoom level
+//! when the bin size allows, or from the values otherwise. The bins are either
+//! fixed-size bins over every chromosome (of any of the bigWigs), or given
+//! regions. Bins overlapping a blacklisted region are excluded, and with
+//! [`CorrelateOptions::skip_zeros`], so are bins that are 0 in every bigWig.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use thiserror::Error;
+
+use crate::bbiread::BBIReadError;
+use crate::utils::intersect::IntersectSummary;
+use crate::utils::matrix::{summarize_bins, MatrixError};
+use crate::utils::parallel::par_chroms;
+use crate::utils::reopen::{Reopen, SeekableRead};
+use crate::BigWigRead;
+
+/// The number of bins that are summarized at once.
+const BIN_CHUNK: usize = 1024;
+
+#[derive(Error, Debug)]
+pub enum CorrelateError {
+    #[error("{}", .0)]
+    InvalidInput(String),
+    #[error("{}", .0)]
+    BBIReadError(#[from] BBIReadError),
+    #[error("{}", .0)]
+    IoError(#[from] io::Error),
+}
+
+impl From<MatrixError> for CorrelateError {
+    fn from(e: MatrixError) -> Self {
+        match e {
+            MatrixError::InvalidInput(e) => CorrelateError::InvalidInput(e),
+            MatrixError::BBIReadError(e) => CorrelateError::BBIReadError(e),
+            MatrixError::IoError(e) => CorrelateError::IoError(e),
+        }
+    }
+}
+
+/// A chromosome, start and end.
+pub type Region = (String, u32, u32);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CorrelateBins {
+    /// Bins of this size over every chromosome. The last bin of each
+    /// chromosome may be smaller.
+    Fixed(u32),
+    /// Each region is a bin.
+    Regions(Vec<Region>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CorrelateOptions {
+    pub bins: CorrelateBins,
+    /// Bins overlapping any of these regions are excluded.
+    pub blacklist: Vec<Region>,
+    /// Whether bins that are 0 in every bigWig are excluded.
+    pub skip_zeros: bool,
+}
+
+/// Merged, sorted regions of one chromosome.
+struct Blacklist(Vec<(u32, u32)>);
+
+impl Blacklist {
+    fn new(mut regions: Vec<(u32, u32)>) -> Self {
+        regions.sort();
+        let mut merged: Vec<(u32, u32)> = vec![];
+        for (start, end) in regions {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        Blacklist(merged)
+    }
+
+    fn overlaps(&self, start: u32, end: u32) -> bool {
+        let i = self.0.partition_point(|b| b.1 <= start);
+        self.0.get(i).is_some_and(|b| b.0 < end)
+    }
+}
+
+/// The bins of one chromosome, with its blacklist applied.
+fn chrom_bins(
+    chrom: &str,
+    length: u32,
+    options: &CorrelateOptions,
+    regions: &BTreeMap<String, Vec<(u32, u32)>>,
+    blacklist: &Blacklist,
+) -> Vec<(u32, u32)> {
+    let bins: Vec<(u32, u32)> = match &options.bins {
+        CorrelateBins::Fixed(size) => (0..length.div_ceil(*size))
+            .map(|i| (i * size, ((i + 1) * size).min(length)))
+            .collect(),
+        CorrelateBins::Regions(_) => regions.get(chrom).cloned().unwrap_or_default(),
+    };
+    bins.into_iter()
+        .filter(|(start, end)| !blacklist.overlaps(*start, *end))
+        .collect()
+}
+
+/// The mean (with missing bases as 0) of `bigwig` over each of `bins`.
+fn bin_means<R: SeekableRead>(
+    bigwig: &mut BigWigRead<R>,
+    chrom: &str,
+    bins: &[(u32, u32)],
+) -> Result<Vec<f32>, CorrelateError> {
+    if bigwig.info.find_chrom(chrom).is_none() {
+        return Ok(vec![0.0; bins.len()]);
+    }
+    let mut values = Vec::with_capacity(bins.len());
+    // Bins are summarized in chunks that are in order and don't overlap
+    let mut chunk: Vec<Option<(u32, u32)>> = vec![];
+    for (i, bin) in bins.iter().enumerate() {
+        chunk.push(Some(*bin));
+        let next = bins.get(i + 1);
+        if chunk.len() == BIN_CHUNK || next.is_none_or(|next| next.0 < bin.1) {
+            let sums = summarize_bins(bigwig, chrom, &chunk, IntersectSummary::Sum, 0.0)?;
+            for (sum, bin) in sums.into_iter().zip(chunk.drain(..)) {
+                let (start, end) = bin.unwrap();
+                values.push(sum / (end - start) as f32);
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// The values of each of `bigwigs` over the bins of `options`, in the same
+/// order. The chromosomes are split between `nthreads` threads, each with
+/// reopened `bigwigs`.
+pub fn bin_values<R>(
+    bigwigs: &[BigWigRead<R>],
+    options: &CorrelateOptions,
+    nthreads: usize,
+) -> Result<Vec<Vec<f32>>, CorrelateError>
+where
+    R: Reopen + SeekableRead + Send,
+{
+    if let CorrelateBins::Fixed(0) = options.bins {
+        return Err(CorrelateError::InvalidInput(
+            "The bin size must be greater than 0.".to_owned(),
+        ));
+    }
+    let mut lengths: HashMap<String, u32> = HashMap::new();
+    for chrom in bigwigs.iter().flat_map(|b| b.info.chrom_info.iter()) {
+        let length = lengths.entry(chrom.name.clone()).or_default();
+        *length = (*length).max(chrom.length);
+    }
+    let mut regions: BTreeMap<String, Vec<(u32, u32)>> = BTreeMap::new();
+    if let CorrelateBins::Regions(r) = &options.bins {
+        for (chrom, start, end) in r {
+            if end <= start {
+                return Err(CorrelateError::InvalidInput(format!(
+                    "Invalid region (empty, or end before start): {}:{}-{}",
+                    chrom, start, end
+                )));
+            }
+            regions
+                .entry(chrom.clone())
+                .or_default()
+                .push((*start, *end));
+        }
+        for bins in regions.values_mut() {
+            bins.sort();
+        }
+    }
+    let mut blacklists: HashMap<&str, Vec<(u32, u32)>> = HashMap::new();
+    for (chrom, start, end) in options.blacklist.iter() {
+        blacklists
+            .entry(chrom.as_str())
+            .or_default()
+            .push((*start, *end));
+    }
+    let mut chroms: Vec<(String, u32)> = match &options.bins {
+        CorrelateBins::Fixed(_) => lengths.into_iter().collect(),
+        CorrelateBins::Regions(_) => regions
+            .keys()
+            .map(|c| (c.clone(), lengths.get(c).copied().unwrap_or(0)))
+            .collect(),
+    };
+    chroms.sort();
+
+    let readers = (0..nthreads.max(1).min(chroms.len()))
+        .map(|_| bigwigs.iter().map(|b| b.reopen()).collect())
+        .collect::<io::Result<Vec<Vec<_>>>>()?;
+    let (results, _) = par_chroms(&chroms, readers, |bigwigs, (chrom, length)| {
+        let blacklist = Blacklist::new(blacklists.get(chrom.as_str()).cloned().unwrap_or_default());
+        let bins = chrom_bins(chrom, *length, options, &regions, &blacklist);
+        bigwigs
+            .iter_mut()
+            .map(|bigwig| bin_means(bigwig, chrom, &bins))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    let mut values = vec![vec![]; bigwigs.len()];
+    for chrom_values in results {
+        let bins = chrom_values.first().map_or(0, |v| v.len());
+        for bin in 0..bins {
+            if options.skip_zeros && chrom_values.iter().all(|v| v[bin] == 0.0) {
+                continue;
+            }
+            for (values, chrom_values) in values.iter_mut().zip(chrom_values.iter()) {
+                values.push(chrom_values[bin]);
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// A summary of the scatter of the values of two bigWigs (`a` and `b`) over
+/// the same bins.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PairSummary {
+    pub bins: usize,
+    pub mean_a: f64,
+    pub mean_b: f64,
+    /// The (population) standard deviation of `a`
+    pub sd_a: f64,
+    pub sd_b: f64,
+    /// The slope of the least squares fit of `b` to `a`
+    pub slope: f64,
+    pub intercept: f64,
+    pub pearson: f64,
+    pub spearman: f64,
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// The means, variances and covariance of `a` and `b`.
+fn moments(a: &[f64], b: &[f64]) -> (f64, f64, f64, f64, f64) {
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let (mut var_a, mut var_b, mut cov) = (0.0, 0.0, 0.0);
+    for (a, b) in a.iter().zip(b.iter()) {
+        var_a += (a - mean_a) * (a - mean_a);
+        var_b += (b - mean_b) * (b - mean_b);
+        cov += (a - mean_a) * (b - mean_b);
+    }
+    let n = a.len() as f64;
+    (mean_a, mean_b, var_a / n, var_b / n, cov / n)
+}
+
+fn pearson_f64(a: &[f64], b: &[f64]) -> f64 {
+    let (_, _, var_a, var_b, cov) = moments(a, b);
+    cov / (var_a * var_b).sqrt()
+}
+
+/// The ranks of `values` (from 1), with ties given their average rank.
+fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|a, b| values[*a].total_cmp(&values[*b]));
+    let mut ranks = vec![0.0; values.len()];
+    let mut i = 0;
+    while i < order.len() {
+        let mut j = i;
+        while j + 1 < order.len() && values[order[j + 1]] == values[order[i]] {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        for k in &order[i..=j] {
+            ranks[*k] = rank;
+        }
+        i = j + 1;
+    }
+    ranks
+}
+
+/// The Pearson correlation of `a` and `b`. `NaN` if either has no variance.
+pub fn pearson(a: &[f32], b: &[f32]) -> f64 {
+    let a: Vec<f64> = a.iter().map(|v| f64::from(*v)).collect();
+    let b: Vec<f64> = b.iter().map(|v| f64::from(*v)).collect();
+    pearson_f64(&a, &b)
+}
+
+/// The Spearman (rank) correlation of `a` and `b`. `NaN` if either has no
+/// variance.
+pub fn spearman(a: &[f32], b: &[f32]) -> f64 {
+    let a: Vec<f64> = a.iter().map(|v| f64::from(*v)).collect();
+    let b: Vec<f64> = b.iter().map(|v| f64::from(*v)).collect();
+    pearson_f64(&ranks(&a), &ranks(&b))
+}
+
+/// The pairwise correlations of some bigWigs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Correlation {
+    pub pearson: Vec<Vec<f64>>,
+    pub spearman: Vec<Vec<f64>>,
+    /// The summary of each pair `(i, j)`, with `i < j`.
+    pub pairs: Vec<(usize, usize, PairSummary)>,
+}
+
+/// The pairwise correlations of `values` (from [`bin_values`]).
+pub fn correlate(values: &[Vec<f32>]) -> Correlation {
+    let values: Vec<Vec<f64>> = values
+        .iter()
+        .map(|v| v.iter().map(|v| f64::from(*v)).collect())
+        .collect();
+    let ranks: Vec<Vec<f64>> = values.iter().map(|v| ranks(v)).collect();
+    let n = values.len();
+    let mut correlation = Correlation {
+        pearson: vec![vec![1.0; n]; n],
+        spearman: vec![vec![1.0; n]; n],
+        pairs: vec![],
+    };
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let (a, b) = (&values[i], &values[j]);
+            let (mean_a, mean_b, var_a, var_b, cov) = moments(a, b);
+            let slope = cov / var_a;
+            let summary = PairSummary {
+                bins: a.len(),
+                mean_a,
+                mean_b,
+                sd_a: var_a.sqrt(),
+                sd_b: var_b.sqrt(),
+                slope,
+                intercept: mean_b - slope * mean_a,
+                pearson: cov / (var_a * var_b).sqrt(),
+                spearman: pearson_f64(&ranks[i], &ranks[j]),
+            };
+            correlation.pearson[i][j] = summary.pearson;
+            correlation.pearson[j][i] = summary.pearson;
+            correlation.spearman[i][j] = summary.spearman;
+            correlation.spearman[j][i] = summary.spearman;
+            correlation.pairs.push((i, j, summary));
+        }
+    }
+    correlation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correlation() {
+        assert_eq!(ranks(&[3.0, 1.0, 3.0, 2.0]), vec![3.5, 1.0, 3.5, 2.0]);
+
+        let a = [1.0, 2.0, 3.0, 4.0];
+        let b = [2.0, 4.0, 6.0, 8.0];
+        let c = [1.0, 8.0, 27.0, 64.0];
+        assert!((pearson(&a, &b) - 1.0).abs() < 1e-12);
+        assert!(pearson(&a, &c) < 1.0);
+        assert!((spearman(&a, &c) - 1.0).abs() < 1e-12);
+        assert!(pearson(&a, &[1.0; 4]).is_nan());
+
+        let correlation = correlate(&[a.to_vec(), b.to_vec(), c.to_vec()]);
+        assert_eq!(correlation.pairs.len(), 3);
+        let (i, j, summary) = correlation.pairs[0];
+        assert_eq!((i, j, summary.bins), (0, 1, 4));
+        assert_eq!((summary.slope, summary.intercept), (2.0, 0.0));
+        assert_eq!(correlation.pearson[0][2], correlation.pearson[2][0]);
+        assert_eq!(correlation.spearman[1][2], 1.0);
+    }
+
+    #[test]
+    fn test_blacklist() {
+        let blacklist = Blacklist::new(vec![(50, 60), (10, 20), (15, 30)]);
+        assert_eq!(blacklist.0, vec![(10, 30), (50, 60)]);
+        assert!(!blacklist.overlaps(0, 10));
+        assert!(blacklist.overlaps(0, 11));
+        assert!(blacklist.overlaps(25, 55));
+        assert!(!blacklist.overlaps(30, 50));
+        assert!(!blacklist.overlaps(60, 70));
+    }
+}
@@ -0,0 +1,524 @@
+//! The distribution of the values of a bigWig, weighted by the bases they
+//! cover: a histogram, quantiles, and the coverage of each chromosome.
+//!
+//! Either whole chromosomes or a set of regions (which are merged if they
+//! overlap) are read, one chromosome at a time on each of some threads.
+//! Quantiles are either exact (from the number of bases of each distinct value)
+//! or approximate, from a [`TDigest`] (which uses much less memory for bigWigs
+//! with many distinct values).
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+
+use thiserror::Error;
+
+use crate::bbiread::BBIReadError;
+use crate::utils::parallel::par_chroms;
+use crate::utils::reopen::{Reopen, SeekableRead};
+use crate::BigWigRead;
+
+#[derive(Error, Debug)]
+pub enum HistogramError {
+    #[error("{}", .0)]
+    InvalidInput(String),
+    #[error("{}", .0)]
+    BBIReadError(#[from] BBIReadError),
+    #[error("{}", .0)]
+    IoError(#[from] io::Error),
+}
+
+/// The bins of a histogram, from `min` to `max`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HistogramBins {
+    /// Bins of equal width.
+    Linear { min: f64, max: f64, bins: usize },
+    /// Bins of equal width in log space. `min` must be positive.
+    Log { min: f64, max: f64, bins: usize },
+}
+
+impl HistogramBins {
+    /// The edges of the bins (one more than the number of bins).
+    pub fn edges(&self) -> Vec<f64> {
+        match *self {
+            HistogramBins::Linear { min, max, bins } => (0..=bins)
+                .map(|i| min + (max - min) * i as f64 / bins as f64)
+                .collect(),
+            HistogramBins::Log { min, max, bins } => {
+                let (log_min, log_max) = (min.ln(), max.ln());
+                let mut edges: Vec<f64> = (0..=bins)
+                    .map(|i| (log_min + (log_max - log_min) * i as f64 / bins as f64).exp())
+                    .collect();
+                // Without the rounding of `exp(ln(x))`
+                edges[0] = min;
+                edges[bins] = max;
+                edges
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<(), HistogramError> {
+        let (min, max, bins) = match *self {
+            HistogramBins::Linear { min, max, bins } => (min, max, bins),
+            HistogramBins::Log { min, max, bins } => {
+                if min <= 0.0 {
+                    return Err(HistogramError::InvalidInput(
+                        "The minimum of log bins must be positive.".to_owned(),
+                    ));
+                }
+                (min, max, bins)
+            }
+        };
+        if bins == 0 || min.partial_cmp(&max) != Some(std::cmp::Ordering::Less) {
+            return Err(HistogramError::InvalidInput(
+                "There must be at least one bin, and the minimum must be less than the maximum."
+                    .to_owned(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The bin of `value`: `Err(true)` if it is below the minimum, and
+    /// `Err(false)` if it is above the maximum. The maximum is in the last bin.
+    fn bin(&self, value: f64) -> Result<usize, bool> {
+        let (min, max, bins, value) = match *self {
+            HistogramBins::Linear { min, max, bins } => (min, max, bins, value),
+            HistogramBins::Log { min, max, bins } if value > 0.0 => {
+                (min.ln(), max.ln(), bins, value.ln())
+            }
+            HistogramBins::Log { .. } => return Err(true),
+        };
+        if value < min {
+            return Err(true);
+        }
+        if value > max {
+            return Err(false);
+        }
+        Ok((((value - min) / (max - min) * bins as f64) as usize).min(bins - 1))
+    }
+}
+
+/// The number of bases of values in each bin.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    pub edges: Vec<f64>,
+    pub counts: Vec<u64>,
+    /// The bases of values below the first edge.
+    pub underflow: u64,
+    /// The bases of values above the last edge.
+    pub overflow: u64,
+}
+
+/// An approximation of a (weighted) distribution, for quantiles, with a
+/// bounded number of centroids. This is the merging t-digest of Dunning and
+/// Ertl, with the `k1` scale function.
+#[derive(Clone, Debug)]
+pub struct TDigest {
+    compression: f64,
+    /// The mean and weight of each centroid, sorted by mean
+    centroids: Vec<(f64, f64)>,
+    buffer: Vec<(f64, f64)>,
+    total: f64,
+    min: f64,
+    max: f64,
+}
+
+impl TDigest {
+    /// A higher `compression` keeps more centroids (about `compression`), for
+    /// more accurate quantiles.
+    pub fn new(compression: f64) -> Self {
+        TDigest {
+            compression,
+            centroids: vec![],
+            buffer: vec![],
+            total: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn add(&mut self, value: f64, weight: f64) {
+        if weight <= 0.0 || value.is_nan() {
+            return;
+        }
+        self.buffer.push((value, weight));
+        self.total += weight;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        if self.buffer.len() as f64 > self.compression * 10.0 {
+            self.compress();
+        }
+    }
+
+    pub fn merge(&mut self, other: &TDigest) {
+        self.buffer
+            .extend(other.centroids.iter().chain(other.buffer.iter()));
+        self.total += other.total;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.compress();
+    }
+
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.centroids);
+        all.append(&mut self.buffer);
+        all.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let k = |q: f64| self.compression / (2.0 * std::f64::consts::PI) * (2.0 * q - 1.0).asin();
+        let k_inv =
+            |k: f64| ((k * 2.0 * std::f64::consts::PI / self.compression).sin() + 1.0) / 2.0;
+        let mut merged: Vec<(f64, f64)> = Vec::with_capacity(all.len());
+        let mut seen = 0.0;
+        let mut limit = 0.0;
+        for (mean, weight) in all {
+            match merged.last_mut() {
+                Some(last) if seen + weight <= limit => {
+                    last.1 += weight;
+                    last.0 += (mean - last.0) * weight / last.1;
+                }
+                _ => {
+                    // The limit of the new centroid starts from its first weight
+                    limit = self.total * k_inv(k(seen / self.total) + 1.0);
+                    merged.push((mean, weight));
+                }
+            }
+            seen += weight;
+        }
+        self.centroids = merged;
+    }
+
+    /// The approximate `q` quantile (from 0 to 1), or `NaN` if empty.
+    pub fn quantile(&mut self, q: f64) -> f64 {
+        self.compress();
+        if self.centroids.is_empty() {
+            return f64::NAN;
+        }
+        let target = q.clamp(0.0, 1.0) * self.total;
+        // Each centroid is centered on the middle of its weight
+        let mut seen = 0.0;
+        let mut prev = (self.min, 0.0);
+        for (mean, weight) in self.centroids.iter() {
+            let middle = seen + weight / 2.0;
+            if target < middle {
+                let (prev_value, prev_middle) = prev;
+                if middle <= prev_middle {
+                    return *mean;
+                }
+                let fraction = (target - prev_middle) / (middle - prev_middle);
+                return prev_value + (mean - prev_value) * fraction;
+            }
+            prev = (*mean, middle);
+            seen += weight;
+        }
+        let (prev_value, prev_middle) = prev;
+        if self.total <= prev_middle {
+            return self.max;
+        }
+        let fraction = (target - prev_middle) / (self.total - prev_middle);
+        prev_value + (self.max - prev_value) * fraction
+    }
+}
+
+/// How quantiles are computed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum QuantileMethod {
+    /// From the bases of each distinct value.
+    Exact,
+    /// From a [`TDigest`] with this compression.
+    TDigest(f64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistogramOptions {
+    pub bins: HistogramBins,
+    /// The quantiles to compute (from 0 to 1).
+    pub quantiles: Vec<f64>,
+    pub method: QuantileMethod,
+    /// Only these regions (chromosome, start, end) are read, instead of every
+    /// chromosome.
+    pub regions: Option<Vec<(String, u32, u32)>>,
+}
+
+/// The bases read and covered of a chromosome.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChromCoverage {
+    pub chrom: String,
+    /// The length of the chromosome, or the bases of its regions.
+    pub bases: u64,
+    pub covered: u64,
+}
+
+impl ChromCoverage {
+    pub fn coverage(&self) -> f64 {
+        if self.bases == 0 {
+            0.0
+        } else {
+            self.covered as f64 / self.bases as f64
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Distribution {
+    pub histogram: Histogram,
+    /// Each quantile and its value (`NaN` if there are no values).
+    pub quantiles: Vec<(f64, f64)>,
+    pub chroms: Vec<ChromCoverage>,
+}
+
+enum QuantileState {
+    /// The bases of each distinct value, by its bits
+    Exact(HashMap<u32, u64>),
+    TDigest(TDigest),
+}
+
+/// The distribution of the values of one thread.
+struct Accumulator {
+    counts: Vec<u64>,
+    underflow: u64,
+    overflow: u64,
+    quantiles: QuantileState,
+}
+
+impl Accumulator {
+    fn add(&mut self, bins: &HistogramBins, value: f32, bases: u32) {
+        if value.is_nan() || bases == 0 {
+            return;
+        }
+        match bins.bin(f64::from(value)) {
+            Ok(bin) => self.counts[bin] += u64::from(bases),
+            Err(true) => self.underflow += u64::from(bases),
+            Err(false) => self.overflow += u64::from(bases),
+        }
+        match &mut self.quantiles {
+            QuantileState::Exact(values) => {
+                // So that `-0.0` and `0.0` are the same value
+                let value = if value == 0.0 { 0.0f32 } else { value };
+                *values.entry(value.to_bits()).or_default() += u64::from(bases);
+            }
+            QuantileState::TDigest(digest) => digest.add(f64::from(value), f64::from(bases)),
+        }
+    }
+
+    fn merge(&mut self, other: Accumulator) {
+        for (count, other) in self.counts.iter_mut().zip(other.counts) {
+            *count += other;
+        }
+        self.underflow += other.underflow;
+        self.overflow += other.overflow;
+        match (&mut self.quantiles, other.quantiles) {
+            (QuantileState::Exact(values), QuantileState::Exact(other)) => {
+                for (value, bases) in other {
+                    *values.entry(value).or_default() += bases;
+                }
+            }
+            (QuantileState::TDigest(digest), QuantileState::TDigest(other)) => digest.merge(&other),
+            _ => unreachable!(),
+        }
+    }
+
+    fn quantiles(self, quantiles: &[f64]) -> Vec<(f64, f64)> {
+        match self.quantiles {
+            QuantileState::Exact(values) => {
+                let mut values: Vec<(f32, u64)> = values
+                    .into_iter()
+                    .map(|(value, bases)| (f32::from_bits(value), bases))
+                    .collect();
+                values.sort_by(|a, b| a.0.total_cmp(&b.0));
+                let total: u64 = values.iter().map(|v| v.1).sum();
+                quantiles
+                    .iter()
+                    .map(|q| {
+                        // The value of the first base at or after the quantile
+                        let target = (q.clamp(0.0, 1.0) * total as f64).ceil().max(1.0) as u64;
+                        let mut seen = 0;
+                        let value = values.iter().find(|(_, bases)| {
+                            seen += bases;
+                            seen >= target
+                        });
+                        (*q, value.map_or(f64::NAN, |v| f64::from(v.0)))
+                    })
+                    .collect()
+            }
+            QuantileState::TDigest(mut digest) => quantiles
+                .iter()
+                .map(|q| (*q, digest.quantile(*q)))
+                .collect(),
+        }
+    }
+}
+
+/// The distribution of the values of `bigwig`, over every chromosome or the
+/// regions of `options`. The chromosomes are split between `nthreads`
+/// threads, each with a reopened `bigwig`.
+pub fn distribution<R>(
+    bigwig: &BigWigRead<R>,
+    options: &HistogramOptions,
+    nthreads: usize,
+) -> Result<Distribution, HistogramError>
+where
+    R: Reopen + SeekableRead + Send,
+{
+    options.bins.validate()?;
+    // The regions of each chromosome, sorted and merged
+    let mut chroms: BTreeMap<String, Vec<(u32, u32)>> = BTreeMap::new();
+    match &options.regions {
+        None => {
+            for chrom in bigwig.info.chrom_info.iter() {
+                chroms.insert(chrom.name.clone(), vec![(0, chrom.length)]);
+            }
+        }
+        Some(regions) => {
+            for (chrom, start, end) in regions {
+                if end < start {
+                    return Err(HistogramError::InvalidInput(format!(
+                        "Invalid region (end before start): {}:{}-{}",
+                        chrom, start, end
+                    )));
+                }
+                chroms
+                    .entry(chrom.clone())
+                    .or_default()
+                    .push((*start, *end));
+            }
+            for regions in chroms.values_mut() {
+                regions.sort();
+                let mut merged: Vec<(u32, u32)> = vec![];
+                for (start, end) in regions.drain(..) {
+                    match merged.last_mut() {
+                        Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                        _ => merged.push((start, end)),
+                    }
+                }
+                *regions = merged;
+            }
+        }
+    }
+    let chroms: Vec<(String, Vec<(u32, u32)>)> = chroms.into_iter().collect();
+
+    let new_accumulator = || Accumulator {
+        counts: vec![0; options.bins.edges().len() - 1],
+        underflow: 0,
+        overflow: 0,
+        quantiles: match options.method {
+            QuantileMethod::Exact => QuantileState::Exact(HashMap::new()),
+            QuantileMethod::TDigest(compression) => {
+                QuantileState::TDigest(TDigest::new(compression))
+            }
+        },
+    };
+    let readers = (0..nthreads.max(1).min(chroms.len()))
+        .map(|_| bigwig.reopen())
+        .collect::<io::Result<Vec<_>>>()?;
+    let states = readers
+        .into_iter()
+        .map(|bigwig| (bigwig, new_accumulator()))
+        .collect();
+    let (coverages, states) = par_chroms(
+        &chroms,
+        states,
+        |(bigwig, accumulator), (chrom, regions)| -> Result<_, HistogramError> {
+            let mut coverage = ChromCoverage {
+                chrom: chrom.clone(),
+                bases: regions.iter().map(|(s, e)| u64::from(e - s)).sum(),
+                covered: 0,
+            };
+            if bigwig.info.find_chrom(chrom).is_some() {
+                for (start, end) in regions {
+                    for value in bigwig.get_interval(chrom, *start, *end)? {
+                        let value = value?;
+                        let bases = value.end.min(*end) - value.start.max(*start);
+                        coverage.covered += u64::from(bases);
+                        accumulator.add(&options.bins, value.value, bases);
+                    }
+                }
+            }
+            Ok(coverage)
+        },
+    )?;
+
+    let mut accumulator = new_accumulator();
+    for (_, thread_accumulator) in states {
+        accumulator.merge(thread_accumulator);
+    }
+    let histogram = Histogram {
+        edges: options.bins.edges(),
+        counts: accumulator.counts.clone(),
+        underflow: accumulator.underflow,
+        overflow: accumulator.overflow,
+    };
+    Ok(Distribution {
+        histogram,
+        quantiles: accumulator.quantiles(&options.quantiles),
+        chroms: coverages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bins() {
+        let linear = HistogramBins::Linear {
+            min: 0.0,
+            max: 10.0,
+            bins: 5,
+        };
+        assert_eq!(linear.edges(), vec![0.0, 2.0, 4.0, 6.0, 8.0, 10.0]);
+        assert_eq!(linear.bin(0.0), Ok(0));
+        assert_eq!(linear.bin(3.9), Ok(1));
+        assert_eq!(linear.bin(10.0), Ok(4));
+        assert_eq!(linear.bin(-1.0), Err(true));
+        assert_eq!(linear.bin(10.5), Err(false));
+
+        let log = HistogramBins::Log {
+            min: 1.0,
+            max: 1000.0,
+            bins: 3,
+        };
+        assert!((log.edges()[2] - 100.0).abs() < 1e-9);
+        assert_eq!(log.bin(50.0), Ok(1));
+        assert_eq!(log.bin(0.0), Err(true));
+        assert!(HistogramBins::Log {
+            min: 0.0,
+            max: 1.0,
+            bins: 1
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_tdigest() {
+        let mut digest = TDigest::new(100.0);
+        for i in 0..100_000 {
+            digest.add(f64::from(i), 1.0);
+        }
+        for q in [0.01, 0.25, 0.5, 0.9, 0.999] {
+            let expected = q * 100_000.0;
+            let value = digest.quantile(q);
+            assert!(
+                (value - expected).abs() < 100.0,
+                "{} {} {}",
+                q,
+                value,
+                expected
+            );
+        }
+        assert!(digest.centroids.len() < 200);
+
+        // Weights are like repeated values (with interpolation between the
+        // middles of centroids)
+        let mut a = TDigest::new(100.0);
+        a.add(1.0, 90.0);
+        let mut b = TDigest::new(100.0);
+        b.add(10.0, 10.0);
+        a.merge(&b);
+        assert_eq!(a.quantile(0.2), 1.0);
+        assert!((a.quantile(0.5) - 1.9).abs() < 1e-9);
+        assert_eq!(a.quantile(1.0), 10.0);
+        assert!(TDigest::new(100.0).quantile(0.5).is_nan());
+    }
+}
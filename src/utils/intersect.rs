@@ -0,0 +1,561 @@
+//! Intersection of the records of a bed file (`a`) with a bigBed or bigWig
+//! (`b`), with the semantics of `bedtools intersect`.
+//!
+//! Each line of `a` is queried against `b`, and a `b` record counts as an
+//! overlap if it overlaps at least one base of the `a` record, and at least
+//! `min_fraction` of the `a` record's bases. Header lines (empty, or starting
+//! with `#`, `track`, or `browser`) are skipped.
+//!
+//! When `a` is also a bigBed, [`intersect_bigbeds`] instead walks the entries
+//! of both files in order, one chromosome at a time on each of some threads
+//! (see [`sweep_intersect`]), rather than querying `b` for each entry of `a`.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::iter::Peekable;
+
+use thiserror::Error;
+
+use crate::bbiread::{BBIRead, BBIReadError};
+use crate::utils::parallel::chroms_in_order;
+use crate::utils::reopen::{Reopen, SeekableRead};
+use crate::{BedEntry, BigBedRead, BigWigRead, Value};
+
+#[derive(Error, Debug)]
+pub enum IntersectError {
+    #[error("{}", .0)]
+    InvalidInput(String),
+    #[error("{}", .0)]
+    BBIReadError(#[from] BBIReadError),
+    #[error("{}", .0)]
+    IoError(#[from] io::Error),
+}
+
+/// A summary of the `b` values overlapping each `a` record.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IntersectSummary {
+    /// The mean over the covered bases.
+    Mean,
+    Min,
+    Max,
+    /// The sum of each value times the number of bases it covers.
+    Sum,
+    /// The fraction of the bases that are covered.
+    Coverage,
+}
+
+/// The totals of the values overlapping a region, from which each
+/// [`IntersectSummary`] is computed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SummaryTotals {
+    /// The number of bases with values.
+    pub covered: f64,
+    /// The sum of each value times the number of bases it covers.
+    pub sum: f64,
+    /// The sum of each squared value times the number of bases it covers.
+    pub sum_squares: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Default for SummaryTotals {
+    fn default() -> Self {
+        SummaryTotals {
+            covered: 0.0,
+            sum: 0.0,
+            sum_squares: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl SummaryTotals {
+    /// Adds `value` over `bases` bases.
+    pub fn add_value(&mut self, value: f64, bases: f64) {
+        self.add(bases, value * bases, value * value * bases, value, value);
+    }
+
+    /// Adds the totals of several values (for example, of a zoom record).
+    pub fn add(&mut self, covered: f64, sum: f64, sum_squares: f64, min: f64, max: f64) {
+        self.covered += covered;
+        self.sum += sum;
+        self.sum_squares += sum_squares;
+        self.min = self.min.min(min);
+        self.max = self.max.max(max);
+    }
+
+    /// The sample standard deviation of the covered bases, or `NaN` if no
+    /// bases have values.
+    pub fn std(&self) -> f64 {
+        if self.covered == 0.0 {
+            return f64::NAN;
+        }
+        if self.covered <= 1.0 {
+            return 0.0;
+        }
+        let variance =
+            (self.sum_squares - self.sum * self.sum / self.covered) / (self.covered - 1.0);
+        variance.max(0.0).sqrt()
+    }
+
+    /// The summary of a region of `length` bases. The mean, min and max are
+    /// `NaN` if no bases have values, and the sum and coverage are 0.
+    pub fn summarize(&self, summary: IntersectSummary, length: u32) -> f64 {
+        match summary {
+            IntersectSummary::Sum => self.sum,
+            IntersectSummary::Coverage if length > 0 => self.covered / f64::from(length),
+            IntersectSummary::Coverage => 0.0,
+            _ if self.covered == 0.0 => f64::NAN,
+            IntersectSummary::Mean => self.sum / self.covered,
+            IntersectSummary::Min => self.min,
+            IntersectSummary::Max => self.max,
+        }
+    }
+}
+
+/// What is written for each `a` record.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IntersectMode {
+    /// A line for each overlap: the overlapping part of `a` (or all of `a`
+    /// with `write_a`), followed by the `b` record with `write_b`.
+    Overlaps { write_a: bool, write_b: bool },
+    /// `a`, once, if there is at least one overlap.
+    Unique,
+    /// `a`, if there are no overlaps.
+    NoOverlap,
+    /// `a`, followed by the number of overlaps.
+    Count,
+    /// `a`, followed by a summary of the overlapping values. Only for bigWigs.
+    Summary(IntersectSummary),
+}
+
+/// How `a` records on chromosomes that aren't in `b` are handled.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UnknownChroms {
+    /// They have no overlaps.
+    NoOverlaps,
+    /// They are an error.
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct IntersectOptions {
+    pub mode: IntersectMode,
+    /// The minimum overlap, as a fraction of the `a` record.
+    pub min_fraction: f64,
+    pub unknown_chroms: UnknownChroms,
+}
+
+impl Default for IntersectOptions {
+    fn default() -> Self {
+        IntersectOptions {
+            mode: IntersectMode::Overlaps {
+                write_a: false,
+                write_b: false,
+            },
+            min_fraction: 0.0,
+            unknown_chroms: UnknownChroms::NoOverlaps,
+        }
+    }
+}
+
+/// A file that can be intersected with, as `b`.
+pub trait IntersectSource {
+    type Record: IntersectRecord;
+
+    /// Returns the records overlapping `start..end` of `chrom`, or `None` if
+    /// `chrom` isn't in the file.
+    fn overlapping(
+        &mut self,
+        chrom: &str,
+        start: u32,
+        end: u32,
+    ) -> Result<Option<Vec<Self::Record>>, BBIReadError>;
+}
+
+/// A record of a `b` file.
+pub trait IntersectRecord {
+    fn start(&self) -> u32;
+    fn end(&self) -> u32;
+    /// The value of the record, if it has one.
+    fn value(&self) -> Option<f32>;
+    /// Writes the fields after the chromosome, start, and end, each preceded
+    /// by a tab.
+    fn write_rest<W: Write>(&self, out: &mut W) -> io::Result<()>;
+}
+
+impl IntersectRecord for BedEntry {
+    fn start(&self) -> u32 {
+        self.start
+    }
+    fn end(&self) -> u32 {
+        self.end
+    }
+    fn value(&self) -> Option<f32> {
+        None
+    }
+    fn write_rest<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        if self.rest.is_empty() {
+            return Ok(());
+        }
+        write!(out, "\t{}", self.rest)
+    }
+}
+
+impl IntersectRecord for Value {
+    fn start(&self) -> u32 {
+        self.start
+    }
+    fn end(&self) -> u32 {
+        self.end
+    }
+    fn value(&self) -> Option<f32> {
+        Some(self.value)
+    }
+    fn write_rest<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "\t{}", self.value)
+    }
+}
+
+impl<R: SeekableRead> IntersectSource for BigBedRead<R> {
+    type Record = BedEntry;
+
+    fn overlapping(
+        &mut self,
+        chrom: &str,
+        start: u32,
+        end: u32,
+    ) -> Result<Option<Vec<BedEntry>>, BBIReadError> {
+        if self.get_info().find_chrom(chrom).is_none() {
+            return Ok(None);
+        }
+        self.get_interval(chrom, start, end)?
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+}
+
+impl<R: SeekableRead> IntersectSource for BigWigRead<R> {
+    type Record = Value;
+
+    fn overlapping(
+        &mut self,
+        chrom: &str,
+        start: u32,
+        end: u32,
+    ) -> Result<Option<Vec<Value>>, BBIReadError> {
+        if self.get_info().find_chrom(chrom).is_none() {
+            return Ok(None);
+        }
+        self.get_interval(chrom, start, end)?
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+}
+
+/// Whether `line` of a bed file is a header line (empty, or starting with `#`,
+/// `track`, or `browser`), which isn't a record.
+pub fn is_header(line: &str) -> bool {
+    line.is_empty()
+        || line.starts_with('#')
+        || line.starts_with("track")
+        || line.starts_with("browser")
+}
+
+fn parse_a(line: &str) -> Result<(&str, u32, u32, Option<&str>), IntersectError> {
+    let mut split = line.splitn(4, '\t');
+    let chrom = split.next().unwrap();
+    let mut coord = |what: &str| {
+        split
+            .next()
+            .and_then(|s| s.parse::<u32>().ok())
+            .ok_or_else(|| IntersectError::InvalidInput(format!("Invalid {}: {}", what, line)))
+    };
+    let start = coord("start")?;
+    let end = coord("end")?;
+    if end < start {
+        return Err(IntersectError::InvalidInput(format!(
+            "Invalid interval (end before start): {}",
+            line
+        )));
+    }
+    Ok((chrom, start, end, split.next()))
+}
+
+fn summarize<T: IntersectRecord>(
+    summary: IntersectSummary,
+    start: u32,
+    end: u32,
+    overlaps: &[T],
+) -> Result<f64, IntersectError> {
+    let mut totals = SummaryTotals::default();
+    for record in overlaps {
+        let value = record.value().ok_or_else(|| {
+            IntersectError::InvalidInput("Summaries are only supported for bigWigs.".to_owned())
+        })? as f64;
+        let bases = record.end().min(end) - record.start().max(start);
+        totals.add_value(value, f64::from(bases));
+    }
+    Ok(totals.summarize(summary, end - start))
+}
+
+/// A record of `a`: its line, and its chromosome, start, end, and the rest of
+/// its fields.
+struct ARecord<'a> {
+    line: &'a str,
+    chrom: &'a str,
+    start: u32,
+    end: u32,
+    rest: Option<&'a str>,
+}
+
+/// Writes the result for one `a` record, given the `b` records overlapping it.
+fn write_result<T: IntersectRecord, W: Write>(
+    out: &mut W,
+    a: ARecord<'_>,
+    overlaps: Vec<T>,
+    options: &IntersectOptions,
+) -> Result<(), IntersectError> {
+    let ARecord {
+        line,
+        chrom,
+        start,
+        end,
+        rest,
+    } = a;
+    let min_bases = options.min_fraction * (end - start) as f64;
+    let overlaps: Vec<T> = overlaps
+        .into_iter()
+        .filter(|r| {
+            let bases = r.end().min(end).saturating_sub(r.start().max(start));
+            bases > 0 && bases as f64 >= min_bases
+        })
+        .collect();
+
+    match options.mode {
+        IntersectMode::Overlaps { write_a, write_b } => {
+            for record in &overlaps {
+                if write_a {
+                    out.write_all(line.as_bytes())?;
+                } else {
+                    let overlap_start = record.start().max(start);
+                    let overlap_end = record.end().min(end);
+                    write!(out, "{}\t{}\t{}", chrom, overlap_start, overlap_end)?;
+                    if let Some(rest) = rest {
+                        write!(out, "\t{}", rest)?;
+                    }
+                }
+                if write_b {
+                    write!(out, "\t{}\t{}\t{}", chrom, record.start(), record.end())?;
+                    record.write_rest(out)?;
+                }
+                writeln!(out)?;
+            }
+        }
+        IntersectMode::Unique => {
+            if !overlaps.is_empty() {
+                writeln!(out, "{}", line)?;
+            }
+        }
+        IntersectMode::NoOverlap => {
+            if overlaps.is_empty() {
+                writeln!(out, "{}", line)?;
+            }
+        }
+        IntersectMode::Count => {
+            writeln!(out, "{}\t{}", line, overlaps.len())?;
+        }
+        IntersectMode::Summary(summary) => {
+            let value = summarize(summary, start, end, &overlaps)?;
+            writeln!(out, "{}\t{}", line, value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Intersects each record of `a` with `b`, writing the results to `out`.
+pub fn intersect<B: IntersectSource, R: BufRead, W: Write>(
+    mut a: R,
+    b: &mut B,
+    options: &IntersectOptions,
+    mut out: W,
+) -> Result<(), IntersectError> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if a.read_line(&mut line)? == 0 {
+            break;
+        }
+        let line = line.trim_end_matches(['\n', '\r']);
+        if is_header(line) {
+            continue;
+        }
+        let (chrom, start, end, rest) = parse_a(line)?;
+
+        let overlaps = match b.overlapping(chrom, start, end)? {
+            Some(records) => records,
+            None if options.unknown_chroms == UnknownChroms::Error => {
+                return Err(IntersectError::InvalidInput(format!(
+                    "Chromosome `{}` is not in `b`.",
+                    chrom
+                )));
+            }
+            None => vec![],
+        };
+        let a = ARecord {
+            line,
+            chrom,
+            start,
+            end,
+            rest,
+        };
+        write_result(&mut out, a, overlaps, options)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// An `Iterator` of each entry of `a` with the entries of `b` that overlap it
+/// (by at least one base), for entries of one chromosome sorted by start.
+/// Created by [`sweep_intersect`].
+pub struct SweepIntersect<A, B: Iterator> {
+    a: A,
+    b: Peekable<B>,
+    /// The entries of `b` that start before the last entry of `a` ends, and
+    /// end after it starts
+    window: VecDeque<BedEntry>,
+}
+
+/// Intersects the entries of `a` and `b` (of the same chromosome, sorted by
+/// start) by sweeping over both once.
+pub fn sweep_intersect<A, B, E>(a: A, b: B) -> SweepIntersect<A, B>
+where
+    A: Iterator<Item = Result<BedEntry, E>>,
+    B: Iterator<Item = Result<BedEntry, E>>,
+{
+    SweepIntersect {
+        a,
+        b: b.peekable(),
+        window: VecDeque::new(),
+    }
+}
+
+impl<A, B, E> Iterator for SweepIntersect<A, B>
+where
+    A: Iterator<Item = Result<BedEntry, E>>,
+    B: Iterator<Item = Result<BedEntry, E>>,
+{
+    type Item = Result<(BedEntry, Vec<BedEntry>), E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let a = match self.a.next()? {
+            Ok(a) => a,
+            Err(e) => return Some(Err(e)),
+        };
+        // Entries of `a` start at or after this one, so the entries of `b` that
+        // end before it can't overlap any of them
+        self.window.retain(|b| b.end > a.start);
+        while self
+            .b
+            .peek()
+            .is_some_and(|b| b.as_ref().map_or(true, |b| b.start < a.end))
+        {
+            match self.b.next().unwrap() {
+                Ok(b) if b.end > a.start => self.window.push_back(b),
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        let overlaps = self
+            .window
+            .iter()
+            .filter(|b| b.start < a.end && b.end > a.start)
+            .cloned()
+            .collect();
+        Some(Ok((a, overlaps)))
+    }
+}
+
+/// The output of intersecting the entries of one chromosome.
+fn intersect_chrom<RA: SeekableRead, RB: SeekableRead>(
+    a: &mut BigBedRead<RA>,
+    b: &mut BigBedRead<RB>,
+    chrom: &str,
+    options: &IntersectOptions,
+) -> Result<Vec<u8>, IntersectError> {
+    let length = a.info.find_chrom(chrom).unwrap().length;
+    let a = a.get_interval(chrom, 0, length)?;
+    let b = match b.info.find_chrom(chrom) {
+        Some(info) => {
+            let length = info.length;
+            Some(b.get_interval(chrom, 0, length)?)
+        }
+        None if options.unknown_chroms == UnknownChroms::Error => {
+            return Err(IntersectError::InvalidInput(format!(
+                "Chromosome `{}` is not in `b`.",
+                chrom
+            )));
+        }
+        None => None,
+    };
+    let mut out = vec![];
+    let mut line = String::new();
+    for result in sweep_intersect(a, b.into_iter().flatten()) {
+        let (entry, overlaps) = result?;
+        line.clear();
+        line.push_str(&format!("{}\t{}\t{}", chrom, entry.start, entry.end));
+        if !entry.rest.is_empty() {
+            line.push('\t');
+            line.push_str(&entry.rest);
+        }
+        let a = ARecord {
+            line: &line,
+            chrom,
+            start: entry.start,
+            end: entry.end,
+            rest: (!entry.rest.is_empty()).then_some(entry.rest.as_str()),
+        };
+        write_result(&mut out, a, overlaps, options)?;
+    }
+    Ok(out)
+}
+
+/// Intersects each entry of the bigBed `a` with the bigBed `b`, writing the
+/// results to `out`, with the chromosomes sorted by name. The chromosomes are
+/// split between `nthreads` threads, each reading reopened files. The output of
+/// each chromosome is written once those before it are, and a thread only
+/// starts a chromosome when there is room for its output, so at most `nthreads`
+/// chromosomes are held at once. Stops at the first error, in order.
+pub fn intersect_bigbeds<RA, RB, W>(
+    a: &BigBedRead<RA>,
+    b: &BigBedRead<RB>,
+    options: &IntersectOptions,
+    nthreads: usize,
+    mut out: W,
+) -> Result<(), IntersectError>
+where
+    RA: Reopen + SeekableRead + Send + 'static,
+    RB: Reopen + SeekableRead + Send + 'static,
+    W: Write,
+{
+    if let IntersectMode::Summary(_) = options.mode {
+        return Err(IntersectError::InvalidInput(
+            "Summaries are only supported for bigWigs.".to_owned(),
+        ));
+    }
+    let mut chroms: Vec<String> = a.info.chrom_info.iter().map(|c| c.name.clone()).collect();
+    chroms.sort();
+    let readers = (0..nthreads.max(1).min(chroms.len()))
+        .map(|_| Ok((a.reopen()?, b.reopen()?)))
+        .collect::<io::Result<Vec<_>>>()?;
+    let options = options.clone();
+    let results = chroms_in_order(chroms, readers, move |(a, b), chrom| {
+        intersect_chrom(a, b, chrom, &options)
+    });
+    for (_, result) in results {
+        out.write_all(&result?)?;
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,892 @@
+//! Mapping bigWig values and bigBed entries between assemblies with UCSC chain
+//! files (like `hg19ToHg38.over.chain.gz`).
+//!
+//! Each chain maps the blocks of a source (the "target" of the chain format)
+//! chromosome to a target (the chain "query") chromosome, possibly on the
+//! opposite strand. Intervals are mapped through every block they overlap, so
+//! an interval spanning a gap between blocks (or chains) is split. The parts
+//! of an interval that aren't in any block are unmapped.
+//!
+//! A bigWig can't have overlapping values, so a mapped value that overlaps an
+//! earlier one (by target start) is unmapped. A bigBed entry is split into
+//! each of its mapped parts, with its strand (the sixth column) flipped for
+//! chains on the opposite strand. Its `thickStart` and `thickEnd`, and the
+//! blocks of a BED12, are mapped along with it and clamped to each part (as
+//! found from the autoSql of the bigBed), and a part is trimmed to the blocks
+//! in it. An entry is unmapped if less than `min_fraction` of its bases map,
+//! if none of its blocks map, or if those fields aren't valid.
+//!
+//! [`liftover_values`] and [`liftover_entries`] read the input one target
+//! chromosome at a time (only the parts of the source covered by the chains to
+//! that chromosome), so at most one chromosome of records is in memory.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use flate2::read::MultiGzDecoder;
+use thiserror::Error;
+
+use crate::bbiread::{BBIRead, BBIReadError};
+use crate::bed::autosql::parse_autosql_fields;
+use crate::utils::reopen::SeekableRead;
+use crate::{BedEntry, BigBedRead, BigWigRead, Value};
+
+#[derive(Error, Debug)]
+pub enum LiftoverError {
+    #[error("Invalid chain file at line {}: {}", .line, .message)]
+    InvalidChain { line: usize, message: String },
+    #[error("{}", .0)]
+    BBIReadError(#[from] BBIReadError),
+    #[error("{}", .0)]
+    IoError(#[from] io::Error),
+}
+
+/// An ungapped block of a chain.
+#[derive(Copy, Clone, Debug)]
+struct Block {
+    start: u32,
+    size: u32,
+    /// The index of the target chromosome
+    target: usize,
+    /// The start on the target, on the strand of the chain
+    target_start: u32,
+    minus: bool,
+}
+
+/// The blocks of a source chromosome, sorted by start.
+#[derive(Clone, Debug, Default)]
+struct SourceChrom {
+    blocks: Vec<Block>,
+    max_size: u32,
+}
+
+/// A part of an interval, mapped to the target.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MappedInterval<'a> {
+    pub chrom: &'a str,
+    pub start: u32,
+    pub end: u32,
+    /// Whether the chain is on the opposite strand.
+    pub minus: bool,
+    /// The part of the source interval that was mapped.
+    pub source_start: u32,
+    pub source_end: u32,
+}
+
+impl MappedInterval<'_> {
+    /// Maps `start..end` of the source, clamped to the part that was mapped.
+    /// Returns `None` if they don't overlap.
+    fn map_within(&self, start: u32, end: u32) -> Option<(u32, u32)> {
+        let (start, end) = (start.max(self.source_start), end.min(self.source_end));
+        if start >= end {
+            return None;
+        }
+        let (from, to) = (start - self.source_start, end - self.source_start);
+        Some(match self.minus {
+            true => (self.end - to, self.end - from),
+            false => (self.start + from, self.start + to),
+        })
+    }
+}
+
+/// The source interval covered by a chain.
+#[derive(Clone, Debug)]
+struct ChainSpan {
+    source: String,
+    start: u32,
+    end: u32,
+    target: usize,
+}
+
+/// Source intervals, as chromosome, start and end.
+type Spans = Vec<(String, u32, u32)>;
+
+/// The blocks of the chains of a chain file, for mapping intervals.
+#[derive(Clone, Debug, Default)]
+pub struct Chains {
+    sources: HashMap<String, SourceChrom>,
+    targets: Vec<(String, u32)>,
+    spans: Vec<ChainSpan>,
+}
+
+impl Chains {
+    /// Loads the chains of a chain file, which may be gzipped.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, LiftoverError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+            Chains::from_reader(BufReader::new(MultiGzDecoder::new(reader)))
+        } else {
+            Chains::from_reader(reader)
+        }
+    }
+
+    /// Reads chains in the UCSC chain format.
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, LiftoverError> {
+        let mut chains = Chains::default();
+        let mut target_ids: HashMap<String, usize> = HashMap::new();
+        // The source chromosome, end, target end, and the next block of the
+        // current chain
+        let mut current: Option<(String, u32, u32, Block)> = None;
+        let mut last_line = 0;
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line_num = i + 1;
+            last_line = line_num;
+            let invalid = |message: String| LiftoverError::InvalidChain {
+                line: line_num,
+                message,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let number = |field: &str, what: &str| {
+                field
+                    .parse::<u32>()
+                    .map_err(|_| invalid(format!("Invalid {}: `{}`", what, field)))
+            };
+            if fields[0] == "chain" {
+                if current.is_some() {
+                    return Err(invalid("The previous chain has no last block.".to_owned()));
+                }
+                if fields.len() < 12 {
+                    return Err(invalid("A chain header has at least 12 fields.".to_owned()));
+                }
+                if fields[4] != "+" {
+                    return Err(invalid("The source strand must be `+`.".to_owned()));
+                }
+                let minus = match fields[9] {
+                    "+" => false,
+                    "-" => true,
+                    strand => return Err(invalid(format!("Invalid strand: `{}`", strand))),
+                };
+                let target_size = number(fields[8], "size")?;
+                let next_id = target_ids.len();
+                let target = *target_ids.entry(fields[7].to_owned()).or_insert(next_id);
+                if target == chains.targets.len() {
+                    chains.targets.push((fields[7].to_owned(), target_size));
+                } else if chains.targets[target].1 != target_size {
+                    return Err(invalid(format!(
+                        "Chromosome `{}` has different sizes.",
+                        fields[7]
+                    )));
+                }
+                let block = Block {
+                    start: number(fields[5], "start")?,
+                    size: 0,
+                    target,
+                    target_start: number(fields[10], "start")?,
+                    minus,
+                };
+                let (end, target_end) = (number(fields[6], "end")?, number(fields[11], "end")?);
+                // Positions on the minus strand are mapped from the end of
+                // the target, so they must be within it
+                let source_size = number(fields[3], "size")?;
+                if block.start > end || end > source_size {
+                    return Err(invalid(format!(
+                        "The chain is outside of `{}` (of size {}).",
+                        fields[2], source_size
+                    )));
+                }
+                if block.target_start > target_end || target_end > target_size {
+                    return Err(invalid(format!(
+                        "The chain is outside of `{}` (of size {}).",
+                        fields[7], target_size
+                    )));
+                }
+                chains.spans.push(ChainSpan {
+                    source: fields[2].to_owned(),
+                    start: block.start,
+                    end,
+                    target,
+                });
+                current = Some((fields[2].to_owned(), end, target_end, block));
+                continue;
+            }
+            let Some((chrom, end, target_end, block)) = current.as_mut() else {
+                return Err(invalid("A block is not in a chain.".to_owned()));
+            };
+            let (size, gaps) = match fields[..] {
+                [size] => (number(size, "size")?, None),
+                [size, gap, target_gap] => (
+                    number(size, "size")?,
+                    Some((number(gap, "gap")?, number(target_gap, "gap")?)),
+                ),
+                _ => return Err(invalid("A block has 1 or 3 fields.".to_owned())),
+            };
+            let source = chains.sources.entry(chrom.clone()).or_default();
+            if size > 0 {
+                source.blocks.push(Block { size, ..*block });
+                source.max_size = source.max_size.max(size);
+            }
+            let past_end = || invalid("The blocks are past the end of the chain.".to_owned());
+            block.start = block.start.checked_add(size).ok_or_else(past_end)?;
+            block.target_start = block.target_start.checked_add(size).ok_or_else(past_end)?;
+            match gaps {
+                Some((gap, target_gap)) => {
+                    block.start = block.start.checked_add(gap).ok_or_else(past_end)?;
+                    block.target_start = block
+                        .target_start
+                        .checked_add(target_gap)
+                        .ok_or_else(past_end)?;
+                }
+                None => {
+                    if block.start != *end || block.target_start != *target_end {
+                        return Err(invalid(
+                            "The blocks don't end at the end of the chain.".to_owned(),
+                        ));
+                    }
+                    current = None;
+                }
+            }
+        }
+        if current.is_some() {
+            return Err(LiftoverError::InvalidChain {
+                line: last_line,
+                message: "The last chain has no last block.".to_owned(),
+            });
+        }
+        for source in chains.sources.values_mut() {
+            source.blocks.sort_by_key(|b| b.start);
+        }
+        Ok(chains)
+    }
+
+    /// The sizes of the target chromosomes.
+    pub fn target_sizes(&self) -> HashMap<String, u32> {
+        self.targets.iter().cloned().collect()
+    }
+
+    /// The target chromosomes, sorted, each with the (merged) source intervals
+    /// of the chains that map to it, sorted by chromosome and start.
+    fn target_spans(&self) -> Vec<(String, Spans)> {
+        let mut spans: Vec<Spans> = vec![vec![]; self.targets.len()];
+        for span in self.spans.iter() {
+            spans[span.target].push((span.source.clone(), span.start, span.end));
+        }
+        let mut targets: Vec<(String, Spans)> = self
+            .targets
+            .iter()
+            .zip(spans)
+            .map(|((target, _), mut spans)| {
+                spans.sort();
+                let mut merged: Spans = Vec::with_capacity(spans.len());
+                for (chrom, start, end) in spans {
+                    match merged.last_mut() {
+                        Some(last) if last.0 == chrom && start <= last.2 => {
+                            last.2 = last.2.max(end);
+                        }
+                        _ => merged.push((chrom, start, end)),
+                    }
+                }
+                (target.clone(), merged)
+            })
+            .collect();
+        targets.sort_by(|a, b| a.0.cmp(&b.0));
+        targets
+    }
+
+    /// Maps `start..end` of `chrom`, returning each mapped part, sorted by
+    /// source start. A zero-length interval is mapped as its first base.
+    pub fn map(&self, chrom: &str, start: u32, end: u32) -> Vec<MappedInterval<'_>> {
+        let Some(source) = self.sources.get(chrom) else {
+            return vec![];
+        };
+        let query_end = end.max(start.saturating_add(1));
+        let blocks = &source.blocks;
+        // Blocks that start `max_size` before `start` can't overlap it
+        let first = blocks.partition_point(|b| b.start.saturating_add(source.max_size) <= start);
+        let last = blocks.partition_point(|b| b.start < query_end);
+        let mut mapped: Vec<MappedInterval<'_>> = blocks[first..last]
+            .iter()
+            .filter(|b| b.start + b.size > start)
+            .map(|b| {
+                let source_start = start.max(b.start);
+                let source_end = query_end.min(b.start + b.size);
+                let (target, target_size) = &self.targets[b.target];
+                let mut mapped_start = b.target_start + (source_start - b.start);
+                let mut mapped_end = b.target_start + (source_end - b.start);
+                if b.minus {
+                    (mapped_start, mapped_end) =
+                        (target_size - mapped_end, target_size - mapped_start);
+                }
+                if end == start {
+                    mapped_end = mapped_start;
+                }
+                MappedInterval {
+                    chrom: target,
+                    start: mapped_start,
+                    end: mapped_end,
+                    minus: b.minus,
+                    source_start,
+                    source_end: source_end.min(end),
+                }
+            })
+            .collect();
+        mapped.sort_by_key(|m| m.source_start);
+        mapped
+    }
+}
+
+/// A record of a file mapped with [`Chains`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Lifted<T> {
+    /// A record (or part of one) mapped to a target chromosome.
+    Mapped(String, T),
+    /// A record (or part of one) that couldn't be mapped, in source
+    /// coordinates.
+    Unmapped(String, T),
+}
+
+/// A bigWig or bigBed that can be mapped with [`Chains`].
+pub trait LiftoverInput: BBIRead {
+    type Record;
+
+    /// Adds the records of source chromosome `chrom` that don't map, at all
+    /// or (for entries) by at least `min_fraction`, to `out` as unmapped.
+    fn unmapped(
+        &mut self,
+        chains: &Chains,
+        chrom: &str,
+        length: u32,
+        min_fraction: f64,
+        out: &mut VecDeque<Lifted<Self::Record>>,
+    ) -> Result<(), LiftoverError>;
+
+    /// Adds the records that map to `target`, sorted by start, to `out`.
+    /// `spans` are the source intervals of the chains to `target`.
+    fn mapped(
+        &mut self,
+        chains: &Chains,
+        target: &str,
+        spans: &[(String, u32, u32)],
+        min_fraction: f64,
+        out: &mut VecDeque<Lifted<Self::Record>>,
+    ) -> Result<(), LiftoverError>;
+}
+
+impl<R: SeekableRead> LiftoverInput for BigWigRead<R> {
+    type Record = Value;
+
+    fn unmapped(
+        &mut self,
+        chains: &Chains,
+        chrom: &str,
+        length: u32,
+        _min_fraction: f64,
+        out: &mut VecDeque<Lifted<Value>>,
+    ) -> Result<(), LiftoverError> {
+        for value in self.get_interval(chrom, 0, length)? {
+            let value = value?;
+            let mut unmapped_start = value.start;
+            let mut unmapped = |start, end| {
+                let part = Value {
+                    start,
+                    end,
+                    ..value
+                };
+                out.push_back(Lifted::Unmapped(chrom.to_owned(), part));
+            };
+            for part in chains.map(chrom, value.start, value.end) {
+                if part.source_start > unmapped_start {
+                    unmapped(unmapped_start, part.source_start);
+                }
+                unmapped_start = unmapped_start.max(part.source_end);
+            }
+            if unmapped_start < value.end {
+                unmapped(unmapped_start, value.end);
+            }
+        }
+        Ok(())
+    }
+
+    fn mapped(
+        &mut self,
+        chains: &Chains,
+        target: &str,
+        spans: &[(String, u32, u32)],
+        _min_fraction: f64,
+        out: &mut VecDeque<Lifted<Value>>,
+    ) -> Result<(), LiftoverError> {
+        // Each mapped value, with the part of the source it came from
+        let mut mapped: Vec<(Value, &str, Value)> = vec![];
+        for (chrom, span_start, span_end) in spans {
+            if self.info.find_chrom(chrom).is_none() {
+                continue;
+            }
+            for value in self.get_interval(chrom, *span_start, *span_end)? {
+                let value = value?;
+                // Only the part in this span, so that no part is mapped twice
+                let (start, end) = (value.start.max(*span_start), value.end.min(*span_end));
+                if start >= end {
+                    continue;
+                }
+                for part in chains.map(chrom, start, end) {
+                    if part.chrom != target {
+                        continue;
+                    }
+                    let mapped_value = Value {
+                        start: part.start,
+                        end: part.end,
+                        value: value.value,
+                    };
+                    let source = Value {
+                        start: part.source_start,
+                        end: part.source_end,
+                        value: value.value,
+                    };
+                    mapped.push((mapped_value, chrom, source));
+                }
+            }
+        }
+        mapped.sort_by_key(|(v, _, _)| (v.start, v.end));
+        let mut last_end = None;
+        for (value, chrom, source) in mapped {
+            if last_end.is_some_and(|end| value.start < end) {
+                out.push_back(Lifted::Unmapped(chrom.to_owned(), source));
+            } else {
+                last_end = Some(value.end);
+                out.push_back(Lifted::Mapped(target.to_owned(), value));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Flips the strand (the third field of `rest`), if there is one.
+fn flip_strand(rest: &str) -> String {
+    let mut fields: Vec<&str> = rest.split('\t').collect();
+    if let Some(strand) = fields.get_mut(2) {
+        match *strand {
+            "+" => *strand = "-",
+            "-" => *strand = "+",
+            _ => {}
+        }
+    }
+    fields.join("\t")
+}
+
+/// Whether enough of `entry` maps, with `parts` being its mapped parts.
+fn is_mapped(entry: &BedEntry, parts: &[MappedInterval<'_>], min_fraction: f64) -> bool {
+    let bases: u32 = parts
+        .iter()
+        .map(|p| p.source_end.max(p.source_start.saturating_add(1)) - p.source_start)
+        .sum();
+    let length = (entry.end - entry.start).max(1);
+    !parts.is_empty() && f64::from(bases) >= min_fraction * f64::from(length)
+}
+
+/// The columns (of `rest`) of a bigBed that are positions, and are mapped
+/// along with its entries.
+#[derive(Copy, Clone, Debug, Default)]
+struct PositionColumns {
+    /// `thickStart` and `thickEnd`
+    thick: Option<(usize, usize)>,
+    /// `blockCount`, `blockSizes` and `chromStarts` (of a BED12)
+    blocks: Option<(usize, usize, usize)>,
+}
+
+/// The positions in the columns of an entry, on the source.
+struct EntryPositions {
+    thick: Option<(u32, u32)>,
+    /// The start and end of each block
+    blocks: Option<Vec<(u32, u32)>>,
+}
+
+impl PositionColumns {
+    /// The columns of a bigBed with `autosql`. Without fields, these are the
+    /// columns of a standard bed.
+    fn new(autosql: &str) -> Self {
+        let names: Vec<String> = match parse_autosql_fields(autosql) {
+            Some(fields) if fields.len() > 3 => {
+                fields.into_iter().skip(3).map(|f| f.name).collect()
+            }
+            _ => [
+                "name",
+                "score",
+                "strand",
+                "thickStart",
+                "thickEnd",
+                "reserved",
+                "blockCount",
+                "blockSizes",
+                "chromStarts",
+            ]
+            .iter()
+            .map(|n| n.to_string())
+            .collect(),
+        };
+        let column = |name: &str| names.iter().position(|n| n == name);
+        PositionColumns {
+            thick: column("thickStart").zip(column("thickEnd")),
+            blocks: match (column("blockCount"), column("blockSizes")) {
+                (Some(count), Some(sizes)) => column("chromStarts")
+                    .or_else(|| column("blockStarts"))
+                    .map(|starts| (count, sizes, starts)),
+                _ => None,
+            },
+        }
+    }
+
+    /// The positions of `entry`, or `None` if they aren't valid. Columns the
+    /// entry doesn't have are ignored.
+    fn positions(&self, entry: &BedEntry) -> Option<EntryPositions> {
+        let fields: Vec<&str> = entry.rest.split('\t').collect();
+        let number = |i: usize| fields[i].trim().parse::<u32>().ok();
+        let list = |i: usize| -> Option<Vec<u32>> {
+            fields[i]
+                .trim()
+                .trim_end_matches(',')
+                .split(',')
+                .map(|n| n.trim().parse().ok())
+                .collect()
+        };
+        let thick = match self.thick {
+            Some((start, end)) if start.max(end) < fields.len() => {
+                let (start, end) = (number(start)?, number(end)?);
+                if start > end {
+                    return None;
+                }
+                Some((start, end))
+            }
+            _ => None,
+        };
+        let blocks = match self.blocks {
+            Some((count, sizes, starts)) if count.max(sizes).max(starts) < fields.len() => {
+                let count = number(count)? as usize;
+                let (sizes, starts) = (list(sizes)?, list(starts)?);
+                if count == 0 || sizes.len() != count || starts.len() != count {
+                    return None;
+                }
+                let blocks = starts
+                    .iter()
+                    .zip(sizes)
+                    .map(|(start, size)| {
+                        let start = entry.start.checked_add(*start)?;
+                        let end = start.checked_add(size)?;
+                        (end <= entry.end).then_some((start, end))
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+                Some(blocks)
+            }
+            _ => None,
+        };
+        Some(EntryPositions { thick, blocks })
+    }
+
+    /// `entry` mapped through `part`, with its positions mapped and clamped
+    /// to the part. Returns `None` if no block of the entry is in the part.
+    fn lift(
+        &self,
+        entry: &BedEntry,
+        positions: &EntryPositions,
+        part: &MappedInterval<'_>,
+    ) -> Option<BedEntry> {
+        let (mut start, mut end) = (part.start, part.end);
+        let mut fields: Vec<String> = entry.rest.split('\t').map(str::to_owned).collect();
+        if let (Some(blocks), Some((count, sizes, starts))) = (&positions.blocks, self.blocks) {
+            let mut mapped: Vec<(u32, u32)> = blocks
+                .iter()
+                .filter_map(|(s, e)| part.map_within(*s, *e))
+                .collect();
+            mapped.sort();
+            start = mapped.first()?.0;
+            end = mapped.iter().map(|(_, e)| *e).max()?;
+            // Lists are written like their input, with or without a trailing
+            // comma
+            let trailing = if fields[sizes].trim().ends_with(',') {
+                ","
+            } else {
+                ""
+            };
+            let join = |values: Vec<u32>| {
+                let values: Vec<String> = values.iter().map(u32::to_string).collect();
+                format!("{}{}", values.join(","), trailing)
+            };
+            fields[count] = mapped.len().to_string();
+            fields[sizes] = join(mapped.iter().map(|(s, e)| e - s).collect());
+            fields[starts] = join(mapped.iter().map(|(s, _)| s - start).collect());
+        }
+        if let (Some((thick_start, thick_end)), Some((start_col, end_col))) =
+            (positions.thick, self.thick)
+        {
+            // Without any thick part, both are at the start
+            let (thick_start, thick_end) = part
+                .map_within(thick_start, thick_end)
+                .map(|(s, e)| (s.max(start), e.min(end)))
+                .filter(|(s, e)| s < e)
+                .unwrap_or((start, start));
+            fields[start_col] = thick_start.to_string();
+            fields[end_col] = thick_end.to_string();
+        }
+        let rest = fields.join("\t");
+        let rest = if part.minus { flip_strand(&rest) } else { rest };
+        Some(BedEntry { start, end, rest })
+    }
+}
+
+/// Each mapped part of `entry` of `chrom`, with the entry mapped to it, or
+/// `None` if the entry is unmapped.
+fn lift_entry<'a>(
+    chains: &'a Chains,
+    columns: &PositionColumns,
+    chrom: &str,
+    entry: &BedEntry,
+    min_fraction: f64,
+) -> Option<Vec<(MappedInterval<'a>, BedEntry)>> {
+    let parts = chains.map(chrom, entry.start, entry.end);
+    if !is_mapped(entry, &parts, min_fraction) {
+        return None;
+    }
+    let positions = columns.positions(entry)?;
+    let lifted: Vec<_> = parts
+        .into_iter()
+        .filter_map(|part| Some((part, columns.lift(entry, &positions, &part)?)))
+        .collect();
+    (!lifted.is_empty()).then_some(lifted)
+}
+
+impl<R: SeekableRead> LiftoverInput for BigBedRead<R> {
+    type Record = BedEntry;
+
+    fn unmapped(
+        &mut self,
+        chains: &Chains,
+        chrom: &str,
+        length: u32,
+        min_fraction: f64,
+        out: &mut VecDeque<Lifted<BedEntry>>,
+    ) -> Result<(), LiftoverError> {
+        let columns = PositionColumns::new(&self.autosql()?);
+        for entry in self.get_interval(chrom, 0, length)? {
+            let entry = entry?;
+            if lift_entry(chains, &columns, chrom, &entry, min_fraction).is_none() {
+                out.push_back(Lifted::Unmapped(chrom.to_owned(), entry));
+            }
+        }
+        Ok(())
+    }
+
+    fn mapped(
+        &mut self,
+        chains: &Chains,
+        target: &str,
+        spans: &[(String, u32, u32)],
+        min_fraction: f64,
+        out: &mut VecDeque<Lifted<BedEntry>>,
+    ) -> Result<(), LiftoverError> {
+        let columns = PositionColumns::new(&self.autosql()?);
+        let mut mapped = vec![];
+        for (chrom, span_start, span_end) in spans {
+            if self.info.find_chrom(chrom).is_none() {
+                continue;
+            }
+            for entry in self.get_interval(chrom, *span_start, *span_end)? {
+                let entry = entry?;
+                let Some(lifted) = lift_entry(chains, &columns, chrom, &entry, min_fraction) else {
+                    continue;
+                };
+                // An entry can be in more than one span, but each part is in
+                // exactly one
+                mapped.extend(
+                    lifted
+                        .into_iter()
+                        .filter(|(p, _)| {
+                            p.chrom == target && (*span_start..*span_end).contains(&p.source_start)
+                        })
+                        .map(|(_, e)| e),
+                );
+            }
+        }
+        mapped.sort_by_key(|e| (e.start, e.end));
+        out.extend(
+            mapped
+                .into_iter()
+                .map(|e| Lifted::Mapped(target.to_owned(), e)),
+        );
+        Ok(())
+    }
+}
+
+/// The records of a file mapped with [`Chains`]. If unmapped records are
+/// requested, these come first, sorted by source chromosome and start. Then
+/// come the mapped records, sorted by target chromosome and start, along with
+/// the (bigWig) values that are unmapped for overlapping an earlier mapped
+/// value.
+pub struct Liftover<I: LiftoverInput> {
+    input: I,
+    chains: Arc<Chains>,
+    min_fraction: f64,
+    /// The source chromosomes left to check for unmapped records
+    sources: std::vec::IntoIter<(String, u32)>,
+    targets: std::vec::IntoIter<(String, Spans)>,
+    /// The records of the current chromosome
+    pending: VecDeque<Lifted<I::Record>>,
+    error: bool,
+}
+
+impl<I: LiftoverInput> Liftover<I> {
+    /// Maps `input` with `chains`. Only with `unmapped` are the unmapped
+    /// records (other than overlapping values) found, which takes an extra
+    /// pass over the input.
+    pub fn new(input: I, chains: Arc<Chains>, min_fraction: f64, unmapped: bool) -> Self {
+        let mut sources: Vec<(String, u32)> = match unmapped {
+            true => input
+                .get_info()
+                .chrom_info
+                .iter()
+                .map(|c| (c.name.clone(), c.length))
+                .collect(),
+            false => vec![],
+        };
+        sources.sort();
+        let targets = chains.target_spans();
+        Liftover {
+            input,
+            chains,
+            min_fraction,
+            sources: sources.into_iter(),
+            targets: targets.into_iter(),
+            pending: VecDeque::new(),
+            error: false,
+        }
+    }
+
+    /// Reads the records of the next chromosome, returning `false` when
+    /// there are none left.
+    fn next_chrom(&mut self) -> Result<bool, LiftoverError> {
+        let (chains, out) = (&*self.chains, &mut self.pending);
+        if let Some((chrom, length)) = self.sources.next() {
+            self.input
+                .unmapped(chains, &chrom, length, self.min_fraction, out)?;
+        } else if let Some((target, spans)) = self.targets.next() {
+            self.input
+                .mapped(chains, &target, &spans, self.min_fraction, out)?;
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+impl<I: LiftoverInput> Iterator for Liftover<I> {
+    type Item = Result<Lifted<I::Record>, LiftoverError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.error {
+            return None;
+        }
+        while self.pending.is_empty() {
+            match self.next_chrom() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => {
+                    self.error = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+/// Maps the values of `bigwig` with `chains`.
+pub fn liftover_values<R: SeekableRead>(
+    bigwig: BigWigRead<R>,
+    chains: Arc<Chains>,
+    unmapped: bool,
+) -> Liftover<BigWigRead<R>> {
+    Liftover::new(bigwig, chains, 0.0, unmapped)
+}
+
+/// Maps the entries of `bigbed` with `chains`. Entries with less than
+/// `min_fraction` of their bases mapped are unmapped.
+pub fn liftover_entries<R: SeekableRead>(
+    bigbed: BigBedRead<R>,
+    chains: Arc<Chains>,
+    min_fraction: f64,
+    unmapped: bool,
+) -> Liftover<BigBedRead<R>> {
+    Liftover::new(bigbed, chains, min_fraction, unmapped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAINS: &str = "\
+chain 1000 chr1 1000 + 100 270 chrA 500 + 0 160 1
+50\t20\t0
+30\t0\t10
+70
+
+chain 500 chr1 1000 + 500 550 chrB 200 - 10 60 2
+50
+";
+
+    #[test]
+    fn test_map() {
+        let chains = Chains::from_reader(CHAINS.as_bytes()).unwrap();
+        assert_eq!(
+            chains.target_sizes(),
+            HashMap::from([("chrA".to_owned(), 500), ("chrB".to_owned(), 200)])
+        );
+        let map = |start, end| {
+            chains
+                .map("chr1", start, end)
+                .into_iter()
+                .map(|m| (m.chrom, m.start, m.end, m.source_start, m.source_end))
+                .collect::<Vec<_>>()
+        };
+        // Blocks: chr1 100-150 -> chrA 0-50, 170-200 -> 50-80, 200-270 -> 90-160
+        assert_eq!(map(110, 120), vec![("chrA", 10, 20, 110, 120)]);
+        assert_eq!(
+            map(140, 210),
+            vec![
+                ("chrA", 40, 50, 140, 150),
+                ("chrA", 50, 80, 170, 200),
+                ("chrA", 90, 100, 200, 210)
+            ]
+        );
+        assert_eq!(map(0, 100), vec![]);
+        assert_eq!(map(155, 160), vec![]);
+        assert_eq!(map(120, 120), vec![("chrA", 20, 20, 120, 120)]);
+        // On the minus strand, 10-60 of the reverse of chrB is 140-190
+        assert_eq!(map(500, 510), vec![("chrB", 180, 190, 500, 510)]);
+        assert!(chains.map("chr2", 0, 10).is_empty());
+        // Positions near the maximum don't overflow
+        assert_eq!(map(u32::MAX, u32::MAX), vec![]);
+        assert_eq!(map(u32::MAX - 1, u32::MAX), vec![]);
+
+        assert_eq!(flip_strand("name\t0\t-\t1"), "name\t0\t+\t1");
+        assert_eq!(flip_strand("name"), "name");
+    }
+
+    #[test]
+    fn test_invalid_chains() {
+        let error = |chains: &str| match Chains::from_reader(chains.as_bytes()) {
+            Err(LiftoverError::InvalidChain { line, .. }) => line,
+            _ => panic!("Expected an error"),
+        };
+        // The blocks don't add up to the end
+        assert_eq!(error("chain 1 chr1 100 + 0 50 chrA 100 + 0 50 1\n40\n"), 2);
+        assert_eq!(error("10\n"), 1);
+        assert_eq!(
+            error("chain 1 chr1 100 + 0 50 chrA 100 + 0 50 1\n50 1\n"),
+            2
+        );
+        assert_eq!(error("chain 1 chr1 100 + 0 50 chrA 100 + 0 50 1\n"), 1);
+        assert_eq!(
+            error("chain 1 chr1 100 + 0 50 chrA 100 + 0 50 1\n4294967295 1 1\n50\n"),
+            2
+        );
+        // The chains end past the end of the target or source chromosome
+        assert_eq!(error("chain 1 chr1 100 + 0 50 chrA 40 - 0 50 1\n50\n"), 1);
+        assert_eq!(error("chain 1 chr1 40 + 0 50 chrA 100 + 0 50 1\n50\n"), 1);
+        assert_eq!(error("chain 1 chr1 100 + 60 50 chrA 100 + 0 50 1\n50\n"), 1);
+    }
+}
@@ -0,0 +1,426 @@
+//! Matrices of the signal of a bigWig over bins of regions, like deepTools
+//! `computeMatrix`.
+//!
+//! Each region is divided into bins, either around a reference point
+//! ([`MatrixMode::ReferencePoint`]) or with its body scaled to a fixed number of
+//! bins ([`MatrixMode::ScaleRegions`]). For regions on the `-` strand, the bins
+//! are reversed, so that upstream always comes first. Each bin is summarized
+//! (see [`IntersectSummary`]) from the zoom level with the largest reduction
+//! level that is at most half of the smallest bin of the region (like the UCSC
+//! tools), or from the values if there is none. Zoom records that partially
+//! overlap a bin are weighted by their overlap. Bins without values, including
+//! those outside the chromosome or on chromosomes that aren't in the bigWig, are
+//! `NaN` (unless [`MatrixOptions::missing_as_zero`]). Like
+//! [`IntersectSummary::Sum`] and [`IntersectSummary::Coverage`] in
+//! `intersect`, these summaries are 0 for bins in the chromosome without values.
+
+use std::io::{self, Write};
+
+use thiserror::Error;
+
+use crate::bbiread::BBIReadError;
+use crate::bigwigread::ZoomIntervalError;
+use crate::utils::intersect::{IntersectSummary, SummaryTotals};
+use crate::utils::reopen::{Reopen, SeekableRead};
+use crate::BigWigRead;
+
+#[derive(Error, Debug)]
+pub enum MatrixError {
+    #[error("{}", .0)]
+    InvalidInput(String),
+    #[error("{}", .0)]
+    BBIReadError(#[from] BBIReadError),
+    #[error("{}", .0)]
+    IoError(#[from] io::Error),
+}
+
+impl From<ZoomIntervalError> for MatrixError {
+    fn from(e: ZoomIntervalError) -> Self {
+        match e {
+            ZoomIntervalError::ReductionLevelNotFound => {
+                MatrixError::InvalidInput("The reduction level was not found.".to_owned())
+            }
+            ZoomIntervalError::BBIReadError(e) => MatrixError::BBIReadError(e),
+        }
+    }
+}
+
+/// The point of each region that bins are placed around, in the direction of
+/// its strand.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReferencePoint {
+    Start,
+    End,
+    Center,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MatrixMode {
+    /// `upstream` bases before and `downstream` bases after the reference point.
+    ReferencePoint {
+        point: ReferencePoint,
+        upstream: u32,
+        downstream: u32,
+    },
+    /// `upstream` bases before the start, the region scaled to `body` bases, and
+    /// `downstream` bases after the end.
+    ScaleRegions {
+        upstream: u32,
+        body: u32,
+        downstream: u32,
+    },
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct MatrixOptions {
+    pub mode: MatrixMode,
+    /// The size of each bin. The flank (and body) sizes must be multiples of it.
+    pub bin_size: u32,
+    pub summary: IntersectSummary,
+    /// Whether bins without values (including those outside the chromosome)
+    /// are 0, instead of `NaN`.
+    pub missing_as_zero: bool,
+}
+
+impl MatrixOptions {
+    /// The number of bins of each region.
+    pub fn bins(&self) -> Result<usize, MatrixError> {
+        let lengths = match self.mode {
+            MatrixMode::ReferencePoint {
+                upstream,
+                downstream,
+                ..
+            } => vec![upstream, downstream],
+            MatrixMode::ScaleRegions {
+                upstream,
+                body,
+                downstream,
+            } => vec![upstream, body, downstream],
+        };
+        if self.bin_size == 0 {
+            return Err(MatrixError::InvalidInput(
+                "The bin size must be greater than 0.".to_owned(),
+            ));
+        }
+        if lengths.iter().any(|l| l % self.bin_size != 0) {
+            return Err(MatrixError::InvalidInput(format!(
+                "The flank and body sizes must be multiples of the bin size ({}).",
+                self.bin_size
+            )));
+        }
+        let bins: u32 = lengths.iter().map(|l| l / self.bin_size).sum();
+        if bins == 0 {
+            return Err(MatrixError::InvalidInput(
+                "There must be at least one bin.".to_owned(),
+            ));
+        }
+        Ok(bins as usize)
+    }
+
+    fn missing(&self) -> f32 {
+        if self.missing_as_zero {
+            0.0
+        } else {
+            f32::NAN
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatrixRegion {
+    pub chrom: String,
+    pub start: u32,
+    pub end: u32,
+    /// Regions on the `-` strand have their bins reversed. Regions without a
+    /// strand are treated as `+`.
+    pub minus_strand: bool,
+}
+
+/// A regions by bins matrix, in row-major order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix {
+    pub rows: usize,
+    pub cols: usize,
+    pub values: Vec<f32>,
+}
+
+impl Matrix {
+    pub fn row(&self, row: usize) -> &[f32] {
+        &self.values[row * self.cols..(row + 1) * self.cols]
+    }
+}
+
+/// The bins of `region`, in the order of the chromosome, clipped to
+/// `0..chrom_length`. Bins that are empty after clipping are `None`.
+fn region_bins(
+    region: &MatrixRegion,
+    chrom_length: u32,
+    options: &MatrixOptions,
+) -> Vec<Option<(u32, u32)>> {
+    let start = i64::from(region.start);
+    let end = i64::from(region.end);
+    let bin_size = i64::from(options.bin_size);
+    let flank = |from: i64, length: u32| {
+        (0..i64::from(length) / bin_size)
+            .map(move |i| (from + i * bin_size, from + (i + 1) * bin_size))
+    };
+    // Upstream is after the region on the `-` strand
+    let flanks = |upstream: u32, downstream: u32| match region.minus_strand {
+        false => (upstream, downstream),
+        true => (downstream, upstream),
+    };
+    let bins: Vec<(i64, i64)> = match options.mode {
+        MatrixMode::ReferencePoint {
+            point,
+            upstream,
+            downstream,
+        } => {
+            let point = match (point, region.minus_strand) {
+                (ReferencePoint::Start, false) | (ReferencePoint::End, true) => start,
+                (ReferencePoint::End, false) | (ReferencePoint::Start, true) => end,
+                (ReferencePoint::Center, _) => (start + end) / 2,
+            };
+            let (before, after) = flanks(upstream, downstream);
+            flank(point - i64::from(before), before)
+                .chain(flank(point, after))
+                .collect()
+        }
+        MatrixMode::ScaleRegions {
+            upstream,
+            body,
+            downstream,
+        } => {
+            let (before, after) = flanks(upstream, downstream);
+            let body_bins = i64::from(body) / bin_size;
+            let body = (0..body_bins).map(|i| {
+                (
+                    start + (end - start) * i / body_bins,
+                    start + (end - start) * (i + 1) / body_bins,
+                )
+            });
+            flank(start - i64::from(before), before)
+                .chain(body)
+                .chain(flank(end, after))
+                .collect()
+        }
+    };
+    bins.into_iter()
+        .map(|(bin_start, bin_end)| {
+            let bin_start = bin_start.max(0);
+            let bin_end = bin_end.min(i64::from(chrom_length));
+            (bin_start < bin_end).then_some((bin_start as u32, bin_end as u32))
+        })
+        .collect()
+}
+
+/// A record of a zoom level or value: its start, end, covered bases, sum, sum
+/// of squares, min and max.
+type BinRecord = (u32, u32, f64, f64, f64, f64, f64);
+
+/// Summarizes `bins` (in order and not overlapping) of `chrom` with `summary`.
+/// Bins that are `None`, or without values, are `missing`.
+pub(crate) fn summarize_bins<R: SeekableRead>(
+    bigwig: &mut BigWigRead<R>,
+    chrom: &str,
+    bins: &[Option<(u32, u32)>],
+    summary: IntersectSummary,
+    missing: f32,
+) -> Result<Vec<f32>, MatrixError> {
+    let mut row = vec![missing; bins.len()];
+    let present: Vec<(usize, u32, u32)> = bins
+        .iter()
+        .enumerate()
+        .filter_map(|(i, bin)| bin.map(|(start, end)| (i, start, end)))
+        .collect();
+    let (first, last) = match (present.first(), present.last()) {
+        (Some(first), Some(last)) => (first.1, last.2),
+        _ => return Ok(row),
+    };
+    let min_width = present.iter().map(|b| b.2 - b.1).min().unwrap();
+    let zoom = bigwig
+        .info
+        .zoom_headers
+        .iter()
+        .map(|z| z.reduction_level)
+        .filter(|r| *r <= min_width / 2)
+        .max();
+    let records: Vec<BinRecord> = match zoom {
+        Some(zoom) => bigwig
+            .get_zoom_interval(chrom, first, last, zoom)?
+            .map(|r| {
+                r.map(|r| {
+                    let s = r.summary;
+                    let covered = s.bases_covered as f64;
+                    (
+                        r.start,
+                        r.end,
+                        covered,
+                        s.sum,
+                        s.sum_squares,
+                        s.min_val,
+                        s.max_val,
+                    )
+                })
+            })
+            .collect::<Result<_, _>>()?,
+        None => bigwig
+            .get_interval(chrom, first, last)?
+            .map(|v| {
+                v.map(|v| {
+                    let value = f64::from(v.value);
+                    let bases = f64::from(v.end - v.start);
+                    (
+                        v.start,
+                        v.end,
+                        bases,
+                        value * bases,
+                        value * value * bases,
+                        value,
+                        value,
+                    )
+                })
+            })
+            .collect::<Result<_, _>>()?,
+    };
+
+    let mut totals = vec![SummaryTotals::default(); present.len()];
+    let mut next = 0;
+    for (start, end, record_covered, record_sum, record_sum_squares, record_min, record_max) in
+        records
+    {
+        if end <= start {
+            continue;
+        }
+        while next < present.len() && present[next].2 <= start {
+            next += 1;
+        }
+        for (i, (_, bin_start, bin_end)) in present.iter().enumerate().skip(next) {
+            if *bin_start >= end {
+                break;
+            }
+            let overlap = end.min(*bin_end) - start.max(*bin_start);
+            let fraction = f64::from(overlap) / f64::from(end - start);
+            totals[i].add(
+                record_covered * fraction,
+                record_sum * fraction,
+                record_sum_squares * fraction,
+                record_min,
+                record_max,
+            );
+        }
+    }
+
+    for ((bin, bin_start, bin_end), totals) in present.into_iter().zip(totals) {
+        let value = totals.summarize(summary, bin_end - bin_start);
+        row[bin] = if value.is_nan() {
+            missing
+        } else {
+            value as f32
+        };
+    }
+    Ok(row)
+}
+
+/// The summarized bins of one region, upstream first.
+pub fn region_row<R: SeekableRead>(
+    bigwig: &mut BigWigRead<R>,
+    region: &MatrixRegion,
+    options: &MatrixOptions,
+) -> Result<Vec<f32>, MatrixError> {
+    let bins = options.bins()?;
+    if region.end < region.start {
+        return Err(MatrixError::InvalidInput(format!(
+            "Invalid region (end before start): {}:{}-{}",
+            region.chrom, region.start, region.end
+        )));
+    }
+    let chrom_length = match bigwig.info.find_chrom(&region.chrom) {
+        Some(chrom) => chrom.length,
+        None => return Ok(vec![options.missing(); bins]),
+    };
+    let bins = region_bins(region, chrom_length, options);
+    let mut row = summarize_bins(
+        bigwig,
+        &region.chrom,
+        &bins,
+        options.summary,
+        options.missing(),
+    )?;
+    if region.minus_strand {
+        row.reverse();
+    }
+    Ok(row)
+}
+
+/// The matrix of `regions` by bins of `bigwig`. The regions are split between
+/// `nthreads` threads, each with a reopened `bigwig`.
+pub fn matrix<R>(
+    bigwig: &BigWigRead<R>,
+    regions: &[MatrixRegion],
+    options: &MatrixOptions,
+    nthreads: usize,
+) -> Result<Matrix, MatrixError>
+where
+    R: Reopen + SeekableRead + Send,
+{
+    let cols = options.bins()?;
+    let chunk_size = (regions.len() / nthreads.max(1) + 1).max(1);
+    let readers = regions
+        .chunks(chunk_size)
+        .map(|chunk| Ok((chunk, bigwig.reopen()?)))
+        .collect::<io::Result<Vec<_>>>()?;
+    let chunks = std::thread::scope(|scope| {
+        let handles: Vec<_> = readers
+            .into_iter()
+            .map(|(chunk, mut bigwig)| {
+                scope.spawn(move || -> Result<Vec<f32>, MatrixError> {
+                    let mut values = Vec::with_capacity(chunk.len() * cols);
+                    for region in chunk {
+                        values.extend(region_row(&mut bigwig, region, options)?);
+                    }
+                    Ok(values)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("Matrix thread panicked."))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+    Ok(Matrix {
+        rows: regions.len(),
+        cols,
+        values: chunks.concat(),
+    })
+}
+
+/// Writes `matrices` (which must all have the same shape) as a 3-dimensional
+/// `.npy` array of little-endian `f32`s, with the shape (matrices, rows, cols).
+pub fn write_npy<W: Write>(mut out: W, matrices: &[Matrix]) -> Result<(), MatrixError> {
+    let (rows, cols) = matrices.first().map_or((0, 0), |m| (m.rows, m.cols));
+    if matrices.iter().any(|m| m.rows != rows || m.cols != cols) {
+        return Err(MatrixError::InvalidInput(
+            "All matrices must have the same shape.".to_owned(),
+        ));
+    }
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, {}, {}), }}",
+        matrices.len(),
+        rows,
+        cols
+    );
+    // The magic string, version, header length, and header are padded to a
+    // multiple of 64 bytes, ending with a newline.
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+    out.write_all(b"\x93NUMPY\x01\x00")?;
+    out.write_all(&(header.len() as u16).to_le_bytes())?;
+    out.write_all(header.as_bytes())?;
+    for matrix in matrices {
+        for value in matrix.values.iter() {
+            out.write_all(&value.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
@@ -306,20 +306,218 @@ pub fn merge_into(one: Value, two: Value) -> (Value, Option<Value>, Option<Value
     }
 }
 
-struct ValueIter<E, I>
+/// Combines the values of the inputs overlapping a segment into one value.
+pub trait Combiner {
+    /// `values` holds the index and value of each input covering the segment,
+    /// in input order, and `inputs` is the total number of inputs. Returning
+    /// `None` leaves the segment without a value.
+    fn combine(&self, values: &[(usize, f32)], inputs: usize) -> Option<f32>;
+}
+
+impl<F: Fn(&[(usize, f32)], usize) -> Option<f32>> Combiner for F {
+    fn combine(&self, values: &[(usize, f32)], inputs: usize) -> Option<f32> {
+        self(values, inputs)
+    }
+}
+
+/// How the values of a segment are combined by a `MergeCombiner`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MergeFunction {
+    Sum,
+    /// The mean (weighted by the weights, if any).
+    Mean,
+    Min,
+    Max,
+    /// The median (weighted by the weights, if any).
+    Median,
+    /// The number of nonzero values.
+    Count,
+}
+
+/// A `Combiner` with a `MergeFunction`, optional per-input weights, a fill
+/// value for the inputs without coverage, and a threshold.
+#[derive(Clone, Debug)]
+pub struct MergeCombiner {
+    pub function: MergeFunction,
+    /// The weight of each input, that its values are multiplied by (or, for
+    /// the mean and median, that they are weighted by). Inputs without a
+    /// weight have a weight of 1.
+    pub weights: Option<Vec<f32>>,
+    /// The value of inputs that don't cover a segment that some other input
+    /// covers. If `None`, these inputs are ignored.
+    pub fill: Option<f32>,
+    /// Combined values below this are dropped.
+    pub threshold: Option<f32>,
+}
+
+impl MergeCombiner {
+    pub fn new(function: MergeFunction) -> Self {
+        MergeCombiner {
+            function,
+            weights: None,
+            fill: None,
+            threshold: None,
+        }
+    }
+
+    fn weight(&self, input: usize) -> f32 {
+        self.weights
+            .as_ref()
+            .and_then(|w| w.get(input).copied())
+            .unwrap_or(1.0)
+    }
+}
+
+impl Combiner for MergeCombiner {
+    fn combine(&self, values: &[(usize, f32)], inputs: usize) -> Option<f32> {
+        let mut all: Vec<(usize, f32)> = match self.fill {
+            Some(fill) => {
+                let mut all = Vec::with_capacity(inputs);
+                let mut covering = values.iter().peekable();
+                for input in 0..inputs {
+                    match covering.next_if(|v| v.0 == input) {
+                        Some(v) => all.push(*v),
+                        None => all.push((input, fill)),
+                    }
+                }
+                all
+            }
+            None => values.to_vec(),
+        };
+        if all.is_empty() {
+            return None;
+        }
+        let weighted = all.iter().map(|(i, v)| v * self.weight(*i));
+        let value = match self.function {
+            MergeFunction::Sum => weighted.sum(),
+            MergeFunction::Mean => {
+                let total: f32 = all.iter().map(|(i, _)| self.weight(*i)).sum();
+                if total == 0.0 {
+                    return None;
+                }
+                weighted.sum::<f32>() / total
+            }
+            MergeFunction::Min => weighted.fold(f32::INFINITY, f32::min),
+            MergeFunction::Max => weighted.fold(f32::NEG_INFINITY, f32::max),
+            MergeFunction::Median => {
+                let mut sorted: Vec<(f32, f32)> = all
+                    .iter()
+                    .map(|(i, v)| (*v, self.weight(*i)))
+                    .filter(|(_, w)| *w > 0.0)
+                    .collect();
+                sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+                let total: f32 = sorted.iter().map(|(_, w)| w).sum();
+                // The first value that at least half of the weight is at or
+                // below. If exactly half is, the median is between it and the
+                // next value.
+                let mut cumulative = 0.0;
+                let mut median = None;
+                for (idx, (v, w)) in sorted.iter().enumerate() {
+                    cumulative += w;
+                    if cumulative * 2.0 >= total {
+                        median = match sorted.get(idx + 1) {
+                            Some((next, _)) if cumulative * 2.0 == total => Some((v + next) / 2.0),
+                            _ => Some(*v),
+                        };
+                        break;
+                    }
+                }
+                median?
+            }
+            MergeFunction::Count => {
+                all.retain(|(_, v)| *v != 0.0);
+                all.len() as f32
+            }
+        };
+        if value.is_nan() || self.threshold.is_some_and(|t| value < t) {
+            return None;
+        }
+        Some(value)
+    }
+}
+
+/// Sweeps over the inputs, combining the values of each segment where the set
+/// of covering inputs doesn't change. Adjacent segments with equal values are
+/// joined.
+struct ValueIter<E, I, C>
 where
     I: Iterator<Item = Result<Value, E>> + Send,
+    C: Combiner,
 {
     error: bool,
-    sections: Vec<(I, Option<Value>)>,
-    next_sections: Option<Box<dyn Iterator<Item = Value> + Send>>,
-    last_val: Option<Value>,
-    next_start: u32,
+    started: bool,
+    sections: Vec<I>,
+    // The next (or current) value of each input
+    current: Vec<Option<Value>>,
+    covering: Vec<(usize, f32)>,
+    combiner: C,
+    pending: Option<Value>,
+    pos: u32,
 }
 
-impl<E, I> Iterator for ValueIter<E, I>
+impl<E, I, C> ValueIter<E, I, C>
 where
     I: Iterator<Item = Result<Value, E>> + Send,
+    C: Combiner,
+{
+    /// Advances the `idx`th input to its next non-empty value that ends after `pos`.
+    fn advance(&mut self, idx: usize, pos: u32) -> Result<(), E> {
+        self.current[idx] = loop {
+            match self.sections[idx].next() {
+                None => break None,
+                Some(Err(e)) => return Err(e),
+                Some(Ok(mut v)) => {
+                    v.start = v.start.max(pos);
+                    if v.start < v.end {
+                        break Some(v);
+                    }
+                }
+            }
+        };
+        Ok(())
+    }
+
+    /// The next combined segment, which may have no value.
+    fn next_segment(&mut self) -> Result<Option<(u32, u32, Option<f32>)>, E> {
+        if !self.started {
+            self.started = true;
+            for idx in 0..self.sections.len() {
+                self.advance(idx, 0)?;
+            }
+        }
+        let start = match self.current.iter().flatten().map(|v| v.start).min() {
+            None => return Ok(None),
+            Some(min_start) => self.pos.max(min_start),
+        };
+        self.covering.clear();
+        let mut end = u32::MAX;
+        for (idx, v) in self.current.iter().enumerate() {
+            let Some(v) = v else {
+                continue;
+            };
+            if v.start <= start {
+                self.covering.push((idx, v.value));
+                end = end.min(v.end);
+            } else {
+                end = end.min(v.start);
+            }
+        }
+        let value = self.combiner.combine(&self.covering, self.sections.len());
+        for i in 0..self.covering.len() {
+            let idx = self.covering[i].0;
+            if self.current[idx].as_ref().is_some_and(|v| v.end == end) {
+                self.advance(idx, end)?;
+            }
+        }
+        self.pos = end;
+        Ok(Some((start, end, value)))
+    }
+}
+
+impl<E, I, C> Iterator for ValueIter<E, I, C>
+where
+    I: Iterator<Item = Result<Value, E>> + Send,
+    C: Combiner,
 {
     type Item = Result<Value, E>;
 
@@ -327,188 +525,68 @@ where
         if self.error {
             return None;
         }
-        if let Some(buf) = &mut self.next_sections {
-            let next = buf.next();
-            match next {
-                None => self.next_sections = None,
-                Some(val) => return Some(Ok(val)),
-            }
-        }
-
-        const DATA_SIZE: usize = 50000;
         loop {
-            let current_start = self.next_start;
-            self.next_start = current_start + DATA_SIZE as u32;
-
-            let mut data = vec![0f32; DATA_SIZE];
-            let mut max_sections: usize = 0;
-            let mut all_none = true;
-            'sections: for (section, last) in &mut self.sections {
-                'section: loop {
-                    let next_val = match last.take() {
-                        Some(next_val) => next_val,
-                        None => match section.next() {
-                            Some(Ok(x)) => x,
-                            Some(Err(e)) => {
-                                self.error = true;
-                                return Some(Err(e));
-                            }
-                            None => continue 'sections,
-                        },
-                    };
-                    all_none = false;
-
-                    let data_start = (current_start.max(next_val.start) - current_start) as usize;
-                    if data_start >= DATA_SIZE {
-                        *last = Some(next_val);
-                        break 'section;
-                    }
-                    let data_end = DATA_SIZE.min((next_val.end - current_start) as usize);
-                    let value = next_val.value;
-                    for i in &mut data[data_start..data_end] {
-                        *i += value
-                    }
-                    max_sections += 1;
-                    if (next_val.end - current_start) as usize >= DATA_SIZE {
-                        *last = Some(next_val);
-                        break 'section;
-                    }
+            let (start, end, value) = match self.next_segment() {
+                Ok(Some(segment)) => segment,
+                Ok(None) => return self.pending.take().map(Ok),
+                Err(e) => {
+                    self.error = true;
+                    return Some(Err(e));
                 }
-            }
-
-            // TODO: coverage so can take average, or 'real' zeros
-            let mut next_sections: Vec<Value> = Vec::with_capacity(max_sections * 2);
-            let mut current: Option<(u32, u32, f32)> = None;
-            for (idx, i) in data[..].iter().enumerate() {
-                match &mut current {
-                    None => {
-                        current = Some((
-                            idx as u32 + current_start,
-                            idx as u32 + current_start + 1,
-                            *i,
-                        ))
-                    }
-                    Some(c) => {
-                        if (c.2 - *i).abs() < std::f32::EPSILON {
-                            c.1 += 1;
-                        } else {
-                            if c.2 != 0.0 {
-                                next_sections.push(Value {
-                                    start: c.0,
-                                    end: c.1,
-                                    value: c.2,
-                                });
-                            }
-                            current = Some((
-                                idx as u32 + current_start,
-                                idx as u32 + current_start + 1,
-                                *i,
-                            ));
-                        }
-                    }
+            };
+            let Some(value) = value else {
+                if let Some(pending) = self.pending.take() {
+                    return Some(Ok(pending));
                 }
-            }
-            if let Some(c) = &mut current {
-                if c.2 != 0.0 {
-                    next_sections.push(Value {
-                        start: c.0,
-                        end: c.1,
-                        value: c.2,
-                    });
+                continue;
+            };
+            match &mut self.pending {
+                Some(pending) if pending.end == start && pending.value == value => {
+                    pending.end = end;
                 }
-            }
-
-            let insert_into_queue = |queue: &mut Vec<Value>, next_val: Value| {
-                let mut insert_val = next_val;
-                'insert: loop {
-                    if queue.is_empty() || queue.last().unwrap().end <= insert_val.start {
-                        queue.push(insert_val);
-                        return;
-                    }
-
-                    for (idx, queued) in queue.iter_mut().enumerate() {
-                        // We know that next_val is somewhere before where the last queued val ends
-                        // It's either:
-                        // - before all queued items (checked in the first loop iteration)
-                        // - between two items
-                        // - overlapping one or more items
-
-                        // Check if next_val is strictly before the current val
-                        // If this is not the first item, we have already checked that it does not overlap others
-                        if insert_val.end <= queued.start {
-                            queue.insert(idx, insert_val);
-                            return;
-                        }
-                        // If the end of the queued val is strictly before next_val, no need to do anything. (If it's before the next item, we will catch that next loop iteration)
-                        if queued.end <= insert_val.start {
-                            continue;
-                        }
-                        // We now know that next_val overlaps with the current item
-                        let nvq = std::mem::replace(
-                            queued,
-                            Value {
-                                start: 0,
-                                end: 0,
-                                value: 0.0,
-                            },
-                        );
-                        // See merge_into for what these are
-                        // In short: one, two, and three are strictly contained within the current val's start-end, while overhang is anything left over
-                        let (one, two, three, overhang) = merge_into(nvq, insert_val);
-                        let _ = std::mem::replace(queued, one);
-
-                        // If these exist, they don't change any of the queue after the current item
-                        if let Some(th) = three {
-                            queue.insert(idx + 1, th);
-                        }
-                        if let Some(tw) = two {
-                            queue.insert(idx + 1, tw);
-                        }
-
-                        // If we have an overhang, we have to propagate this down the queue
-                        match overhang {
-                            Some(o) => {
-                                insert_val = o;
-                                continue 'insert;
-                            }
-                            None => return,
-                        }
+                _ => {
+                    let next = Value { start, end, value };
+                    if let Some(pending) = self.pending.replace(next) {
+                        return Some(Ok(pending));
                     }
-                    unreachable!();
                 }
-            };
-
-            let last_val = self.last_val.take();
-            if let Some(last) = last_val {
-                insert_into_queue(&mut next_sections, last);
-            }
-
-            if !next_sections.is_empty() {
-                self.last_val = Some(next_sections.remove(next_sections.len() - 1));
-            }
-
-            if !next_sections.is_empty() {
-                // TODO: will split values across boundary line
-                self.next_sections = Some(Box::new(next_sections.into_iter()));
-                return self.next_sections.as_mut().unwrap().next().map(Result::Ok);
-            }
-            if all_none {
-                return self.last_val.take().map(Result::Ok);
             }
         }
     }
 }
 
+/// Merges sorted sections of values, summing the values that overlap.
+/// Segments that sum to zero are dropped.
 pub fn merge_sections_many<I, E>(sections: Vec<I>) -> impl Iterator<Item = Result<Value, E>> + Send
 where
     I: Iterator<Item = Result<Value, E>> + Send,
+{
+    let sum = |values: &[(usize, f32)], _: usize| {
+        let sum: f32 = values.iter().map(|v| v.1).sum();
+        (sum != 0.0).then_some(sum)
+    };
+    merge_sections_many_with(sections, sum)
+}
+
+/// Merges sorted sections of values, with `combiner` giving the value of each
+/// segment covered by at least one section.
+pub fn merge_sections_many_with<I, E, C>(
+    sections: Vec<I>,
+    combiner: C,
+) -> impl Iterator<Item = Result<Value, E>> + Send
+where
+    I: Iterator<Item = Result<Value, E>> + Send,
+    C: Combiner + Send,
 {
     ValueIter {
         error: false,
-        sections: sections.into_iter().map(|s| (s, None)).collect(),
-        next_sections: None,
-        last_val: None,
-        next_start: 0,
+        started: false,
+        current: sections.iter().map(|_| None).collect(),
+        sections,
+        covering: vec![],
+        combiner,
+        pending: None,
+        pos: 0,
     }
 }
 
@@ -582,6 +660,107 @@ mod tests {
         }
     */
 
+    fn merged_with<C: Combiner + Send>(
+        sections: Vec<Vec<(u32, u32, f32)>>,
+        combiner: C,
+    ) -> Vec<(u32, u32, f32)> {
+        let sections = sections
+            .into_iter()
+            .map(|s| {
+                s.into_iter()
+                    .map(|(start, end, value)| Ok::<_, ()>(Value { start, end, value }))
+            })
+            .collect();
+        merge_sections_many_with(sections, combiner)
+            .map(|v| v.map(|v| (v.start, v.end, v.value)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_combiners() {
+        let sections = || {
+            vec![
+                vec![(0, 10, 1.0), (10, 20, 3.0)],
+                vec![(5, 15, 2.0)],
+                vec![(12, 30, 4.0)],
+            ]
+        };
+        let merged = |combiner| merged_with(sections(), combiner);
+
+        assert_eq!(
+            merged(MergeCombiner::new(MergeFunction::Sum)),
+            vec![
+                (0, 5, 1.0),
+                (5, 10, 3.0),
+                (10, 12, 5.0),
+                (12, 15, 9.0),
+                (15, 20, 7.0),
+                (20, 30, 4.0)
+            ]
+        );
+        assert_eq!(
+            merged(MergeCombiner::new(MergeFunction::Max)),
+            vec![(0, 5, 1.0), (5, 10, 2.0), (10, 12, 3.0), (12, 30, 4.0)]
+        );
+        assert_eq!(
+            merged(MergeCombiner::new(MergeFunction::Median)),
+            vec![
+                (0, 5, 1.0),
+                (5, 10, 1.5),
+                (10, 12, 2.5),
+                (12, 15, 3.0),
+                (15, 20, 3.5),
+                (20, 30, 4.0)
+            ]
+        );
+        assert_eq!(
+            merged(MergeCombiner::new(MergeFunction::Count)),
+            vec![
+                (0, 5, 1.0),
+                (5, 12, 2.0),
+                (12, 15, 3.0),
+                (15, 20, 2.0),
+                (20, 30, 1.0)
+            ]
+        );
+
+        // Missing inputs are zero, and values below 2 are dropped
+        let mut combiner = MergeCombiner::new(MergeFunction::Mean);
+        combiner.fill = Some(0.0);
+        combiner.threshold = Some(2.0);
+        assert_eq!(merged(combiner), vec![(12, 15, 3.0), (15, 20, 7.0 / 3.0)]);
+
+        let mut combiner = MergeCombiner::new(MergeFunction::Sum);
+        combiner.weights = Some(vec![2.0, 0.5, 0.0]);
+        assert_eq!(
+            merged(combiner),
+            vec![
+                (0, 5, 2.0),
+                (5, 10, 3.0),
+                (10, 15, 7.0),
+                (15, 20, 6.0),
+                (20, 30, 0.0)
+            ]
+        );
+
+        // The values are weighted, not multiplied by the weights
+        let mut combiner = MergeCombiner::new(MergeFunction::Median);
+        combiner.weights = Some(vec![1.0, 3.0, 1.0]);
+        assert_eq!(
+            merged(combiner),
+            vec![(0, 5, 1.0), (5, 15, 2.0), (15, 20, 3.5), (20, 30, 4.0)]
+        );
+        let mut combiner = MergeCombiner::new(MergeFunction::Median);
+        combiner.weights = Some(vec![2.0, 1.0, 0.0]);
+        assert_eq!(merged(combiner), vec![(0, 10, 1.0), (10, 20, 3.0)]);
+
+        // The combiner sees the number of inputs covering each segment
+        let all_covered = |values: &[(usize, f32)], inputs: usize| {
+            (values.len() == inputs).then_some(values[0].1)
+        };
+        assert_eq!(merged_with(sections(), all_covered), vec![(12, 15, 3.0)]);
+    }
+
     #[test]
     fn can_gen() {
         let _sections = generate_sections_seq(50, 150, 1234);
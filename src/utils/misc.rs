@@ -11,26 +11,96 @@ use thiserror::Error;
 
 #[derive(Copy, Clone)]
 pub enum Name {
+    /// `chrom:start-end`
     Interval,
+    /// The whole bed line, so that the stats are appended to it
     None,
+    /// A (zero-indexed) column of the bed line
     Column(usize),
 }
 
 pub struct BigWigAverageOverBedEntry {
     pub name: String,
+    /// The number of bases in the entry (or its blocks)
     pub size: u32,
+    /// The number of bases with a value
     pub bases: u32,
     pub sum: f64,
+    /// The mean, with bases without a value counting as 0
     pub mean0: f64,
+    /// The mean over the bases with a value
     pub mean: f64,
 }
 
+/// The stats of [`BigWigAverageOverBedEntry`], and more of the distribution of
+/// the values.
+pub struct BigWigAverageOverBedStats {
+    pub entry: BigWigAverageOverBedEntry,
+    /// The minimum value (0 if no bases have a value)
+    pub min: f64,
+    /// The maximum value (0 if no bases have a value)
+    pub max: f64,
+    /// The (population) standard deviation over the bases with a value
+    pub std: f64,
+    /// The median over the bases with a value
+    pub median: f64,
+    /// The fraction of bases with a value
+    pub coverage: f64,
+}
+
+#[derive(Copy, Clone)]
+pub struct StatsOptions {
+    pub name: Name,
+    /// Whether only the blocks (exons) of BED12 entries are included. Entries
+    /// with fewer columns are one block.
+    pub blocks: bool,
+}
+
 #[derive(Error, Debug)]
 pub enum StatsError {
     #[error("{}", .0)]
     BBIReadError(#[from] BBIReadError),
     #[error("{}", .0)]
     InvalidNameCol(String),
+    #[error("{}", .0)]
+    InvalidBlocks(String),
+}
+
+/// The blocks (exons) of a BED12 entry, or the entry itself if it has fewer
+/// columns.
+fn entry_blocks(entry: &BedEntry) -> Result<Vec<(u32, u32)>, StatsError> {
+    let cols: Vec<&str> = entry.rest.split('\t').collect();
+    if cols.len() < 9 {
+        return Ok(vec![(entry.start, entry.end)]);
+    }
+    let invalid = || {
+        StatsError::InvalidBlocks(format!(
+            "Invalid blocks for entry at {}-{}: {}",
+            entry.start, entry.end, entry.rest
+        ))
+    };
+    let list = |col: &str| -> Result<Vec<u32>, StatsError> {
+        col.trim_end_matches(',')
+            .split(',')
+            .map(|v| v.trim().parse::<u32>().map_err(|_| invalid()))
+            .collect()
+    };
+    let count: usize = cols[6].parse().map_err(|_| invalid())?;
+    let sizes = list(cols[7])?;
+    let starts = list(cols[8])?;
+    if sizes.len() != count || starts.len() != count {
+        return Err(invalid());
+    }
+    let mut blocks = Vec::with_capacity(count);
+    for (start, size) in starts.into_iter().zip(sizes) {
+        let start = entry.start.checked_add(start).ok_or_else(invalid)?;
+        let end = start.checked_add(size).ok_or_else(invalid)?;
+        if end > entry.end {
+            return Err(invalid());
+        }
+        blocks.push((start, end));
+    }
+    Ok(blocks)
 }
 
 pub fn stats_for_bed_item<R: SeekableRead>(
@@ -39,33 +109,90 @@ pub fn stats_for_bed_item<R: SeekableRead>(
     entry: BedEntry,
     bigwig: &mut BigWigRead<R>,
 ) -> Result<BigWigAverageOverBedEntry, StatsError> {
+    let options = StatsOptions {
+        name,
+        blocks: false,
+    };
+    stats_for_bed_item_with_options(options, chrom, entry, bigwig).map(|stats| stats.entry)
+}
+
+/// Computes the stats of the values of `bigwig` over `entry`, as in
+/// [`stats_for_bed_item`], with the rest of [`BigWigAverageOverBedStats`].
+pub fn stats_for_bed_item_with_options<R: SeekableRead>(
+    options: StatsOptions,
+    chrom: &str,
+    entry: BedEntry,
+    bigwig: &mut BigWigRead<R>,
+) -> Result<BigWigAverageOverBedStats, StatsError> {
     let start = entry.start;
     let end = entry.end;
 
-    let interval = bigwig
-        .get_interval(chrom, start, end)?
-        .collect::<Result<Vec<_>, _>>();
-    let interval = match interval {
-        Ok(i) => i,
-        Err(e) => return Err(e.into()),
+    let regions = if options.blocks {
+        entry_blocks(&entry)?
+    } else {
+        vec![(start, end)]
     };
 
+    let mut size = 0;
+    // Each value, with the number of bases it covers
+    let mut values: Vec<(f32, u32)> = vec![];
+    for (region_start, region_end) in regions {
+        size += region_end - region_start;
+        for val in bigwig.get_interval(chrom, region_start, region_end)? {
+            let val = val?;
+            let num_bases = val
+                .end
+                .min(region_end)
+                .saturating_sub(val.start.max(region_start));
+            if num_bases > 0 {
+                values.push((val.value, num_bases));
+            }
+        }
+    }
+
     let mut bases = 0;
     let mut sum = 0.0;
-    for val in interval {
-        let num_bases = val.end - val.start;
+    let mut sum_squares = 0.0;
+    let mut min = f64::INFINITY;
+    let mut max = f64::NEG_INFINITY;
+    for (value, num_bases) in values.iter() {
+        let value = f64::from(*value);
         bases += num_bases;
-        sum += f64::from(num_bases) * f64::from(val.value);
+        sum += f64::from(*num_bases) * value;
+        sum_squares += f64::from(*num_bases) * value * value;
+        min = min.min(value);
+        max = max.max(value);
     }
-    let size = end - start;
     let mean0 = sum / f64::from(size);
-    let mean = if bases == 0 {
+    let (mean, min, max, std, median) = if bases == 0 {
+        (0.0, 0.0, 0.0, 0.0, 0.0)
+    } else {
+        let mean = sum / f64::from(bases);
+        let std = (sum_squares / f64::from(bases) - mean * mean)
+            .max(0.0)
+            .sqrt();
+        values.sort_by(|a, b| a.0.total_cmp(&b.0));
+        // The value of the `n`th covered base, in order of value
+        let nth = |n: u32| {
+            let mut seen = 0;
+            for (value, num_bases) in values.iter() {
+                seen += num_bases;
+                if n < seen {
+                    return f64::from(*value);
+                }
+            }
+            unreachable!()
+        };
+        let median = (nth((bases - 1) / 2) + nth(bases / 2)) / 2.0;
+        (mean, min, max, std, median)
+    };
+    let coverage = if size == 0 {
         0.0
     } else {
-        sum / f64::from(bases)
+        f64::from(bases) / f64::from(size)
     };
 
-    let name = match name {
+    let name = match options.name {
         Name::Column(col) => match col {
             0 => chrom.to_string(),
             1 => start.to_string(),
@@ -80,16 +207,24 @@ pub fn stats_for_bed_item<R: SeekableRead>(
             }
         },
         Name::Interval => format!("{}:{}-{}", chrom, start, end),
-        Name::None => format!("{}\t{}\t{}\t{}\n", chrom, start, end, entry.rest),
+        Name::None if entry.rest.is_empty() => format!("{}\t{}\t{}", chrom, start, end),
+        Name::None => format!("{}\t{}\t{}\t{}", chrom, start, end, entry.rest),
     };
 
-    Ok(BigWigAverageOverBedEntry {
-        name,
-        size,
-        bases,
-        sum,
-        mean0,
-        mean,
+    Ok(BigWigAverageOverBedStats {
+        entry: BigWigAverageOverBedEntry {
+            name,
+            size,
+            bases,
+            sum,
+            mean0,
+            mean,
+        },
+        min,
+        max,
+        std,
+        median,
+        coverage,
     })
 }
 
@@ -103,14 +238,29 @@ pub enum BigWigAverageOverBedError {
 
 pub fn bigwig_average_over_bed<R: SeekableRead + 'static>(
     bed: impl BufRead,
-    mut bigwig: BigWigRead<R>,
+    bigwig: BigWigRead<R>,
     name: Name,
 ) -> impl Iterator<Item = Result<BigWigAverageOverBedEntry, BigWigAverageOverBedError>> {
+    let options = StatsOptions {
+        name,
+        blocks: false,
+    };
+    bigwig_average_over_bed_with_options(bed, bigwig, options)
+        .map(|stats| stats.map(|stats| stats.entry))
+}
+
+/// Like [`bigwig_average_over_bed`], with the stats of
+/// [`stats_for_bed_item_with_options`].
+pub fn bigwig_average_over_bed_with_options<R: SeekableRead + 'static>(
+    bed: impl BufRead,
+    mut bigwig: BigWigRead<R>,
+    options: StatsOptions,
+) -> impl Iterator<Item = Result<BigWigAverageOverBedStats, BigWigAverageOverBedError>> {
     let mut bedstream = StreamingLineReader::new(bed);
 
     let mut error: bool = false;
-    let iter = std::iter::from_fn(
-        move || -> Option<Result<BigWigAverageOverBedEntry, BigWigAverageOverBedError>> {
+    std::iter::from_fn(
+        move || -> Option<Result<BigWigAverageOverBedStats, BigWigAverageOverBedError>> {
             if error {
                 return None;
             }
@@ -130,7 +280,7 @@ pub fn bigwig_average_over_bed<R: SeekableRead + 'static>(
                 Some(Ok(v)) => v,
             };
 
-            match stats_for_bed_item(name, chrom, entry, &mut bigwig) {
+            match stats_for_bed_item_with_options(options, chrom, entry, &mut bigwig) {
                 Err(e) => {
                     error = true;
                     Some(Err(e.into()))
@@ -138,7 +288,5 @@ pub fn bigwig_average_over_bed<R: SeekableRead + 'static>(
                 Ok(v) => Some(Ok(v)),
             }
         },
-    );
-
-    iter
+    )
 }
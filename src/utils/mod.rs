@@ -1,9 +1,19 @@
+pub mod calc;
+pub mod chromalias;
+pub mod chromsizes;
 pub mod chromvalues;
+pub mod correlate;
 pub mod file;
 pub mod fill;
+pub mod histogram;
 pub mod idmap;
 pub mod indexlist;
+pub mod intersect;
+pub mod liftover;
+pub mod matrix;
 pub mod merge;
 pub mod misc;
+pub(crate) mod parallel;
+pub mod threshold;
 
 pub use file::*;
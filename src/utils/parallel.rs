@@ -0,0 +1,163 @@
+//! Splitting work over chromosomes between threads.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// Calls `f` on each of `chroms`, split between one thread per state in
+/// `states` (for example, a reopened bigWig). Each thread takes the next
+/// chromosome once it is done with the last, and stops at the first error.
+/// Returns the results in the order of `chroms`, and the final states.
+pub(crate) fn par_chroms<C, S, T, E, F>(
+    chroms: &[C],
+    states: Vec<S>,
+    f: F,
+) -> Result<(Vec<T>, Vec<S>), E>
+where
+    C: Sync,
+    S: Send,
+    T: Send,
+    E: Send,
+    F: Fn(&mut S, &C) -> Result<T, E> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results = std::thread::scope(|scope| {
+        let handles: Vec<_> = states
+            .into_iter()
+            .map(|mut state| {
+                let (next, f) = (&next, &f);
+                scope.spawn(move || {
+                    let mut results = vec![];
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(chrom) = chroms.get(i) else {
+                            break;
+                        };
+                        match f(&mut state, chrom) {
+                            Ok(result) => results.push((i, result)),
+                            Err(e) => {
+                                // Stop the other threads too
+                                next.store(chroms.len(), Ordering::Relaxed);
+                                return Err(e);
+                            }
+                        }
+                    }
+                    Ok((results, state))
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("Chromosome thread panicked."))
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+    let mut ordered = vec![];
+    let mut states = vec![];
+    for (thread_results, state) in results {
+        ordered.extend(thread_results);
+        states.push(state);
+    }
+    ordered.sort_by_key(|(i, _)| *i);
+    Ok((ordered.into_iter().map(|(_, r)| r).collect(), states))
+}
+
+type ChromFn<C, S, T> = Arc<dyn Fn(&mut S, &C) -> T + Send + Sync>;
+
+/// An `Iterator` of each chromosome with its result, in order. Created by
+/// [`chroms_in_order`].
+pub(crate) struct ChromsInOrder<C, S, T> {
+    chroms: std::vec::IntoIter<C>,
+    f: ChromFn<C, S, T>,
+    // The states that aren't being used by a thread
+    idle: Vec<S>,
+    pending: VecDeque<JoinHandle<(S, C, T)>>,
+}
+
+/// Calls `f` on each of `chroms`, on a thread with one of `states`, returning
+/// the results in the order of `chroms`. At most one chromosome per state is
+/// started ahead of the next result, so no more results than states are held at
+/// once. Unlike [`par_chroms`], the results can be written as they come.
+pub(crate) fn chroms_in_order<C, S, T, F>(
+    chroms: Vec<C>,
+    states: Vec<S>,
+    f: F,
+) -> ChromsInOrder<C, S, T>
+where
+    F: Fn(&mut S, &C) -> T + Send + Sync + 'static,
+{
+    ChromsInOrder {
+        chroms: chroms.into_iter(),
+        f: Arc::new(f),
+        idle: states,
+        pending: VecDeque::new(),
+    }
+}
+
+impl<C, S, T> Iterator for ChromsInOrder<C, S, T>
+where
+    C: Send + 'static,
+    S: Send + 'static,
+    T: Send + 'static,
+{
+    type Item = (C, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.idle.is_empty() {
+            let Some(chrom) = self.chroms.next() else {
+                break;
+            };
+            let mut state = self.idle.pop().unwrap();
+            let f = self.f.clone();
+            self.pending.push_back(std::thread::spawn(move || {
+                let result = f(&mut state, &chrom);
+                (state, chrom, result)
+            }));
+        }
+        let handle = self.pending.pop_front()?;
+        let (state, chrom, result) = handle.join().expect("Chromosome thread panicked.");
+        self.idle.push(state);
+        Some((chrom, result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_par_chroms() {
+        let chroms: Vec<u32> = (0..20).collect();
+        let (results, states) = par_chroms(&chroms, vec![0u32; 3], |count, chrom| {
+            *count += 1;
+            Ok::<_, ()>(chrom * 2)
+        })
+        .unwrap();
+        assert_eq!(results, chroms.iter().map(|c| c * 2).collect::<Vec<_>>());
+        assert_eq!(states.iter().sum::<u32>(), 20);
+
+        let result = par_chroms(&chroms, vec![(); 3], |_, chrom| {
+            if *chrom == 5 {
+                Err(*chrom)
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(result.unwrap_err(), 5);
+    }
+
+    #[test]
+    fn test_chroms_in_order() {
+        let chroms: Vec<u32> = (0..20).collect();
+        let results: Vec<_> = chroms_in_order(chroms.clone(), vec![(); 3], |_, chrom| {
+            // Later chromosomes finish first
+            std::thread::sleep(std::time::Duration::from_millis(u64::from(20 - chrom)));
+            chrom * 2
+        })
+        .collect();
+        assert_eq!(
+            results,
+            chroms.iter().map(|c| (*c, c * 2)).collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,294 @@
+//! Calling the regions of a bigWig where the signal passes a threshold.
+//!
+//! Bases with a value greater than [`ThresholdOptions::threshold`] pass. Passing
+//! bases separated by fewer than [`ThresholdOptions::max_gap`] bases (without
+//! values, or not passing) are merged into one region, and regions shorter than
+//! [`ThresholdOptions::min_length`] are dropped. Each region has the max and
+//! the summit (the middle of the first value with the max) of its values, and
+//! the mean over its bases with values (including those in merged gaps).
+
+use crate::bbiread::BBIReadError;
+use crate::utils::parallel::{chroms_in_order, ChromsInOrder};
+use crate::utils::reopen::{Reopen, SeekableRead};
+use crate::{BigWigRead, Value};
+
+/// The autosql of regions written as a bigBed (a bed3+3).
+pub const THRESHOLD_AUTOSQL: &str = r#"table threshold
+"Regions where a bigWig passes a threshold"
+(
+    string chrom;        "Reference sequence chromosome or scaffold"
+    uint   chromStart;   "Start position in chromosome"
+    uint   chromEnd;     "End position in chromosome"
+    float  max;          "Maximum value"
+    float  mean;         "Mean value over the bases with values"
+    uint   summit;       "Position of the maximum value"
+)
+"#;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ThresholdOptions {
+    pub threshold: f32,
+    /// The minimum length of a region.
+    pub min_length: u32,
+    /// Regions separated by fewer bases than this are merged.
+    pub max_gap: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ThresholdRegion {
+    pub start: u32,
+    pub end: u32,
+    pub max: f32,
+    pub mean: f64,
+    pub summit: u32,
+}
+
+impl ThresholdRegion {
+    /// The fields after the chromosome, start and end, as in
+    /// [`THRESHOLD_AUTOSQL`].
+    pub fn rest(&self) -> String {
+        format!("{}\t{}\t{}", self.max, self.mean as f32, self.summit)
+    }
+}
+
+/// A region that is being extended.
+struct Building {
+    start: u32,
+    end: u32,
+    max: f32,
+    summit: u32,
+    sum: f64,
+    bases: u64,
+    // The values after `end`, which are included if the region is extended
+    gap_sum: f64,
+    gap_bases: u64,
+}
+
+/// An `Iterator` of the regions of the values of one chromosome that pass a
+/// threshold. Created by [`threshold_regions`].
+pub struct ThresholdIter<I> {
+    values: I,
+    options: ThresholdOptions,
+    current: Option<Building>,
+    done: bool,
+}
+
+/// The regions of `values` (of one chromosome, in order) that pass the
+/// threshold of `options`.
+pub fn threshold_regions<I, E>(values: I, options: ThresholdOptions) -> ThresholdIter<I>
+where
+    I: Iterator<Item = Result<Value, E>>,
+{
+    ThresholdIter {
+        values,
+        options,
+        current: None,
+        done: false,
+    }
+}
+
+impl<I> ThresholdIter<I> {
+    /// Returns the current region if it is long enough.
+    fn finish(&mut self) -> Option<ThresholdRegion> {
+        let current = self.current.take()?;
+        if current.end - current.start < self.options.min_length {
+            return None;
+        }
+        Some(ThresholdRegion {
+            start: current.start,
+            end: current.end,
+            max: current.max,
+            mean: current.sum / current.bases as f64,
+            summit: current.summit,
+        })
+    }
+}
+
+impl<I, E> Iterator for ThresholdIter<I>
+where
+    I: Iterator<Item = Result<Value, E>>,
+{
+    type Item = Result<ThresholdRegion, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let value = match self.values.next() {
+                Some(Ok(value)) => value,
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.done = true;
+                    return self.finish().map(Ok);
+                }
+            };
+            if value.end <= value.start {
+                continue;
+            }
+            let mut finished = None;
+            if let Some(current) = &self.current {
+                let gap = value.start.saturating_sub(current.end);
+                if gap > 0 && gap >= self.options.max_gap {
+                    finished = self.finish();
+                }
+            }
+            let bases = u64::from(value.end - value.start);
+            let sum = f64::from(value.value) * bases as f64;
+            let summit = value.start + (value.end - value.start) / 2;
+            match &mut self.current {
+                Some(current) if value.value > self.options.threshold => {
+                    current.end = value.end;
+                    current.sum += current.gap_sum + sum;
+                    current.bases += current.gap_bases + bases;
+                    current.gap_sum = 0.0;
+                    current.gap_bases = 0;
+                    if value.value > current.max {
+                        current.max = value.value;
+                        current.summit = summit;
+                    }
+                }
+                Some(current) if !value.value.is_nan() => {
+                    current.gap_sum += sum;
+                    current.gap_bases += bases;
+                }
+                Some(_) => {}
+                None if value.value > self.options.threshold => {
+                    self.current = Some(Building {
+                        start: value.start,
+                        end: value.end,
+                        max: value.value,
+                        summit,
+                        sum,
+                        bases,
+                        gap_sum: 0.0,
+                        gap_bases: 0,
+                    });
+                }
+                None => {}
+            }
+            if let Some(region) = finished {
+                return Some(Ok(region));
+            }
+        }
+        None
+    }
+}
+
+/// An `Iterator` of the regions of each chromosome of a bigWig that pass a
+/// threshold, sorted by chromosome. Created by [`threshold_chroms`].
+pub struct ThresholdChroms<R> {
+    chroms: ChromsInOrder<String, BigWigRead<R>, Result<Vec<ThresholdRegion>, BBIReadError>>,
+}
+
+/// The regions of every chromosome of `bigwig` that pass the threshold of
+/// `options`, sorted by chromosome. Up to `nthreads` chromosomes are read at
+/// once, each by a thread with a reopened `bigwig`, so only their regions are
+/// held at a time.
+pub fn threshold_chroms<R>(
+    bigwig: &BigWigRead<R>,
+    options: ThresholdOptions,
+    nthreads: usize,
+) -> Result<ThresholdChroms<R>, BBIReadError>
+where
+    R: Reopen + SeekableRead + Send + 'static,
+{
+    let mut chroms: Vec<String> = bigwig
+        .info
+        .chrom_info
+        .iter()
+        .map(|c| c.name.clone())
+        .collect();
+    chroms.sort();
+    let readers = (0..nthreads.max(1).min(chroms.len()))
+        .map(|_| bigwig.reopen())
+        .collect::<Result<Vec<_>, _>>()?;
+    let chroms = chroms_in_order(chroms, readers, move |bigwig, chrom| {
+        let length = bigwig.info.find_chrom(chrom).unwrap().length;
+        bigwig
+            .get_interval(chrom, 0, length)
+            .and_then(|values| threshold_regions(values, options).collect::<Result<Vec<_>, _>>())
+    });
+    Ok(ThresholdChroms { chroms })
+}
+
+impl<R> Iterator for ThresholdChroms<R>
+where
+    R: Reopen + SeekableRead + Send + 'static,
+{
+    type Item = Result<(String, Vec<ThresholdRegion>), BBIReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (chrom, regions) = self.chroms.next()?;
+        Some(regions.map(|regions| (chrom, regions)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regions(values: &[(u32, u32, f32)], options: ThresholdOptions) -> Vec<ThresholdRegion> {
+        let values = values
+            .iter()
+            .map(|(start, end, value)| {
+                Ok::<_, BBIReadError>(Value {
+                    start: *start,
+                    end: *end,
+                    value: *value,
+                })
+            })
+            .collect::<Vec<_>>();
+        threshold_regions(values.into_iter(), options)
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_threshold_regions() {
+        let values = [
+            (0, 10, 1.0),
+            (10, 20, 5.0),
+            (20, 30, 7.0),
+            (30, 32, 1.0),
+            (35, 40, 3.0),
+            (50, 52, 4.0),
+        ];
+        let mut options = ThresholdOptions {
+            threshold: 2.0,
+            min_length: 0,
+            max_gap: 0,
+        };
+        let region = |start, end, max, mean, summit| ThresholdRegion {
+            start,
+            end,
+            max,
+            mean,
+            summit,
+        };
+        assert_eq!(
+            regions(&values, options),
+            vec![
+                region(10, 30, 7.0, 6.0, 25),
+                region(35, 40, 3.0, 3.0, 37),
+                region(50, 52, 4.0, 4.0, 51),
+            ]
+        );
+
+        // The gap of 5 bases (with 2 with values) is merged, but not of 10
+        options.max_gap = 10;
+        assert_eq!(
+            regions(&values, options),
+            vec![
+                region(10, 40, 7.0, 137.0 / 27.0, 25),
+                region(50, 52, 4.0, 4.0, 51),
+            ]
+        );
+
+        options.min_length = 5;
+        assert_eq!(
+            regions(&values, options),
+            vec![region(10, 40, 7.0, 137.0 / 27.0, 25)]
+        );
+
+        options.threshold = 10.0;
+        assert_eq!(regions(&values, options), vec![]);
+    }
+}
@@ -0,0 +1,187 @@
+#![cfg(feature = "arrow")]
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, AsArray, Float64Array, Int64Array, StringArray};
+use arrow::datatypes::{DataType, Field, Schema, UInt32Type};
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+
+use bigtools::arrowdata::{
+    arrow_ipc_bigwig_data, bigbed_batches, bigbed_schema, bigwig_batches, bigwig_schema,
+    parquet_bigwig_data, ArrowDataError,
+};
+use bigtools::bbi::{BigBedRead, BigBedWrite, BigWigRead, BigWigWrite};
+use bigtools::bed::autosql::{parse_autosql_fields, BED3};
+use bigtools::bed::bedparser::BedParser;
+use bigtools::bedchromdata::BedParserStreamingIterator;
+use bigtools::utils::chromvalues::ChromValues;
+
+pub mod common;
+use common::{pool, resource, values};
+
+#[test]
+fn test_bigwig_parquet_roundtrip() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let original = resource("valid.bigWig");
+    let mut bigwig = BigWigRead::open_file(&original.to_string_lossy())?;
+
+    let parquet = dir.path().join("valid.parquet");
+    let mut writer = ArrowWriter::try_new(File::create(&parquet)?, bigwig_schema(), None)?;
+    let mut rows = 0;
+    for batch in bigwig_batches(&mut bigwig, "chr17", 0, 83257441, 1000)? {
+        let batch = batch?;
+        assert!(batch.num_rows() <= 1000);
+        assert_eq!(batch.schema(), bigwig_schema());
+        rows += batch.num_rows();
+        writer.write(&batch)?;
+    }
+    writer.close()?;
+    let original_values = values(&original, "chr17");
+    assert_eq!(rows, original_values.len());
+
+    // Batches of 0 values would lose every value
+    assert!(matches!(
+        bigwig_batches(&mut bigwig, "chr17", 0, 83257441, 0),
+        Err(ArrowDataError::InvalidInput(_))
+    ));
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_bigwigtoparquet"))
+        .arg(&original)
+        .arg(dir.path().join("empty.parquet"))
+        .args(["--batch-size", "0"])
+        .output()?;
+    assert!(!output.status.success());
+
+    let out = dir.path().join("out.bigWig");
+    let data = parquet_bigwig_data(&parquet)?;
+    let chrom_map = HashMap::from([("chr17".to_string(), 83257441)]);
+    BigWigWrite::create_file(out.to_string_lossy().to_string()).write(chrom_map, data, pool())?;
+    assert_eq!(values(&out, "chr17"), original_values);
+
+    Ok(())
+}
+
+#[test]
+fn test_bigwig_from_arrow_ipc() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+
+    // Columns are cast to the bigWig types, and null values are skipped
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("chrom", DataType::Utf8, false),
+        Field::new("start", DataType::Int64, false),
+        Field::new("end", DataType::Int64, false),
+        Field::new("value", DataType::Float64, true),
+    ]));
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(vec!["chr1", "chr1", "chr1", "chr2"])),
+        Arc::new(Int64Array::from(vec![0, 10, 20, 5])),
+        Arc::new(Int64Array::from(vec![10, 20, 30, 6])),
+        Arc::new(Float64Array::from(vec![
+            Some(1.0),
+            None,
+            Some(2.5),
+            Some(3.0),
+        ])),
+    ];
+    let ipc = dir.path().join("values.arrow");
+    let mut writer = FileWriter::try_new(File::create(&ipc)?, &schema)?;
+    writer.write(&RecordBatch::try_new(schema, columns)?)?;
+    writer.finish()?;
+
+    let out = dir.path().join("out.bigWig");
+    let chrom_map = HashMap::from([("chr1".to_string(), 1000), ("chr2".to_string(), 500)]);
+    BigWigWrite::create_file(out.to_string_lossy().to_string()).write(
+        chrom_map,
+        arrow_ipc_bigwig_data(&ipc)?,
+        pool(),
+    )?;
+    assert_eq!(values(&out, "chr1"), vec![(0, 10, 1.0), (20, 30, 2.5)]);
+
+    Ok(())
+}
+
+#[test]
+fn test_bigbed_batches() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let bigbed = dir.path().join("small.bigBed");
+
+    let mut vals_iter = BedParser::from_bed_file(File::open(resource("small.bed"))?);
+    let mut outb = BigBedWrite::create_file(bigbed.to_string_lossy().to_string());
+    outb.autosql = {
+        let (_, mut group) = vals_iter.next_chrom().unwrap().unwrap();
+        let first = group.peek().unwrap().unwrap();
+        Some(bigtools::bed::autosql::bed_autosql(&first.rest))
+    };
+    let chrom_map = HashMap::from([
+        ("chr17".to_string(), 83257441),
+        ("chr18".to_string(), 80373285),
+        ("chr19".to_string(), 58617616),
+    ]);
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    outb.write(chrom_map, chsi, pool())?;
+
+    let mut bigbed = BigBedRead::open_file(bigbed.to_string_lossy().to_string())?;
+    let schema = bigbed_schema(&bigbed.autosql()?);
+    let fields: Vec<_> = schema
+        .fields()
+        .iter()
+        .map(|f| (f.name().as_str(), f.data_type().clone()))
+        .collect();
+    assert_eq!(
+        fields,
+        vec![
+            ("chrom", DataType::Utf8),
+            ("chromStart", DataType::UInt32),
+            ("chromEnd", DataType::UInt32),
+            ("name", DataType::Utf8),
+            ("score", DataType::UInt32),
+        ]
+    );
+
+    let batches = bigbed_batches(&mut bigbed, schema.clone(), "chr17", 0, 1000, 2)?
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(batches.len(), 2);
+    let batch = &batches[0];
+    assert_eq!(batch.num_rows(), 2);
+    assert_eq!(batch.column(0).as_string::<i32>().value(1), "chr17");
+    assert_eq!(batch.column(1).as_primitive::<UInt32Type>().value(1), 101);
+    assert_eq!(batch.column(3).as_string::<i32>().value(1), "test2");
+    assert_eq!(batch.column(4).as_primitive::<UInt32Type>().value(1), 0);
+
+    // Without an autoSql, the remaining fields are a single column
+    let schema = bigbed_schema("");
+    assert_eq!(schema.fields().len(), 4);
+    assert_eq!(schema.field(3).name(), "rest");
+    let batch = bigbed_batches(&mut bigbed, schema.clone(), "chr18", 0, 1000, 10)?
+        .next()
+        .unwrap()?;
+    assert_eq!(batch.column(3).as_string::<i32>().value(0), "test4\t0");
+
+    assert!(matches!(
+        bigbed_batches(&mut bigbed, schema, "chr18", 0, 1000, 0),
+        Err(ArrowDataError::InvalidInput(_))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_autosql_fields() {
+    let fields = parse_autosql_fields(BED3).unwrap();
+    let names: Vec<_> = fields.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, vec!["chrom", "chromStart", "chromEnd"]);
+    assert_eq!(fields[1].field_type, "uint");
+
+    let fields = parse_autosql_fields(
+        "table t \"A (table)\" ( string chrom; \"x; y\" uint[2] a; enum(x, y) b; )",
+    )
+    .unwrap();
+    assert_eq!(fields[1].field_type, "uint[2]");
+    assert_eq!(fields[2].field_type, "enum(x, y)");
+    assert_eq!(fields[2].name, "b");
+    assert!(parse_autosql_fields("").is_none());
+}
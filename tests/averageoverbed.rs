@@ -0,0 +1,86 @@
+use std::error::Error;
+
+use bigtools::utils::misc::{
+    stats_for_bed_item, stats_for_bed_item_with_options, Name, StatsOptions,
+};
+use bigtools::{BedEntry, BigWigRead};
+
+pub mod common;
+use common::{chrom_map, value, write_bigwig};
+
+#[test]
+fn test_stats_for_bed_item() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("test.bigWig");
+    let vals = vec![
+        value("chr1", 10, 20, 1.0),
+        value("chr1", 20, 30, 3.0),
+        value("chr1", 40, 50, 2.0),
+    ];
+    write_bigwig(&path, chrom_map(&[("chr1", 100)]), vals)?;
+    let mut bigwig = BigWigRead::open_file(&path.to_string_lossy())?;
+
+    let entry = BedEntry {
+        start: 0,
+        end: 60,
+        rest: "gene\t0\t+\t0\t60\t0\t2\t15,10,\t10,40,".to_string(),
+    };
+
+    let options = |name, blocks| StatsOptions { name, blocks };
+    let stats = stats_for_bed_item(Name::Column(3), "chr1", entry.clone(), &mut bigwig)?;
+    assert_eq!(stats.name, "gene");
+    assert_eq!((stats.size, stats.bases), (60, 30));
+    assert_eq!((stats.sum, stats.mean0, stats.mean), (60.0, 1.0, 2.0));
+
+    let stats = stats_for_bed_item_with_options(
+        options(Name::Column(3), false),
+        "chr1",
+        entry.clone(),
+        &mut bigwig,
+    )?;
+    assert_eq!((stats.entry.size, stats.entry.mean), (60, 2.0));
+    assert_eq!((stats.min, stats.max, stats.median), (1.0, 3.0, 2.0));
+    assert!((stats.std - (2.0f64 / 3.0).sqrt()).abs() < 1e-9);
+    assert_eq!(stats.coverage, 0.5);
+
+    // Only the blocks, 10-25 and 40-50
+    let stats = stats_for_bed_item_with_options(
+        options(Name::None, true),
+        "chr1",
+        entry.clone(),
+        &mut bigwig,
+    )?;
+    assert_eq!(stats.entry.name, format!("chr1\t0\t60\t{}", entry.rest));
+    assert_eq!((stats.entry.size, stats.entry.bases), (25, 25));
+    assert_eq!((stats.entry.sum, stats.entry.mean), (45.0, 1.8));
+    assert_eq!((stats.min, stats.max, stats.median), (1.0, 3.0, 2.0));
+    assert_eq!(stats.coverage, 1.0);
+
+    // Without values
+    let empty = BedEntry {
+        start: 60,
+        end: 80,
+        rest: String::new(),
+    };
+    let stats = stats_for_bed_item_with_options(
+        options(Name::Interval, true),
+        "chr1",
+        empty.clone(),
+        &mut bigwig,
+    )?;
+    assert_eq!(stats.entry.name, "chr1:60-80");
+    assert_eq!(
+        (stats.entry.size, stats.entry.bases, stats.entry.mean),
+        (20, 0, 0.0)
+    );
+    assert_eq!((stats.min, stats.max, stats.coverage), (0.0, 0.0, 0.0));
+
+    let invalid = BedEntry {
+        rest: "gene\t0\t+\t0\t60\t0\t2\t15,\t10,40,".to_string(),
+        ..entry
+    };
+    let options = options(Name::Interval, true);
+    assert!(stats_for_bed_item_with_options(options, "chr1", invalid, &mut bigwig).is_err());
+
+    Ok(())
+}
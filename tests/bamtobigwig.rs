@@ -0,0 +1,120 @@
+#![cfg(feature = "bam")]
+
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use noodles::bam;
+use noodles::sam;
+use noodles::sam::alignment::io::Write;
+
+use bigtools::bamchromdata::{BamCoverage, BamCoverageOptions};
+use bigtools::bbi::{BBIRead, BigWigRead, BigWigWrite};
+use bigtools::normalize::Normalization;
+
+pub mod common;
+use common::{pool, values};
+
+const SAM: &str = "\
+@HD\tVN:1.6\tSO:coordinate
+@SQ\tSN:chr1\tLN:1000
+@SQ\tSN:chr2\tLN:500
+@SQ\tSN:chr3\tLN:500
+r1\t0\tchr1\t11\t60\t10M\t*\t0\t0\t*\t*
+r2\t16\tchr1\t16\t60\t5M3N5M\t*\t0\t0\t*\t*
+r3\t1024\tchr1\t16\t60\t10M\t*\t0\t0\t*\t*
+r4\t0\tchr1\t21\t5\t10M\t*\t0\t0\t*\t*
+r5\t4\tchr1\t30\t0\t*\t*\t0\t0\t*\t*
+r6\t0\tchr2\t1\t60\t2M1D1M\t*\t0\t0\t*\t*
+";
+
+/// Writes `SAM` as an indexed BAM.
+fn write_bam(path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut reader = sam::io::Reader::new(SAM.as_bytes());
+    let header = reader.read_header()?;
+    let mut writer = bam::io::Writer::new(File::create(path)?);
+    writer.write_header(&header)?;
+    for record in reader.records() {
+        writer.write_alignment_record(&header, &record?)?;
+    }
+    writer.try_finish()?;
+
+    match bam::fs::index(path)? {
+        bam::Index::Bai(index) => bam::bai::fs::write(path.with_extension("bam.bai"), &index)?,
+        bam::Index::Csi(_) => unreachable!(),
+    }
+    Ok(())
+}
+
+fn coverage(bam: &Path, options: BamCoverageOptions) -> Result<PathBuf, Box<dyn Error>> {
+    let out = tempfile::NamedTempFile::new()?.into_temp_path().keep()?;
+
+    let data = BamCoverage::new(bam.to_path_buf(), options)?;
+    let chrom_map = data.chrom_sizes().clone();
+    BigWigWrite::create_file(out.to_string_lossy().to_string()).write(chrom_map, data, pool())?;
+
+    Ok(out)
+}
+
+#[test]
+fn test_bam_coverage() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let bam = dir.path().join("reads.bam");
+    write_bam(&bam)?;
+
+    // Per-base, skipping the duplicate, unmapped and low MAPQ reads
+    let options = BamCoverageOptions {
+        min_mapq: 10,
+        ..Default::default()
+    };
+    let out = coverage(&bam, options.clone())?;
+    let chroms: Vec<String> = BigWigRead::open_file(&out.to_string_lossy())?
+        .get_chroms()
+        .into_iter()
+        .map(|c| c.name)
+        .collect();
+    assert_eq!(chroms, vec!["chr1", "chr2"]);
+    assert_eq!(
+        values(&out, "chr1"),
+        vec![(10, 15, 1.0), (15, 20, 2.0), (23, 28, 1.0)]
+    );
+    assert_eq!(values(&out, "chr2"), vec![(0, 4, 1.0)]);
+    std::fs::remove_file(out)?;
+
+    let total = BamCoverage::<()>::new(bam.clone(), options.clone())?.count_reads(2)?;
+    assert_eq!(total, 3);
+    assert_eq!(
+        Normalization::Cpm(None).scale_for_count(total)?.factor,
+        1e6 / 3.0
+    );
+
+    // Extended reads, with the reverse read extended from its end
+    let out = coverage(
+        &bam,
+        BamCoverageOptions {
+            min_mapq: 10,
+            extend: Some(10),
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(
+        values(&out, "chr1"),
+        vec![(10, 18, 1.0), (18, 20, 2.0), (20, 28, 1.0)]
+    );
+    std::fs::remove_file(out)?;
+
+    // Binned, keeping duplicates and all MAPQs
+    let out = coverage(
+        &bam,
+        BamCoverageOptions {
+            keep_duplicates: true,
+            bin_size: 10,
+            scale: 2.0,
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(values(&out, "chr1"), vec![(10, 30, 6.0)]);
+    std::fs::remove_file(out)?;
+
+    Ok(())
+}
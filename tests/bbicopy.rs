@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+use bigtools::bbi::{BBIRead, BigBedRead, BigBedWrite, BigWigRead, BigWigWrite};
+use bigtools::bbicopy::{BBICopy, BBICopyError, CopyZooms};
+use bigtools::bed::bedparser::BedParser;
+use bigtools::bedchromdata::BedParserStreamingIterator;
+use bigtools::utils::chromvalues::ChromValues;
+
+pub mod common;
+use common::{all_entries, chrom_map, entry, pool, resource, values, write_bigbed};
+
+fn write_multi_chrom(path: &str) -> Result<bigtools::WriteReport, Box<dyn Error>> {
+    let vals_iter = BedParser::from_bedgraph_file(File::open(resource("multi_chrom.bedGraph"))?);
+    let outb = BigWigWrite::create_file(path.to_string());
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr1".to_string(), 248956422);
+    chrom_map.insert("chr2".to_string(), 242193529);
+    chrom_map.insert("chr3".to_string(), 198295559);
+    chrom_map.insert("chr4".to_string(), 190214555);
+    chrom_map.insert("chr5".to_string(), 181538259);
+    chrom_map.insert("chr6".to_string(), 170805979);
+
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    Ok(outb.write(chrom_map, chsi, pool())?)
+}
+
+#[test]
+fn test_subset_bigwig() -> Result<(), Box<dyn Error>> {
+    let original = tempfile::NamedTempFile::new()?;
+    let original = original.path().to_string_lossy().to_string();
+    write_multi_chrom(&original)?;
+
+    for zooms in [CopyZooms::Copy, CopyZooms::Regenerate] {
+        let out = tempfile::NamedTempFile::new()?;
+        let out = out.path().to_string_lossy().to_string();
+        let mut copy = BBICopy::create_file(out.clone());
+        copy.zooms = zooms;
+        let report = copy.subset(BigWigRead::open_file(&original)?, |chrom| {
+            chrom == "chr2" || chrom == "chr6"
+        })?;
+        assert_eq!(report.chrom_ids.len(), 2);
+        assert_eq!(report.summary.total_items, 2200);
+
+        let mut read = BigWigRead::open_file(&out)?;
+        let chroms = read.get_chroms();
+        assert_eq!(chroms.len(), 2);
+        assert_eq!(chroms[0].name, "chr2");
+        assert_eq!(chroms[1].name, "chr6");
+        assert_eq!(chroms[1].length, 170805979);
+        assert_eq!(values(&out, "chr2"), values(&original, "chr2"));
+        assert_eq!(values(&out, "chr6"), values(&original, "chr6"));
+
+        // Zoom records should match the original, whether copied or regenerated
+        let mut original_read = BigWigRead::open_file(&original)?;
+        let level = original_read.info.zoom_headers[0].reduction_level;
+        assert!(read
+            .info
+            .zoom_headers
+            .iter()
+            .any(|z| z.reduction_level == level));
+        let expected = original_read
+            .get_zoom_interval("chr6", 0, 170805979, level)?
+            .collect::<Result<Vec<_>, _>>()?;
+        let zooms = read
+            .get_zoom_interval("chr6", 0, 170805979, level)?
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(expected.len(), zooms.len());
+        for (a, b) in expected.iter().zip(zooms.iter()) {
+            assert_eq!((a.start, a.end), (b.start, b.end));
+            assert_eq!(a.summary.bases_covered, b.summary.bases_covered);
+            assert!((a.summary.sum - b.summary.sum).abs() < 1e-3);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_cat_bigwig() -> Result<(), Box<dyn Error>> {
+    let original = tempfile::NamedTempFile::new()?;
+    let original = original.path().to_string_lossy().to_string();
+    let original_report = write_multi_chrom(&original)?;
+
+    let first = tempfile::NamedTempFile::new()?;
+    let first = first.path().to_string_lossy().to_string();
+    BBICopy::create_file(first.clone())
+        .subset(BigWigRead::open_file(&original)?, |chrom| chrom <= "chr3")?;
+    let second = tempfile::NamedTempFile::new()?;
+    let second = second.path().to_string_lossy().to_string();
+    BBICopy::create_file(second.clone())
+        .subset(BigWigRead::open_file(&original)?, |chrom| chrom > "chr3")?;
+
+    let out = tempfile::NamedTempFile::new()?;
+    let out = out.path().to_string_lossy().to_string();
+    let report = BBICopy::create_file(out.clone()).concat(
+        vec![
+            BigWigRead::open_file(&second)?,
+            BigWigRead::open_file(&first)?,
+        ],
+        false,
+    )?;
+    assert_eq!(report.chrom_ids, original_report.chrom_ids);
+    assert_eq!(
+        report.summary.bases_covered,
+        original_report.summary.bases_covered
+    );
+    assert_eq!(
+        report.summary.total_items,
+        original_report.summary.total_items
+    );
+    for chrom in ["chr1", "chr2", "chr3", "chr4", "chr5", "chr6"] {
+        assert_eq!(values(&out, chrom), values(&original, chrom));
+    }
+
+    // Overlapping chromosomes are an error, unless replacing
+    let res = BBICopy::create_file(out.clone()).concat(
+        vec![
+            BigWigRead::open_file(&original)?,
+            BigWigRead::open_file(&first)?,
+        ],
+        false,
+    );
+    assert!(matches!(res, Err(BBICopyError::InvalidInput(_))));
+    // Invalid sources don't touch the existing output
+    assert_eq!(values(&out, "chr1"), values(&original, "chr1"));
+    let res = BBICopy::create_file(out.clone()).subset(BigWigRead::open_file(&first)?, |_| false);
+    assert!(matches!(res, Err(BBICopyError::InvalidInput(_))));
+    assert_eq!(values(&out, "chr6"), values(&original, "chr6"));
+    let report = BBICopy::create_file(out.clone()).concat(
+        vec![
+            BigWigRead::open_file(&original)?,
+            BigWigRead::open_file(&first)?,
+        ],
+        true,
+    )?;
+    assert_eq!(report.chrom_ids.len(), 6);
+    assert_eq!(values(&out, "chr1"), values(&original, "chr1"));
+
+    Ok(())
+}
+
+#[test]
+fn test_subset_bigbed() -> Result<(), Box<dyn Error>> {
+    let bed = resource("small.bed");
+
+    let original = tempfile::NamedTempFile::new()?;
+    let original = original.path().to_string_lossy().to_string();
+    let mut vals_iter = BedParser::from_bed_file(File::open(&bed)?);
+    let mut outb = BigBedWrite::create_file(original.clone());
+    outb.autosql = {
+        let (_, mut group) = vals_iter.next_chrom().unwrap().unwrap();
+        let first = group.peek().unwrap().unwrap();
+        Some(bigtools::bed::autosql::bed_autosql(&first.rest))
+    };
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr17".to_string(), 83257441);
+    chrom_map.insert("chr18".to_string(), 80373285);
+    chrom_map.insert("chr19".to_string(), 58617616);
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    let original_report = outb.write(chrom_map, chsi, pool())?;
+
+    let out = tempfile::NamedTempFile::new()?;
+    let out = out.path().to_string_lossy().to_string();
+    let mut copy = BBICopy::create_file(out.clone());
+    copy.zooms = CopyZooms::Regenerate;
+    let report = copy.subset(BigBedRead::open_file(original.clone())?, |chrom| {
+        chrom != "chr17"
+    })?;
+    assert_eq!(report.chrom_ids.len(), 2);
+
+    let mut original_read = BigBedRead::open_file(original)?;
+    let mut read = BigBedRead::open_file(out)?;
+    assert_eq!(read.autosql()?, original_read.autosql()?);
+    assert_eq!(read.get_chroms().len(), 2);
+    for (chrom, summary) in original_report.chrom_summaries.iter().skip(1) {
+        let expected = original_read
+            .get_interval(chrom, 0, u32::MAX)?
+            .collect::<Result<Vec<_>, _>>()?;
+        let entries = read
+            .get_interval(chrom, 0, u32::MAX)?
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(expected, entries);
+        let copied_summary = &report
+            .chrom_summaries
+            .iter()
+            .find(|(c, _)| c == chrom)
+            .unwrap()
+            .1;
+        assert_eq!(summary.total_items, copied_summary.total_items);
+        assert_eq!(summary.bases_covered, copied_summary.bases_covered);
+        assert_eq!(summary.max_val, copied_summary.max_val);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_rename_chroms() -> Result<(), Box<dyn Error>> {
+    use bigtools::bbicopy::rename_chroms;
+
+    let original = tempfile::NamedTempFile::new()?;
+    let original = original.path().to_string_lossy().to_string();
+    write_multi_chrom(&original)?;
+
+    let out = tempfile::NamedTempFile::new()?;
+    let out = out.path().to_string_lossy().to_string();
+    let mut renames = HashMap::new();
+    // "1" sorts before the rest, "chrZ" after, so the tree order changes
+    renames.insert("chr6".to_string(), "1".to_string());
+    renames.insert("chr1".to_string(), "chrZ".to_string());
+    rename_chroms(&original, &out, &renames)?;
+
+    let mut read = BigWigRead::open_file(&out)?;
+    let chroms: Vec<String> = read.get_chroms().into_iter().map(|c| c.name).collect();
+    assert_eq!(chroms, vec!["1", "chr2", "chr3", "chr4", "chr5", "chrZ"]);
+    assert_eq!(values(&out, "1"), values(&original, "chr6"));
+    assert_eq!(values(&out, "chrZ"), values(&original, "chr1"));
+    assert_eq!(values(&out, "chr3"), values(&original, "chr3"));
+    assert_eq!(read.get_summary()?.bases_covered, {
+        BigWigRead::open_file(&original)?
+            .get_summary()?
+            .bases_covered
+    });
+
+    // Renaming onto an existing name is an error
+    let mut renames = HashMap::new();
+    renames.insert("chr1".to_string(), "chr2".to_string());
+    assert!(matches!(
+        rename_chroms(&original, &out, &renames),
+        Err(BBICopyError::InvalidInput(_))
+    ));
+
+    // Invalid renames are caught before an output is written
+    let dir = tempfile::tempdir()?;
+    let missing = dir.path().join("out.bigWig");
+    let missing = missing.to_string_lossy().to_string();
+    for name in ["", "chr 1"] {
+        let mut renames = HashMap::new();
+        renames.insert("chr1".to_string(), name.to_string());
+        assert!(matches!(
+            rename_chroms(&original, &missing, &renames),
+            Err(BBICopyError::InvalidInput(_))
+        ));
+        assert!(!Path::new(&missing).exists());
+    }
+
+    // Renaming in place through another path modifies the file, rather than
+    // copying it onto itself
+    let inplace = dir.path().join("in.bigWig");
+    std::fs::copy(&original, &inplace)?;
+    std::fs::create_dir(dir.path().join("sub"))?;
+    let alias = dir.path().join("sub/../in.bigWig");
+    let mut renames = HashMap::new();
+    renames.insert("chr1".to_string(), "chrZ".to_string());
+    rename_chroms(
+        &inplace.to_string_lossy(),
+        &alias.to_string_lossy(),
+        &renames,
+    )?;
+    let inplace = inplace.to_string_lossy().to_string();
+    assert_eq!(values(&inplace, "chrZ"), values(&original, "chr1"));
+
+    Ok(())
+}
+
+#[test]
+fn test_output_is_input() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let original = dir.path().join("in.bigWig");
+    let original = original.to_string_lossy().to_string();
+    write_multi_chrom(&original)?;
+    let expected = values(&original, "chr1");
+
+    // The same file, through another path
+    std::fs::create_dir(dir.path().join("sub"))?;
+    let alias = dir.path().join("sub/../in.bigWig");
+    let copy = BBICopy::create_file(alias.to_string_lossy().to_string());
+    match copy.check_inputs(&[&original]) {
+        Err(BBICopyError::InvalidInput(_)) => {}
+        other => panic!("Expected an invalid input error, got {:?}", other),
+    }
+    let other = dir.path().join("out.bigWig");
+    let copy = BBICopy::create_file(other.to_string_lossy().to_string());
+    copy.check_inputs(&[&original])?;
+
+    // The binary refuses, without truncating the input
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_bigtools"))
+        .args([
+            "subset",
+            &original,
+            &alias.to_string_lossy(),
+            "--chrom",
+            "chr1",
+        ])
+        .output()?;
+    assert!(!output.status.success());
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_bigtools"))
+        .args(["cat", &original, "-o", &alias.to_string_lossy()])
+        .output()?;
+    assert!(!output.status.success());
+    assert_eq!(values(&original, "chr1"), expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_cat_input_types() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let a = dir.path().join("a.bigBed");
+    write_bigbed(
+        &a,
+        chrom_map(&[("chr1", 1000)]),
+        vec![entry("chr1", 0, 10, "a")],
+    )?;
+    let b = dir.path().join("b.bigBed");
+    write_bigbed(
+        &b,
+        chrom_map(&[("chr2", 1000)]),
+        vec![entry("chr2", 0, 10, "b")],
+    )?;
+    let cat = |inputs: &[&Path], out: &Path| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_bigtools"))
+            .arg("cat")
+            .args(inputs)
+            .arg("-o")
+            .arg(out)
+            .output()
+    };
+
+    // bigBeds are detected without trying to open them as bigWigs first
+    let out = dir.path().join("out.bigBed");
+    let output = cat(&[&a, &b], &out)?;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains("Error"), "{}", stderr);
+    assert_eq!(
+        all_entries(&out),
+        vec![
+            ("chr1".to_string(), 0, 10, "a".to_string()),
+            ("chr2".to_string(), 0, 10, "b".to_string()),
+        ]
+    );
+
+    // A mix of bigWigs and bigBeds is refused
+    let bigwig = dir.path().join("c.bigWig");
+    write_multi_chrom(&bigwig.to_string_lossy())?;
+    let output = cat(&[&bigwig, &b], &dir.path().join("mixed.bigWig"))?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("not the same type of file"), "{}", stderr);
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::error::Error;
+use std::process::Command;
+
+pub mod common;
+use common::{all_values, resource};
+
+const CHROM_SIZES: &str = "\
+chr1\t248956422
+chr2\t242193529
+chr3\t198295559
+chr4\t190214555
+chr5\t181538259
+chr6\t170805979
+";
+
+#[test]
+fn test_parallel() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let chrom_sizes = dir.path().join("chrom.sizes");
+    std::fs::write(&chrom_sizes, CHROM_SIZES)?;
+
+    // Reading the bedGraph in parallel (by chromosome) gives the same bigWig
+    let mut outputs = vec![];
+    for parallel in ["no", "yes", "auto"] {
+        let out = dir.path().join(format!("{}.bigWig", parallel));
+        let output = Command::new(env!("CARGO_BIN_EXE_bedgraphtobigwig"))
+            .arg(resource("multi_chrom.bedGraph"))
+            .arg(&chrom_sizes)
+            .arg(&out)
+            .args(["-t", "2", "-p", parallel])
+            .output()?;
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        outputs.push(all_values(&out));
+    }
+    assert_eq!(outputs[0].len(), 3000);
+    assert_eq!(outputs[1], outputs[0]);
+    assert_eq!(outputs[2], outputs[0]);
+
+    Ok(())
+}
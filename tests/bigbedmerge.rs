@@ -0,0 +1,119 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use bigtools::bed::autosql::parse_autosql_fields;
+use bigtools::mergechromdata::{AutosqlReconcile, BigBedMergeOptions, MergedBigBeds};
+use bigtools::{BigBedRead, BigBedWrite};
+
+pub mod common;
+use common::{all_entries, chrom_map, entry, pool, write_bigbed};
+
+fn write(
+    path: &Path,
+    chroms: &[(&str, u32)],
+    entries: &[(&str, u32, u32, &str)],
+) -> Result<PathBuf, Box<dyn Error>> {
+    let entries = entries
+        .iter()
+        .map(|(chrom, start, end, rest)| entry(chrom, *start, *end, rest))
+        .collect();
+    write_bigbed(path, chrom_map(chroms), entries)?;
+    Ok(path.to_owned())
+}
+
+fn open(path: &Path) -> BigBedRead<bigtools::utils::reopen::ReopenableFile> {
+    BigBedRead::open_file(path.to_string_lossy().to_string()).unwrap()
+}
+
+/// The entries of a bigBed, from [`all_entries`].
+type Entries = Vec<(String, u32, u32, String)>;
+
+fn merge(
+    out: &Path,
+    inputs: &[&Path],
+    options: BigBedMergeOptions,
+) -> Result<Entries, Box<dyn Error>> {
+    let data = MergedBigBeds::new(inputs.iter().map(|p| open(p)).collect(), options)?;
+    let mut outb = BigBedWrite::create_file(out.to_string_lossy().to_string());
+    outb.autosql = Some(data.autosql().to_owned());
+    outb.write(data.chrom_sizes().clone(), data, pool())?;
+    Ok(all_entries(out))
+}
+
+#[test]
+fn test_bigbedmerge() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let chroms = [("chr1", 1000), ("chr2", 1000)];
+    let a = write(
+        &dir.path().join("a.bigBed"),
+        &chroms,
+        &[
+            ("chr1", 10, 20, "a1"),
+            ("chr1", 30, 40, "a2"),
+            ("chr2", 5, 6, "a3"),
+        ],
+    )?;
+    let b = write(
+        &dir.path().join("b.bigBed"),
+        &[("chr1", 1000), ("chr3", 500)],
+        &[
+            ("chr1", 10, 15, "b1"),
+            ("chr1", 25, 35, "b2"),
+            ("chr3", 5, 10, "b3"),
+        ],
+    )?;
+    let out = dir.path().join("out.bigBed");
+
+    let entry =
+        |chrom: &str, start, end, rest: &str| (chrom.to_owned(), start, end, rest.to_owned());
+    assert_eq!(
+        merge(&out, &[&a, &b], BigBedMergeOptions::default())?,
+        vec![
+            entry("chr1", 10, 20, "a1"),
+            entry("chr1", 10, 15, "b1"),
+            entry("chr1", 25, 35, "b2"),
+            entry("chr1", 30, 40, "a2"),
+            entry("chr2", 5, 6, "a3"),
+            entry("chr3", 5, 10, "b3"),
+        ]
+    );
+
+    let options = BigBedMergeOptions {
+        sources: Some(vec!["a".to_owned(), "b".to_owned()]),
+        ..Default::default()
+    };
+    let merged = merge(&out, &[&a, &b], options)?;
+    assert_eq!(merged[1], entry("chr1", 10, 15, "b1\tb"));
+    let autosql = open(&out).autosql()?;
+    let fields = parse_autosql_fields(&autosql).unwrap();
+    let names: Vec<&str> = fields.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["chrom", "chromStart", "chromEnd", "name", "source"]);
+
+    // A bed5 only has its name in common with a bed4
+    let c = write(
+        &dir.path().join("c.bigBed"),
+        &chroms,
+        &[("chr1", 1, 2, "c1\t5")],
+    )?;
+    assert!(merge(&out, &[&a, &c], BigBedMergeOptions::default()).is_err());
+    let options = BigBedMergeOptions {
+        autosql: AutosqlReconcile::Common,
+        ..Default::default()
+    };
+    let merged = merge(&out, &[&c, &a], options)?;
+    assert_eq!(merged[0], entry("chr1", 1, 2, "c1"));
+    assert_eq!(
+        parse_autosql_fields(&open(&out).autosql()?).unwrap().len(),
+        4
+    );
+
+    // Chromosome sizes must agree
+    let d = write(
+        &dir.path().join("d.bigBed"),
+        &[("chr1", 2000)],
+        &[("chr1", 1, 2, "d1")],
+    )?;
+    assert!(MergedBigBeds::new(vec![open(&a), open(&d)], BigBedMergeOptions::default()).is_err());
+
+    Ok(())
+}
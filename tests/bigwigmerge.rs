@@ -0,0 +1,230 @@
+use std::error::Error;
+use std::path::Path;
+use std::process::Command;
+
+pub mod common;
+use common::{all_values, chrom_map, value, write_bigwig};
+
+const CHROMS: &[(&str, u32)] = &[("chr1", 1000), ("chr2", 1000), ("chr3", 1000)];
+
+/// Writes a bigWig of `values` to `dir`, returning its path.
+fn write_chroms(
+    dir: &Path,
+    name: &str,
+    values: &[(&str, u32, u32, f32)],
+) -> Result<String, Box<dyn Error>> {
+    let path = dir.join(name);
+    let values = values
+        .iter()
+        .map(|(chrom, start, end, v)| value(chrom, *start, *end, *v))
+        .collect();
+    write_bigwig(&path, chrom_map(CHROMS), values)?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// Writes a bigWig on chr1 of `values` to `dir`, returning its path.
+fn write(dir: &Path, name: &str, values: &[(u32, u32, f32)]) -> Result<String, Box<dyn Error>> {
+    let values: Vec<_> = values
+        .iter()
+        .map(|(s, e, v)| ("chr1", *s, *e, *v))
+        .collect();
+    write_chroms(dir, name, &values)
+}
+
+/// Runs `bigwigmerge` on `inputs` with `args`, writing to `out`.
+fn run(out: &Path, inputs: &[String], args: &[&str]) -> Result<(), Box<dyn Error>> {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_bigwigmerge"));
+    command.arg(out);
+    for input in inputs {
+        command.arg("-b").arg(input);
+    }
+    let output = command.args(args).output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Ok(())
+}
+
+/// Runs `bigwigmerge` on `inputs` with `args`, returning the merged bedGraph.
+fn merge(dir: &Path, inputs: &[String], args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let out = dir.join("out.bedGraph");
+    run(&out, inputs, args)?;
+    Ok(std::fs::read_to_string(out)?)
+}
+
+/// Open file limits that allow merging two inputs at once, with one thread,
+/// to bedGraph and to bigWig (with zooms).
+const BEDGRAPH_FAN_IN_2: &str = "--max-open-files=9";
+const BIGWIG_FAN_IN_2: &str = "--max-open-files=29";
+
+#[test]
+fn test_merge_zeros() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let inputs = vec![
+        write(dir.path(), "a.bigWig", &[(0, 10, 1.0)])?,
+        write(dir.path(), "b.bigWig", &[(0, 10, -1.0), (10, 20, 2.0)])?,
+    ];
+
+    // Values that sum to zero are dropped
+    assert_eq!(merge(dir.path(), &inputs, &[])?, "chr1\t10\t20\t2\n");
+
+    // Unless there is a threshold or fill value
+    assert_eq!(
+        merge(dir.path(), &inputs, &["--threshold=-1"])?,
+        "chr1\t0\t10\t0\nchr1\t10\t20\t2\n"
+    );
+    assert_eq!(
+        merge(dir.path(), &inputs, &["--combine", "min", "--fill", "0"])?,
+        "chr1\t0\t10\t-1\nchr1\t10\t20\t0\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_weighted_median() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let inputs = vec![
+        write(dir.path(), "a.bigWig", &[(0, 10, 1.0)])?,
+        write(dir.path(), "b.bigWig", &[(0, 10, 2.0)])?,
+        write(dir.path(), "c.bigWig", &[(0, 10, 3.0)])?,
+    ];
+
+    let args = ["--combine", "median", "--weights", "1,1,3"];
+    assert_eq!(merge(dir.path(), &inputs, &args)?, "chr1\t0\t10\t3\n");
+    let args = ["--combine", "median", "--weights", "1,1,2"];
+    assert_eq!(merge(dir.path(), &inputs, &args)?, "chr1\t0\t10\t2.5\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_tree_merge_zeros() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let inputs = vec![
+        write(dir.path(), "a.bigWig", &[(10, 20, 0.0)])?,
+        write(dir.path(), "b.bigWig", &[(10, 20, 3.0)])?,
+        write(dir.path(), "c.bigWig", &[(10, 20, 5.0), (20, 30, 0.0)])?,
+        write(dir.path(), "d.bigWig", &[(10, 20, 5.0), (20, 40, 2.0)])?,
+    ];
+    let args = ["--combine", "min", "-t", "1"];
+    let direct = merge(dir.path(), &inputs, &args)?;
+    assert_eq!(direct, "chr1\t30\t40\t2\n");
+
+    // The zero of the first chunk (and at 20-30 in the second) is kept until
+    // the final merge
+    let tree = merge(
+        dir.path(),
+        &inputs,
+        &[&args[..], &[BEDGRAPH_FAN_IN_2]].concat(),
+    )?;
+    assert_eq!(tree, direct);
+
+    Ok(())
+}
+
+#[test]
+fn test_tree_merge_empty_chunks() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    // Sums with NaN values have no value, so chr2 of the first chunk, chr1 of
+    // the second, and all of the last merge to nothing
+    let inputs = vec![
+        write_chroms(
+            dir.path(),
+            "a.bigWig",
+            &[("chr1", 0, 10, 1.0), ("chr2", 20, 30, f32::NAN)],
+        )?,
+        write_chroms(dir.path(), "b.bigWig", &[("chr1", 5, 15, 2.0)])?,
+        write_chroms(dir.path(), "c.bigWig", &[("chr2", 0, 10, 4.0)])?,
+        write_chroms(dir.path(), "d.bigWig", &[("chr1", 50, 60, f32::NAN)])?,
+        write_chroms(dir.path(), "e.bigWig", &[("chr1", 50, 60, f32::NAN)])?,
+    ];
+    let args = ["-t", "1"];
+    let direct = merge(dir.path(), &inputs, &args)?;
+    assert_eq!(
+        direct,
+        "chr1\t0\t5\t1\nchr1\t5\t10\t3\nchr1\t10\t15\t2\nchr2\t0\t10\t4\n"
+    );
+    let tree = merge(
+        dir.path(),
+        &inputs,
+        &[&args[..], &[BEDGRAPH_FAN_IN_2]].concat(),
+    )?;
+    assert_eq!(tree, direct);
+
+    // Every chunk merges to nothing
+    let empty = vec![inputs[3].clone(), inputs[4].clone(), inputs[4].clone()];
+    assert_eq!(
+        merge(
+            dir.path(),
+            &empty,
+            &[&args[..], &[BEDGRAPH_FAN_IN_2]].concat()
+        )?,
+        ""
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_tree_merge_bigwig() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    // Enough inputs for two levels of temporary bigWigs
+    let inputs = (0..7u32)
+        .map(|i| {
+            let values = [
+                ("chr1", i * 10, i * 10 + 25, i as f32),
+                ("chr1", 100, 110, 1.0),
+                ("chr3", i, 50 - i, 0.5),
+            ];
+            write_chroms(dir.path(), &format!("{}.bigWig", i), &values)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let direct = dir.path().join("direct.bigWig");
+    run(&direct, &inputs, &["-t", "1"])?;
+    let tree = dir.path().join("tree.bigWig");
+    run(&tree, &inputs, &["-t", "1", BIGWIG_FAN_IN_2])?;
+    assert_eq!(all_values(&tree), all_values(&direct));
+    assert!(!all_values(&tree).is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_bedgraph_parallel() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let inputs = vec![
+        write_chroms(
+            dir.path(),
+            "a.bigWig",
+            &[
+                ("chr1", 0, 10, 1.0),
+                ("chr2", 0, 10, 2.0),
+                ("chr3", 0, 10, 3.0),
+            ],
+        )?,
+        write_chroms(
+            dir.path(),
+            "b.bigWig",
+            &[("chr1", 5, 10, 1.0), ("chr3", 5, 20, 1.0)],
+        )?,
+    ];
+
+    // The chromosomes are merged in parallel, but written in order
+    let expected = "\
+chr1\t0\t5\t1
+chr1\t5\t10\t2
+chr2\t0\t10\t2
+chr3\t0\t5\t3
+chr3\t5\t10\t4
+chr3\t10\t20\t1
+";
+    for nthreads in ["1", "2", "3"] {
+        assert_eq!(merge(dir.path(), &inputs, &["-t", nthreads])?, expected);
+    }
+
+    Ok(())
+}
@@ -57,3 +57,84 @@ fn test_values() -> Result<(), Box<dyn Error>> {
     assert_eq!(vals[59898], 0.06792);
     Ok(())
 }
+
+#[test]
+fn test_chrom_aliases() -> Result<(), Box<dyn Error>> {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use bigtools::bbi::BigWigRead;
+    use bigtools::utils::chromalias::ChromAliases;
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let mut valid_bigwig = dir.clone();
+    valid_bigwig.push("valid.bigWig");
+
+    let mut bwread = BigWigRead::open_file(&valid_bigwig.to_string_lossy()).unwrap();
+    assert!(bwread.get_interval("17", 0, 59899).is_err());
+
+    let aliases = "# ucsc\tensembl\tgenbank\nchr17\t17\tCM000679.2\n";
+    bwread
+        .info
+        .set_chrom_aliases(Some(Arc::new(ChromAliases::from_reader(
+            aliases.as_bytes(),
+        )?)));
+    assert_eq!(bwread.info.find_chrom("CM000679.2").unwrap().name, "chr17");
+
+    let first_interval = bwread.get_interval("17", 0, 59899)?.next().unwrap()?;
+    assert_eq!(first_interval.start, 59898);
+    assert_eq!(first_interval.value, 0.06792);
+    let zoom_level = bwread.info.zoom_headers[0].reduction_level;
+    assert!(bwread
+        .get_zoom_interval("CM000679.2", 0, 83257441, zoom_level)?
+        .next()
+        .is_some());
+    assert!(bwread.get_interval("chr1", 0, 100).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_zoom_values() -> Result<(), Box<dyn Error>> {
+    use std::path::PathBuf;
+
+    use bigtools::bbi::BigWigRead;
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let mut valid_bigwig = dir.clone();
+    valid_bigwig.push("valid.bigWig");
+
+    let mut bwread = BigWigRead::open_file(&valid_bigwig.to_string_lossy()).unwrap();
+    let (bases, sum) = bwread
+        .get_interval("chr17", 0, 83257441)?
+        .map(|v| v.unwrap())
+        .fold((0u64, 0f64), |(bases, sum), v| {
+            let len = u64::from(v.end - v.start);
+            (bases + len, sum + len as f64 * f64::from(v.value))
+        });
+
+    // Zoom records are 32 bytes each, and every level covers all the values
+    let levels: Vec<u32> = bwread
+        .info
+        .zoom_headers
+        .iter()
+        .map(|z| z.reduction_level)
+        .collect();
+    assert!(!levels.is_empty());
+    for level in levels {
+        let records = bwread
+            .get_zoom_interval("chr17", 0, 83257441, level)?
+            .collect::<Result<Vec<_>, _>>()?;
+        assert!(records.iter().all(|r| r.chrom == 0 && r.start < r.end));
+        let zoom_bases: u64 = records.iter().map(|r| r.summary.bases_covered).sum();
+        let zoom_sum: f64 = records.iter().map(|r| r.summary.sum).sum();
+        assert_eq!(zoom_bases, bases);
+        assert!((zoom_sum - sum).abs() < 1e-3 * sum.abs().max(1.0));
+    }
+
+    Ok(())
+}
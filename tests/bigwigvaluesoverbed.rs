@@ -0,0 +1,64 @@
+use std::error::Error;
+use std::path::Path;
+use std::process::Command;
+
+pub mod common;
+use common::{chrom_map, value, write_bigwig};
+
+/// Runs bigwigvaluesoverbed on `bed`, returning the output.
+fn values_over_bed(dir: &Path, bed: &str, args: &[&str]) -> Result<String, Box<dyn Error>> {
+    let bigwig = dir.join("in.bigWig");
+    write_bigwig(
+        &bigwig,
+        chrom_map(&[("chr1", 100)]),
+        vec![
+            value("chr1", 0, 2, 1.0),
+            value("chr1", 2, 4, 3.0),
+            value("chr1", 6, 8, 5.0),
+        ],
+    )?;
+    let bedpath = dir.join("in.bed");
+    std::fs::write(&bedpath, bed)?;
+    let out = dir.join("out.txt");
+    let output = Command::new(env!("CARGO_BIN_EXE_bigwigvaluesoverbed"))
+        .arg(&bigwig)
+        .arg(&bedpath)
+        .arg(&out)
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into());
+    }
+    Ok(std::fs::read_to_string(&out)?)
+}
+
+#[test]
+fn test_values_over_bed() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let bed = "track name=test\nbrowser position chr1:0-10\n#comment\n\nchr1\t0\t8\n";
+    assert_eq!(
+        values_over_bed(dir.path(), bed, &[])?,
+        "1\t1\t3\t3\t0\t0\t5\t5\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_values_over_bed_bins() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let bed = "track name=test\nchr1\t0\t8\nchr1\t4\t6\n";
+    let bins = |summary: &str| {
+        values_over_bed(
+            dir.path(),
+            bed,
+            &["--bins", "2", "--summary", summary, "--missing", "nan"],
+        )
+    };
+    // The bins are 0-4 and 4-8, then 4-5 and 5-6 (without values)
+    assert_eq!(bins("mean")?, "2\t5\nNaN\tNaN\n");
+    assert_eq!(bins("min")?, "1\t5\nNaN\tNaN\n");
+    assert_eq!(bins("max")?, "3\t5\nNaN\tNaN\n");
+    assert_eq!(bins("sum")?, "8\t10\n0\t0\n");
+    assert_eq!(bins("coverage")?, "1\t0.5\n0\t0\n");
+    Ok(())
+}
@@ -96,13 +96,29 @@ fn test_multi() -> io::Result<()> {
     chrom_map.insert("chr6".to_string(), 170805979);
 
     let chsi = BedParserStreamingIterator::new(vals_iter, false);
-    outb.write(chrom_map, chsi, pool.clone()).unwrap();
+    let report = outb.write(chrom_map, chsi, pool.clone()).unwrap();
 
     let mut bwread = BigWigRead::open_file(&tempfile.path().to_string_lossy()).unwrap();
 
     let chroms = bwread.get_chroms();
     assert_eq!(chroms.len(), 6);
 
+    assert_eq!(report.chrom_summaries.len(), 6);
+    assert_eq!(report.chrom_summaries[5].0, "chr6");
+    assert_eq!(report.chrom_summaries[5].1.total_items, 2000);
+    assert_eq!(report.chrom_ids.len(), 6);
+    assert_eq!(report.summary.total_items, 3000);
+    assert_eq!(report.zooms.len(), 10);
+    let written_zooms = report
+        .zooms
+        .iter()
+        .filter(|z| z.status == bigtools::bbiwrite::ZoomLevelStatus::Written)
+        .count();
+    assert_eq!(written_zooms, bwread.info.zoom_headers.len());
+    assert_eq!(report.file_size, std::fs::metadata(tempfile.path())?.len());
+    let summary = bwread.get_summary()?;
+    assert_eq!(summary.bases_covered, report.summary.bases_covered);
+
     assert_eq!(
         bwread.get_interval("chr1", 0, 248956422).unwrap().count(),
         200
@@ -114,3 +130,114 @@ fn test_multi() -> io::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_observer_and_cancellation() -> io::Result<()> {
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+
+    use tempfile;
+
+    use bigtools::bbi::BigWigWrite;
+    use bigtools::bbiwrite::{CancellationToken, ProcessChromError, WriteEvent, WriteObserver};
+    use bigtools::bed::bedparser::BedParser;
+
+    struct Events(Mutex<Vec<WriteEvent>>);
+    impl WriteObserver for Events {
+        fn on_event(&self, event: WriteEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("resources/test");
+
+    let mut multi_chrom_bedgraph = dir.clone();
+    multi_chrom_bedgraph.push("multi_chrom.bedGraph");
+
+    let pool = futures::executor::ThreadPoolBuilder::new()
+        .pool_size(6)
+        .create()
+        .expect("Unable to create thread pool.");
+
+    let mut chrom_map = HashMap::new();
+    chrom_map.insert("chr1".to_string(), 248956422);
+    chrom_map.insert("chr2".to_string(), 242193529);
+    chrom_map.insert("chr3".to_string(), 198295559);
+    chrom_map.insert("chr4".to_string(), 190214555);
+    chrom_map.insert("chr5".to_string(), 181538259);
+    chrom_map.insert("chr6".to_string(), 170805979);
+
+    let tempfile = tempfile::NamedTempFile::new()?;
+    let events = Arc::new(Events(Mutex::new(vec![])));
+    let mut outb = BigWigWrite::create_file(tempfile.path().to_string_lossy().to_string());
+    outb.observer = Some(events.clone());
+    let vals_iter = BedParser::from_bedgraph_file(File::open(multi_chrom_bedgraph.clone())?);
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    outb.write(chrom_map.clone(), chsi, pool.clone()).unwrap();
+
+    let events = events.0.lock().unwrap();
+    let started = events
+        .iter()
+        .filter(|e| matches!(e, WriteEvent::ChromStarted { .. }))
+        .count();
+    assert_eq!(started, 6);
+    let finished_items: u64 = events
+        .iter()
+        .filter_map(|e| match e {
+            WriteEvent::ChromFinished { items, .. } => Some(*items),
+            _ => None,
+        })
+        .sum();
+    assert_eq!(finished_items, 200 * 5 + 2000);
+    assert!(matches!(
+        events.last(),
+        Some(WriteEvent::ZoomsFinished { .. })
+    ));
+
+    let out_dir = tempfile::tempdir()?;
+    let out_path = out_dir.path().join("cancelled.bigWig");
+    let outb = BigWigWrite::create_file(out_path.to_string_lossy().to_string());
+    outb.cancellation.cancel();
+    let vals_iter = BedParser::from_bedgraph_file(File::open(multi_chrom_bedgraph.clone())?);
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    let res = outb.write(chrom_map.clone(), chsi, pool.clone());
+    assert!(matches!(res, Err(ProcessChromError::Cancelled)));
+    assert!(!out_path.exists());
+
+    // Cancelling from the observer, once the first chromosome is written
+    struct CancelAfterChrom {
+        cancellation: CancellationToken,
+        events: Mutex<Vec<WriteEvent>>,
+    }
+    impl WriteObserver for CancelAfterChrom {
+        fn on_event(&self, event: WriteEvent) {
+            if matches!(event, WriteEvent::ChromFinished { .. }) {
+                self.cancellation.cancel();
+            }
+            self.events.lock().unwrap().push(event);
+        }
+    }
+    let mut outb = BigWigWrite::create_file(out_path.to_string_lossy().to_string());
+    let observer = Arc::new(CancelAfterChrom {
+        cancellation: outb.cancellation.clone(),
+        events: Mutex::new(vec![]),
+    });
+    outb.observer = Some(observer.clone());
+    let vals_iter = BedParser::from_bedgraph_file(File::open(multi_chrom_bedgraph)?);
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    let res = outb.write(chrom_map, chsi, pool);
+    assert!(matches!(res, Err(ProcessChromError::Cancelled)));
+    assert!(!out_path.exists());
+    let events = observer.events.lock().unwrap();
+    assert!(events
+        .iter()
+        .any(|e| matches!(e, WriteEvent::ChromFinished { .. })));
+    assert!(!events
+        .iter()
+        .any(|e| matches!(e, WriteEvent::ZoomsFinished { .. })));
+
+    Ok(())
+}
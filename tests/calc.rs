@@ -0,0 +1,74 @@
+use std::error::Error;
+use std::path::Path;
+
+use bigtools::mergechromdata::MergedBigWigs;
+use bigtools::utils::calc::{CalcCombiner, Expr, MissingData};
+use bigtools::{BigWigRead, BigWigWrite};
+
+pub mod common;
+use common::{chrom_map, pool, resource, value, values_in, write_bigwig};
+
+fn calc(
+    out: &Path,
+    inputs: &[&Path],
+    expr: &str,
+    missing: MissingData,
+) -> Result<(), Box<dyn Error>> {
+    let combiner = CalcCombiner {
+        expr: Expr::parse(expr, &["a", "b"])?,
+        missing,
+        pseudocount: 0.0,
+    };
+    let inputs = inputs
+        .iter()
+        .map(|p| BigWigRead::open_file(&p.to_string_lossy()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let data = MergedBigWigs::new(inputs, combiner)?;
+    BigWigWrite::create_file(out.to_string_lossy().to_string()).write(
+        data.chrom_sizes().clone(),
+        data,
+        pool(),
+    )?;
+    Ok(())
+}
+
+#[test]
+fn test_calc() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let a = resource("valid.bigWig");
+
+    // A bigWig of 1s over part of the first values of `a`
+    let b = dir.path().join("b.bigWig");
+    write_bigwig(
+        &b,
+        chrom_map(&[("chr17", 83257441)]),
+        vec![value("chr17", 59920, 59960, 1.0)],
+    )?;
+
+    let out = dir.path().join("out.bigWig");
+    calc(&out, &[&a, &b], "a + b", MissingData::Skip)?;
+    assert_eq!(
+        values_in(&out, "chr17", 0, 83257441),
+        vec![(59920, 59947, 1.16627), (59947, 59960, 1.85137)]
+    );
+
+    calc(&out, &[&a, &b], "a + b", MissingData::Zero)?;
+    assert_eq!(
+        values_in(&out, "chr17", 59910, 59990),
+        vec![
+            (59910, 59920, 0.16627),
+            (59920, 59947, 1.16627),
+            (59947, 59960, 1.85137),
+            (59960, 59990, 0.85137)
+        ]
+    );
+
+    // With `NaN`, max ignores the missing input
+    calc(&out, &[&a, &b], "max(a, b) * 2", MissingData::NaN)?;
+    assert_eq!(
+        values_in(&out, "chr17", 59910, 59919),
+        vec![(59910, 59919, 0.33254)]
+    );
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use std::error::Error;
+use std::path::Path;
+use std::process::Command;
+
+use bigtools::{read_file_type, BBIFile};
+
+pub mod common;
+use common::{all_values, chrom_map, value, write_bigwig};
+
+fn chromintersect(a: &Path, b: &Path, out: &Path) -> Result<(), Box<dyn Error>> {
+    let output = Command::new(env!("CARGO_BIN_EXE_bigtools"))
+        .arg("chromintersect")
+        .arg("-a")
+        .arg(a)
+        .arg("-b")
+        .arg(b)
+        .arg("-o")
+        .arg(out)
+        .output()?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).into());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_read_file_type() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let bigwig = dir.path().join("in.bigWig");
+    write_bigwig(
+        &bigwig,
+        chrom_map(&[("chr1", 100)]),
+        vec![value("chr1", 0, 10, 1.0)],
+    )?;
+    assert!(matches!(
+        read_file_type(&mut std::fs::File::open(&bigwig)?)?,
+        Some(BBIFile::BigWig)
+    ));
+    assert!(read_file_type(&mut "chr1\t0\t10\n".as_bytes())?.is_none());
+    // Too short to have a magic
+    assert!(read_file_type(&mut "".as_bytes())?.is_none());
+    assert!(read_file_type(&mut "ch".as_bytes())?.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_chromintersect_bed() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let b = dir.path().join("b.bigWig");
+    write_bigwig(
+        &b,
+        chrom_map(&[("chr1", 100), ("chr3", 100)]),
+        vec![value("chr1", 0, 10, 1.0), value("chr3", 50, 60, 1.0)],
+    )?;
+
+    let a = dir.path().join("a.bed");
+    std::fs::write(&a, "chr1\t0\t10\nchr2\t0\t10\nchr3\t5\t15\tx\n")?;
+    let out = dir.path().join("out.bed");
+    chromintersect(&a, &b, &out)?;
+    assert_eq!(
+        std::fs::read_to_string(&out)?,
+        "chr1\t0\t10\nchr3\t5\t15\tx\n"
+    );
+
+    // Empty and short beds aren't mistaken for broken bigWigs
+    for contents in ["", "c\n"] {
+        std::fs::write(&a, contents)?;
+        chromintersect(&a, &b, &out)?;
+        assert_eq!(std::fs::read_to_string(&out)?, "");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_chromintersect_bigwig() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let b = dir.path().join("b.bigWig");
+    write_bigwig(
+        &b,
+        chrom_map(&[("chr1", 100), ("chr3", 100)]),
+        vec![value("chr1", 0, 10, 1.0), value("chr3", 50, 60, 1.0)],
+    )?;
+
+    let a = dir.path().join("a.bigWig");
+    write_bigwig(
+        &a,
+        chrom_map(&[("chr1", 100), ("chr2", 100), ("chr3", 100)]),
+        vec![
+            value("chr1", 0, 10, 1.0),
+            value("chr2", 0, 10, 2.0),
+            value("chr3", 5, 15, 3.0),
+        ],
+    )?;
+    let out = dir.path().join("out.bigWig");
+    chromintersect(&a, &b, &out)?;
+    assert_eq!(
+        all_values(&out),
+        vec![
+            ("chr1".to_string(), 0, 10, 1.0),
+            ("chr3".to_string(), 5, 15, 3.0),
+        ]
+    );
+
+    Ok(())
+}
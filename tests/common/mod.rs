@@ -0,0 +1,141 @@
+//! Fixtures shared by the integration tests. Each test declares this as
+//! `pub mod common;`, so that the fixtures it doesn't use aren't dead code.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use bigtools::bbiread::ChromInfo;
+use bigtools::bed::autosql::bed_autosql;
+use bigtools::bed::bedparser::BedParser;
+use bigtools::bedchromdata::BedParserStreamingIterator;
+use bigtools::{BedEntry, BigBedRead, BigBedWrite, BigWigRead, BigWigWrite, Value};
+
+/// The path of a file in `resources/test`.
+pub fn resource(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("resources/test");
+    path.push(name);
+    path
+}
+
+pub fn pool() -> futures::executor::ThreadPool {
+    futures::executor::ThreadPoolBuilder::new()
+        .pool_size(2)
+        .create()
+        .expect("Unable to create thread pool.")
+}
+
+pub fn chrom_map(chroms: &[(&str, u32)]) -> HashMap<String, u32> {
+    chroms.iter().map(|(c, l)| (c.to_string(), *l)).collect()
+}
+
+pub fn value(chrom: &str, start: u32, end: u32, value: f32) -> (String, Value) {
+    (chrom.to_owned(), Value { start, end, value })
+}
+
+pub fn entry(chrom: &str, start: u32, end: u32, rest: &str) -> (String, BedEntry) {
+    let entry = BedEntry {
+        start,
+        end,
+        rest: rest.to_owned(),
+    };
+    (chrom.to_owned(), entry)
+}
+
+/// Writes a bigWig of `values`, which must be sorted.
+pub fn write_bigwig(
+    path: impl AsRef<Path>,
+    chrom_map: HashMap<String, u32>,
+    values: Vec<(String, Value)>,
+) -> Result<(), Box<dyn Error>> {
+    let values = values.into_iter().map(Ok::<_, io::Error>);
+    let data = BedParserStreamingIterator::new(BedParser::wrap_iter(values), false);
+    BigWigWrite::create_file(path.as_ref().to_string_lossy().to_string()).write(
+        chrom_map,
+        data,
+        pool(),
+    )?;
+    Ok(())
+}
+
+/// Writes a bigBed of `entries`, which must be sorted, with the autosql of a
+/// bed with the fields of the first entry.
+pub fn write_bigbed(
+    path: impl AsRef<Path>,
+    chrom_map: HashMap<String, u32>,
+    entries: Vec<(String, BedEntry)>,
+) -> Result<(), Box<dyn Error>> {
+    let mut outb = BigBedWrite::create_file(path.as_ref().to_string_lossy().to_string());
+    outb.autosql = entries.first().map(|(_, e)| bed_autosql(&e.rest));
+    let entries = entries.into_iter().map(Ok::<_, io::Error>);
+    let data = BedParserStreamingIterator::new(BedParser::wrap_iter(entries), false);
+    outb.write(chrom_map, data, pool())?;
+    Ok(())
+}
+
+fn sorted_chroms(chrom_info: &[ChromInfo]) -> Vec<(String, u32)> {
+    let mut chroms: Vec<(String, u32)> = chrom_info
+        .iter()
+        .map(|c| (c.name.clone(), c.length))
+        .collect();
+    chroms.sort();
+    chroms
+}
+
+/// The values of the bigWig at `path` between `start` and `end` of `chrom`.
+pub fn values_in(
+    path: impl AsRef<Path>,
+    chrom: &str,
+    start: u32,
+    end: u32,
+) -> Vec<(u32, u32, f32)> {
+    let mut read = BigWigRead::open_file(&path.as_ref().to_string_lossy()).unwrap();
+    read.get_interval(chrom, start, end)
+        .unwrap()
+        .map(|v| v.unwrap())
+        .map(|v| (v.start, v.end, v.value))
+        .collect()
+}
+
+/// The values of `chrom` of the bigWig at `path`.
+pub fn values(path: impl AsRef<Path>, chrom: &str) -> Vec<(u32, u32, f32)> {
+    let read = BigWigRead::open_file(&path.as_ref().to_string_lossy()).unwrap();
+    let length = read
+        .info
+        .chrom_info
+        .iter()
+        .find(|c| c.name == chrom)
+        .unwrap()
+        .length;
+    values_in(path, chrom, 0, length)
+}
+
+/// The values of every chromosome of the bigWig at `path`, sorted by
+/// chromosome.
+pub fn all_values(path: impl AsRef<Path>) -> Vec<(String, u32, u32, f32)> {
+    let read = BigWigRead::open_file(&path.as_ref().to_string_lossy()).unwrap();
+    let mut values = vec![];
+    for (chrom, length) in sorted_chroms(&read.info.chrom_info) {
+        for (start, end, value) in values_in(&path, &chrom, 0, length) {
+            values.push((chrom.clone(), start, end, value));
+        }
+    }
+    values
+}
+
+/// The entries of every chromosome of the bigBed at `path`, sorted by
+/// chromosome.
+pub fn all_entries(path: impl AsRef<Path>) -> Vec<(String, u32, u32, String)> {
+    let mut read = BigBedRead::open_file(path.as_ref().to_string_lossy().to_string()).unwrap();
+    let mut entries = vec![];
+    let chroms = sorted_chroms(&read.info.chrom_info);
+    for (chrom, length) in chroms {
+        for entry in read.get_interval(&chrom, 0, length).unwrap() {
+            let entry = entry.unwrap();
+            entries.push((chrom.clone(), entry.start, entry.end, entry.rest));
+        }
+    }
+    entries
+}
@@ -0,0 +1,57 @@
+use std::error::Error;
+use std::path::Path;
+
+use bigtools::utils::correlate::{bin_values, correlate, CorrelateBins, CorrelateOptions};
+use bigtools::BigWigRead;
+
+pub mod common;
+use common::{chrom_map, value, write_bigwig};
+
+fn write(path: &Path, vals: &[(&str, u32, u32, f32)]) -> Result<(), Box<dyn Error>> {
+    let vals = vals
+        .iter()
+        .map(|(chrom, start, end, v)| value(chrom, *start, *end, *v))
+        .collect();
+    write_bigwig(path, chrom_map(&[("chr1", 40), ("chr2", 20)]), vals)
+}
+
+#[test]
+fn test_bin_values() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let a = dir.path().join("a.bigWig");
+    let b = dir.path().join("b.bigWig");
+    write(&a, &[("chr1", 0, 10, 1.0), ("chr1", 20, 25, 4.0)])?;
+    write(&b, &[("chr1", 0, 10, 2.0), ("chr2", 0, 10, 1.0)])?;
+    let bigwigs = vec![
+        BigWigRead::open_file(&a.to_string_lossy())?,
+        BigWigRead::open_file(&b.to_string_lossy())?,
+    ];
+
+    let mut options = CorrelateOptions {
+        bins: CorrelateBins::Fixed(10),
+        blacklist: vec![],
+        skip_zeros: false,
+    };
+    let values = bin_values(&bigwigs, &options, 2)?;
+    assert_eq!(values[0], vec![1.0, 0.0, 2.0, 0.0, 0.0, 0.0]);
+    assert_eq!(values[1], vec![2.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+
+    options.skip_zeros = true;
+    options.blacklist = vec![("chr2".to_string(), 5, 6)];
+    let values = bin_values(&bigwigs, &options, 2)?;
+    assert_eq!(values, vec![vec![1.0, 2.0], vec![2.0, 0.0]]);
+    assert_eq!(correlate(&values).pearson[0][1], -1.0);
+
+    options.bins = CorrelateBins::Regions(vec![
+        ("chr1".to_string(), 5, 25),
+        ("chr1".to_string(), 0, 5),
+        ("chr3".to_string(), 0, 5),
+    ]);
+    options.skip_zeros = false;
+    let values = bin_values(&bigwigs, &options, 1)?;
+    assert_eq!(values, vec![vec![1.0, 1.25, 0.0], vec![2.0, 0.5, 0.0]]);
+
+    options.bins = CorrelateBins::Fixed(0);
+    assert!(bin_values(&bigwigs, &options, 1).is_err());
+    Ok(())
+}
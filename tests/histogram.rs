@@ -0,0 +1,94 @@
+use std::error::Error;
+
+use bigtools::utils::histogram::{distribution, HistogramBins, HistogramOptions, QuantileMethod};
+use bigtools::BigWigRead;
+
+pub mod common;
+use common::resource;
+
+#[test]
+fn test_histogram() -> Result<(), Box<dyn Error>> {
+    let mut bigwig = BigWigRead::open_file(&resource("valid.bigWig").to_string_lossy())?;
+    let summary = bigwig.get_summary()?;
+    let mut values: Vec<(f32, u32)> = bigwig
+        .get_interval("chr17", 0, 83257441)?
+        .map(|v| v.map(|v| (v.value, v.end - v.start)))
+        .collect::<Result<_, _>>()?;
+    values.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let total: u64 = values.iter().map(|v| u64::from(v.1)).sum();
+
+    let mut options = HistogramOptions {
+        bins: HistogramBins::Linear {
+            min: 0.0,
+            max: 1000.0,
+            bins: 10,
+        },
+        quantiles: vec![0.0, 0.1, 0.5, 0.9, 1.0],
+        method: QuantileMethod::Exact,
+        regions: None,
+    };
+    let exact = distribution(&bigwig, &options, 2)?;
+    assert_eq!(exact.chroms.len(), 1);
+    assert_eq!(exact.chroms[0].covered, summary.bases_covered);
+    assert_eq!(exact.chroms[0].bases, 83257441);
+    let histogram = &exact.histogram;
+    assert_eq!(histogram.edges.len(), 11);
+    assert_eq!(
+        histogram.counts.iter().sum::<u64>() + histogram.underflow + histogram.overflow,
+        total
+    );
+    let over: u64 = values
+        .iter()
+        .filter(|v| v.0 > 1000.0)
+        .map(|v| u64::from(v.1))
+        .sum();
+    assert_eq!(histogram.overflow, over);
+    // The median is the value of the middle base
+    let mut seen = 0;
+    let median = values
+        .iter()
+        .find(|v| {
+            seen += u64::from(v.1);
+            seen * 2 >= total
+        })
+        .unwrap()
+        .0;
+    assert_eq!(exact.quantiles[2], (0.5, f64::from(median)));
+    assert_eq!(exact.quantiles[0].1, summary.min_val);
+    assert_eq!(exact.quantiles[4].1, summary.max_val);
+
+    // A t-digest is close to the exact quantiles, in rank
+    options.method = QuantileMethod::TDigest(100.0);
+    let approximate = distribution(&bigwig, &options, 3)?;
+    assert_eq!(approximate.histogram, exact.histogram);
+    for (q, value) in approximate.quantiles {
+        let rank: u64 = values
+            .iter()
+            .filter(|v| f64::from(v.0) < value)
+            .map(|v| u64::from(v.1))
+            .sum();
+        let rank = rank as f64 / total as f64;
+        assert!((rank - q).abs() < 0.02, "{} {} {}", q, value, rank);
+    }
+
+    // Overlapping regions are only read once
+    options.regions = Some(vec![
+        ("chr17".to_owned(), 0, 100000),
+        ("chr17".to_owned(), 50000, 200000),
+        ("chrX".to_owned(), 0, 10),
+    ]);
+    let regions = distribution(&bigwig, &options, 2)?;
+    assert_eq!(regions.chroms.len(), 2);
+    assert_eq!(regions.chroms[0].bases, 200000);
+    let covered: u64 = bigwig
+        .get_interval("chr17", 0, 200000)?
+        .map(|v| v.map(|v| u64::from(v.end.min(200000) - v.start)))
+        .sum::<Result<_, _>>()?;
+    assert_eq!(regions.chroms[0].covered, covered);
+    assert_eq!(
+        (regions.chroms[1].bases, regions.chroms[1].covered),
+        (10, 0)
+    );
+
+    Ok(())
+}
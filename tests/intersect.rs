@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+
+use bigtools::bbi::{BigBedRead, BigBedWrite, BigWigRead};
+use bigtools::bed::bedparser::BedParser;
+use bigtools::bedchromdata::BedParserStreamingIterator;
+use bigtools::utils::intersect::{
+    intersect, intersect_bigbeds, IntersectMode, IntersectOptions, IntersectSource,
+    IntersectSummary, UnknownChroms,
+};
+use bigtools::BedEntry;
+
+pub mod common;
+use common::{chrom_map, entry, pool, resource, write_bigbed};
+
+const A: &str = "\
+track name=a
+chr17\t59900\t60200\tx
+chr17\t0\t10\ty
+chr1\t0\t10\tz
+";
+
+fn run<B: IntersectSource>(b: &mut B, options: IntersectOptions) -> String {
+    let mut out = vec![];
+    intersect(A.as_bytes(), b, &options, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn with_mode(mode: IntersectMode) -> IntersectOptions {
+    IntersectOptions {
+        mode,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_intersect_bigwig() -> Result<(), Box<dyn Error>> {
+    let mut b = BigWigRead::open_file(&resource("valid.bigWig").to_string_lossy())?;
+
+    let out = run(&mut b, IntersectOptions::default());
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[0], "chr17\t59900\t59947\tx");
+
+    let out = run(
+        &mut b,
+        with_mode(IntersectMode::Overlaps {
+            write_a: true,
+            write_b: true,
+        }),
+    );
+    assert_eq!(
+        out.lines().next().unwrap(),
+        "chr17\t59900\t60200\tx\tchr17\t59900\t59947\t0.16627"
+    );
+
+    assert_eq!(
+        run(&mut b, with_mode(IntersectMode::Count)),
+        "chr17\t59900\t60200\tx\t6\nchr17\t0\t10\ty\t0\nchr1\t0\t10\tz\t0\n"
+    );
+    assert_eq!(
+        run(&mut b, with_mode(IntersectMode::Unique)),
+        "chr17\t59900\t60200\tx\n"
+    );
+    assert_eq!(
+        run(&mut b, with_mode(IntersectMode::NoOverlap)),
+        "chr17\t0\t10\ty\nchr1\t0\t10\tz\n"
+    );
+    assert_eq!(
+        run(
+            &mut b,
+            with_mode(IntersectMode::Summary(IntersectSummary::Coverage))
+        ),
+        "chr17\t59900\t60200\tx\t0.8166666666666667\nchr17\t0\t10\ty\t0\nchr1\t0\t10\tz\t0\n"
+    );
+
+    // No single value covers half of the first record
+    let options = IntersectOptions {
+        mode: IntersectMode::Count,
+        min_fraction: 0.5,
+        ..Default::default()
+    };
+    assert!(run(&mut b, options).starts_with("chr17\t59900\t60200\tx\t0\n"));
+
+    let options = IntersectOptions {
+        unknown_chroms: UnknownChroms::Error,
+        ..Default::default()
+    };
+    assert!(intersect(A.as_bytes(), &mut b, &options, vec![]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_intersect_bigbed() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("small.bigBed");
+
+    let vals_iter = BedParser::from_bed_file(File::open(resource("small.bed"))?);
+    let chrom_map = HashMap::from([
+        ("chr17".to_string(), 83257441),
+        ("chr18".to_string(), 80373285),
+        ("chr19".to_string(), 58617616),
+    ]);
+    let chsi = BedParserStreamingIterator::new(vals_iter, false);
+    BigBedWrite::create_file(path.to_string_lossy().to_string()).write(chrom_map, chsi, pool())?;
+    let mut b = BigBedRead::open_file(path.to_string_lossy().to_string())?;
+
+    let a = "chr17\t50\t150\n";
+    let options = with_mode(IntersectMode::Overlaps {
+        write_a: false,
+        write_b: true,
+    });
+    let mut out = vec![];
+    intersect(a.as_bytes(), &mut b, &options, &mut out)?;
+    assert_eq!(
+        String::from_utf8(out)?,
+        "chr17\t50\t100\tchr17\t1\t100\ttest1\t0\nchr17\t101\t150\tchr17\t101\t200\ttest2\t0\n"
+    );
+
+    // Summaries need values
+    let options = with_mode(IntersectMode::Summary(IntersectSummary::Mean));
+    assert!(intersect(a.as_bytes(), &mut b, &options, vec![]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_intersect_bigbeds() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    // Pseudo-random entries, with some long ones that overlap many others
+    let mut state = 12345u64;
+    let mut random = |max: u32| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+        ((state >> 33) % u64::from(max)) as u32
+    };
+    let mut entries = |n: usize, name: &str, chroms: &[&str]| {
+        let mut entries: Vec<(String, BedEntry)> = (0..n)
+            .map(|i| {
+                let chrom = chroms[random(chroms.len() as u32) as usize].to_owned();
+                let start = random(100_000);
+                let length = if i % 50 == 0 {
+                    random(5000)
+                } else {
+                    random(200)
+                };
+                let entry = BedEntry {
+                    start,
+                    end: start + length,
+                    rest: format!("{}{}", name, i),
+                };
+                (chrom, entry)
+            })
+            .collect();
+        entries.sort_by(|a, b| (&a.0, a.1.start).cmp(&(&b.0, b.1.start)));
+        entries
+    };
+    let a_entries = entries(2000, "a", &["chr1", "chr2", "chr3"]);
+    let b_entries = entries(2000, "b", &["chr1", "chr2"]);
+    let chrom_map = |chroms: &[&str]| {
+        chroms
+            .iter()
+            .map(|c| (c.to_string(), 200_000))
+            .collect::<HashMap<_, _>>()
+    };
+    let a_path = dir.path().join("a.bigBed");
+    let b_path = dir.path().join("b.bigBed");
+    write_bigbed(
+        &a_path,
+        chrom_map(&["chr1", "chr2", "chr3"]),
+        a_entries.clone(),
+    )?;
+    write_bigbed(&b_path, chrom_map(&["chr1", "chr2"]), b_entries)?;
+    let a = BigBedRead::open_file(a_path.to_string_lossy().to_string())?;
+    let mut b = BigBedRead::open_file(b_path.to_string_lossy().to_string())?;
+    let a_bed: String = a_entries
+        .iter()
+        .map(|(chrom, e)| format!("{}\t{}\t{}\t{}\n", chrom, e.start, e.end, e.rest))
+        .collect();
+
+    // The same as querying `b` for each entry of `a`
+    for mode in [
+        IntersectMode::Overlaps {
+            write_a: false,
+            write_b: true,
+        },
+        IntersectMode::Overlaps {
+            write_a: true,
+            write_b: false,
+        },
+        IntersectMode::Unique,
+        IntersectMode::NoOverlap,
+        IntersectMode::Count,
+    ] {
+        for min_fraction in [0.0, 0.5] {
+            let options = IntersectOptions {
+                mode,
+                min_fraction,
+                ..Default::default()
+            };
+            let mut expected = vec![];
+            intersect(a_bed.as_bytes(), &mut b, &options, &mut expected)?;
+            for nthreads in [1, 3] {
+                let mut out = vec![];
+                intersect_bigbeds(&a, &b, &options, nthreads, &mut out)?;
+                assert_eq!(
+                    String::from_utf8(out)?,
+                    String::from_utf8(expected.clone())?
+                );
+            }
+        }
+    }
+
+    let options = IntersectOptions {
+        unknown_chroms: UnknownChroms::Error,
+        ..Default::default()
+    };
+    assert!(intersect_bigbeds(&a, &b, &options, 2, vec![]).is_err());
+    let options = with_mode(IntersectMode::Summary(IntersectSummary::Mean));
+    assert!(intersect_bigbeds(&a, &b, &options, 2, vec![]).is_err());
+
+    Ok(())
+}
+
+/// Runs `bigtools intersect` with `args`, returning its output and stderr.
+fn intersect_cli(args: &[&std::path::Path]) -> Result<(String, String), Box<dyn Error>> {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_bigtools"))
+        .arg("intersect")
+        .arg("-a")
+        .arg(args[0])
+        .arg("-b")
+        .arg(args[1])
+        .output()?;
+    let stderr = String::from_utf8(output.stderr)?;
+    if !output.status.success() {
+        return Err(stderr.into());
+    }
+    Ok((String::from_utf8(output.stdout)?, stderr))
+}
+
+#[test]
+fn test_intersect_cli() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let b = dir.path().join("b.bigBed");
+    write_bigbed(
+        &b,
+        chrom_map(&[("chr17", 300000)]),
+        vec![entry("chr17", 100, 2000, "x")],
+    )?;
+
+    // A bed shorter than a bigBed header is still a bed
+    let a = dir.path().join("a.bed");
+    std::fs::write(&a, "chr17\t1000\t200000\n")?;
+    let (out, stderr) = intersect_cli(&[&a, &b])?;
+    assert_eq!(out, "chr17\t1000\t2000\n");
+    assert!(!stderr.contains("Error"), "{}", stderr);
+
+    // A bigBed `a` is intersected with the bigBed `b`
+    let a = dir.path().join("a.bigBed");
+    write_bigbed(
+        &a,
+        chrom_map(&[("chr17", 300000)]),
+        vec![entry("chr17", 1000, 200000, "y")],
+    )?;
+    let (out, stderr) = intersect_cli(&[&a, &b])?;
+    assert_eq!(out, "chr17\t1000\t2000\ty\n");
+    assert!(!stderr.contains("Error"), "{}", stderr);
+
+    Ok(())
+}
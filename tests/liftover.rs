@@ -0,0 +1,251 @@
+use std::error::Error;
+use std::io::Write;
+use std::sync::Arc;
+
+use bigtools::utils::liftover::{liftover_entries, liftover_values, Chains, Lifted};
+use bigtools::{BigBedRead, BigWigRead};
+
+pub mod common;
+use common::{all_entries, all_values, chrom_map, entry, value, write_bigbed, write_bigwig};
+
+// chr1 100-150 -> chrA 0-50, 170-200 -> 50-80, 200-270 -> 90-160
+// chr1 500-550 -> chrB 140-190 (on the minus strand)
+// chr2 0-20 -> chrA 40-60
+const CHAINS: &str = "\
+chain 1000 chr1 1000 + 100 270 chrA 500 + 0 160 1
+50\t20\t0
+30\t0\t10
+70
+
+chain 500 chr1 1000 + 500 550 chrB 200 - 10 60 2
+50
+
+chain 100 chr2 100 + 0 20 chrA 500 + 40 60 3
+20
+";
+
+const CHROMS: &[(&str, u32)] = &[("chr1", 1000), ("chr2", 100)];
+
+/// Records with the chromosome they are on.
+type Records<T> = Vec<(String, T)>;
+
+/// Splits lifted records into the mapped and unmapped ones.
+fn split<T, E: std::fmt::Debug>(
+    lifted: impl Iterator<Item = Result<Lifted<T>, E>>,
+) -> (Records<T>, Records<T>) {
+    let (mut mapped, mut unmapped) = (vec![], vec![]);
+    for record in lifted {
+        match record.unwrap() {
+            Lifted::Mapped(chrom, record) => mapped.push((chrom, record)),
+            Lifted::Unmapped(chrom, record) => unmapped.push((chrom, record)),
+        }
+    }
+    (mapped, unmapped)
+}
+
+#[test]
+fn test_liftover_values() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let chains = Arc::new(Chains::from_reader(CHAINS.as_bytes())?);
+
+    let path = dir.path().join("in.bigWig");
+    let values = vec![
+        value("chr1", 90, 120, 1.0),
+        value("chr1", 140, 210, 2.0),
+        value("chr1", 500, 510, 3.0),
+        value("chr2", 0, 20, 4.0),
+    ];
+    write_bigwig(&path, chrom_map(CHROMS), values)?;
+
+    let open = || BigWigRead::open_file(&path.to_string_lossy());
+    let (mapped, unmapped) = split(liftover_values(open()?, chains.clone(), true));
+    assert_eq!(
+        mapped,
+        vec![
+            value("chrA", 0, 20, 1.0),
+            value("chrA", 40, 50, 2.0),
+            value("chrA", 50, 80, 2.0),
+            value("chrA", 90, 100, 2.0),
+            value("chrB", 180, 190, 3.0),
+        ]
+    );
+    // The unmapped parts, then the value of chr2, which overlaps the mapped
+    // value at chrA:40-50
+    assert_eq!(
+        unmapped,
+        vec![
+            value("chr1", 90, 100, 1.0),
+            value("chr1", 150, 170, 2.0),
+            value("chr2", 0, 20, 4.0),
+        ]
+    );
+    // Without looking for unmapped values, only overlapping ones are found
+    let (without, unmapped) = split(liftover_values(open()?, chains.clone(), false));
+    assert_eq!(without, mapped);
+    assert_eq!(unmapped, vec![value("chr2", 0, 20, 4.0)]);
+
+    // The mapped values can be written with the target chromosome sizes
+    let out = dir.path().join("out.bigWig");
+    let expected: Vec<_> = mapped
+        .iter()
+        .map(|(chrom, v)| (chrom.clone(), v.start, v.end, v.value))
+        .collect();
+    write_bigwig(&out, chains.target_sizes(), mapped)?;
+    assert_eq!(all_values(&out), expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_liftover_entries() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let chains = Arc::new(Chains::from_reader(CHAINS.as_bytes())?);
+    let path = dir.path().join("in.bigBed");
+    write_bigbed(
+        &path,
+        chrom_map(CHROMS),
+        vec![
+            entry("chr1", 100, 140, "a\t0\t+"),
+            entry("chr1", 140, 210, "b\t0\t+"),
+            entry("chr1", 500, 520, "c\t0\t+"),
+            entry("chr1", 900, 950, "d\t0\t-"),
+        ],
+    )?;
+
+    let open = || BigBedRead::open_file(path.to_string_lossy().to_string());
+    let (mapped, unmapped) = split(liftover_entries(open()?, chains.clone(), 0.95, true));
+    assert_eq!(
+        mapped,
+        vec![
+            entry("chrA", 0, 40, "a\t0\t+"),
+            entry("chrB", 170, 190, "c\t0\t-"),
+        ]
+    );
+    // Only 50 of the 70 bases of `b` map
+    assert_eq!(
+        unmapped,
+        vec![
+            entry("chr1", 140, 210, "b\t0\t+"),
+            entry("chr1", 900, 950, "d\t0\t-"),
+        ]
+    );
+
+    // `b` is in two chains' spans, but each part is only mapped once
+    let (mapped, unmapped) = split(liftover_entries(open()?, chains.clone(), 0.5, true));
+    assert_eq!(
+        mapped,
+        vec![
+            entry("chrA", 0, 40, "a\t0\t+"),
+            entry("chrA", 40, 50, "b\t0\t+"),
+            entry("chrA", 50, 80, "b\t0\t+"),
+            entry("chrA", 90, 100, "b\t0\t+"),
+            entry("chrB", 170, 190, "c\t0\t-"),
+        ]
+    );
+    assert_eq!(unmapped, vec![entry("chr1", 900, 950, "d\t0\t-")]);
+    let (without, unmapped) = split(liftover_entries(open()?, chains, 0.5, false));
+    assert_eq!(without, mapped);
+    assert!(unmapped.is_empty());
+
+    // The binary lifts bigBeds over without first trying to open them as bigWigs
+    let chain = dir.path().join("chain");
+    std::fs::write(&chain, CHAINS)?;
+    let out = dir.path().join("out.bigBed");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_bigtools"))
+        .arg("liftover")
+        .arg(&path)
+        .arg(&chain)
+        .arg(&out)
+        .args(["--min-fraction", "0.5"])
+        .output()?;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(output.status.success(), "{}", stderr);
+    assert!(!stderr.contains("Error"), "{}", stderr);
+    let expected: Vec<_> = mapped
+        .into_iter()
+        .map(|(chrom, e)| (chrom, e.start, e.end, e.rest))
+        .collect();
+    assert_eq!(all_entries(&out), expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_liftover_bed12() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let chains = Arc::new(Chains::from_reader(CHAINS.as_bytes())?);
+    let path = dir.path().join("in.bigBed");
+    write_bigbed(
+        &path,
+        chrom_map(CHROMS),
+        vec![
+            // Blocks at 100-120 and 180-210, split between three chain blocks
+            entry("chr1", 100, 210, "t\t0\t+\t110\t205\t0\t2\t20,30,\t0,80,"),
+            // Its only block is in the gap between chain blocks
+            entry("chr1", 140, 180, "g\t0\t+\t140\t180\t0\t1\t16,\t12,"),
+            // Blocks at 500-510 and 540-550, on the minus strand
+            entry("chr1", 500, 550, "m\t0\t+\t505\t520\t0\t2\t10,10,\t0,40,"),
+            // The block count doesn't match the blocks
+            entry("chr2", 0, 10, "b\t0\t+\t0\t10\t0\t2\t10,\t0,"),
+        ],
+    )?;
+
+    let open = || BigBedRead::open_file(path.to_string_lossy().to_string());
+    let (mapped, unmapped) = split(liftover_entries(open()?, chains.clone(), 0.5, true));
+    // Each part is trimmed to its blocks, with the thick part clamped to it
+    assert_eq!(
+        mapped,
+        vec![
+            entry("chrA", 0, 20, "t\t0\t+\t10\t20\t0\t1\t20,\t0,"),
+            entry("chrA", 60, 80, "t\t0\t+\t60\t80\t0\t1\t20,\t0,"),
+            entry("chrA", 90, 100, "t\t0\t+\t90\t95\t0\t1\t10,\t0,"),
+            entry("chrB", 140, 190, "m\t0\t-\t170\t185\t0\t2\t10,10,\t0,40,"),
+        ]
+    );
+    assert_eq!(
+        unmapped,
+        vec![
+            entry("chr1", 140, 180, "g\t0\t+\t140\t180\t0\t1\t16,\t12,"),
+            entry("chr2", 0, 10, "b\t0\t+\t0\t10\t0\t2\t10,\t0,"),
+        ]
+    );
+
+    // The mapped entries make a valid bigBed
+    let out = dir.path().join("out.bigBed");
+    let expected: Vec<_> = mapped
+        .iter()
+        .map(|(chrom, e)| (chrom.clone(), e.start, e.end, e.rest.clone()))
+        .collect();
+    write_bigbed(&out, chains.target_sizes(), mapped)?;
+    assert_eq!(all_entries(&out), expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_chains_from_file() -> Result<(), Box<dyn Error>> {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    let dir = tempfile::tempdir()?;
+    let expected = Chains::from_reader(CHAINS.as_bytes())?.target_sizes();
+
+    let plain = dir.path().join("chains.over.chain");
+    std::fs::write(&plain, CHAINS)?;
+    assert_eq!(Chains::from_file(&plain)?.target_sizes(), expected);
+
+    // Like bgzip, with each chain in its own gzip member
+    let gzipped = dir.path().join("chains.over.chain.gz");
+    let mut file = std::fs::File::create(&gzipped)?;
+    for chain in CHAINS.split_inclusive("\n\n") {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(chain.as_bytes())?;
+        file.write_all(&encoder.finish()?)?;
+    }
+    drop(file);
+    let chains = Chains::from_file(&gzipped)?;
+    assert_eq!(chains.target_sizes(), expected);
+    assert_eq!(chains.map("chr2", 0, 20)[0].chrom, "chrA");
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::error::Error;
+
+use bigtools::utils::intersect::IntersectSummary;
+use bigtools::utils::matrix::{
+    matrix, region_row, write_npy, MatrixMode, MatrixOptions, MatrixRegion, ReferencePoint,
+};
+use bigtools::BigWigRead;
+
+pub mod common;
+use common::{chrom_map, resource, value, write_bigwig};
+
+fn region(chrom: &str, start: u32, end: u32, minus_strand: bool) -> MatrixRegion {
+    MatrixRegion {
+        chrom: chrom.to_string(),
+        start,
+        end,
+        minus_strand,
+    }
+}
+
+/// Compares rows, where `NaN`s are equal
+fn assert_row(row: &[f32], expected: &[f32]) {
+    assert_eq!(row.len(), expected.len(), "{:?}", row);
+    for (a, b) in row.iter().zip(expected.iter()) {
+        assert!(a == b || (a.is_nan() && b.is_nan()), "{:?}", row);
+    }
+}
+
+#[test]
+fn test_matrix() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("test.bigWig");
+    let vals = vec![
+        value("chr1", 10, 20, 1.0),
+        value("chr1", 20, 30, 3.0),
+        value("chr1", 40, 50, 2.0),
+    ];
+    write_bigwig(&path, chrom_map(&[("chr1", 55)]), vals)?;
+    let mut bigwig = BigWigRead::open_file(&path.to_string_lossy())?;
+
+    let nan = f32::NAN;
+    let mut options = MatrixOptions {
+        mode: MatrixMode::ReferencePoint {
+            point: ReferencePoint::Start,
+            upstream: 20,
+            downstream: 30,
+        },
+        bin_size: 10,
+        summary: IntersectSummary::Mean,
+        missing_as_zero: false,
+    };
+    let plus = region("chr1", 20, 40, false);
+    let minus = region("chr1", 20, 40, true);
+    assert_row(
+        &region_row(&mut bigwig, &plus, &options)?,
+        &[nan, 1.0, 3.0, nan, 2.0],
+    );
+    // Around the end, with the last bin partially outside of the chromosome
+    assert_row(
+        &region_row(&mut bigwig, &minus, &options)?,
+        &[nan, 2.0, nan, 3.0, 1.0],
+    );
+    assert_row(
+        &region_row(&mut bigwig, &region("chr2", 20, 40, false), &options)?,
+        &[nan; 5],
+    );
+
+    options.mode = MatrixMode::ScaleRegions {
+        upstream: 10,
+        body: 40,
+        downstream: 10,
+    };
+    options.summary = IntersectSummary::Coverage;
+    assert_row(
+        &region_row(&mut bigwig, &region("chr1", 10, 30, false), &options)?,
+        &[0.0, 1.0, 1.0, 1.0, 1.0, 0.0],
+    );
+    options.summary = IntersectSummary::Max;
+    options.missing_as_zero = true;
+    assert_row(
+        &region_row(&mut bigwig, &region("chr1", 15, 45, true), &options)?,
+        &[2.0, 2.0, 0.0, 3.0, 3.0, 1.0],
+    );
+
+    let regions = vec![plus, minus, region("chr1", 0, 55, false)];
+    let m = matrix(&bigwig, &regions, &options, 2)?;
+    assert_eq!((m.rows, m.cols), (3, 6));
+    for (i, r) in regions.iter().enumerate() {
+        assert_row(m.row(i), &region_row(&mut bigwig, r, &options)?);
+    }
+
+    options.bin_size = 3;
+    assert!(region_row(&mut bigwig, &regions[0], &options).is_err());
+
+    let mut npy = vec![];
+    write_npy(&mut npy, &[m.clone(), m])?;
+    assert_eq!(&npy[..6], b"\x93NUMPY");
+    let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+    assert_eq!((10 + header_len) % 64, 0);
+    let header = std::str::from_utf8(&npy[10..10 + header_len])?;
+    assert!(header.contains("'shape': (2, 3, 6)"), "{}", header);
+    assert_eq!(npy.len(), 10 + header_len + 2 * 3 * 6 * 4);
+
+    Ok(())
+}
+
+#[test]
+fn test_matrix_zooms() -> Result<(), Box<dyn Error>> {
+    let mut bigwig = BigWigRead::open_file(&resource("valid.bigWig").to_string_lossy())?;
+    let region = region("chr17", 100_000, 200_000, false);
+    let options = |bin_size| MatrixOptions {
+        mode: MatrixMode::ScaleRegions {
+            upstream: 0,
+            body: 100_000,
+            downstream: 0,
+        },
+        bin_size,
+        summary: IntersectSummary::Sum,
+        missing_as_zero: false,
+    };
+    // A single bin uses a zoom level, and 1 base bins use the values. Zoom
+    // records at the edges only partially overlap the bin, so are approximate.
+    let zoomed: f32 = region_row(&mut bigwig, &region, &options(100_000))?[0];
+    let values: f32 = region_row(&mut bigwig, &region, &options(1))?.iter().sum();
+    assert!(values > 0.0);
+    assert!(
+        (zoomed - values).abs() / values < 0.05,
+        "{} {}",
+        zoomed,
+        values
+    );
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use std::error::Error;
+
+use bigtools::normalize::{normalize, Normalization};
+use bigtools::{BigWigRead, BigWigWrite, ValueScale};
+
+pub mod common;
+use common::{pool, resource, values};
+
+#[test]
+fn test_normalize() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let input = resource("valid.bigWig");
+    let original: Vec<f32> = values(&input, "chr17").iter().map(|v| v.2).collect();
+    let out = dir.path().join("out.bigWig");
+    let run = |normalization| {
+        let input = BigWigRead::open_file(&input.to_string_lossy()).unwrap();
+        let output = BigWigWrite::create_file(out.to_string_lossy().to_string());
+        normalize(input, output, normalization, pool())
+    };
+
+    let report = run(Normalization::Scale(2.0))?;
+    assert_eq!(
+        report.scale,
+        Some(ValueScale {
+            factor: 2.0,
+            offset: 0.0
+        })
+    );
+    let scaled: Vec<f32> = values(&out, "chr17").iter().map(|v| v.2).collect();
+    assert_eq!(scaled.len(), original.len());
+    assert!(scaled
+        .iter()
+        .zip(original.iter())
+        .all(|(s, o)| *s == o * 2.0));
+    // Zooms are recomputed from the scaled values
+    let mut read = BigWigRead::open_file(&out.to_string_lossy())?;
+    let zoom = read.info.zoom_headers[0].reduction_level;
+    let max = read
+        .get_zoom_interval("chr17", 0, 83257441, zoom)?
+        .map(|z| z.unwrap().summary.max_val)
+        .fold(f64::MIN, f64::max);
+    assert_eq!(
+        max as f32,
+        original.iter().copied().fold(f32::MIN, f32::max) * 2.0
+    );
+
+    // The total summary of the output has a mean of 0 and variance of 1
+    let report = run(Normalization::ZScore)?;
+    let summary = report.summary;
+    let mean = summary.sum / summary.bases_covered as f64;
+    let variance = summary.sum_squares / summary.bases_covered as f64 - mean * mean;
+    assert!(mean.abs() < 1e-3, "{}", mean);
+    assert!((variance - 1.0).abs() < 1e-3, "{}", variance);
+
+    let report = run(Normalization::Cpm(None))?;
+    assert!((report.summary.sum - 1e6).abs() < 1.0);
+    let report = run(Normalization::Cpm(Some(2e6)))?;
+    assert_eq!(report.scale.unwrap().factor, 0.5);
+
+    assert!(run(Normalization::Cpm(Some(0.0))).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_scale_for_count() -> Result<(), Box<dyn Error>> {
+    let factor = |normalization: Normalization| {
+        normalization
+            .scale_for_count(2_000_000)
+            .map(|scale| scale.factor)
+    };
+    assert_eq!(factor(Normalization::Scale(2.0))?, 2.0);
+    assert_eq!(factor(Normalization::Cpm(None))?, 0.5);
+    assert_eq!(factor(Normalization::Cpm(Some(1e6)))?, 1.0);
+    assert_eq!(
+        factor(Normalization::Rpkm {
+            total: None,
+            bin_size: 1000
+        })?,
+        0.5
+    );
+    assert_eq!(
+        factor(Normalization::Rpkm {
+            total: None,
+            bin_size: 50
+        })?,
+        10.0
+    );
+    assert!(factor(Normalization::ZScore).is_err());
+    assert!(Normalization::Cpm(None).scale_for_count(0).is_err());
+    Ok(())
+}
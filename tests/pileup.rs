@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io;
+use std::path::Path;
+
+use bigtools::bed::bedparser::BedParser;
+use bigtools::bedchromdata::BedParserStreamingIterator;
+use bigtools::mergechromdata::{BigBedMergeOptions, MergedBigBeds};
+use bigtools::pileupchromdata::{Pileup, PileupOptions, PileupStrand};
+use bigtools::{BedEntry, BigBedRead, BigBedWrite, BigWigWrite};
+
+pub mod common;
+use common::{all_values, pool};
+
+const READS: &[(&str, u32, u32, &str)] = &[
+    ("chr1", 0, 10, "r1\t0\t+"),
+    ("chr1", 5, 15, "r2\t0\t-"),
+    ("chr1", 20, 30, "r3\t0\t+"),
+    ("chr1", 95, 100, "r4\t0\t+"),
+    ("chr2", 0, 5, "r5\t0\t-"),
+];
+
+fn reads(reads: &[(&str, u32, u32, &str)]) -> Vec<Result<(String, BedEntry), io::Error>> {
+    reads
+        .iter()
+        .map(|(chrom, start, end, rest)| {
+            let entry = BedEntry {
+                start: *start,
+                end: *end,
+                rest: rest.to_string(),
+            };
+            Ok((chrom.to_string(), entry))
+        })
+        .collect()
+}
+
+fn chrom_map() -> HashMap<String, u32> {
+    HashMap::from([("chr1".to_string(), 100), ("chr2".to_string(), 50)])
+}
+
+fn pileup(out: &Path, options: PileupOptions) -> Result<(), Box<dyn Error>> {
+    let data =
+        BedParserStreamingIterator::new(BedParser::wrap_iter(reads(READS).into_iter()), false);
+    let data = Pileup::new(data, chrom_map(), options)?;
+    BigWigWrite::create_file(out.to_string_lossy().to_string()).write(chrom_map(), data, pool())?;
+    Ok(())
+}
+
+fn v(chrom: &str, start: u32, end: u32, value: f32) -> (String, u32, u32, f32) {
+    (chrom.to_owned(), start, end, value)
+}
+
+#[test]
+fn test_pileup() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let out = dir.path().join("out.bigWig");
+
+    pileup(&out, PileupOptions::default())?;
+    assert_eq!(
+        all_values(&out),
+        vec![
+            v("chr1", 0, 5, 1.0),
+            v("chr1", 5, 10, 2.0),
+            v("chr1", 10, 15, 1.0),
+            v("chr1", 20, 30, 1.0),
+            v("chr1", 95, 100, 1.0),
+            v("chr2", 0, 5, 1.0),
+        ]
+    );
+
+    // Extended from the 5' end, truncated to the chromosome
+    let options = PileupOptions {
+        extend: Some(20),
+        scale: 0.5,
+        ..Default::default()
+    };
+    pileup(&out, options)?;
+    assert_eq!(
+        all_values(&out),
+        vec![
+            v("chr1", 0, 15, 1.0),
+            v("chr1", 15, 40, 0.5),
+            v("chr1", 95, 100, 0.5),
+            v("chr2", 0, 5, 0.5),
+        ]
+    );
+
+    let options = PileupOptions {
+        strand: PileupStrand::Minus,
+        ..Default::default()
+    };
+    pileup(&out, options)?;
+    assert_eq!(
+        all_values(&out),
+        vec![v("chr1", 5, 15, 1.0), v("chr2", 0, 5, 1.0)]
+    );
+
+    // The same from a bigBed, in bins
+    let bigbed = dir.path().join("reads.bigBed");
+    let data =
+        BedParserStreamingIterator::new(BedParser::wrap_iter(reads(READS).into_iter()), false);
+    BigBedWrite::create_file(bigbed.to_string_lossy().to_string()).write(
+        chrom_map(),
+        data,
+        pool(),
+    )?;
+    let input = BigBedRead::open_file(bigbed.to_string_lossy().to_string())?;
+    let data = MergedBigBeds::new(vec![input], BigBedMergeOptions::default())?;
+    let options = PileupOptions {
+        bin_size: 10,
+        ..Default::default()
+    };
+    let data = Pileup::new(data, chrom_map(), options)?;
+    BigWigWrite::create_file(out.to_string_lossy().to_string()).write(chrom_map(), data, pool())?;
+    assert_eq!(
+        all_values(&out),
+        vec![
+            v("chr1", 0, 10, 2.0),
+            v("chr1", 10, 30, 1.0),
+            v("chr1", 90, 100, 1.0),
+            v("chr2", 0, 10, 1.0),
+        ]
+    );
+
+    // Entries must be sorted
+    let unsorted = reads(&[("chr1", 10, 20, ""), ("chr1", 5, 8, "")]);
+    let data = BedParserStreamingIterator::new(BedParser::wrap_iter(unsorted.into_iter()), false);
+    let data = Pileup::new(data, chrom_map(), PileupOptions::default())?;
+    let result = BigWigWrite::create_file(out.to_string_lossy().to_string()).write(
+        chrom_map(),
+        data,
+        pool(),
+    );
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_bedtobigwig_bigbed_without_extension() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let bigbed = dir.path().join("reads");
+    let data =
+        BedParserStreamingIterator::new(BedParser::wrap_iter(reads(READS).into_iter()), false);
+    BigBedWrite::create_file(bigbed.to_string_lossy().to_string()).write(
+        chrom_map(),
+        data,
+        pool(),
+    )?;
+
+    // The bigBed is detected by its magic, not its extension
+    let out = dir.path().join("out.bigWig");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_bedtobigwig"))
+        .arg(&bigbed)
+        .arg(&out)
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let expected = dir.path().join("expected.bigWig");
+    pileup(&expected, PileupOptions::default())?;
+    assert_eq!(all_values(&out), all_values(&expected));
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use std::error::Error;
+
+use bigtools::utils::threshold::{threshold_chroms, threshold_regions, ThresholdOptions};
+use bigtools::BigWigRead;
+
+pub mod common;
+use common::resource;
+
+#[test]
+fn test_threshold_chroms() -> Result<(), Box<dyn Error>> {
+    let mut bigwig = BigWigRead::open_file(&resource("valid.bigWig").to_string_lossy())?;
+    let options = ThresholdOptions {
+        threshold: 0.8,
+        min_length: 50,
+        max_gap: 10,
+    };
+
+    let regions = threshold_chroms(&bigwig, options, 4)?.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(regions.len(), 1);
+    let (chrom, regions) = &regions[0];
+    assert_eq!(chrom, "chr17");
+    assert!(!regions.is_empty());
+
+    let values = bigwig.get_interval("chr17", 0, 83257441)?;
+    let serial = threshold_regions(values, options).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(regions, &serial);
+    for region in regions {
+        assert!(region.end - region.start >= 50);
+        assert!(region.start <= region.summit && region.summit < region.end);
+        assert!(region.max > 0.8 && region.mean <= region.max as f64);
+    }
+    for pair in regions.windows(2) {
+        assert!(pair[1].start - pair[0].end >= 10);
+    }
+
+    Ok(())
+}